@@ -93,6 +93,8 @@ fn create_test_channels(count: usize) -> Vec<Channel> {
             tvg_id: format!("ch{}", i),
             resolution: if i % 3 == 0 { "1080p".to_string() } else { "720p".to_string() },
             extra_info: if i % 5 == 0 { format!("[HD] Extra info {}", i) } else { "".to_string() },
+            http_options: None,
+            source_list_id: None,
         }
     }).collect()
 }
@@ -1,59 +1,12 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
 use tollo_lib::database::populate_channels;
 use tollo_lib::m3u_parser::Channel;
+use tollo_lib::migrations::run_migrations;
 use rusqlite::Connection;
 
 fn create_test_db() -> Connection {
-    let conn = Connection::open_in_memory().unwrap();
-    
-    conn.execute(
-        "CREATE TABLE channels (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            logo TEXT NOT NULL,
-            url TEXT NOT NULL,
-            group_title TEXT NOT NULL,
-            tvg_id TEXT NOT NULL,
-            resolution TEXT NOT NULL,
-            extra_info TEXT NOT NULL
-        )",
-        [],
-    ).unwrap();
-    
-    conn.execute(
-        "CREATE TABLE favorites (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            logo TEXT NOT NULL,
-            url TEXT NOT NULL,
-            group_title TEXT NOT NULL,
-            tvg_id TEXT NOT NULL,
-            resolution TEXT NOT NULL,
-            extra_info TEXT NOT NULL
-        )",
-        [],
-    ).unwrap();
-    
-    conn.execute(
-        "CREATE TABLE history (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            logo TEXT NOT NULL,
-            url TEXT NOT NULL,
-            group_title TEXT NOT NULL,
-            tvg_id TEXT NOT NULL,
-            resolution TEXT NOT NULL,
-            extra_info TEXT NOT NULL,
-            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
-        )",
-        [],
-    ).unwrap();
-    
-    conn.execute(
-        "CREATE VIRTUAL TABLE channels_fts USING fts5(name, content='channels', content_rowid='id')",
-        [],
-    ).unwrap();
-    
+    let mut conn = Connection::open_in_memory().unwrap();
+    run_migrations(&mut conn, None).unwrap();
     conn
 }
 
@@ -66,6 +19,8 @@ fn create_test_channels(count: usize) -> Vec<Channel> {
         tvg_id: format!("test{}", i),
         resolution: "1080p".to_string(),
         extra_info: format!("Extra info {}", i),
+        http_options: None,
+        source_list_id: None,
     }).collect()
 }
 
@@ -98,6 +53,8 @@ fn create_realistic_channels(count: usize) -> Vec<Channel> {
             tvg_id: format!("ch{}", i),
             resolution: if i % 3 == 0 { "1080p".to_string() } else { "720p".to_string() },
             extra_info: if i % 5 == 0 { format!("[HD] Extra info {}", i) } else { "".to_string() },
+            http_options: None,
+            source_list_id: None,
         }
     }).collect()
 }
@@ -113,7 +70,7 @@ fn bench_populate_channels(c: &mut Criterion) {
                 b.iter(|| {
                     let mut conn = create_test_db();
                     let channels = create_test_channels(size);
-                    populate_channels(&mut conn, black_box(&channels)).unwrap();
+                    populate_channels(&mut conn, 1, black_box(&channels)).unwrap();
                 });
             },
         );
@@ -125,7 +82,7 @@ fn bench_populate_channels(c: &mut Criterion) {
                 b.iter(|| {
                     let mut conn = create_test_db();
                     let channels = create_realistic_channels(size);
-                    populate_channels(&mut conn, black_box(&channels)).unwrap();
+                    populate_channels(&mut conn, 1, black_box(&channels)).unwrap();
                 });
             },
         );
@@ -140,7 +97,7 @@ fn bench_database_operations(c: &mut Criterion) {
     // Setup database with test data
     let mut conn = create_test_db();
     let channels = create_realistic_channels(1000);
-    populate_channels(&mut conn, &channels).unwrap();
+    populate_channels(&mut conn, 1, &channels).unwrap();
     
     // Benchmark basic database operations
     group.bench_function("simple_query", |b| {
@@ -163,7 +120,7 @@ fn bench_database_queries(c: &mut Criterion) {
     // Setup database with test data
     let mut conn = create_test_db();
     let channels = create_realistic_channels(5000);
-    populate_channels(&mut conn, &channels).unwrap();
+    populate_channels(&mut conn, 1, &channels).unwrap();
     
     // Benchmark different query patterns
     group.bench_function("filter_by_group", |b| {
@@ -181,6 +138,8 @@ fn bench_database_queries(c: &mut Criterion) {
                     tvg_id: row.get(5)?,
                     resolution: row.get(6)?,
                     extra_info: row.get(7)?,
+                    http_options: None,
+                    source_list_id: None,
                 })
             }).unwrap();
             
@@ -204,6 +163,8 @@ fn bench_database_queries(c: &mut Criterion) {
                     tvg_id: row.get(5)?,
                     resolution: row.get(6)?,
                     extra_info: row.get(7)?,
+                    http_options: None,
+                    source_list_id: None,
                 })
             }).unwrap();
             
@@ -238,11 +199,13 @@ fn bench_batch_operations(c: &mut Criterion) {
                 (conn, channels)
             },
             |(mut conn, channels)| {
-                for channel in channels {
+                for (position, channel) in channels.iter().enumerate() {
                     conn.execute(
-                        "INSERT INTO channels (name, logo, url, group_title, tvg_id, resolution, extra_info) 
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        "INSERT INTO channels (channel_list_id, identity, position, name, logo, url, group_title, tvg_id, resolution, extra_info) 
+                         VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
                         rusqlite::params![
+                            position.to_string(),
+                            position as i64,
                             channel.name,
                             channel.logo,
                             channel.url,
@@ -266,7 +229,23 @@ fn bench_batch_operations(c: &mut Criterion) {
                 (conn, channels)
             },
             |(mut conn, channels)| {
-                populate_channels(&mut conn, black_box(&channels)).unwrap()
+                populate_channels(&mut conn, 1, black_box(&channels)).unwrap()
+            },
+            criterion::BatchSize::SmallInput,
+        );
+    });
+    
+    // Reloading an unchanged playlist only compares rows
+    group.bench_function("unchanged_resync", |b| {
+        b.iter_batched(
+            || {
+                let mut conn = create_test_db();
+                let channels = create_test_channels(1000);
+                populate_channels(&mut conn, 1, &channels).unwrap();
+                (conn, channels)
+            },
+            |(mut conn, channels)| {
+                populate_channels(&mut conn, 1, black_box(&channels)).unwrap()
             },
             criterion::BatchSize::SmallInput,
         );
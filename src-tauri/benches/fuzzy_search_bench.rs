@@ -1,6 +1,10 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
+use rusqlite::Connection;
+use tollo_lib::database::populate_channels;
+use tollo_lib::fts_search::narrow_by_fts;
 use tollo_lib::fuzzy_search::*;
 use tollo_lib::m3u_parser::Channel;
+use tollo_lib::migrations::run_migrations;
 
 fn create_test_channels(count: usize) -> Vec<Channel> {
     (0..count).map(|i| Channel {
@@ -11,6 +15,8 @@ fn create_test_channels(count: usize) -> Vec<Channel> {
         tvg_id: format!("test{}", i),
         resolution: "1080p".to_string(),
         extra_info: format!("Extra info {}", i),
+        http_options: None,
+        source_list_id: None,
     }).collect()
 }
 
@@ -47,6 +53,8 @@ fn create_realistic_channels(count: usize) -> Vec<Channel> {
             tvg_id: format!("ch{}", i),
             resolution: if i % 3 == 0 { "1080p".to_string() } else { "720p".to_string() },
             extra_info: "".to_string(),
+            http_options: None,
+            source_list_id: None,
         }
     }).collect()
 }
@@ -181,11 +189,59 @@ fn bench_search_result_limiting(c: &mut Criterion) {
     group.finish();
 }
 
+fn create_search_db(channels: &[Channel]) -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    run_migrations(&mut conn, None).unwrap();
+    conn.execute(
+        "INSERT INTO channel_lists (id, name, source) VALUES (1, 'Bench List', 'http://example.com')",
+        [],
+    ).unwrap();
+    populate_channels(&mut conn, 1, channels).unwrap();
+    conn
+}
+
+fn bench_search_backends(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search_backends");
+    
+    // In-memory scoring of the whole list against FTS narrowing plus scoring of the candidates
+    for size in [1000, 5000, 20000, 50000].iter() {
+        let channels = create_realistic_channels(*size);
+        let conn = create_search_db(&channels);
+        let matcher = FuzzyMatcher::new();
+        
+        for query in ["sports", "cnn t"] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("memory_{}", query), size),
+                &channels,
+                |b, channels| {
+                    b.iter(|| matcher.search_channels(black_box(channels), black_box(query)));
+                },
+            );
+            
+            group.bench_with_input(
+                BenchmarkId::new(format!("fts_{}", query), size),
+                &channels,
+                |b, channels| {
+                    b.iter(|| {
+                        let candidates = narrow_by_fts(&conn, 1, black_box(channels), black_box(query))
+                            .unwrap()
+                            .unwrap();
+                        matcher.search_channels(&candidates, query)
+                    });
+                },
+            );
+        }
+    }
+    
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_fuzzy_search_scaling,
     bench_fuzzy_search_query_types,
     bench_fuzzy_search_algorithms,
-    bench_search_result_limiting
+    bench_search_result_limiting,
+    bench_search_backends
 );
 criterion_main!(benches);
\ No newline at end of file
@@ -60,6 +60,8 @@ fn parse_m3u_content(m3u_content: &str) -> Vec<Channel> {
                         tvg_id,
                         resolution,
                         extra_info,
+                        http_options: None,
+                        source_list_id: None,
                     });
                 }
             }
@@ -133,6 +135,8 @@ where
                         tvg_id,
                         resolution,
                         extra_info,
+                        http_options: None,
+                        source_list_id: None,
                     });
                     i += 1; // Skip the URL line
                 }
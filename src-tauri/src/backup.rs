@@ -0,0 +1,954 @@
+use crate::channels::invalidate_channel_cache;
+use crate::database::PER_LIST_TABLES;
+use crate::migrations::{schema_version, MIGRATIONS};
+use crate::paths::data_paths;
+use crate::playlists::reseal_request_config;
+use crate::state::{ChannelCacheState, DbState};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, Result as RusqliteResult, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use tauri::State;
+
+/// Bumped when the layout of the archive itself changes.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// Tables holding user data, parents before the tables referencing them.
+/// Everything else in the database is derived from the channel list files.
+pub const BACKUP_TABLES: &[&str] = &[
+    "channel_lists",
+    "favorites",
+    "history",
+    "group_selections",
+    "saved_filters",
+    "channel_overrides",
+    "group_layout",
+    "group_aliases",
+    "group_rules",
+    "virtual_lists",
+    "virtual_list_members",
+    "playlist_request_configs",
+    "channel_list_mirrors",
+    "settings",
+];
+
+// Tables whose rows belong to channel lists, with the columns holding list ids
+const LIST_SCOPED_TABLES: &[(&str, &[&str])] = &[
+    ("group_selections", &["channel_list_id"]),
+    ("saved_filters", &["channel_list_id"]),
+    ("channel_overrides", &["channel_list_id"]),
+    ("group_layout", &["channel_list_id"]),
+    ("group_aliases", &["channel_list_id"]),
+    ("group_rules", &["channel_list_id"]),
+    ("virtual_lists", &["channel_list_id"]),
+    (
+        "virtual_list_members",
+        &["virtual_list_id", "member_list_id"],
+    ),
+    ("playlist_request_configs", &["channel_list_id"]),
+    ("channel_list_mirrors", &["channel_list_id"]),
+];
+
+fn list_id_columns(table: &str) -> Option<&'static [&'static str]> {
+    LIST_SCOPED_TABLES
+        .iter()
+        .find(|(name, _)| *name == table)
+        .map(|(_, columns)| *columns)
+}
+
+type Row = Map<String, Value>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub app_version: String,
+    pub schema_version: u32,
+    pub created_at: i64,
+    /// Row count of every table in the archive.
+    pub tables: BTreeMap<String, usize>,
+    /// Cached playlist files, by file name in `channel_lists/`.
+    pub files: Vec<String>,
+}
+
+/// A gzip-compressed JSON document holding the manifest, the rows of
+/// `BACKUP_TABLES` and the cached playlist files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupArchive {
+    pub manifest: BackupManifest,
+    pub tables: BTreeMap<String, Vec<Row>>,
+    pub files: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseSnapshot {
+    pub schema_version: u32,
+    pub tables: BTreeMap<String, Vec<Row>>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    /// Adds what the archive has and this installation lacks. Existing rows,
+    /// including settings, are kept.
+    Merge,
+    /// Discards the current user data and restores the archive as it is.
+    Replace,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RestoreSummary {
+    pub mode: RestoreMode,
+    /// Rows written per table.
+    pub restored: BTreeMap<String, usize>,
+    pub files: usize,
+}
+
+fn value_to_json(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::from(f),
+        ValueRef::Text(text) => Value::from(String::from_utf8_lossy(text).into_owned()),
+        // Text is always a JSON string, so an object can't be mistaken for it
+        ValueRef::Blob(blob) => {
+            let mut object = Map::new();
+            object.insert("blob".to_string(), Value::from(STANDARD.encode(blob)));
+            Value::Object(object)
+        }
+    }
+}
+
+fn json_to_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        Value::Object(object) => match object.get("blob").and_then(Value::as_str) {
+            Some(blob) => match STANDARD.decode(blob) {
+                Ok(bytes) => SqlValue::Blob(bytes),
+                Err(_) => SqlValue::Text(blob.to_string()),
+            },
+            None => SqlValue::Text(value.to_string()),
+        },
+        // Not produced by backups; kept as text rather than dropped
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+fn table_columns(conn: &Connection, table: &str) -> RusqliteResult<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1) ORDER BY cid")?;
+    let columns = stmt
+        .query_map([table], |row| row.get(0))?
+        .collect::<RusqliteResult<Vec<String>>>()?;
+    Ok(columns)
+}
+
+fn dump_table(conn: &Connection, table: &str) -> RusqliteResult<Vec<Row>> {
+    let columns = table_columns(conn, table)?;
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {}", table))?;
+    let mut rows = stmt.query([])?;
+    let mut dumped = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut values = Row::new();
+        for (index, column) in columns.iter().enumerate() {
+            values.insert(column.clone(), value_to_json(row.get_ref(index)?));
+        }
+        dumped.push(values);
+    }
+    Ok(dumped)
+}
+
+pub fn export_database(conn: &Connection) -> RusqliteResult<DatabaseSnapshot> {
+    let mut tables = BTreeMap::new();
+    for table in BACKUP_TABLES {
+        tables.insert(table.to_string(), dump_table(conn, table)?);
+    }
+    Ok(DatabaseSnapshot {
+        schema_version: schema_version(conn)?,
+        tables,
+    })
+}
+
+/// Bundles a database snapshot with the playlist files its channel lists point
+/// to. Files missing from `channel_lists_dir` are left out; they are fetched
+/// again after a restore.
+pub fn build_archive(
+    snapshot: DatabaseSnapshot,
+    channel_lists_dir: &Path,
+    created_at: i64,
+) -> Result<BackupArchive, String> {
+    let mut files = BTreeMap::new();
+    for list in snapshot.tables.get("channel_lists").into_iter().flatten() {
+        let Some(filename) = list.get("filepath").and_then(Value::as_str) else {
+            continue;
+        };
+        if !is_safe_file_name(filename) {
+            continue;
+        }
+        match fs::read_to_string(channel_lists_dir.join(filename)) {
+            Ok(content) => {
+                files.insert(filename.to_string(), content);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to read {}: {}", filename, e)),
+        }
+    }
+
+    let manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: snapshot.schema_version,
+        created_at,
+        tables: snapshot
+            .tables
+            .iter()
+            .map(|(table, rows)| (table.clone(), rows.len()))
+            .collect(),
+        files: files.keys().cloned().collect(),
+    };
+    Ok(BackupArchive {
+        manifest,
+        tables: snapshot.tables,
+        files,
+    })
+}
+
+pub fn write_archive(archive: &BackupArchive, path: &Path) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+    serde_json::to_writer(&mut encoder, archive).map_err(|e| e.to_string())?;
+    encoder
+        .finish()
+        .and_then(|mut writer| writer.flush())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+pub fn read_archive(path: &Path) -> Result<BackupArchive, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    serde_json::from_reader(GzDecoder::new(BufReader::new(file)))
+        .map_err(|e| format!("Not a valid backup archive: {}", e))
+}
+
+// Playlist files are restored into `channel_lists/` and must not escape it
+fn is_safe_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', '\0'])
+        && !name.contains(':')
+}
+
+fn row_id(row: &Row, column: &str) -> Option<i64> {
+    row.get(column).and_then(Value::as_i64)
+}
+
+/// Checks that an archive is complete and consistent, and that this version can
+/// restore it, before anything is changed.
+pub fn validate_archive(archive: &BackupArchive, current_schema: u32) -> Result<(), String> {
+    let manifest = &archive.manifest;
+    if manifest.format_version != BACKUP_FORMAT_VERSION {
+        return Err(format!(
+            "Unsupported backup format version {}",
+            manifest.format_version
+        ));
+    }
+    if manifest.schema_version == 0 || manifest.schema_version > current_schema {
+        return Err(format!(
+            "Backup is from database version {}, this version of Tollo supports up to {}",
+            manifest.schema_version, current_schema
+        ));
+    }
+
+    for (table, rows) in &archive.tables {
+        if !BACKUP_TABLES.contains(&table.as_str()) {
+            return Err(format!("Backup contains unknown table '{}'", table));
+        }
+        if manifest.tables.get(table) != Some(&rows.len()) {
+            return Err(format!(
+                "Row count of '{}' does not match the manifest",
+                table
+            ));
+        }
+    }
+    if manifest.tables.len() != archive.tables.len() {
+        return Err("Backup is missing tables listed in its manifest".to_string());
+    }
+
+    let file_names: Vec<&String> = archive.files.keys().collect();
+    if manifest.files.iter().collect::<Vec<_>>() != file_names {
+        return Err("Playlist files do not match the manifest".to_string());
+    }
+    if let Some(name) = file_names.iter().find(|name| !is_safe_file_name(name)) {
+        return Err(format!("Invalid playlist file name '{}'", name));
+    }
+
+    let mut list_ids = HashSet::new();
+    let mut list_names = HashSet::new();
+    for list in archive.tables.get("channel_lists").into_iter().flatten() {
+        let (Some(id), Some(name), Some(_)) = (
+            row_id(list, "id"),
+            list.get("name").and_then(Value::as_str),
+            list.get("source").and_then(Value::as_str),
+        ) else {
+            return Err("Backup contains a channel list without id, name or source".to_string());
+        };
+        if !list_ids.insert(id) || !list_names.insert(name) {
+            return Err(format!("Backup contains channel list '{}' twice", name));
+        }
+    }
+    for (table, columns) in LIST_SCOPED_TABLES {
+        for row in archive.tables.get(*table).into_iter().flatten() {
+            let known =
+                |column: &&str| row_id(row, column).is_some_and(|id| list_ids.contains(&id));
+            if !columns.iter().all(known) {
+                return Err(format!(
+                    "'{}' refers to a channel list not in the backup",
+                    table
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn insert_row(
+    tx: &Transaction,
+    table: &str,
+    columns: &[String],
+    row: &Row,
+    or_ignore: bool,
+) -> RusqliteResult<usize> {
+    let present: Vec<&String> = columns.iter().filter(|c| row.contains_key(*c)).collect();
+    if present.is_empty() {
+        return Ok(0);
+    }
+    let placeholders: Vec<String> = (1..=present.len()).map(|i| format!("?{}", i)).collect();
+    let sql = format!(
+        "INSERT {}INTO {} ({}) VALUES ({})",
+        if or_ignore { "OR IGNORE " } else { "" },
+        table,
+        present
+            .iter()
+            .map(|c| c.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        placeholders.join(", ")
+    );
+    tx.execute(
+        &sql,
+        params_from_iter(present.iter().map(|c| json_to_value(&row[*c]))),
+    )
+}
+
+fn row_exists(
+    tx: &Transaction,
+    table: &str,
+    columns: &[String],
+    row: &Row,
+) -> RusqliteResult<bool> {
+    let present: Vec<&String> = columns.iter().filter(|c| row.contains_key(*c)).collect();
+    let conditions: Vec<String> = present
+        .iter()
+        .enumerate()
+        .map(|(i, c)| format!("{} IS ?{}", c, i + 1))
+        .collect();
+    tx.query_row(
+        &format!(
+            "SELECT EXISTS(SELECT 1 FROM {} WHERE {})",
+            table,
+            conditions.join(" AND ")
+        ),
+        params_from_iter(present.iter().map(|c| json_to_value(&row[*c]))),
+        |row| row.get(0),
+    )
+}
+
+// Request settings are sealed for their list id with the key of the
+// installation, so they are sealed again for the id they are restored under.
+// Settings from another installation can't be opened and are left out.
+fn reseal_config(row: &mut Row, from: i64, to: i64) -> bool {
+    let Some(SqlValue::Blob(blob)) = row.get("config").map(json_to_value) else {
+        return false;
+    };
+    match reseal_request_config(&blob, from as i32, to as i32) {
+        Ok(sealed) => {
+            row.insert("config".to_string(), value_to_json(ValueRef::Blob(&sealed)));
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+// Points a row at the lists the merge matched or created, None when the row
+// is left out
+fn remap_list_row(
+    tx: &Transaction,
+    table: &str,
+    columns: &[String],
+    row: &Row,
+    list_ids: &HashMap<i64, i64>,
+    new_lists: &HashSet<i64>,
+) -> RusqliteResult<Option<Row>> {
+    let Some(list_columns) = list_id_columns(table) else {
+        return Ok(None);
+    };
+    let mut mapped = row.clone();
+    for column in list_columns {
+        let Some(id) = row_id(row, column).and_then(|id| list_ids.get(&id)) else {
+            return Ok(None);
+        };
+        mapped.insert(column.to_string(), Value::from(*id));
+    }
+    match table {
+        // A list that is already here stays a plain or a virtual list
+        "virtual_lists" | "virtual_list_members"
+            if !row_id(&mapped, list_columns[0]).is_some_and(|id| new_lists.contains(&id)) =>
+        {
+            return Ok(None);
+        }
+        "playlist_request_configs" => {
+            let (Some(from), Some(to)) = (
+                row_id(row, "channel_list_id"),
+                row_id(&mapped, "channel_list_id"),
+            ) else {
+                return Ok(None);
+            };
+            if !reseal_config(&mut mapped, from, to) {
+                return Ok(None);
+            }
+        }
+        // Rule ids are local; a rule that is already here is not added twice
+        "group_rules" => {
+            mapped.remove("id");
+            if row_exists(tx, table, columns, &mapped)? {
+                return Ok(None);
+            }
+        }
+        _ => {}
+    }
+    Ok(Some(mapped))
+}
+
+fn restore_replace(
+    tx: &Transaction,
+    archive: &BackupArchive,
+    restored: &mut BTreeMap<String, usize>,
+) -> RusqliteResult<()> {
+    // Every table keyed by list id is cleared, backed up or not, so nothing
+    // left behind gets attached to an archived list reusing that id
+    for table in PER_LIST_TABLES {
+        tx.execute(&format!("DELETE FROM {}", table), [])?;
+    }
+    // Children first
+    for table in BACKUP_TABLES.iter().rev() {
+        tx.execute(&format!("DELETE FROM {}", table), [])?;
+    }
+    for table in BACKUP_TABLES {
+        let columns = table_columns(tx, table)?;
+        let mut count = 0;
+        for row in archive.tables.get(*table).into_iter().flatten() {
+            let mut row = row.clone();
+            if *table == "playlist_request_configs" {
+                let id = row_id(&row, "channel_list_id").unwrap_or_default();
+                if !reseal_config(&mut row, id, id) {
+                    continue;
+                }
+            }
+            count += insert_row(tx, table, &columns, &row, false)?;
+        }
+        restored.insert(table.to_string(), count);
+    }
+    Ok(())
+}
+
+fn restore_merge(
+    tx: &Transaction,
+    archive: &BackupArchive,
+    restored: &mut BTreeMap<String, usize>,
+) -> RusqliteResult<()> {
+    // Lists are matched by name; new ones get fresh ids
+    let columns = table_columns(tx, "channel_lists")?;
+    let has_default: bool = tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM channel_lists WHERE is_default = 1)",
+        [],
+        |row| row.get(0),
+    )?;
+    let mut list_ids = HashMap::new();
+    let mut new_lists = HashSet::new();
+    let mut count = 0;
+    for list in archive.tables.get("channel_lists").into_iter().flatten() {
+        let (Some(backup_id), Some(name)) =
+            (row_id(list, "id"), list.get("name").and_then(Value::as_str))
+        else {
+            continue;
+        };
+        let existing: Option<i64> = tx
+            .query_row(
+                "SELECT id FROM channel_lists WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .ok();
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let mut row = list.clone();
+                row.remove("id");
+                if has_default {
+                    row.insert("is_default".to_string(), Value::from(0));
+                }
+                count += insert_row(tx, "channel_lists", &columns, &row, false)?;
+                let id = tx.last_insert_rowid();
+                new_lists.insert(id);
+                id
+            }
+        };
+        list_ids.insert(backup_id, id);
+    }
+    restored.insert("channel_lists".to_string(), count);
+
+    for table in &BACKUP_TABLES[1..] {
+        // Settings are per installation; merging keeps the local ones
+        if *table == "settings" {
+            restored.insert(table.to_string(), 0);
+            continue;
+        }
+        let columns = table_columns(tx, table)?;
+        let scoped = list_id_columns(table).is_some();
+        let mut count = 0;
+        for row in archive.tables.get(*table).into_iter().flatten() {
+            let row = if scoped {
+                match remap_list_row(tx, table, &columns, row, &list_ids, &new_lists)? {
+                    Some(row) => row,
+                    None => continue,
+                }
+            } else {
+                let mut row = row.clone();
+                row.remove("id");
+                row
+            };
+            count += insert_row(tx, table, &columns, &row, true)?;
+        }
+        restored.insert(table.to_string(), count);
+    }
+    Ok(())
+}
+
+/// Writes the rows of a validated archive in one transaction.
+pub fn apply_restore(
+    conn: &mut Connection,
+    archive: &BackupArchive,
+    mode: RestoreMode,
+) -> RusqliteResult<BTreeMap<String, usize>> {
+    let tx = conn.transaction()?;
+    let mut restored = BTreeMap::new();
+    match mode {
+        RestoreMode::Replace => restore_replace(&tx, archive, &mut restored)?,
+        RestoreMode::Merge => restore_merge(&tx, archive, &mut restored)?,
+    }
+    tx.commit()?;
+    Ok(restored)
+}
+
+/// Copies the archived playlist files into `channel_lists_dir`. Files already
+/// there are kept, since playlist file names are unique.
+pub fn restore_files(archive: &BackupArchive, channel_lists_dir: &Path) -> Result<usize, String> {
+    fs::create_dir_all(channel_lists_dir)
+        .map_err(|e| format!("Failed to create directory: {}", e))?;
+    let mut written = 0;
+    for (name, content) in &archive.files {
+        let path = channel_lists_dir.join(name);
+        if path.exists() {
+            continue;
+        }
+        fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+fn latest_schema_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+#[tauri::command]
+pub async fn create_backup(
+    db_state: State<'_, DbState>,
+    path: String,
+) -> Result<BackupManifest, String> {
+    let snapshot = db_state.read(export_database).await?;
+    tokio::task::spawn_blocking(move || {
+        let archive = build_archive(
+            snapshot,
+            &data_paths().channel_lists_dir(),
+            chrono::Utc::now().timestamp(),
+        )?;
+        write_archive(&archive, Path::new(&path))?;
+        Ok(archive.manifest)
+    })
+    .await
+    .map_err(|e| format!("Backup failed: {}", e))?
+}
+
+/// Reads and validates an archive without restoring it, for a confirmation step.
+#[tauri::command]
+pub async fn inspect_backup(path: String) -> Result<BackupManifest, String> {
+    tokio::task::spawn_blocking(move || {
+        let archive = read_archive(Path::new(&path))?;
+        validate_archive(&archive, latest_schema_version())?;
+        Ok(archive.manifest)
+    })
+    .await
+    .map_err(|e| format!("Reading backup failed: {}", e))?
+}
+
+#[tauri::command]
+pub async fn restore_backup(
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    path: String,
+    mode: RestoreMode,
+) -> Result<RestoreSummary, String> {
+    let current_schema = db_state.read(schema_version).await?;
+    let archive = tokio::task::spawn_blocking(move || {
+        let archive = read_archive(Path::new(&path))?;
+        validate_archive(&archive, current_schema)?;
+        Ok::<_, String>(archive)
+    })
+    .await
+    .map_err(|e| format!("Restore failed: {}", e))??;
+
+    // Files are only copied once the rows pointing to them are committed
+    let (restored, archive) = db_state
+        .write(move |conn| Ok((apply_restore(conn, &archive, mode)?, archive)))
+        .await?;
+    invalidate_channel_cache(cache_state)?;
+    let files = tokio::task::spawn_blocking(move || {
+        restore_files(&archive, &data_paths().channel_lists_dir())
+    })
+    .await
+    .map_err(|e| format!("Restore failed: {}", e))??;
+
+    Ok(RestoreSummary {
+        mode,
+        restored,
+        files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlists::{load_request_config, save_request_config, PlaylistRequestConfig};
+    use crate::test_support::migrated_db;
+    use tempfile::TempDir;
+
+    fn populate(conn: &Connection) {
+        conn.execute_batch(
+            "INSERT INTO channel_lists (id, name, source, is_default, filepath, last_fetched)
+                VALUES (1, 'Main', 'http://example.com/main.m3u', 1, 'main.m3u', 100),
+                       (2, 'Sports', 'http://example.com/sports.m3u', 0, NULL, NULL);
+             INSERT INTO favorites (name, logo, url, group_title, tvg_id, resolution, extra_info, http_options)
+                VALUES ('BBC', '', 'http://example.com/bbc', 'News', '', '', '', '{\"user_agent\":\"Tollo\"}');
+             INSERT INTO history (name, logo, url, group_title, tvg_id, resolution, extra_info, timestamp)
+                VALUES ('CNN', '', 'http://example.com/cnn', 'News', '', '', '', '2024-01-01 10:00:00');
+             INSERT INTO group_selections (channel_list_id, group_name, is_enabled) VALUES (2, 'Football', 0);
+             INSERT INTO saved_filters (channel_list_id, slot_number, search_query, selected_group, name)
+                VALUES (1, 3, 'news', 'News', 'Evening');
+             INSERT INTO settings (id, player_command, cache_duration_hours) VALUES (1, 'vlc', 12);
+             INSERT INTO channel_overrides (channel_list_id, identity, original_name, original_url, name, updated_at)
+                VALUES (1, 'bbc', 'BBC', 'http://example.com/bbc', 'BBC One', 0);
+             INSERT INTO group_layout (channel_list_id, group_name, position, hidden) VALUES (1, 'News', 0, 0);
+             INSERT INTO group_aliases (channel_list_id, source_group, display_name) VALUES (1, 'UK News', 'News');
+             INSERT INTO group_rules (channel_list_id, pattern, match_field, target_group, position)
+                VALUES (1, '^BBC', 'name', 'News', 0);
+             INSERT INTO channel_list_mirrors (channel_list_id, position, url)
+                VALUES (1, 0, 'http://mirror.example.com/main.m3u');",
+        )
+        .unwrap();
+        let config = PlaylistRequestConfig {
+            user_agent: Some("VLC/3.0".to_string()),
+            ..Default::default()
+        };
+        save_request_config(conn, 1, &config).unwrap();
+    }
+
+    fn create_archive(conn: &Connection, dir: &Path) -> BackupArchive {
+        fs::write(dir.join("main.m3u"), "#EXTM3U\n").unwrap();
+        build_archive(export_database(conn).unwrap(), dir, 1_700_000_000).unwrap()
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let conn = migrated_db();
+        populate(&conn);
+
+        let archive = create_archive(&conn, dir.path());
+        assert_eq!(archive.manifest.tables["channel_lists"], 2);
+        assert_eq!(archive.manifest.tables["favorites"], 1);
+        assert_eq!(archive.manifest.files, vec!["main.m3u".to_string()]);
+        assert_eq!(archive.manifest.schema_version, latest_schema_version());
+
+        let path = dir.path().join("backup.tollo");
+        write_archive(&archive, &path).unwrap();
+        let read = read_archive(&path).unwrap();
+        assert_eq!(read.manifest, archive.manifest);
+        assert_eq!(read.tables, archive.tables);
+        assert!(validate_archive(&read, latest_schema_version()).is_ok());
+
+        fs::write(&path, "not an archive").unwrap();
+        assert!(read_archive(&path).is_err());
+    }
+
+    #[test]
+    fn test_replace_restores_everything() {
+        let dir = TempDir::new().unwrap();
+        let source = migrated_db();
+        populate(&source);
+        let archive = create_archive(&source, dir.path());
+
+        let mut target = migrated_db();
+        target
+            .execute_batch(
+                "INSERT INTO channel_lists (id, name, source, is_default) VALUES (1, 'Local', 'http://local', 1);
+                 INSERT INTO favorites (name, logo, url, group_title, tvg_id, resolution, extra_info)
+                    VALUES ('Local', '', 'http://local/1', '', '', '', '');",
+            )
+            .unwrap();
+
+        target
+            .execute_batch(
+                "INSERT INTO channel_health (channel_list_id, url, status, last_checked)
+                    VALUES (1, 'http://local/1', 'dead', 0);
+                 INSERT INTO playlist_changes (channel_list_id, changed_at, details) VALUES (1, 0, '{}');",
+            )
+            .unwrap();
+
+        let restored = apply_restore(&mut target, &archive, RestoreMode::Replace).unwrap();
+        assert_eq!(restored["channel_lists"], 2);
+        assert_eq!(count(&target, "favorites"), 1);
+        // Data of the replaced lists does not carry over to the restored ones
+        assert_eq!(count(&target, "channel_health"), 0);
+        assert_eq!(count(&target, "playlist_changes"), 0);
+
+        let exported = export_database(&target).unwrap();
+        assert_eq!(exported.tables, archive.tables);
+    }
+
+    #[test]
+    fn test_merge_keeps_local_data() {
+        let dir = TempDir::new().unwrap();
+        let source = migrated_db();
+        populate(&source);
+        let archive = create_archive(&source, dir.path());
+
+        let mut target = migrated_db();
+        target
+            .execute_batch(
+                "INSERT INTO channel_lists (id, name, source, is_default) VALUES (1, 'Sports', 'http://local', 1);
+                 INSERT INTO group_selections (channel_list_id, group_name, is_enabled) VALUES (1, 'Football', 1);
+                 INSERT INTO settings (id, player_command, cache_duration_hours) VALUES (1, 'mpv', 24);",
+            )
+            .unwrap();
+
+        let restored = apply_restore(&mut target, &archive, RestoreMode::Merge).unwrap();
+        assert_eq!(restored["channel_lists"], 1);
+        assert_eq!(restored["settings"], 0);
+
+        // 'Main' is new and does not take over as default
+        let (main_id, is_default): (i64, bool) = target
+            .query_row(
+                "SELECT id, is_default FROM channel_lists WHERE name = 'Main'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_ne!(main_id, 1);
+        assert!(!is_default);
+
+        // The saved filter follows its list to the new id
+        let filter_list: i64 = target
+            .query_row("SELECT channel_list_id FROM saved_filters", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(filter_list, main_id);
+
+        // Existing group choice and settings win
+        let enabled: bool = target
+            .query_row(
+                "SELECT is_enabled FROM group_selections WHERE channel_list_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(enabled);
+        let player: String = target
+            .query_row("SELECT player_command FROM settings", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(player, "mpv");
+
+        // Merging twice adds nothing
+        let restored = apply_restore(&mut target, &archive, RestoreMode::Merge).unwrap();
+        assert!(restored.values().all(|count| *count == 0));
+    }
+
+    #[test]
+    fn test_merge_moves_list_data_to_new_ids() {
+        let dir = TempDir::new().unwrap();
+        let source = migrated_db();
+        populate(&source);
+        source
+            .execute_batch(
+                "INSERT INTO channel_lists (id, name, source) VALUES (3, 'Both', 'virtual://3');
+                 INSERT INTO virtual_lists (channel_list_id) VALUES (3);
+                 INSERT INTO virtual_list_members (virtual_list_id, member_list_id, position)
+                    VALUES (3, 1, 0), (3, 2, 1);",
+            )
+            .unwrap();
+        let archive = create_archive(&source, dir.path());
+
+        let mut target = migrated_db();
+        target
+            .execute_batch(
+                "INSERT INTO channel_lists (id, name, source, is_default) VALUES (1, 'Sports', 'http://local', 1);",
+            )
+            .unwrap();
+        apply_restore(&mut target, &archive, RestoreMode::Merge).unwrap();
+
+        let list_id = |name: &str| -> i64 {
+            target
+                .query_row(
+                    "SELECT id FROM channel_lists WHERE name = ?1",
+                    [name],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        let (main_id, both_id) = (list_id("Main"), list_id("Both"));
+
+        for table in [
+            "channel_overrides",
+            "group_layout",
+            "group_aliases",
+            "group_rules",
+            "channel_list_mirrors",
+        ] {
+            let list: i64 = target
+                .query_row(
+                    &format!("SELECT channel_list_id FROM {}", table),
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(list, main_id, "{}", table);
+        }
+        let members: Vec<(i64, i64)> = target
+            .prepare("SELECT virtual_list_id, member_list_id FROM virtual_list_members ORDER BY position")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<RusqliteResult<_>>()
+            .unwrap();
+        assert_eq!(members, vec![(both_id, main_id), (both_id, 1)]);
+
+        // The request settings are sealed again for the new id
+        let config = load_request_config(&target, main_id as i32).unwrap();
+        assert_eq!(config.user_agent.as_deref(), Some("VLC/3.0"));
+
+        let restored = apply_restore(&mut target, &archive, RestoreMode::Merge).unwrap();
+        assert!(restored.values().all(|count| *count == 0));
+    }
+
+    #[test]
+    fn test_validation_rejects_bad_archives() {
+        let dir = TempDir::new().unwrap();
+        let conn = migrated_db();
+        populate(&conn);
+        let archive = create_archive(&conn, dir.path());
+        let current = latest_schema_version();
+
+        let mut newer = archive.clone();
+        newer.manifest.schema_version = current + 1;
+        assert!(validate_archive(&newer, current).is_err());
+
+        let mut miscounted = archive.clone();
+        miscounted.tables.get_mut("favorites").unwrap().clear();
+        assert!(validate_archive(&miscounted, current).is_err());
+
+        let mut escaping = archive.clone();
+        escaping
+            .files
+            .insert("../evil.m3u".to_string(), String::new());
+        escaping.manifest.files = escaping.files.keys().cloned().collect();
+        assert!(validate_archive(&escaping, current).is_err());
+
+        let mut orphaned = archive.clone();
+        orphaned.tables.get_mut("channel_lists").unwrap().remove(1);
+        orphaned
+            .manifest
+            .tables
+            .insert("channel_lists".to_string(), 1);
+        assert!(validate_archive(&orphaned, current).is_err());
+    }
+
+    #[test]
+    fn test_older_schema_backup_restores() {
+        let dir = TempDir::new().unwrap();
+        let conn = migrated_db();
+        populate(&conn);
+        let mut archive = create_archive(&conn, dir.path());
+
+        // A backup taken before favorites stored request options
+        archive.manifest.schema_version = 1;
+        for row in archive.tables.get_mut("favorites").unwrap() {
+            row.remove("http_options");
+        }
+        assert!(validate_archive(&archive, latest_schema_version()).is_ok());
+
+        let mut target = migrated_db();
+        apply_restore(&mut target, &archive, RestoreMode::Replace).unwrap();
+        let options: Option<String> = target
+            .query_row("SELECT http_options FROM favorites", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(options, None);
+    }
+
+    #[test]
+    fn test_restore_files() {
+        let dir = TempDir::new().unwrap();
+        let conn = migrated_db();
+        populate(&conn);
+        let archive = create_archive(&conn, dir.path());
+
+        let target = dir.path().join("restored");
+        assert_eq!(restore_files(&archive, &target).unwrap(), 1);
+        assert_eq!(
+            fs::read_to_string(target.join("main.m3u")).unwrap(),
+            "#EXTM3U\n"
+        );
+        assert_eq!(restore_files(&archive, &target).unwrap(), 0);
+    }
+}
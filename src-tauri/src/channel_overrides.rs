@@ -0,0 +1,482 @@
+use crate::channels::invalidate_channel_cache;
+use crate::database::{channel_identities, resolve_channel_list_id};
+use crate::m3u_parser::Channel;
+use crate::playlists::PlaylistDiff;
+use crate::state::{ChannelCacheState, DbState};
+use chrono::Utc;
+use rusqlite::{Connection, OptionalExtension, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::State;
+
+/// Fields to change on a channel. `None` leaves the current override, or the
+/// playlist value, as it is.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChannelOverrideEdit {
+    pub name: Option<String>,
+    pub group_title: Option<String>,
+    pub logo: Option<String>,
+    pub tvg_id: Option<String>,
+    pub hidden: Option<bool>,
+}
+
+/// A user edit of one channel, kept across refreshes by the channel identity
+/// and carried over when a refresh renames the channel or changes its URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelOverride {
+    pub channel_list_id: i64,
+    pub identity: String,
+    /// Name and URL in the playlist, to show hidden and renamed channels
+    pub original_name: String,
+    pub original_url: String,
+    pub name: Option<String>,
+    pub group_title: Option<String>,
+    pub logo: Option<String>,
+    pub tvg_id: Option<String>,
+    pub hidden: bool,
+    pub updated_at: i64,
+}
+
+impl ChannelOverride {
+    fn apply(&self, channel: &mut Channel) {
+        if let Some(name) = &self.name {
+            channel.name = name.clone();
+        }
+        if let Some(group_title) = &self.group_title {
+            channel.group_title = group_title.clone();
+        }
+        if let Some(logo) = &self.logo {
+            channel.logo = logo.clone();
+        }
+        if let Some(tvg_id) = &self.tvg_id {
+            channel.tvg_id = tvg_id.clone();
+        }
+    }
+}
+
+pub fn load_channel_overrides(
+    conn: &Connection,
+    channel_list_id: i64,
+) -> RusqliteResult<Vec<ChannelOverride>> {
+    let mut stmt = conn.prepare(
+        "SELECT channel_list_id, identity, original_name, original_url, name, group_title, logo, tvg_id, hidden, updated_at
+         FROM channel_overrides WHERE channel_list_id = ?1 ORDER BY original_name",
+    )?;
+    let rows = stmt.query_map([channel_list_id], |row| {
+        Ok(ChannelOverride {
+            channel_list_id: row.get(0)?,
+            identity: row.get(1)?,
+            original_name: row.get(2)?,
+            original_url: row.get(3)?,
+            name: row.get(4)?,
+            group_title: row.get(5)?,
+            logo: row.get(6)?,
+            tvg_id: row.get(7)?,
+            hidden: row.get(8)?,
+            updated_at: row.get(9)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn has_channel_overrides(conn: &Connection, channel_list_id: i64) -> bool {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM channel_overrides WHERE channel_list_id = ?1)",
+        [channel_list_id],
+        |row| row.get(0),
+    )
+    .unwrap_or(false)
+}
+
+/// Applies overrides to the channels of a list as parsed from the playlist,
+/// dropping hidden channels.
+pub fn apply_overrides(
+    channel_list_id: i64,
+    overrides: &[ChannelOverride],
+    channels: &mut Vec<Channel>,
+) {
+    if overrides.is_empty() {
+        return;
+    }
+    let by_identity: HashMap<&str, &ChannelOverride> =
+        overrides.iter().map(|o| (o.identity.as_str(), o)).collect();
+    let identities = channel_identities(channel_list_id, channels);
+    let mut kept = Vec::with_capacity(channels.len());
+    for (mut channel, identity) in channels.drain(..).zip(identities) {
+        match by_identity.get(identity.as_str()) {
+            Some(o) if o.hidden => {}
+            Some(o) => {
+                o.apply(&mut channel);
+                kept.push(channel);
+            }
+            None => kept.push(channel),
+        }
+    }
+    *channels = kept;
+}
+
+/// Applies the overrides of list `id` (`None` for the default list) to its
+/// freshly parsed channels. Without overrides, or on error, they stay as parsed.
+pub fn apply_channel_overrides(conn: &Connection, id: Option<i32>, channels: &mut Vec<Channel>) {
+    let Some(channel_list_id) = resolve_channel_list_id(conn, id) else {
+        return;
+    };
+    match load_channel_overrides(conn, channel_list_id) {
+        Ok(overrides) => apply_overrides(channel_list_id, &overrides, channels),
+        Err(e) => eprintln!("Warning: Failed to load channel overrides: {}", e),
+    }
+}
+
+/// Moves overrides onto the channels a refresh renamed or gave a new URL.
+/// The identity an override is keyed on hashes both, so without this an edit
+/// would be lost whenever the playlist changes either of them.
+pub fn carry_over_overrides(
+    conn: &Connection,
+    channel_list_id: i64,
+    channels: &[Channel],
+    diff: &PlaylistDiff,
+) -> RusqliteResult<usize> {
+    let mut identities: HashMap<(&str, &str), &str> = HashMap::new();
+    let current = channel_identities(channel_list_id, channels);
+    for (channel, identity) in channels.iter().zip(&current) {
+        identities
+            .entry((channel.name.as_str(), channel.url.as_str()))
+            .or_insert(identity.as_str());
+    }
+
+    let renamed = diff.renamed.iter().map(|c| {
+        (
+            (c.old_name.as_str(), c.url.as_str()),
+            (c.new_name.as_str(), c.url.as_str()),
+        )
+    });
+    let moved = diff.url_changed.iter().map(|c| {
+        (
+            (c.name.as_str(), c.old_url.as_str()),
+            (c.name.as_str(), c.new_url.as_str()),
+        )
+    });
+    let mut carried = 0;
+    for ((old_name, old_url), (new_name, new_url)) in renamed.chain(moved) {
+        let Some(identity) = identities.get(&(new_name, new_url)) else {
+            continue;
+        };
+        // An override the channel already has under its new identity wins
+        carried += conn.execute(
+            "UPDATE OR IGNORE channel_overrides SET identity = ?1, original_name = ?2, original_url = ?3
+             WHERE channel_list_id = ?4 AND original_name = ?5 AND original_url = ?6",
+            rusqlite::params![identity, new_name, new_url, channel_list_id, old_name, old_url],
+        )?;
+    }
+    Ok(carried)
+}
+
+/// Finds the stored channel shown as `channel`, whose name may already be
+/// overridden. Returns its identity, playlist name and URL.
+pub fn find_channel_identity(
+    conn: &Connection,
+    channel_list_id: i64,
+    channel: &Channel,
+) -> RusqliteResult<Option<(String, String, String)>> {
+    conn.query_row(
+        "SELECT c.identity, c.name, c.url FROM channels c
+         LEFT JOIN channel_overrides o ON o.channel_list_id = c.channel_list_id AND o.identity = c.identity
+         WHERE c.channel_list_id = ?1 AND c.url = ?2 AND COALESCE(o.name, c.name) = ?3
+         ORDER BY c.position LIMIT 1",
+        rusqlite::params![channel_list_id, channel.url, channel.name],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )
+    .optional()
+}
+
+/// Merges `edit` into the overrides of `channels` in one transaction. Fails
+/// without changes if any channel is not stored for the list.
+pub fn edit_channel_overrides(
+    conn: &mut Connection,
+    channel_list_id: i64,
+    channels: &[Channel],
+    edit: &ChannelOverrideEdit,
+    now: i64,
+) -> Result<usize, String> {
+    if edit
+        .name
+        .as_deref()
+        .is_some_and(|name| name.trim().is_empty())
+    {
+        return Err("Channel name cannot be empty".to_string());
+    }
+    let name = edit.name.as_deref().map(str::trim);
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    for channel in channels {
+        let (identity, original_name, original_url) =
+            find_channel_identity(&tx, channel_list_id, channel)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("Channel '{}' not found", channel.name))?;
+        tx.execute(
+            "INSERT INTO channel_overrides (channel_list_id, identity, original_name, original_url, name, group_title, logo, tvg_id, hidden, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, COALESCE(?9, 0), ?10)
+             ON CONFLICT(channel_list_id, identity) DO UPDATE SET
+                name = COALESCE(?5, name), group_title = COALESCE(?6, group_title),
+                logo = COALESCE(?7, logo), tvg_id = COALESCE(?8, tvg_id),
+                hidden = COALESCE(?9, hidden), updated_at = ?10",
+            rusqlite::params![
+                channel_list_id,
+                identity,
+                original_name,
+                original_url,
+                name,
+                edit.group_title,
+                edit.logo,
+                edit.tvg_id,
+                edit.hidden,
+                now,
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(channels.len())
+}
+
+/// Removes the given overrides of a list, or all of them with `None`.
+pub fn reset_overrides(
+    conn: &Connection,
+    channel_list_id: i64,
+    identities: Option<&[String]>,
+) -> RusqliteResult<usize> {
+    match identities {
+        None => conn.execute(
+            "DELETE FROM channel_overrides WHERE channel_list_id = ?1",
+            [channel_list_id],
+        ),
+        Some(identities) => {
+            let mut removed = 0;
+            for identity in identities {
+                removed += conn.execute(
+                    "DELETE FROM channel_overrides WHERE channel_list_id = ?1 AND identity = ?2",
+                    rusqlite::params![channel_list_id, identity],
+                )?;
+            }
+            Ok(removed)
+        }
+    }
+}
+
+fn require_list_id(conn: &Connection, id: Option<i32>) -> Result<i64, String> {
+    resolve_channel_list_id(conn, id).ok_or_else(|| "Channel list not found".to_string())
+}
+
+#[tauri::command]
+pub fn get_channel_overrides(
+    db_state: State<DbState>,
+    id: Option<i32>,
+) -> Result<Vec<ChannelOverride>, String> {
+    let db = db_state.reader();
+    let channel_list_id = require_list_id(&db, id)?;
+    load_channel_overrides(&db, channel_list_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_channel_override(
+    db_state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    id: Option<i32>,
+    channel: Channel,
+    edit: ChannelOverrideEdit,
+) -> Result<(), String> {
+    bulk_edit_channel_overrides(db_state, cache_state, id, vec![channel], edit)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn bulk_edit_channel_overrides(
+    db_state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    id: Option<i32>,
+    channels: Vec<Channel>,
+    edit: ChannelOverrideEdit,
+) -> Result<usize, String> {
+    let edited = {
+        let mut db = db_state.db.lock().unwrap();
+        let channel_list_id = require_list_id(&db, id)?;
+        edit_channel_overrides(
+            &mut db,
+            channel_list_id,
+            &channels,
+            &edit,
+            Utc::now().timestamp(),
+        )?
+    };
+    invalidate_channel_cache(cache_state)?;
+    Ok(edited)
+}
+
+#[tauri::command]
+pub fn reset_channel_overrides(
+    db_state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    id: Option<i32>,
+    identities: Option<Vec<String>>,
+) -> Result<usize, String> {
+    let removed = {
+        let db = db_state.db.lock().unwrap();
+        let channel_list_id = require_list_id(&db, id)?;
+        reset_overrides(&db, channel_list_id, identities.as_deref()).map_err(|e| e.to_string())?
+    };
+    invalidate_channel_cache(cache_state)?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{populate_channels, populate_channels_with_changes};
+    use crate::test_support::{self, channel};
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn playlist() -> Vec<Channel> {
+        vec![
+            channel("News", "http://a/news", "News"),
+            channel("Sport", "http://a/sport", "Sports"),
+            channel("Kids", "http://a/kids", "Kids"),
+        ]
+    }
+
+    fn create_test_db() -> Connection {
+        let mut conn = test_support::create_test_db();
+        populate_channels(&mut conn, 1, &playlist()).unwrap();
+        conn
+    }
+
+    fn loaded(conn: &Connection) -> Vec<Channel> {
+        let mut channels = playlist();
+        apply_channel_overrides(conn, None, &mut channels);
+        channels
+    }
+
+    #[test]
+    fn test_overrides_apply_to_parsed_channels() {
+        let mut conn = create_test_db();
+        let edit = ChannelOverrideEdit {
+            name: Some("World News".to_string()),
+            logo: Some("http://a/logo.png".to_string()),
+            ..Default::default()
+        };
+        edit_channel_overrides(&mut conn, 1, &playlist()[..1], &edit, NOW).unwrap();
+        let hide = ChannelOverrideEdit {
+            hidden: Some(true),
+            ..Default::default()
+        };
+        edit_channel_overrides(&mut conn, 1, &playlist()[2..], &hide, NOW).unwrap();
+
+        let channels = loaded(&conn);
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].name, "World News");
+        assert_eq!(channels[0].logo, "http://a/logo.png");
+        assert_eq!(channels[0].group_title, "News");
+        assert_eq!(channels[1].name, "Sport");
+        assert!(has_channel_overrides(&conn, 1));
+    }
+
+    #[test]
+    fn test_edits_merge_and_find_renamed_channels() {
+        let mut conn = create_test_db();
+        let rename = ChannelOverrideEdit {
+            name: Some("Sport HD".to_string()),
+            ..Default::default()
+        };
+        edit_channel_overrides(&mut conn, 1, &playlist()[1..2], &rename, NOW).unwrap();
+
+        // Bulk move, addressing the channel by the name it is shown with
+        let shown = loaded(&conn);
+        let regroup = ChannelOverrideEdit {
+            group_title: Some("Favourites".to_string()),
+            ..Default::default()
+        };
+        let edited = edit_channel_overrides(&mut conn, 1, &shown, &regroup, NOW + 1).unwrap();
+        assert_eq!(edited, 3);
+
+        let channels = loaded(&conn);
+        assert!(channels.iter().all(|c| c.group_title == "Favourites"));
+        assert_eq!(channels[1].name, "Sport HD");
+
+        let overrides = load_channel_overrides(&conn, 1).unwrap();
+        let sport = overrides
+            .iter()
+            .find(|o| o.original_name == "Sport")
+            .unwrap();
+        assert_eq!(sport.name.as_deref(), Some("Sport HD"));
+        assert_eq!(sport.updated_at, NOW + 1);
+    }
+
+    #[test]
+    fn test_failed_bulk_edit_changes_nothing() {
+        let mut conn = create_test_db();
+        let channels = vec![
+            playlist()[0].clone(),
+            channel("Missing", "http://a/missing", ""),
+        ];
+        let edit = ChannelOverrideEdit {
+            hidden: Some(true),
+            ..Default::default()
+        };
+        assert!(edit_channel_overrides(&mut conn, 1, &channels, &edit, NOW).is_err());
+        assert!(!has_channel_overrides(&conn, 1));
+
+        let empty_name = ChannelOverrideEdit {
+            name: Some("  ".to_string()),
+            ..Default::default()
+        };
+        assert!(edit_channel_overrides(&mut conn, 1, &playlist(), &empty_name, NOW).is_err());
+    }
+
+    #[test]
+    fn test_overrides_survive_refresh_and_reset() {
+        let mut conn = create_test_db();
+        let hide = ChannelOverrideEdit {
+            hidden: Some(true),
+            ..Default::default()
+        };
+        edit_channel_overrides(&mut conn, 1, &playlist()[..1], &hide, NOW).unwrap();
+
+        // A refresh that reorders the playlist keeps the override on its channel
+        let mut refreshed = playlist();
+        refreshed.reverse();
+        populate_channels(&mut conn, 1, &refreshed).unwrap();
+        apply_channel_overrides(&conn, Some(1), &mut refreshed);
+        assert_eq!(refreshed.len(), 2);
+        assert!(refreshed.iter().all(|c| c.name != "News"));
+
+        let identity = load_channel_overrides(&conn, 1).unwrap()[0]
+            .identity
+            .clone();
+        assert_eq!(reset_overrides(&conn, 1, Some(&[identity])).unwrap(), 1);
+        assert_eq!(loaded(&conn), playlist());
+        assert_eq!(reset_overrides(&conn, 1, None).unwrap(), 0);
+    }
+    #[test]
+    fn test_overrides_follow_renamed_and_moved_channels() {
+        let mut conn = create_test_db();
+        let regroup = ChannelOverrideEdit {
+            group_title: Some("Favourites".to_string()),
+            ..Default::default()
+        };
+        edit_channel_overrides(&mut conn, 1, &playlist()[..2], &regroup, NOW).unwrap();
+
+        // News is renamed and Sport moves to a new URL
+        let mut refreshed = playlist();
+        refreshed[0].name = "News 24".to_string();
+        refreshed[1].url = "http://b/sport".to_string();
+        populate_channels_with_changes(&mut conn, 1, &refreshed).unwrap();
+
+        apply_channel_overrides(&conn, Some(1), &mut refreshed);
+        assert_eq!(refreshed[0].group_title, "Favourites");
+        assert_eq!(refreshed[1].group_title, "Favourites");
+        assert_eq!(refreshed[2].group_title, "Kids");
+        let overrides = load_channel_overrides(&conn, 1).unwrap();
+        assert!(overrides.iter().any(|o| o.original_name == "News 24"));
+        assert!(overrides.iter().any(|o| o.original_url == "http://b/sport"));
+    }
+}
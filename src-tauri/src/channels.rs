@@ -1,15 +1,30 @@
+use crate::channel_overrides::apply_channel_overrides;
+use crate::database::{resolve_channel_list_id, sync_stored_channels};
+use crate::failover::{
+    cached_channels_snapshot, equivalence_key, find_equivalent_channels, order_fallbacks,
+    remember_working_source, FailoverEvent, MAX_FAILOVER_ATTEMPTS,
+};
+use crate::groups::apply_group_layout;
+use crate::history::add_to_history;
+use crate::http_client::current_proxy_settings;
 use crate::m3u_parser::{self, Channel};
 use crate::m3u_parser_helpers::{get_m3u_content, parse_m3u_with_progress};
+use crate::playback::PlaybackState;
+use crate::play_sessions::track_player_session;
+use crate::player::{self, LaunchError};
+use crate::player_failures::{record_player_failure, PlayerFailure};
+use crate::playlists::{
+    cancelled_error, emit_playlist_changes, load_virtual_channels_unlocked, FetchState,
+};
+use crate::relay::RelayState;
 use crate::search::clear_advanced_cache;
+use crate::stream_inspector::apply_inspected_resolutions;
 use crate::state::{ChannelCache, ChannelCacheState, DbState};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
-#[cfg(target_os = "windows")]
-use std::os::windows::process::CommandExt;
-use std::time::{Duration, SystemTime};
+use std::time::SystemTime;
 use std::sync::{Mutex, MutexGuard};
 use tauri::{AppHandle, Emitter, State};
-use tokio::time;
 
 // Helper function for safe mutex locking with timeout
 fn lock_with_timeout<'a, T>(mutex: &'a Mutex<T>, resource_name: &str) -> Result<MutexGuard<'a, T>, String> {
@@ -26,15 +41,17 @@ pub struct ChannelLoadingStatus {
 
 #[tauri::command]
 pub fn get_channels(
+    app_handle: AppHandle,
     db_state: State<DbState>,
     cache_state: State<ChannelCacheState>,
     id: Option<i32>,
 ) -> std::result::Result<Vec<Channel>, String> {
-    get_cached_channels(db_state, cache_state, id)
+    get_cached_channels(app_handle, db_state, cache_state, id)
 }
 
 #[tauri::command]
 pub fn get_cached_channels(
+    app_handle: AppHandle,
     db_state: State<DbState>,
     cache_state: State<ChannelCacheState>,
     id: Option<i32>,
@@ -51,8 +68,25 @@ pub fn get_cached_channels(
 
     // Cache miss - load channels and update cache
     println!("Loading channels from M3U parser for list {:?}", id);
-    let mut db = lock_with_timeout(&db_state.db, "database_connection")?;
-    let channels = m3u_parser::get_channels(&mut db, id);
+    let parse = |content: &str| Ok(m3u_parser::parse_m3u_content(content));
+    let mut channels = match load_source_channels(&db_state, id, parse) {
+        Ok(channels) => channels,
+        Err(e) => {
+            eprintln!("Failed to load channels for list {:?}: {}", id, e);
+            Vec::new()
+        }
+    };
+    let changes = {
+        let mut db = lock_with_timeout(&db_state.db, "database_connection")?;
+        sync_stored_channels(&mut db, id, &channels)
+    };
+    if let Some(changes) = &changes {
+        emit_playlist_changes(&app_handle, changes);
+    }
+    let db = db_state.reader();
+    apply_inspected_resolutions(&db, id, &mut channels);
+    apply_channel_overrides(&db, id, &mut channels);
+    apply_group_layout(&db, id, &mut channels);
     println!("Loaded {} channels for list {:?}", channels.len(), id);
 
     // Store original channels in cache for future use
@@ -77,90 +111,163 @@ pub fn invalidate_channel_cache(cache_state: State<ChannelCacheState>) -> Result
     Ok(())
 }
 
+/// Channels of list `id` as its source has them, virtual lists merged from
+/// their members. Nothing here holds the writer, so a stale list can be
+/// downloaded while other commands keep using the database.
+fn load_source_channels(
+    db_state: &DbState,
+    id: Option<i32>,
+    parse: impl FnOnce(&str) -> Result<Vec<Channel>, String>,
+) -> Result<Vec<Channel>, String> {
+    if let Some(channels) = load_virtual_channels_unlocked(db_state, id)? {
+        return Ok(channels);
+    }
+    parse(&get_m3u_content(db_state, id)?)
+}
+
 #[tauri::command]
-pub async fn play_channel(state: State<'_, DbState>, channel: Channel) -> Result<(), String> {
-    let player_command: String = {
-        let db = state.db.lock().unwrap();
-
-        // First, try to add to history
-        if let Err(e) = db.execute(
-            "INSERT OR REPLACE INTO history (name, logo, url, group_title, tvg_id, resolution, extra_info, timestamp) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, CURRENT_TIMESTAMP)",
-            &[&channel.name, &channel.logo, &channel.url, &channel.group_title, &channel.tvg_id, &channel.resolution, &channel.extra_info],
-        ) {
-            eprintln!("Warning: Failed to add channel to history: {}", e);
-            // Continue anyway, this shouldn't prevent playback
-        }
+pub async fn play_channel(
+    app_handle: AppHandle,
+    state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    playback_state: State<'_, PlaybackState>,
+    relay_state: State<'_, RelayState>,
+    channel: Channel,
+    variant_url: Option<String>,
+) -> Result<(), PlayerFailure> {
+    let (id, cached_channels) = cached_channels_snapshot(&cache_state);
+    let key = equivalence_key(&channel);
+
+    let (player_command, use_relay, channel_list_id, candidates) = {
+        let channel = channel.clone();
+        let key = key.clone();
+        state
+            .write(move |db| {
+                // First, try to add to history
+                if let Err(e) = add_to_history(db, &channel) {
+                    eprintln!("Warning: Failed to add channel to history: {}", e);
+                    // Continue anyway, this shouldn't prevent playback
+                }
+
+                let channel_list_id = resolve_channel_list_id(db, id);
+                let candidates = order_fallbacks(
+                    db,
+                    channel_list_id,
+                    &key,
+                    &channel.url,
+                    find_equivalent_channels(&cached_channels, &channel),
+                );
+
+                Ok((
+                    player::get_player_command(db),
+                    player::use_stream_relay(db),
+                    channel_list_id,
+                    candidates,
+                ))
+            })
+            .await?
+    };
+
+    let (command, mut args) = player::split_player_command(&player_command);
+    let ipc_path = player::prepare_mpv_ipc(&command, &mut args);
+    // With the relay the player only talks to localhost, and the relay uses the proxy
+    if !use_relay {
+        args.extend(player::proxy_args(&command, &current_proxy_settings()));
+    }
+
+    // An explicitly chosen variant is always tried first
+    let mut sources: Vec<(String, &Channel)> = Vec::new();
+    if let Some(url) = variant_url {
+        sources.push((url, &channel));
+    }
+    sources.extend(candidates.iter().map(|c| (c.url.clone(), c)));
+    let mut seen_urls = std::collections::HashSet::new();
+    sources.retain(|(url, _)| seen_urls.insert(url.clone()));
+    sources.truncate(MAX_FAILOVER_ATTEMPTS);
+    let stream_urls: Vec<String> = sources.iter().map(|(url, _)| url.clone()).collect();
 
-        db.query_row(
-            "SELECT player_command FROM settings WHERE id = 1",
-            [],
-            |row| row.get(0),
-        )
-        .unwrap_or_else(|_| crate::settings::detect_default_player())
-    }; // Release the database lock here
-
-    let mut command_parts = player_command.split_whitespace();
-    let default_player = crate::settings::detect_default_player();
-    let command = command_parts.next().unwrap_or(&default_player);
-    let args = command_parts.collect::<Vec<&str>>();
-
-    // Try to spawn the external player
-    #[cfg(target_os = "windows")]
-    let spawn_result = Command::new(command)
-        .args(args)
-        .arg(&channel.url)
-        .creation_flags(0x08000000) // CREATE_NO_WINDOW flag to hide CMD window
-        .spawn();
-    
-    #[cfg(not(target_os = "windows"))]
-    let spawn_result = Command::new(command)
-        .args(args)
-        .arg(&channel.url)
-        .spawn();
-    
-    match spawn_result {
-        Ok(mut child) => {
-            println!("Successfully launched player for channel: {}", channel.name);
-
-            // Wait a bit to see if the player exits quickly (indicating failure)
-            time::sleep(Duration::from_millis(3000)).await;
-
-            // Check if the process is still running
-            match child.try_wait() {
-                Ok(Some(exit_status)) => {
-                    // Process has exited
-                    if exit_status.success() {
-                        println!("Player exited successfully for channel: {}", channel.name);
-                        Ok(())
-                    } else {
-                        eprintln!(
-                            "Player exited with error for channel: {} (exit code: {:?})",
-                            channel.name,
-                            exit_status.code()
-                        );
-                        Err("Player failed to play the channel".to_string())
+    let mut last_failure: Option<PlayerFailure> = None;
+    for (attempt, (stream_url, source)) in sources.iter().enumerate() {
+        // Request options reach the stream either through the relay or as player flags
+        let mut launch_args = args.clone();
+        let launch_url = if use_relay {
+            relay_state.register(source, stream_url).await?
+        } else {
+            if let Some(ref options) = source.http_options {
+                launch_args.extend(player::http_option_args(&command, options));
+            }
+            stream_url.clone()
+        };
+
+        // Sessions start when the player does, not after the launch window
+        let launched_at = Utc::now().timestamp();
+        match player::launch_player(&command, &launch_args, &launch_url).await {
+            Ok(child) => {
+                println!("Successfully launched player for channel: {}", channel.name);
+                playback_state.player_started(ipc_path, &channel.url);
+                // A player that handed the stream to an already running instance has exited
+                track_player_session(&app_handle, &channel, child, launched_at);
+                let is_candidate = candidates.iter().any(|c| &c.url == stream_url);
+                if candidates.len() > 1 && is_candidate {
+                    if let Some(list_id) = channel_list_id {
+                        let (key, candidates) = (key.clone(), candidates.clone());
+                        let working_url = stream_url.clone();
+                        if let Err(e) = state
+                            .write(move |db| {
+                                remember_working_source(
+                                    db,
+                                    list_id,
+                                    &key,
+                                    &candidates,
+                                    &working_url,
+                                )
+                            })
+                            .await
+                        {
+                            eprintln!("Warning: Failed to remember working source: {}", e);
+                        }
                     }
                 }
-                Ok(None) => {
-                    // Process is still running, assume success
-                    println!("Player is still running for channel: {}", channel.name);
-                    Ok(())
+                return Ok(());
+            }
+            Err(e) => {
+                let failure = PlayerFailure::from_launch_error(&e, &channel.name, stream_url);
+                let (channel_url, recorded) = (channel.url.clone(), failure.clone());
+                if let Err(e) = state
+                    .write(move |db| record_player_failure(db, &channel_url, &recorded))
+                    .await
+                {
+                    eprintln!("Warning: Failed to record player failure: {}", e);
                 }
-                Err(e) => {
-                    eprintln!(
-                        "Failed to check player status for channel {}: {}",
-                        channel.name, e
+
+                // A missing or broken player binary will not be fixed by another source
+                if matches!(e, LaunchError::Spawn(_)) {
+                    return Err(failure);
+                }
+                if let Some(next_url) = stream_urls.get(attempt + 1) {
+                    println!(
+                        "Source {} failed for channel {} ({}), trying {}",
+                        stream_url,
+                        channel.name,
+                        failure.category.as_str(),
+                        next_url
+                    );
+                    let _ = app_handle.emit(
+                        "channel_failover",
+                        FailoverEvent {
+                            channel_name: channel.name.clone(),
+                            failed_url: stream_url.clone(),
+                            next_url: next_url.clone(),
+                            attempt: attempt + 1,
+                        },
                     );
-                    // If we can't check the status, assume it's working
-                    Ok(())
                 }
+                last_failure = Some(failure);
             }
         }
-        Err(e) => {
-            eprintln!("Failed to launch video player '{}': {}", command, e);
-            Err(format!("Failed to launch video player: {}", e))
-        }
     }
+
+    Err(last_failure.unwrap_or_else(|| PlayerFailure::from("No stream to play".to_string())))
 }
 
 // NEW ASYNC COMMANDS
@@ -169,6 +276,7 @@ pub async fn get_channels_async(
     app_handle: AppHandle,
     db_state: State<'_, DbState>,
     cache_state: State<'_, ChannelCacheState>,
+    fetch_state: State<'_, FetchState>,
     id: Option<i32>,
 ) -> Result<Vec<Channel>, String> {
     // Emit loading start
@@ -201,31 +309,57 @@ pub async fn get_channels_async(
         }
     }
 
-    // Get the file content on the main thread (database operations are fast)
-    let m3u_content = {
-        let mut db = db_state.db.lock().unwrap();
-        get_m3u_content(&mut db, id)?
-    };
+    // `cancel_playlist_fetch` on the list stops the parse
+    let list_id = db_state
+        .read(move |db| Ok(resolve_channel_list_id(db, id)))
+        .await?;
+    let operation = list_id.map(|list_id| fetch_state.begin(list_id as i32, None));
+    let token = operation
+        .as_ref()
+        .map(|operation| operation.token().clone())
+        .unwrap_or_default();
 
-    // Clone app handle for background parsing
+    // Loading reads through the reader pool and downloads without the writer
+    let loader = db_state.inner().clone();
     let app_handle_clone = app_handle.clone();
-
-    // Move only the heavy parsing to background thread
     let channels = tokio::task::spawn_blocking(move || {
-        parse_m3u_with_progress(&m3u_content, |progress, message, count| {
-            let _ = app_handle_clone.emit(
-                "channel_loading",
-                ChannelLoadingStatus {
-                    progress,
-                    message,
-                    channel_count: if count > 0 { Some(count) } else { None },
-                    is_complete: false,
-                },
-            );
+        load_source_channels(&loader, id, |m3u_content| {
+            let is_cancelled = || token.is_cancelled();
+            parse_m3u_with_progress(m3u_content, is_cancelled, |progress, message, count| {
+                let _ = app_handle_clone.emit(
+                    "channel_loading",
+                    ChannelLoadingStatus {
+                        progress,
+                        message,
+                        channel_count: if count > 0 { Some(count) } else { None },
+                        is_complete: false,
+                    },
+                );
+            })
+            .ok_or_else(cancelled_error)
         })
     })
     .await
-    .map_err(|e| format!("Background parsing failed: {}", e))?;
+    .map_err(|e| format!("Background parsing failed: {}", e))??;
+
+    // Storing a large list takes a while, keep it off the async runtime
+    let (mut channels, changes) = db_state
+        .write(move |db| {
+            let changes = sync_stored_channels(db, id, &channels);
+            Ok((channels, changes))
+        })
+        .await?;
+    let channels = db_state
+        .read(move |db| {
+            apply_inspected_resolutions(db, id, &mut channels);
+            apply_channel_overrides(db, id, &mut channels);
+            apply_group_layout(db, id, &mut channels);
+            Ok(channels)
+        })
+        .await?;
+    if let Some(changes) = &changes {
+        emit_playlist_changes(&app_handle, changes);
+    }
 
     // Update cache with new channels
     {
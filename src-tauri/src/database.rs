@@ -1,148 +1,52 @@
+use crate::db_pool::{enable_foreign_keys, enable_wal};
 use crate::error::{Result, TolloError};
-use crate::m3u_parser::Channel;
-use rusqlite::{Connection, Result as RusqliteResult};
+use crate::m3u_parser::{http_options_to_json, Channel};
+use crate::migrations::run_migrations;
+use crate::paths::data_paths;
+use crate::playlists::{store_channels_with_changes, PlaylistChangeLog};
+use chrono::Utc;
+use rusqlite::{params, Connection, Result as RusqliteResult};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::path::PathBuf;
+
+/// Tables holding data of a single channel list in their `channel_list_id`
+/// column. Their foreign keys cascade from `channel_lists`, but tables kept
+/// from schemas before versioning were created without them, so whatever
+/// removes a list still clears these itself.
+pub const PER_LIST_TABLES: &[&str] = &[
+    "channels",
+    "group_selections",
+    "saved_filters",
+    "channel_health",
+    "stream_variants",
+    "channel_source_order",
+    "playlist_changes",
+    "favorite_flags",
+    "channel_overrides",
+    "group_layout",
+    "group_aliases",
+    "group_rules",
+    "virtual_lists",
+    "playlist_request_configs",
+    "channel_list_mirrors",
+];
+
+pub fn database_path() -> Result<PathBuf> {
+    let paths = data_paths();
+    fs::create_dir_all(paths.root())
+        .map_err(|_e| TolloError::directory_creation(paths.root().display().to_string()))?;
+
+    Ok(paths.database_file())
+}
 
 pub fn initialize_database() -> Result<Connection> {
-    let data_dir = dirs::data_dir()
-        .ok_or_else(|| TolloError::DataDirectoryAccess)?
-        .join("tollo");
-
-    fs::create_dir_all(&data_dir)
-        .map_err(|_e| TolloError::directory_creation(data_dir.display().to_string()))?;
-
-    let db_path = data_dir.join("database.sqlite");
-    let conn = Connection::open(&db_path)?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS favorites (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            logo TEXT NOT NULL,
-            url TEXT NOT NULL,
-            group_title TEXT NOT NULL,
-            tvg_id TEXT NOT NULL,
-            resolution TEXT NOT NULL,
-            extra_info TEXT NOT NULL
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS history (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            logo TEXT NOT NULL,
-            url TEXT NOT NULL,
-            group_title TEXT NOT NULL,
-            tvg_id TEXT NOT NULL,
-            resolution TEXT NOT NULL,
-            extra_info TEXT NOT NULL,
-            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS channels (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            logo TEXT NOT NULL,
-            url TEXT NOT NULL,
-            group_title TEXT NOT NULL,
-            tvg_id TEXT NOT NULL,
-            resolution TEXT NOT NULL,
-            extra_info TEXT NOT NULL
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE VIRTUAL TABLE IF NOT EXISTS channels_fts USING fts5(name, content='channels', content_rowid='id')",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS settings (
-            id INTEGER PRIMARY KEY,
-            player_command TEXT NOT NULL,
-            cache_duration_hours INTEGER NOT NULL DEFAULT 24,
-            enable_preview BOOLEAN NOT NULL DEFAULT 1,
-            mute_on_start BOOLEAN NOT NULL DEFAULT 0,
-            show_controls BOOLEAN NOT NULL DEFAULT 1,
-            autoplay BOOLEAN NOT NULL DEFAULT 0
-        )",
-        [],
-    )?;
-
-    // Add the enable_preview column to existing settings table if it doesn't exist
-    conn.execute(
-        "ALTER TABLE settings ADD COLUMN enable_preview BOOLEAN NOT NULL DEFAULT 1",
-        [],
-    )
-    .ok(); // Use ok() to ignore error if column already exists
-
-    // Add the mute_on_start column to existing settings table if it doesn't exist
-    conn.execute(
-        "ALTER TABLE settings ADD COLUMN mute_on_start BOOLEAN NOT NULL DEFAULT 0",
-        [],
-    )
-    .ok();
-    // Add the show_controls column to existing settings table if it doesn't exist
-    conn.execute(
-        "ALTER TABLE settings ADD COLUMN show_controls BOOLEAN NOT NULL DEFAULT 1",
-        [],
-    )
-    .ok();
-    // Add the autoplay column to existing settings table if it doesn't exist
-    conn.execute(
-        "ALTER TABLE settings ADD COLUMN autoplay BOOLEAN NOT NULL DEFAULT 0",
-        [],
-    )
-    .ok();
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS channel_lists (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            source TEXT NOT NULL,
-            filepath TEXT,
-            last_fetched INTEGER,
-            is_default BOOLEAN NOT NULL DEFAULT 0,
-            CONSTRAINT is_default_check CHECK (is_default IN (0, 1))
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE UNIQUE INDEX IF NOT EXISTS idx_one_default_list ON channel_lists (is_default) WHERE is_default = 1",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS group_selections (
-            channel_list_id INTEGER NOT NULL,
-            group_name TEXT NOT NULL,
-            is_enabled BOOLEAN NOT NULL DEFAULT 1,
-            PRIMARY KEY (channel_list_id, group_name),
-            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS saved_filters (
-            channel_list_id INTEGER NOT NULL,
-            slot_number INTEGER NOT NULL CHECK (slot_number >= 0 AND slot_number <= 9),
-            search_query TEXT NOT NULL DEFAULT '',
-            selected_group TEXT,
-            name TEXT NOT NULL DEFAULT '',
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            PRIMARY KEY (channel_list_id, slot_number),
-            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
+    let db_path = database_path()?;
+    let mut conn = Connection::open(&db_path)?;
+    run_migrations(&mut conn, Some(&db_path))?;
+    enable_wal(&conn)?;
+    enable_foreign_keys(&conn)?;
 
     let list_count: i64 =
         conn.query_row("SELECT COUNT(*) FROM channel_lists", [], |row| row.get(0))?;
@@ -166,30 +70,176 @@ pub fn initialize_database() -> Result<Connection> {
     Ok(conn)
 }
 
-pub fn populate_channels(conn: &mut Connection, channels: &[Channel]) -> RusqliteResult<()> {
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ChannelSyncStats {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+/// Stable key of a channel within a list. `occurrence` tells apart entries that
+/// repeat the same name and URL, counting from the top of the playlist.
+pub fn channel_identity(channel_list_id: i64, channel: &Channel, occurrence: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(channel_list_id.to_string().as_bytes());
+    hasher.update([0]);
+    hasher.update(channel.url.as_bytes());
+    hasher.update([0]);
+    hasher.update(channel.name.as_bytes());
+    hasher.update([0]);
+    hasher.update(occurrence.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Identities of the channels of a list, in playlist order.
+pub fn channel_identities(channel_list_id: i64, channels: &[Channel]) -> Vec<String> {
+    let mut occurrences: HashMap<(&str, &str), usize> = HashMap::new();
+    channels
+        .iter()
+        .map(|channel| {
+            let occurrence = occurrences
+                .entry((channel.url.as_str(), channel.name.as_str()))
+                .or_insert(0);
+            let identity = channel_identity(channel_list_id, channel, *occurrence);
+            *occurrence += 1;
+            identity
+        })
+        .collect()
+}
+
+/// Brings the stored channels of a list in line with `channels`. Only rows that
+/// were added, changed or removed are written; triggers keep `channels_fts` in sync.
+pub fn populate_channels(
+    conn: &mut Connection,
+    channel_list_id: i64,
+    channels: &[Channel],
+) -> RusqliteResult<ChannelSyncStats> {
+    let tx = conn.transaction()?;
+    let stats = store_channels(&tx, channel_list_id, channels)?;
+    tx.commit()?;
+    Ok(stats)
+}
+
+/// Like `populate_channels`, and logs what changed since the list was last
+/// stored, flagging favorites that point at removed or changed channels.
+pub fn populate_channels_with_changes(
+    conn: &mut Connection,
+    channel_list_id: i64,
+    channels: &[Channel],
+) -> RusqliteResult<(ChannelSyncStats, Option<PlaylistChangeLog>)> {
     let tx = conn.transaction()?;
+    let stored =
+        store_channels_with_changes(&tx, channel_list_id, channels, Utc::now().timestamp())?;
+    tx.commit()?;
+    Ok(stored)
+}
+
+/// Writes the channels of a list without opening a transaction, for callers
+/// that store other changes with them atomically.
+pub fn store_channels(
+    conn: &Connection,
+    channel_list_id: i64,
+    channels: &[Channel],
+) -> RusqliteResult<ChannelSyncStats> {
+    let identities = channel_identities(channel_list_id, channels);
+
+    let mut stats = ChannelSyncStats::default();
     {
-        let mut stmt = tx.prepare("INSERT OR IGNORE INTO channels (name, logo, url, group_title, tvg_id, resolution, extra_info) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
-        for channel in channels {
-            stmt.execute(&[
-                &channel.name,
-                &channel.logo,
-                &channel.url,
-                &channel.group_title,
-                &channel.tvg_id,
-                &channel.resolution,
-                &channel.extra_info,
+        let existing: HashSet<String> = {
+            let mut stmt = conn.prepare("SELECT identity FROM channels WHERE channel_list_id = ?1")?;
+            let rows = stmt.query_map([channel_list_id], |row| row.get(0))?;
+            rows.collect::<RusqliteResult<_>>()?
+        };
+
+        let wanted: HashSet<&str> = identities.iter().map(|i| i.as_str()).collect();
+        let mut delete_stmt =
+            conn.prepare("DELETE FROM channels WHERE channel_list_id = ?1 AND identity = ?2")?;
+        for identity in existing.iter().filter(|i| !wanted.contains(i.as_str())) {
+            delete_stmt.execute(params![channel_list_id, identity])?;
+            stats.removed += 1;
+        }
+
+        let mut upsert_stmt = conn.prepare(
+            "INSERT INTO channels (channel_list_id, identity, position, name, logo, url, group_title, tvg_id, resolution, extra_info, http_options)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+             ON CONFLICT(channel_list_id, identity) DO UPDATE SET
+                position = excluded.position, logo = excluded.logo, group_title = excluded.group_title,
+                tvg_id = excluded.tvg_id, resolution = excluded.resolution,
+                extra_info = excluded.extra_info, http_options = excluded.http_options
+             WHERE channels.position IS NOT excluded.position OR channels.logo IS NOT excluded.logo
+                OR channels.group_title IS NOT excluded.group_title OR channels.tvg_id IS NOT excluded.tvg_id
+                OR channels.resolution IS NOT excluded.resolution OR channels.extra_info IS NOT excluded.extra_info
+                OR channels.http_options IS NOT excluded.http_options",
+        )?;
+        for (position, (channel, identity)) in channels.iter().zip(&identities).enumerate() {
+            let changed = upsert_stmt.execute(params![
+                channel_list_id,
+                identity,
+                position as i64,
+                channel.name,
+                channel.logo,
+                channel.url,
+                channel.group_title,
+                channel.tvg_id,
+                channel.resolution,
+                channel.extra_info,
+                http_options_to_json(&channel.http_options),
             ])?;
+            if !existing.contains(identity) {
+                stats.added += 1;
+            } else if changed > 0 {
+                stats.updated += 1;
+            }
         }
     }
-    tx.commit()?;
 
-    conn.execute(
-        "INSERT INTO channels_fts(rowid, name) SELECT id, name FROM channels",
-        [],
-    )?;
+    Ok(stats)
+}
 
-    Ok(())
+/// Stores the channels just loaded for list `id` (`None` for the default list),
+/// logging rather than failing since the channel cache does not depend on it.
+/// Returns the change log entry when the list changed since it was last stored.
+pub fn sync_stored_channels(
+    conn: &mut Connection,
+    id: Option<i32>,
+    channels: &[Channel],
+) -> Option<PlaylistChangeLog> {
+    let channel_list_id = resolve_channel_list_id(conn, id)?;
+    match populate_channels_with_changes(conn, channel_list_id, channels) {
+        Ok((stats, changes)) => {
+            if stats != ChannelSyncStats::default() {
+                println!(
+                    "Stored channels for list {}: {} added, {} updated, {} removed",
+                    channel_list_id, stats.added, stats.updated, stats.removed
+                );
+            }
+            changes
+        }
+        Err(e) => {
+            eprintln!("Warning: Failed to store channels: {}", e);
+            None
+        }
+    }
+}
+
+/// Resolves `None` to the default channel list, as the commands do.
+pub fn resolve_channel_list_id(conn: &Connection, id: Option<i32>) -> Option<i64> {
+    match id {
+        Some(list_id) => conn
+            .query_row(
+                "SELECT id FROM channel_lists WHERE id = ?1",
+                [list_id],
+                |row| row.get(0),
+            )
+            .ok(),
+        None => conn
+            .query_row(
+                "SELECT id FROM channel_lists WHERE is_default = 1",
+                [],
+                |row| row.get(0),
+            )
+            .ok(),
+    }
 }
 
 pub fn get_enabled_groups(conn: &Connection, channel_list_id: i64) -> RusqliteResult<Vec<String>> {
@@ -360,119 +410,17 @@ pub fn delete_saved_filter(
 mod tests {
     use super::*;
     use crate::m3u_parser::Channel;
+    use crate::test_support::migrated_db;
     use rusqlite::Connection;
 
-    fn create_test_db() -> Connection {
-        let conn = Connection::open_in_memory().unwrap();
-
-        // Create the basic table structure that initialize_database would create
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS favorites (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                logo TEXT NOT NULL,
-                url TEXT NOT NULL,
-                group_title TEXT NOT NULL,
-                tvg_id TEXT NOT NULL,
-                resolution TEXT NOT NULL,
-                extra_info TEXT NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS history (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                logo TEXT NOT NULL,
-                url TEXT NOT NULL,
-                group_title TEXT NOT NULL,
-                tvg_id TEXT NOT NULL,
-                resolution TEXT NOT NULL,
-                extra_info TEXT NOT NULL,
-                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS channels (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                logo TEXT NOT NULL,
-                url TEXT NOT NULL,
-                group_title TEXT NOT NULL,
-                tvg_id TEXT NOT NULL,
-                resolution TEXT NOT NULL,
-                extra_info TEXT NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE VIRTUAL TABLE IF NOT EXISTS channels_fts USING fts5(name, content='channels', content_rowid='id')",
-            [],
-        ).unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS channel_lists (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                source TEXT NOT NULL,
-                filepath TEXT,
-                last_fetched INTEGER,
-                is_default BOOLEAN NOT NULL DEFAULT 0,
-                CONSTRAINT is_default_check CHECK (is_default IN (0, 1))
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS group_selections (
-                channel_list_id INTEGER NOT NULL,
-                group_name TEXT NOT NULL,
-                is_enabled BOOLEAN NOT NULL DEFAULT 1,
-                PRIMARY KEY (channel_list_id, group_name),
-                FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS saved_filters (
-                channel_list_id INTEGER NOT NULL,
-                slot_number INTEGER NOT NULL CHECK (slot_number >= 0 AND slot_number <= 9),
-                search_query TEXT NOT NULL DEFAULT '',
-                selected_group TEXT,
-                name TEXT NOT NULL DEFAULT '',
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                PRIMARY KEY (channel_list_id, slot_number),
-                FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS settings (
-                id INTEGER PRIMARY KEY,
-                player_command TEXT NOT NULL,
-                cache_duration_hours INTEGER NOT NULL DEFAULT 24,
-                enable_preview BOOLEAN NOT NULL DEFAULT 1,
-                mute_on_start BOOLEAN NOT NULL DEFAULT 0,
-                show_controls BOOLEAN NOT NULL DEFAULT 1,
-                autoplay BOOLEAN NOT NULL DEFAULT 0
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn
+    fn create_test_lists(conn: &Connection, count: i64) {
+        for id in 1..=count {
+            conn.execute(
+                "INSERT INTO channel_lists (id, name, source) VALUES (?1, ?2, 'http://example.com')",
+                params![id, format!("Test List {}", id)],
+            )
+            .unwrap();
+        }
     }
 
     fn create_test_channel() -> Channel {
@@ -484,15 +432,18 @@ mod tests {
             tvg_id: "test123".to_string(),
             resolution: "1080p".to_string(),
             extra_info: "Test extra info".to_string(),
+            http_options: None,
+            source_list_id: None,
         }
     }
 
     #[test]
     fn test_populate_channels_success() {
-        let mut conn = create_test_db();
+        let mut conn = migrated_db();
+        create_test_lists(&conn, 1);
         let channels = vec![create_test_channel()];
 
-        let result = populate_channels(&mut conn, &channels);
+        let result = populate_channels(&mut conn, 1, &channels);
         assert!(result.is_ok());
 
         // Verify channel was inserted
@@ -513,25 +464,123 @@ mod tests {
 
     #[test]
     fn test_populate_channels_duplicate_handling() {
-        let mut conn = create_test_db();
+        let mut conn = migrated_db();
+        create_test_lists(&conn, 1);
         let channels = vec![create_test_channel(), create_test_channel()];
 
-        let result = populate_channels(&mut conn, &channels);
+        let result = populate_channels(&mut conn, 1, &channels);
         assert!(result.is_ok());
 
-        // Should only have one channel due to UNIQUE constraint on name
+        // Repeated entries within a list are kept, each with its own identity
         let count: i64 = conn
             .query_row("SELECT COUNT(*) FROM channels", [], |row| row.get(0))
             .unwrap();
-        assert_eq!(count, 1);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_populate_channels_keeps_lists_apart() {
+        let mut conn = migrated_db();
+        create_test_lists(&conn, 2);
+        let channels = vec![create_test_channel()];
+
+        populate_channels(&mut conn, 1, &channels).unwrap();
+        populate_channels(&mut conn, 2, &channels).unwrap();
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM channels", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_ne!(
+            channel_identity(1, &channels[0], 0),
+            channel_identity(2, &channels[0], 0)
+        );
+    }
+
+    #[test]
+    fn test_populate_channels_is_incremental() {
+        let mut conn = migrated_db();
+        create_test_lists(&conn, 1);
+        let mut first = create_test_channel();
+        let mut second = create_test_channel();
+        second.name = "Second Channel".to_string();
+        second.url = "http://example.com/second".to_string();
+
+        let stats = populate_channels(&mut conn, 1, &[first.clone(), second.clone()]).unwrap();
+        assert_eq!(stats.added, 2);
+        let first_id: i64 = conn
+            .query_row(
+                "SELECT id FROM channels WHERE name = 'Test Channel'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        // Reloading the same playlist writes nothing
+        let stats = populate_channels(&mut conn, 1, &[first.clone(), second.clone()]).unwrap();
+        assert_eq!(stats, ChannelSyncStats::default());
+
+        first.group_title = "Moved Group".to_string();
+        let third = Channel {
+            name: "Third Channel".to_string(),
+            url: "http://example.com/third".to_string(),
+            ..create_test_channel()
+        };
+        let stats = populate_channels(&mut conn, 1, &[first, third]).unwrap();
+        assert_eq!(
+            stats,
+            ChannelSyncStats {
+                added: 1,
+                updated: 1,
+                removed: 1
+            }
+        );
+
+        // The identity, and so the row id, survives the update
+        let (id, group): (i64, String) = conn
+            .query_row(
+                "SELECT id, group_title FROM channels WHERE name = 'Test Channel'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(id, first_id);
+        assert_eq!(group, "Moved Group");
+    }
+
+    #[test]
+    fn test_populate_channels_keeps_fts_in_sync() {
+        let mut conn = migrated_db();
+        create_test_lists(&conn, 1);
+        let fts_count = |conn: &Connection, query: &str| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM channels_fts WHERE channels_fts MATCH ?1",
+                [query],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        let mut channel = create_test_channel();
+        populate_channels(&mut conn, 1, &[channel.clone()]).unwrap();
+        assert_eq!(fts_count(&conn, "test"), 1);
+
+        channel.group_title = "Sports".to_string();
+        populate_channels(&mut conn, 1, &[channel]).unwrap();
+        assert_eq!(fts_count(&conn, "group_title:sports"), 1);
+        assert_eq!(fts_count(&conn, "group_title:group"), 0);
+
+        populate_channels(&mut conn, 1, &[]).unwrap();
+        assert_eq!(fts_count(&conn, "test"), 0);
     }
 
     #[test]
     fn test_populate_channels_empty_list() {
-        let mut conn = create_test_db();
+        let mut conn = migrated_db();
+        create_test_lists(&conn, 1);
         let channels = vec![];
 
-        let result = populate_channels(&mut conn, &channels);
+        let result = populate_channels(&mut conn, 1, &channels);
         assert!(result.is_ok());
 
         let count: i64 = conn
@@ -542,7 +591,7 @@ mod tests {
 
     #[test]
     fn test_get_enabled_groups_empty() {
-        let conn = create_test_db();
+        let conn = migrated_db();
 
         let result = get_enabled_groups(&conn, 1);
         assert!(result.is_ok());
@@ -551,7 +600,7 @@ mod tests {
 
     #[test]
     fn test_set_and_get_enabled_groups() {
-        let conn = create_test_db();
+        let conn = migrated_db();
 
         // Insert a channel list first
         conn.execute(
@@ -573,7 +622,7 @@ mod tests {
 
     #[test]
     fn test_sync_channel_list_groups() {
-        let mut conn = create_test_db();
+        let mut conn = migrated_db();
 
         // Insert a channel list
         conn.execute(
@@ -605,7 +654,7 @@ mod tests {
 
     #[test]
     fn test_enable_all_groups() {
-        let mut conn = create_test_db();
+        let mut conn = migrated_db();
 
         // Insert a channel list
         conn.execute(
@@ -629,7 +678,7 @@ mod tests {
 
     #[test]
     fn test_save_and_get_saved_filters() {
-        let conn = create_test_db();
+        let conn = migrated_db();
 
         // Insert a channel list
         conn.execute(
@@ -675,7 +724,7 @@ mod tests {
 
     #[test]
     fn test_delete_saved_filter() {
-        let conn = create_test_db();
+        let conn = migrated_db();
 
         // Insert a channel list
         conn.execute(
@@ -713,7 +762,7 @@ mod tests {
 
     #[test]
     fn test_saved_filter_replace() {
-        let conn = create_test_db();
+        let conn = migrated_db();
 
         // Insert a channel list
         conn.execute(
@@ -754,7 +803,7 @@ mod tests {
 
     #[test]
     fn test_saved_filter_slot_constraints() {
-        let conn = create_test_db();
+        let conn = migrated_db();
 
         // Insert a channel list
         conn.execute(
@@ -774,7 +823,7 @@ mod tests {
 
     #[test]
     fn test_foreign_key_constraints() {
-        let conn = create_test_db();
+        let conn = migrated_db();
 
         // Enable foreign key constraints
         conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
@@ -790,7 +839,7 @@ mod tests {
 
     #[test]
     fn test_cascade_delete() {
-        let conn = create_test_db();
+        let conn = migrated_db();
 
         // Enable foreign key constraints
         conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
@@ -835,7 +884,8 @@ mod tests {
 
         #[test]
         fn test_populate_channels_with_invalid_transaction() {
-            let mut conn = create_test_db();
+            let mut conn = migrated_db();
+            create_test_lists(&conn, 1);
 
             // Create an empty channel with all required fields
             let invalid_channel = Channel {
@@ -846,10 +896,12 @@ mod tests {
                 tvg_id: "".to_string(),
                 resolution: "".to_string(),
                 extra_info: "".to_string(),
+                http_options: None,
+                source_list_id: None,
             };
 
             let channels = vec![invalid_channel];
-            let result = populate_channels(&mut conn, &channels);
+            let result = populate_channels(&mut conn, 1, &channels);
 
             // Should still succeed as empty strings are valid in SQLite
             assert!(result.is_ok());
@@ -857,7 +909,8 @@ mod tests {
 
         #[test]
         fn test_populate_channels_with_extremely_long_strings() {
-            let mut conn = create_test_db();
+            let mut conn = migrated_db();
+            create_test_lists(&conn, 1);
 
             // Create channel with very long strings to test limits
             let long_string = "a".repeat(10000);
@@ -869,10 +922,12 @@ mod tests {
                 tvg_id: long_string.clone(),
                 resolution: long_string.clone(),
                 extra_info: long_string,
+                http_options: None,
+                source_list_id: None,
             };
 
             let channels = vec![long_channel];
-            let result = populate_channels(&mut conn, &channels);
+            let result = populate_channels(&mut conn, 1, &channels);
 
             // Should succeed as SQLite handles long strings
             assert!(result.is_ok());
@@ -880,7 +935,8 @@ mod tests {
 
         #[test]
         fn test_populate_channels_with_special_characters() {
-            let mut conn = create_test_db();
+            let mut conn = migrated_db();
+            create_test_lists(&conn, 1);
 
             // Create channel with special characters that might cause SQL injection
             let special_channel = Channel {
@@ -891,10 +947,12 @@ mod tests {
                 tvg_id: "test'; --".to_string(),
                 resolution: "1080p'; --".to_string(),
                 extra_info: "Extra'; --".to_string(),
+                http_options: None,
+                source_list_id: None,
             };
 
             let channels = vec![special_channel];
-            let result = populate_channels(&mut conn, &channels);
+            let result = populate_channels(&mut conn, 1, &channels);
 
             // Should succeed as we use prepared statements
             assert!(result.is_ok());
@@ -908,7 +966,7 @@ mod tests {
 
         #[test]
         fn test_get_enabled_groups_with_invalid_channel_list_id() {
-            let conn = create_test_db();
+            let conn = migrated_db();
 
             // Try to get groups for non-existent channel list
             let result = get_enabled_groups(&conn, -1);
@@ -922,7 +980,7 @@ mod tests {
 
         #[test]
         fn test_set_group_enabled_with_very_long_group_name() {
-            let conn = create_test_db();
+            let conn = migrated_db();
 
             // Insert a channel list
             conn.execute(
@@ -941,7 +999,7 @@ mod tests {
 
         #[test]
         fn test_sync_channel_list_groups_with_empty_groups() {
-            let mut conn = create_test_db();
+            let mut conn = migrated_db();
 
             // Insert a channel list
             conn.execute(
@@ -964,7 +1022,7 @@ mod tests {
 
         #[test]
         fn test_enable_all_groups_with_empty_list() {
-            let mut conn = create_test_db();
+            let mut conn = migrated_db();
 
             // Insert a channel list
             conn.execute(
@@ -982,7 +1040,7 @@ mod tests {
 
         #[test]
         fn test_save_filter_with_invalid_slot_numbers() {
-            let conn = create_test_db();
+            let conn = migrated_db();
 
             // Insert a channel list
             conn.execute(
@@ -1003,7 +1061,7 @@ mod tests {
 
         #[test]
         fn test_save_filter_with_extremely_long_values() {
-            let conn = create_test_db();
+            let conn = migrated_db();
 
             // Insert a channel list
             conn.execute(
@@ -1025,7 +1083,7 @@ mod tests {
 
         #[test]
         fn test_get_saved_filters_with_invalid_channel_list_id() {
-            let conn = create_test_db();
+            let conn = migrated_db();
 
             let result = get_saved_filters(&conn, -1);
             assert!(result.is_ok());
@@ -1038,7 +1096,7 @@ mod tests {
 
         #[test]
         fn test_delete_saved_filter_nonexistent() {
-            let conn = create_test_db();
+            let conn = migrated_db();
 
             // Insert a channel list
             conn.execute(
@@ -1057,7 +1115,7 @@ mod tests {
 
         #[test]
         fn test_concurrent_operations_simulation() {
-            let conn = create_test_db();
+            let conn = migrated_db();
 
             // Insert a channel list
             conn.execute(
@@ -1078,7 +1136,7 @@ mod tests {
 
         #[test]
         fn test_unicode_and_emoji_handling() {
-            let conn = create_test_db();
+            let conn = migrated_db();
 
             // Insert a channel list
             conn.execute(
@@ -0,0 +1,219 @@
+use rusqlite::{Connection, OpenFlags, Result as RusqliteResult};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Read-only connections kept open next to the writer.
+pub const READER_CONNECTIONS: usize = 4;
+
+/// How long a connection waits on a lock held by another connection before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Switches the database file to write-ahead logging, so readers see the last
+/// committed state while a write transaction is open.
+pub fn enable_wal(conn: &Connection) -> RusqliteResult<()> {
+    let _mode: String = conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.busy_timeout(BUSY_TIMEOUT)
+}
+
+/// Makes `ON DELETE CASCADE` apply. SQLite only enforces foreign keys on
+/// connections that ask for it, whatever it was built with.
+pub fn enable_foreign_keys(conn: &Connection) -> RusqliteResult<()> {
+    conn.pragma_update(None, "foreign_keys", true)
+}
+
+pub fn open_reader(path: &Path) -> RusqliteResult<Connection> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_URI,
+    )?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    enable_foreign_keys(&conn)?;
+    Ok(conn)
+}
+
+/// A fixed set of read-only connections handed out one caller at a time.
+pub struct ReaderPool {
+    idle: Mutex<Vec<Connection>>,
+    returned: Condvar,
+    size: usize,
+}
+
+impl ReaderPool {
+    /// A pool without connections. Reads then go through the writer.
+    pub fn empty() -> Self {
+        Self::from_connections(Vec::new())
+    }
+
+    pub fn open(path: &Path, size: usize) -> RusqliteResult<Self> {
+        let connections = (0..size)
+            .map(|_| open_reader(path))
+            .collect::<RusqliteResult<Vec<_>>>()?;
+        Ok(Self::from_connections(connections))
+    }
+
+    fn from_connections(connections: Vec<Connection>) -> Self {
+        Self {
+            size: connections.len(),
+            idle: Mutex::new(connections),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Takes an idle connection, waiting for one to be returned if all are in use.
+    /// Returns `None` for an empty pool.
+    pub fn checkout(self: &Arc<Self>) -> Option<PooledConnection> {
+        if self.size == 0 {
+            return None;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        loop {
+            if let Some(conn) = idle.pop() {
+                return Some(PooledConnection {
+                    conn: Some(conn),
+                    pool: Arc::clone(self),
+                });
+            }
+            idle = self.returned.wait(idle).unwrap();
+        }
+    }
+
+    fn check_in(&self, conn: Connection) {
+        self.idle.lock().unwrap().push(conn);
+        self.returned.notify_one();
+    }
+}
+
+/// A reader connection that goes back to its pool when dropped.
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    pool: Arc<ReaderPool>,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("pooled connection already returned")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.check_in(conn);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::DbState;
+    use tempfile::TempDir;
+
+    fn create_file_db(dir: &TempDir) -> (Connection, std::path::PathBuf) {
+        let path = dir.path().join("database.sqlite");
+        let conn = Connection::open(&path).unwrap();
+        enable_wal(&conn).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+             INSERT INTO items (name) VALUES ('first');",
+        )
+        .unwrap();
+        (conn, path)
+    }
+
+    fn count_items(conn: &Connection) -> RusqliteResult<i64> {
+        conn.query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0))
+    }
+
+    #[test]
+    fn test_enable_wal() {
+        let dir = TempDir::new().unwrap();
+        let (conn, _path) = create_file_db(&dir);
+        let mode: String = conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+    }
+
+    #[test]
+    fn test_checkout_returns_connection_to_pool() {
+        let dir = TempDir::new().unwrap();
+        let (_conn, path) = create_file_db(&dir);
+        let pool = Arc::new(ReaderPool::open(&path, 1).unwrap());
+
+        let reader = pool.checkout().unwrap();
+        assert_eq!(count_items(&reader).unwrap(), 1);
+        drop(reader);
+
+        // The single connection is available again
+        assert!(pool.checkout().is_some());
+        assert!(Arc::new(ReaderPool::empty()).checkout().is_none());
+    }
+
+    #[test]
+    fn test_readers_are_read_only() {
+        let dir = TempDir::new().unwrap();
+        let (_conn, path) = create_file_db(&dir);
+        let reader = open_reader(&path).unwrap();
+        assert!(reader
+            .execute("INSERT INTO items (name) VALUES ('second')", [])
+            .is_err());
+    }
+
+    #[test]
+    fn test_reads_do_not_wait_for_open_write() {
+        let dir = TempDir::new().unwrap();
+        let (conn, path) = create_file_db(&dir);
+        let state = DbState::with_readers(conn, &path, 2).unwrap();
+
+        // Hold the writer inside an uncommitted transaction, as an import would
+        let writer = state.db.lock().unwrap();
+        writer
+            .execute_batch("BEGIN IMMEDIATE; INSERT INTO items (name) VALUES ('second');")
+            .unwrap();
+
+        // Readers see the last committed state without taking the writer lock
+        assert_eq!(count_items(&state.reader()).unwrap(), 1);
+
+        writer.execute_batch("COMMIT").unwrap();
+        drop(writer);
+        assert_eq!(count_items(&state.reader()).unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_async_read_and_write() {
+        let dir = TempDir::new().unwrap();
+        let (conn, path) = create_file_db(&dir);
+        let state = DbState::with_readers(conn, &path, 2).unwrap();
+
+        state
+            .write(|conn| conn.execute("INSERT INTO items (name) VALUES ('second')", []))
+            .await
+            .unwrap();
+        assert_eq!(state.read(count_items).await.unwrap(), 2);
+
+        let error = state
+            .read(|conn| conn.execute("DELETE FROM items", []))
+            .await
+            .unwrap_err();
+        assert!(error.contains("readonly"));
+    }
+
+    #[tokio::test]
+    async fn test_reads_fall_back_to_writer_without_pool() {
+        let dir = TempDir::new().unwrap();
+        let (conn, _path) = create_file_db(&dir);
+        let state = DbState::new(conn);
+        assert_eq!(state.read(count_items).await.unwrap(), 1);
+        assert_eq!(count_items(&state.reader()).unwrap(), 1);
+    }
+}
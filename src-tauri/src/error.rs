@@ -119,6 +119,13 @@ impl TolloError {
         }
     }
     
+    /// Create a new database migration error
+    pub fn database_migration(reason: impl Into<String>) -> Self {
+        Self::DatabaseMigration {
+            reason: reason.into(),
+        }
+    }
+    
     /// Create a new directory creation error
     pub fn directory_creation(path: impl Into<String>) -> Self {
         Self::DirectoryCreation {
@@ -168,6 +175,13 @@ impl TolloError {
         }
     }
     
+    /// Create a new cancellation error
+    pub fn cancelled(operation: impl Into<String>) -> Self {
+        Self::Cancelled {
+            operation: operation.into(),
+        }
+    }
+    
     /// Create a new internal error
     pub fn internal(reason: impl Into<String>) -> Self {
         Self::Internal {
@@ -200,6 +214,7 @@ impl TolloError {
         match self {
             TolloError::Database(_) => "Database operation failed. Please try again.".to_string(),
             TolloError::DatabaseInitialization { .. } => "Failed to initialize database. Please check your permissions.".to_string(),
+            TolloError::DatabaseMigration { reason } => format!("Failed to upgrade database: {}", reason),
             TolloError::Network(_) => "Network connection failed. Please check your internet connection.".to_string(),
             TolloError::PlaylistFetch { .. } => "Failed to load playlist. Please check the URL and try again.".to_string(),
             TolloError::FileDownload { .. } => "Failed to download file. Please check your connection and try again.".to_string(),
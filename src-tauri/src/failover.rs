@@ -0,0 +1,324 @@
+use crate::database::resolve_channel_list_id;
+use crate::m3u_parser::Channel;
+use crate::state::{ChannelCacheState, DbState};
+use regex::Regex;
+use rusqlite::{Connection, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+use tauri::State;
+
+// Upper bound on sources tried for a single play request
+pub const MAX_FAILOVER_ATTEMPTS: usize = 5;
+
+// Quality and source tags that providers append to otherwise identical names
+static RE_NAME_NOISE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\[.*?\]|\(.*?\)|\b(\d+p|uhd|fhd|hd|sd|4k|backup|alt)\b").unwrap()
+});
+static RE_NON_ALPHANUMERIC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[^\p{L}\p{N}]+").unwrap());
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FailoverEvent {
+    pub channel_name: String,
+    pub failed_url: String,
+    pub next_url: String,
+    pub attempt: usize,
+}
+
+fn normalize_channel_name(name: &str) -> String {
+    let without_noise = RE_NAME_NOISE.replace_all(name, " ");
+    RE_NON_ALPHANUMERIC
+        .replace_all(&without_noise.to_lowercase(), " ")
+        .trim()
+        .to_string()
+}
+
+/// Key under which duplicate sources of the same channel are grouped.
+/// `tvg_id` is authoritative when present, otherwise the normalized name is used.
+pub fn equivalence_key(channel: &Channel) -> String {
+    let tvg_id = channel.tvg_id.trim();
+    if !tvg_id.is_empty() {
+        return format!("tvg:{}", tvg_id.to_lowercase());
+    }
+    format!("name:{}", normalize_channel_name(&channel.name))
+}
+
+/// Returns all channels sharing the equivalence key of `channel`, in playlist
+/// order and without duplicate URLs. The channel itself is always included.
+pub fn find_equivalent_channels(channels: &[Channel], channel: &Channel) -> Vec<Channel> {
+    let key = equivalence_key(channel);
+    let mut seen_urls = HashSet::new();
+    let mut equivalents: Vec<Channel> = channels
+        .iter()
+        .filter(|c| equivalence_key(c) == key && seen_urls.insert(c.url.clone()))
+        .cloned()
+        .collect();
+
+    if !seen_urls.contains(&channel.url) {
+        equivalents.insert(0, channel.clone());
+    }
+    equivalents
+}
+
+fn load_source_order(
+    conn: &Connection,
+    channel_list_id: i64,
+    key: &str,
+) -> RusqliteResult<HashMap<String, i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT url, position FROM channel_source_order WHERE channel_list_id = ?1 AND equivalence_key = ?2",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![channel_list_id, key], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+    rows.collect()
+}
+
+/// Sorts candidates by their saved order. Sources without a saved position keep
+/// their playlist order after the ones that have one.
+pub fn order_candidates(
+    conn: &Connection,
+    channel_list_id: i64,
+    key: &str,
+    mut candidates: Vec<Channel>,
+) -> Vec<Channel> {
+    let positions = load_source_order(conn, channel_list_id, key).unwrap_or_default();
+    if positions.is_empty() {
+        return candidates;
+    }
+    candidates.sort_by_key(|c| positions.get(&c.url).copied().unwrap_or(i64::MAX));
+    candidates
+}
+
+/// The sources to try when `requested_url` is played: the requested source
+/// first, then its fallbacks in their saved order.
+pub fn order_fallbacks(
+    conn: &Connection,
+    channel_list_id: Option<i64>,
+    key: &str,
+    requested_url: &str,
+    mut candidates: Vec<Channel>,
+) -> Vec<Channel> {
+    let requested = candidates
+        .iter()
+        .position(|c| c.url == requested_url)
+        .map(|index| candidates.remove(index));
+    let mut ordered = match channel_list_id {
+        Some(channel_list_id) => order_candidates(conn, channel_list_id, key, candidates),
+        None => candidates,
+    };
+    if let Some(requested) = requested {
+        ordered.insert(0, requested);
+    }
+    ordered
+}
+
+pub fn save_source_order(
+    conn: &mut Connection,
+    channel_list_id: i64,
+    key: &str,
+    urls: &[String],
+) -> RusqliteResult<()> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM channel_source_order WHERE channel_list_id = ?1 AND equivalence_key = ?2",
+        rusqlite::params![channel_list_id, key],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR IGNORE INTO channel_source_order (channel_list_id, equivalence_key, url, position) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (position, url) in urls.iter().enumerate() {
+            stmt.execute(rusqlite::params![
+                channel_list_id,
+                key,
+                url,
+                position as i64
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Moves the source that played successfully to the front so it is tried first next time.
+pub fn remember_working_source(
+    conn: &mut Connection,
+    channel_list_id: i64,
+    key: &str,
+    candidates: &[Channel],
+    working_url: &str,
+) -> RusqliteResult<()> {
+    let mut urls = vec![working_url.to_string()];
+    urls.extend(
+        candidates
+            .iter()
+            .filter(|c| c.url != working_url)
+            .map(|c| c.url.clone()),
+    );
+    save_source_order(conn, channel_list_id, key, &urls)
+}
+
+/// Snapshot of the currently cached channel list, used as the failover candidate pool.
+pub fn cached_channels_snapshot(cache_state: &ChannelCacheState) -> (Option<i32>, Vec<Channel>) {
+    let cache = cache_state.cache.lock().unwrap();
+    match *cache {
+        Some(ref cached) => (cached.channel_list_id, cached.channels.clone()),
+        None => (None, Vec::new()),
+    }
+}
+
+#[tauri::command]
+pub fn get_channel_sources(
+    db_state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    channel: Channel,
+) -> Result<Vec<Channel>, String> {
+    let (id, channels) = cached_channels_snapshot(&cache_state);
+    let candidates = find_equivalent_channels(&channels, &channel);
+
+    let db = db_state.reader();
+    match resolve_channel_list_id(&db, id) {
+        Some(channel_list_id) => Ok(order_candidates(
+            &db,
+            channel_list_id,
+            &equivalence_key(&channel),
+            candidates,
+        )),
+        None => Ok(candidates),
+    }
+}
+
+#[tauri::command]
+pub fn set_channel_source_order(
+    db_state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    channel: Channel,
+    urls: Vec<String>,
+) -> Result<(), String> {
+    let (id, _) = cached_channels_snapshot(&cache_state);
+    let mut db = db_state.db.lock().unwrap();
+    let channel_list_id =
+        resolve_channel_list_id(&db, id).ok_or_else(|| "Channel list not found".to_string())?;
+    save_source_order(&mut db, channel_list_id, &equivalence_key(&channel), &urls)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{channel, create_test_db};
+
+    fn create_channel(name: &str, url: &str, tvg_id: &str) -> Channel {
+        Channel {
+            tvg_id: tvg_id.to_string(),
+            ..channel(name, url, "News")
+        }
+    }
+
+    #[test]
+    fn test_equivalence_key_prefers_tvg_id() {
+        let a = create_channel("CNN HD", "http://a", "CNN.us");
+        let b = create_channel("CNN International", "http://b", "cnn.us");
+        assert_eq!(equivalence_key(&a), equivalence_key(&b));
+    }
+
+    #[test]
+    fn test_equivalence_key_normalizes_names() {
+        let a = create_channel("BBC One HD", "http://a", "");
+        let b = create_channel("BBC One [Backup] 720p", "http://b", "");
+        let c = create_channel("bbc-one", "http://c", "");
+        let d = create_channel("BBC Two", "http://d", "");
+        assert_eq!(equivalence_key(&a), equivalence_key(&b));
+        assert_eq!(equivalence_key(&a), equivalence_key(&c));
+        assert_ne!(equivalence_key(&a), equivalence_key(&d));
+    }
+
+    #[test]
+    fn test_find_equivalent_channels_keeps_playlist_order() {
+        let channels = vec![
+            create_channel("BBC One", "http://1", ""),
+            create_channel("BBC Two", "http://2", ""),
+            create_channel("BBC One HD", "http://3", ""),
+            create_channel("BBC One", "http://1", ""),
+        ];
+
+        let equivalents = find_equivalent_channels(&channels, &channels[2]);
+        let urls: Vec<&str> = equivalents.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(urls, vec!["http://1", "http://3"]);
+
+        // A channel that is not in the list is still a candidate
+        let outside = create_channel("BBC One", "http://9", "");
+        let equivalents = find_equivalent_channels(&channels, &outside);
+        assert_eq!(equivalents[0].url, "http://9");
+        assert_eq!(equivalents.len(), 3);
+    }
+
+    #[test]
+    fn test_remember_working_source_reorders_candidates() {
+        let mut conn = create_test_db();
+        let candidates = vec![
+            create_channel("BBC One", "http://1", ""),
+            create_channel("BBC One", "http://2", ""),
+            create_channel("BBC One", "http://3", ""),
+        ];
+        let key = equivalence_key(&candidates[0]);
+
+        // Without saved order the playlist order is kept
+        let ordered = order_candidates(&conn, 1, &key, candidates.clone());
+        assert_eq!(ordered[0].url, "http://1");
+
+        remember_working_source(&mut conn, 1, &key, &candidates, "http://3").unwrap();
+        let ordered = order_candidates(&conn, 1, &key, candidates.clone());
+        let urls: Vec<&str> = ordered.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(urls, vec!["http://3", "http://1", "http://2"]);
+
+        // Order is stored per channel list
+        let ordered = order_candidates(&conn, 2, &key, candidates);
+        assert_eq!(ordered[0].url, "http://1");
+    }
+
+    #[test]
+    fn test_order_candidates_puts_unknown_sources_last() {
+        let mut conn = create_test_db();
+        let candidates = vec![
+            create_channel("BBC One", "http://new", ""),
+            create_channel("BBC One", "http://1", ""),
+            create_channel("BBC One", "http://2", ""),
+        ];
+        let key = equivalence_key(&candidates[0]);
+        save_source_order(
+            &mut conn,
+            1,
+            &key,
+            &["http://2".to_string(), "http://1".to_string()],
+        )
+        .unwrap();
+
+        let ordered = order_candidates(&conn, 1, &key, candidates);
+        let urls: Vec<&str> = ordered.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(urls, vec!["http://2", "http://1", "http://new"]);
+    }
+
+    #[test]
+    fn test_order_fallbacks_keeps_requested_source_first() {
+        let mut conn = create_test_db();
+        let candidates = vec![
+            create_channel("BBC One", "http://1", ""),
+            create_channel("BBC One", "http://2", ""),
+            create_channel("BBC One", "http://3", ""),
+        ];
+        let key = equivalence_key(&candidates[0]);
+        remember_working_source(&mut conn, 1, &key, &candidates, "http://3").unwrap();
+
+        // The remembered source only leads the fallbacks
+        let ordered = order_fallbacks(&conn, Some(1), &key, "http://2", candidates.clone());
+        let urls: Vec<&str> = ordered.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(urls, vec!["http://2", "http://3", "http://1"]);
+
+        let ordered = order_fallbacks(&conn, None, &key, "http://2", candidates);
+        let urls: Vec<&str> = ordered.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(urls, vec!["http://2", "http://1", "http://3"]);
+    }
+}
@@ -1,13 +1,14 @@
-use crate::m3u_parser::Channel;
+use crate::m3u_parser::{http_options_from_json, http_options_to_json, Channel};
 use crate::state::DbState;
+use rusqlite::Connection;
 use tauri::{AppHandle, Emitter, State};
 
 #[tauri::command]
 pub fn add_favorite(state: State<DbState>, channel: Channel) -> Result<(), String> {
     let db = state.db.lock().unwrap();
     db.execute(
-        "INSERT INTO favorites (name, logo, url, group_title, tvg_id, resolution, extra_info) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-        &[&channel.name, &channel.logo, &channel.url, &channel.group_title, &channel.tvg_id, &channel.resolution, &channel.extra_info],
+        "INSERT INTO favorites (name, logo, url, group_title, tvg_id, resolution, extra_info, http_options) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        rusqlite::params![channel.name, channel.logo, channel.url, channel.group_title, channel.tvg_id, channel.resolution, channel.extra_info, http_options_to_json(&channel.http_options)],
     ).map_err(|e| e.to_string())?;
     Ok(())
 }
@@ -20,33 +21,29 @@ pub fn remove_favorite(state: State<DbState>, name: String) -> Result<(), String
     Ok(())
 }
 
-#[tauri::command]
-pub fn get_favorites(state: State<DbState>) -> Result<Vec<Channel>, String> {
-    let db = state.db.lock().unwrap();
-    let mut stmt = db
-        .prepare(
-            "SELECT name, logo, url, group_title, tvg_id, resolution, extra_info FROM favorites",
-        )
-        .map_err(|e| e.to_string())?;
-    let channel_iter = stmt
-        .query_map([], |row| {
-            Ok(Channel {
-                name: row.get(0)?,
-                logo: row.get(1)?,
-                url: row.get(2)?,
-                group_title: row.get(3)?,
-                tvg_id: row.get(4)?,
-                resolution: row.get(5)?,
-                extra_info: row.get(6)?,
-            })
+pub fn load_favorites(conn: &Connection) -> rusqlite::Result<Vec<Channel>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, logo, url, group_title, tvg_id, resolution, extra_info, http_options FROM favorites",
+    )?;
+    let channel_iter = stmt.query_map([], |row| {
+        Ok(Channel {
+            name: row.get(0)?,
+            logo: row.get(1)?,
+            url: row.get(2)?,
+            group_title: row.get(3)?,
+            tvg_id: row.get(4)?,
+            resolution: row.get(5)?,
+            extra_info: row.get(6)?,
+            http_options: http_options_from_json(row.get(7)?),
+            source_list_id: None,
         })
-        .map_err(|e| e.to_string())?;
+    })?;
+    channel_iter.collect()
+}
 
-    let mut channels = Vec::new();
-    for channel in channel_iter {
-        channels.push(channel.map_err(|e| e.to_string())?);
-    }
-    Ok(channels)
+#[tauri::command]
+pub fn get_favorites(state: State<DbState>) -> Result<Vec<Channel>, String> {
+    load_favorites(&state.reader()).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -93,8 +90,7 @@ pub async fn get_favorites_async(
     // Emit start
     let _ = app_handle.emit("favorites_loading", "Loading favorites...");
 
-    // Use blocking version for now
-    let result = get_favorites(state);
+    let result = state.read(load_favorites).await;
 
     // Emit completion
     let _ = app_handle.emit("favorites_loading", "Favorites loaded!");
@@ -10,7 +10,7 @@ pub fn save_filter(state: State<DbState>, channel_list_id: i64, slot_number: i32
 
 #[tauri::command]
 pub fn get_saved_filters(state: State<DbState>, channel_list_id: i64) -> Result<Vec<database::SavedFilter>, String> {
-    let db = state.db.lock().unwrap();
+    let db = state.reader();
     database::get_saved_filters(&db, channel_list_id).map_err(|e| e.to_string())
 }
 
@@ -0,0 +1,210 @@
+use crate::m3u_parser::Channel;
+use rusqlite::{params_from_iter, Connection, Result as RusqliteResult};
+
+/// Lists at least this large are narrowed through the FTS indexes before fuzzy scoring.
+/// Below it, scoring the whole cached list is faster than the round trip to SQLite.
+pub const FTS_MIN_CHANNELS: usize = 5_000;
+
+// The trigram tokenizer cannot match terms shorter than one trigram
+const TRIGRAM_LENGTH: usize = 3;
+
+pub fn use_fts_backend(channel_count: usize) -> bool {
+    channel_count >= FTS_MIN_CHANNELS
+}
+
+/// MATCH expressions for one query: words long enough for the trigram index
+/// match as substrings, shorter ones as word prefixes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtsQuery {
+    pub trigram: Option<String>,
+    pub prefix: Option<String>,
+}
+
+fn quote_term(term: &str) -> String {
+    format!("\"{}\"", term.replace('"', "\"\""))
+}
+
+pub fn build_fts_query(query: &str) -> Option<FtsQuery> {
+    let mut substrings = Vec::new();
+    let mut prefixes = Vec::new();
+    for word in query.split_whitespace() {
+        if word.chars().count() >= TRIGRAM_LENGTH {
+            substrings.push(quote_term(word));
+        } else if word.chars().any(|c| c.is_alphanumeric()) {
+            prefixes.push(format!("{}*", quote_term(word)));
+        }
+    }
+
+    if substrings.is_empty() && prefixes.is_empty() {
+        return None;
+    }
+    let join = |terms: Vec<String>| (!terms.is_empty()).then(|| terms.join(" AND "));
+    Some(FtsQuery {
+        trigram: join(substrings),
+        prefix: join(prefixes),
+    })
+}
+
+/// Playlist positions and URLs of the stored channels of a list matching every
+/// word of `query`. Returns `None` when the query has nothing the indexes can use.
+pub fn fts_candidates(
+    conn: &Connection,
+    channel_list_id: i64,
+    query: &str,
+) -> RusqliteResult<Option<Vec<(usize, String)>>> {
+    let Some(fts_query) = build_fts_query(query) else {
+        return Ok(None);
+    };
+
+    let mut sql = "SELECT position, url FROM channels WHERE channel_list_id = ?1".to_string();
+    let mut values = vec![channel_list_id.to_string()];
+    if let Some(trigram) = fts_query.trigram {
+        values.push(trigram);
+        sql.push_str(&format!(
+            " AND id IN (SELECT rowid FROM channels_trigram WHERE channels_trigram MATCH ?{})",
+            values.len()
+        ));
+    }
+    if let Some(prefix) = fts_query.prefix {
+        values.push(prefix);
+        sql.push_str(&format!(
+            " AND id IN (SELECT rowid FROM channels_fts WHERE channels_fts MATCH ?{})",
+            values.len()
+        ));
+    }
+    sql.push_str(" ORDER BY position");
+
+    let mut stmt = conn.prepare(&sql)?;
+    let candidates = stmt
+        .query_map(params_from_iter(values), |row| {
+            Ok((row.get::<_, i64>(0)? as usize, row.get(1)?))
+        })?
+        .collect::<RusqliteResult<Vec<_>>>()?;
+    Ok(Some(candidates))
+}
+
+/// Narrows `channels`, the loaded playlist of the list, to the FTS candidates
+/// for `query`. Returns `None` when the indexes cannot answer, or are out of
+/// step with `channels`; the caller then searches the whole list.
+pub fn narrow_by_fts(
+    conn: &Connection,
+    channel_list_id: i64,
+    channels: &[Channel],
+    query: &str,
+) -> RusqliteResult<Option<Vec<Channel>>> {
+    let Some(candidates) = fts_candidates(conn, channel_list_id, query)? else {
+        return Ok(None);
+    };
+
+    let mut narrowed = Vec::with_capacity(candidates.len());
+    for (position, url) in candidates {
+        match channels.get(position) {
+            Some(channel) if channel.url == url => narrowed.push(channel.clone()),
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(narrowed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::populate_channels;
+    use crate::test_support::{self, channel};
+
+    fn create_channel(name: &str, group: &str) -> Channel {
+        let url = format!("http://example.com/{}.m3u8", name.replace(' ', "_"));
+        channel(name, &url, group)
+    }
+
+    fn create_test_db(channels: &[Channel]) -> Connection {
+        let mut conn = test_support::create_test_db();
+        populate_channels(&mut conn, 1, channels).unwrap();
+        conn
+    }
+
+    fn test_channels() -> Vec<Channel> {
+        vec![
+            create_channel("BBC World News", "News"),
+            create_channel("CNN International", "News"),
+            create_channel("Eurosport 2", "Sports"),
+            create_channel("Sky Sports", "Sports"),
+            create_channel("TV8", "Entertainment"),
+        ]
+    }
+
+    fn names(channels: &[Channel]) -> Vec<&str> {
+        channels.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_build_fts_query() {
+        assert_eq!(
+            build_fts_query("bbc w"),
+            Some(FtsQuery {
+                trigram: Some("\"bbc\"".to_string()),
+                prefix: Some("\"w\"*".to_string()),
+            })
+        );
+        assert_eq!(
+            build_fts_query("say \"hi\"").unwrap().trigram,
+            Some("\"say\" AND \"\"\"hi\"\"\"".to_string())
+        );
+        assert_eq!(build_fts_query("  "), None);
+        assert_eq!(build_fts_query("- +"), None);
+    }
+
+    #[test]
+    fn test_substring_and_prefix_matching() {
+        let channels = test_channels();
+        let conn = create_test_db(&channels);
+
+        let narrowed = narrow_by_fts(&conn, 1, &channels, "sport")
+            .unwrap()
+            .unwrap();
+        assert_eq!(names(&narrowed), vec!["Eurosport 2", "Sky Sports"]);
+
+        // Short words match the start of a word, in the name or the group
+        let narrowed = narrow_by_fts(&conn, 1, &channels, "news w")
+            .unwrap()
+            .unwrap();
+        assert_eq!(names(&narrowed), vec!["BBC World News"]);
+
+        let narrowed = narrow_by_fts(&conn, 1, &channels, "tv").unwrap().unwrap();
+        assert_eq!(names(&narrowed), vec!["TV8"]);
+
+        let narrowed = narrow_by_fts(&conn, 1, &channels, "zdf").unwrap().unwrap();
+        assert!(narrowed.is_empty());
+    }
+
+    #[test]
+    fn test_candidates_are_scoped_to_the_list() {
+        let channels = test_channels();
+        let conn = create_test_db(&channels);
+        conn.execute(
+            "INSERT INTO channel_lists (id, name, source) VALUES (2, 'Other List', 'http://example.com')",
+            [],
+        )
+        .unwrap();
+
+        let candidates = fts_candidates(&conn, 2, "sport").unwrap().unwrap();
+        assert!(candidates.is_empty());
+    }
+
+    #[test]
+    fn test_stale_index_is_not_used() {
+        let channels = test_channels();
+        let conn = create_test_db(&channels);
+
+        // A playlist that changed since the index was written must be searched in full
+        let mut reloaded = channels.clone();
+        reloaded.remove(0);
+        assert_eq!(narrow_by_fts(&conn, 1, &reloaded, "sport").unwrap(), None);
+    }
+
+    #[test]
+    fn test_use_fts_backend() {
+        assert!(!use_fts_backend(100));
+        assert!(use_fts_backend(FTS_MIN_CHANNELS));
+    }
+}
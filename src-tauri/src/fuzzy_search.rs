@@ -211,6 +211,8 @@ mod tests {
                 tvg_id: "bbc1".to_string(),
                 resolution: "1080p".to_string(),
                 extra_info: "HD".to_string(),
+                http_options: None,
+                source_list_id: None,
             },
             Channel {
                 name: "CNN International".to_string(),
@@ -220,6 +222,8 @@ mod tests {
                 tvg_id: "cnn1".to_string(),
                 resolution: "720p".to_string(),
                 extra_info: "".to_string(),
+                http_options: None,
+                source_list_id: None,
             },
             Channel {
                 name: "ESPN Sports".to_string(),
@@ -229,6 +233,8 @@ mod tests {
                 tvg_id: "espn1".to_string(),
                 resolution: "1080p".to_string(),
                 extra_info: "Live".to_string(),
+                http_options: None,
+                source_list_id: None,
             },
             Channel {
                 name: "Discovery Channel".to_string(),
@@ -238,6 +244,8 @@ mod tests {
                 tvg_id: "disc1".to_string(),
                 resolution: "720p".to_string(),
                 extra_info: "".to_string(),
+                http_options: None,
+                source_list_id: None,
             },
             Channel {
                 name: "BBC iPlayer".to_string(),
@@ -247,6 +255,8 @@ mod tests {
                 tvg_id: "iplayer1".to_string(),
                 resolution: "1080p".to_string(),
                 extra_info: "On Demand".to_string(),
+                http_options: None,
+                source_list_id: None,
             },
         ]
     }
@@ -553,6 +563,8 @@ mod tests {
             tvg_id: "test1".to_string(),
             resolution: "1080p".to_string(),
             extra_info: "HD".to_string(),
+            http_options: None,
+            source_list_id: None,
         };
         
         let search_match = SearchMatch {
@@ -585,6 +597,8 @@ mod tests {
             tvg_id: "".to_string(),
             resolution: "".to_string(),
             extra_info: "".to_string(),
+            http_options: None,
+            source_list_id: None,
         };
         
         // All words match - should return a result
@@ -620,6 +634,8 @@ mod tests {
                 tvg_id: format!("ch{}", i),
                 resolution: "1080p".to_string(),
                 extra_info: "".to_string(),
+                http_options: None,
+                source_list_id: None,
             });
         }
         
@@ -657,6 +673,8 @@ mod tests {
                     tvg_id: format!("tv{}", i),
                     resolution: if i % 2 == 0 { "1080p" } else { "720p" }.to_string(),
                     extra_info: if i % 3 == 0 { "HD" } else { "" }.to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 });
             }
             
@@ -739,6 +757,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "CNN Sports".to_string(),
@@ -748,6 +768,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "Fox Entertainment".to_string(),
@@ -757,6 +779,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -784,6 +808,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -809,6 +835,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "News Channel".to_string(),
@@ -818,6 +846,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -846,6 +876,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "Movie Channel".to_string(),
@@ -855,6 +887,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -879,6 +913,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -905,6 +941,8 @@ mod tests {
                 tvg_id: "".to_string(),
                 resolution: "".to_string(),
                 extra_info: "".to_string(),
+                http_options: None,
+                source_list_id: None,
             };
             
             let matcher = FuzzyMatcher::new();
@@ -961,6 +999,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "Canal Español".to_string(),
@@ -970,6 +1010,8 @@ mod tests {
                     tvg_id: "".to_string(),
                     resolution: "".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1019,6 +1061,8 @@ mod tests {
                     tvg_id: "1".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "BBC News".to_string(),
@@ -1028,6 +1072,8 @@ mod tests {
                     tvg_id: "2".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "CNN BBC Report".to_string(),
@@ -1037,6 +1083,8 @@ mod tests {
                     tvg_id: "3".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1060,6 +1108,8 @@ mod tests {
                     tvg_id: "1".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1086,6 +1136,8 @@ mod tests {
                     tvg_id: "1".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "CNN International".to_string(),
@@ -1095,6 +1147,8 @@ mod tests {
                     tvg_id: "2".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "Local Weather".to_string(),
@@ -1104,6 +1158,8 @@ mod tests {
                     tvg_id: "3".to_string(),
                     resolution: "720p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1131,6 +1187,8 @@ mod tests {
                     tvg_id: "1".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "BBCNEWS24".to_string(),
@@ -1140,6 +1198,8 @@ mod tests {
                     tvg_id: "2".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1164,6 +1224,8 @@ mod tests {
                     tvg_id: "bbc-news-24-7".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "HD+".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "CNN (International)".to_string(),
@@ -1173,6 +1235,8 @@ mod tests {
                     tvg_id: "cnn-intl".to_string(),
                     resolution: "720p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1219,6 +1283,8 @@ mod tests {
                     tvg_id: "1".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1248,6 +1314,8 @@ mod tests {
                 tvg_id: "1".to_string(),
                 resolution: "1080p".to_string(),
                 extra_info: "".to_string(),
+                http_options: None,
+                source_list_id: None,
             };
             
             let channel2 = Channel {
@@ -1258,6 +1326,8 @@ mod tests {
                 tvg_id: "2".to_string(),
                 resolution: "720p".to_string(),
                 extra_info: "".to_string(),
+                http_options: None,
+                source_list_id: None,
             };
             
             let channels = vec![channel1, channel2];
@@ -1306,6 +1376,8 @@ mod tests {
                     tvg_id: "1".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "BBC News".to_string(),
@@ -1315,6 +1387,8 @@ mod tests {
                     tvg_id: "2".to_string(),
                     resolution: "720p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "Random Channel".to_string(),
@@ -1324,6 +1398,8 @@ mod tests {
                     tvg_id: "3".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1376,6 +1452,8 @@ mod tests {
                     tvg_id: "1".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "CNN العربية".to_string(),
@@ -1385,6 +1463,8 @@ mod tests {
                     tvg_id: "2".to_string(),
                     resolution: "720p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "NHK 日本放送協会".to_string(),
@@ -1394,6 +1474,8 @@ mod tests {
                     tvg_id: "3".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1480,6 +1562,8 @@ mod tests {
                     tvg_id: "1".to_string(),
                     resolution: "1080p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
                 Channel {
                     name: "A Big Broadcasting Company".to_string(),
@@ -1489,6 +1573,8 @@ mod tests {
                     tvg_id: "2".to_string(),
                     resolution: "720p".to_string(),
                     extra_info: "".to_string(),
+                    http_options: None,
+                    source_list_id: None,
                 },
             ];
             
@@ -1513,6 +1599,8 @@ mod tests {
                 tvg_id: "1".to_string(),
                 resolution: "1080p".to_string(),
                 extra_info: "".to_string(),
+                http_options: None,
+                source_list_id: None,
             };
             
             let channels = vec![channel];
@@ -1,10 +1,16 @@
 use tauri::State;
-use crate::state::DbState;
-use crate::database;
+use crate::channels::invalidate_channel_cache;
+use crate::state::{ChannelCacheState, DbState};
+use crate::database::{self, resolve_channel_list_id};
+use crate::m3u_parser::Channel;
+use regex::Regex;
+use rusqlite::{Connection, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 #[tauri::command]
 pub fn get_enabled_groups(state: State<DbState>, channel_list_id: i64) -> Result<Vec<String>, String> {
-    let db = state.db.lock().unwrap();
+    let db = state.reader();
     database::get_enabled_groups(&db, channel_list_id).map_err(|e| e.to_string())
 }
 
@@ -30,4 +36,581 @@ pub fn enable_all_groups(state: State<DbState>, channel_list_id: i64, groups: Ve
 pub fn disable_all_groups(state: State<DbState>, channel_list_id: i64, groups: Vec<String>) -> Result<(), String> {
     let mut db = state.db.lock().unwrap();
     database::disable_all_groups(&mut db, channel_list_id, groups).map_err(|e| e.to_string())
-} 
\ No newline at end of file
+} 
+
+/// Moves channels whose name or source group matches `pattern` into `target_group`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupRule {
+    pub id: i64,
+    pub channel_list_id: i64,
+    pub pattern: String,
+    /// "name" or "group"
+    pub match_field: String,
+    pub target_group: String,
+    pub position: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupAlias {
+    pub source_group: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GroupLayout {
+    /// Shown groups in their saved order; others follow alphabetically
+    pub order: Vec<String>,
+    pub hidden: Vec<String>,
+    pub aliases: Vec<GroupAlias>,
+    pub rules: Vec<GroupRule>,
+}
+
+struct CompiledRule {
+    regex: Regex,
+    match_name: bool,
+    target_group: String,
+}
+
+/// The group layout of one list, ready to apply to its channels.
+#[derive(Default)]
+pub struct GroupMapping {
+    rules: Vec<CompiledRule>,
+    aliases: HashMap<String, String>,
+    hidden: HashSet<String>,
+}
+
+impl GroupMapping {
+    pub fn load(conn: &Connection, channel_list_id: i64) -> RusqliteResult<Self> {
+        let layout = load_group_layout(conn, channel_list_id)?;
+        let rules = layout
+            .rules
+            .into_iter()
+            // Patterns are checked when saved; skip one that no longer compiles
+            .filter_map(|rule| {
+                Some(CompiledRule {
+                    regex: Regex::new(&rule.pattern).ok()?,
+                    match_name: rule.match_field == "name",
+                    target_group: rule.target_group,
+                })
+            })
+            .collect();
+        Ok(Self {
+            rules,
+            aliases: layout
+                .aliases
+                .into_iter()
+                .map(|alias| (alias.source_group, alias.display_name))
+                .collect(),
+            hidden: layout.hidden.into_iter().collect(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.aliases.is_empty() && self.hidden.is_empty()
+    }
+
+    /// The group a channel is shown in: the first matching rule's target or its
+    /// own group, then renamed by an alias.
+    pub fn group_for(&self, channel: &Channel) -> String {
+        let group = self
+            .rules
+            .iter()
+            .find(|rule| {
+                let field = if rule.match_name {
+                    &channel.name
+                } else {
+                    &channel.group_title
+                };
+                rule.regex.is_match(field)
+            })
+            .map_or(channel.group_title.as_str(), |rule| {
+                rule.target_group.as_str()
+            });
+        self.aliases
+            .get(group)
+            .map_or(group, String::as_str)
+            .to_string()
+    }
+
+    /// Regroups the channels and drops those in hidden groups.
+    pub fn apply(&self, channels: &mut Vec<Channel>) {
+        if self.is_empty() {
+            return;
+        }
+        channels.retain_mut(|channel| {
+            channel.group_title = self.group_for(channel);
+            !self.hidden.contains(&channel.group_title)
+        });
+    }
+}
+
+pub fn load_group_layout(conn: &Connection, channel_list_id: i64) -> RusqliteResult<GroupLayout> {
+    let mut layout = GroupLayout::default();
+
+    let mut stmt = conn.prepare(
+        "SELECT group_name, position, hidden FROM group_layout WHERE channel_list_id = ?1
+         ORDER BY position IS NULL, position, group_name",
+    )?;
+    let rows = stmt.query_map([channel_list_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Option<i64>>(1)?,
+            row.get::<_, bool>(2)?,
+        ))
+    })?;
+    for row in rows {
+        let (group_name, position, hidden) = row?;
+        if hidden {
+            layout.hidden.push(group_name.clone());
+        }
+        if position.is_some() {
+            layout.order.push(group_name);
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT source_group, display_name FROM group_aliases WHERE channel_list_id = ?1 ORDER BY source_group",
+    )?;
+    layout.aliases = stmt
+        .query_map([channel_list_id], |row| {
+            Ok(GroupAlias {
+                source_group: row.get(0)?,
+                display_name: row.get(1)?,
+            })
+        })?
+        .collect::<RusqliteResult<_>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, channel_list_id, pattern, match_field, target_group, position FROM group_rules
+         WHERE channel_list_id = ?1 ORDER BY position, id",
+    )?;
+    layout.rules = stmt
+        .query_map([channel_list_id], |row| {
+            Ok(GroupRule {
+                id: row.get(0)?,
+                channel_list_id: row.get(1)?,
+                pattern: row.get(2)?,
+                match_field: row.get(3)?,
+                target_group: row.get(4)?,
+                position: row.get(5)?,
+            })
+        })?
+        .collect::<RusqliteResult<_>>()?;
+
+    Ok(layout)
+}
+
+/// Applies the group layout of list `id` (`None` for the default list) to its
+/// loaded channels. Without a layout, or on error, they stay as they are.
+pub fn apply_group_layout(conn: &Connection, id: Option<i32>, channels: &mut Vec<Channel>) {
+    let Some(channel_list_id) = resolve_channel_list_id(conn, id) else {
+        return;
+    };
+    match GroupMapping::load(conn, channel_list_id) {
+        Ok(mapping) => mapping.apply(channels),
+        Err(e) => eprintln!("Warning: Failed to load group layout: {}", e),
+    }
+}
+
+/// Whether the list shows channels under groups other than their own.
+pub fn has_group_mapping(conn: &Connection, channel_list_id: i64) -> bool {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM group_aliases WHERE channel_list_id = ?1)
+             OR EXISTS (SELECT 1 FROM group_rules WHERE channel_list_id = ?1)",
+        [channel_list_id],
+        |row| row.get(0),
+    )
+    .unwrap_or(false)
+}
+
+/// Distinct groups in the saved order of the list, the rest alphabetically.
+pub fn order_groups<I>(conn: &Connection, channel_list_id: Option<i64>, groups: I) -> Vec<String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let groups: BTreeSet<String> = groups.into_iter().collect();
+    let order = channel_list_id
+        .and_then(|id| load_group_layout(conn, id).ok())
+        .map(|layout| layout.order)
+        .unwrap_or_default();
+    let positions: HashMap<&str, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(position, group)| (group.as_str(), position))
+        .collect();
+
+    let mut ordered: Vec<String> = groups.into_iter().collect();
+    // Stable, so groups without a position keep their alphabetical order
+    ordered.sort_by_key(|group| positions.get(group.as_str()).copied().unwrap_or(usize::MAX));
+    ordered
+}
+
+pub fn save_group_order(
+    conn: &mut Connection,
+    channel_list_id: i64,
+    groups: &[String],
+) -> RusqliteResult<()> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "UPDATE group_layout SET position = NULL WHERE channel_list_id = ?1",
+        [channel_list_id],
+    )?;
+    for (position, group) in groups.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO group_layout (channel_list_id, group_name, position) VALUES (?1, ?2, ?3)
+             ON CONFLICT(channel_list_id, group_name) DO UPDATE SET position = excluded.position",
+            rusqlite::params![channel_list_id, group, position as i64],
+        )?;
+    }
+    tx.execute(
+        "DELETE FROM group_layout WHERE channel_list_id = ?1 AND position IS NULL AND hidden = 0",
+        [channel_list_id],
+    )?;
+    tx.commit()
+}
+
+pub fn save_group_hidden(
+    conn: &Connection,
+    channel_list_id: i64,
+    group_name: &str,
+    hidden: bool,
+) -> RusqliteResult<()> {
+    conn.execute(
+        "INSERT INTO group_layout (channel_list_id, group_name, hidden) VALUES (?1, ?2, ?3)
+         ON CONFLICT(channel_list_id, group_name) DO UPDATE SET hidden = excluded.hidden",
+        rusqlite::params![channel_list_id, group_name, hidden],
+    )?;
+    Ok(())
+}
+
+/// Shows the groups in `sources` under `target`, following aliases and rules
+/// that pointed at them. Renaming is merging a single group.
+pub fn merge_groups_into(
+    conn: &mut Connection,
+    channel_list_id: i64,
+    sources: &[String],
+    target: &str,
+) -> RusqliteResult<()> {
+    let tx = conn.transaction()?;
+    for source in sources.iter().filter(|source| source.as_str() != target) {
+        let params = rusqlite::params![channel_list_id, source, target];
+        tx.execute(
+            "UPDATE group_aliases SET display_name = ?3 WHERE channel_list_id = ?1 AND display_name = ?2",
+            params,
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO group_aliases (channel_list_id, source_group, display_name) VALUES (?1, ?2, ?3)",
+            params,
+        )?;
+        tx.execute(
+            "UPDATE group_rules SET target_group = ?3 WHERE channel_list_id = ?1 AND target_group = ?2",
+            params,
+        )?;
+        // The target keeps its own place and visibility
+        tx.execute(
+            "DELETE FROM group_layout WHERE channel_list_id = ?1 AND group_name = ?2",
+            [&channel_list_id as &dyn rusqlite::ToSql, source],
+        )?;
+        // A selection row the target already has wins over the source's
+        tx.execute(
+            "UPDATE OR IGNORE group_selections SET group_name = ?3 WHERE channel_list_id = ?1 AND group_name = ?2",
+            params,
+        )?;
+        tx.execute(
+            "DELETE FROM group_selections WHERE channel_list_id = ?1 AND group_name = ?2",
+            [&channel_list_id as &dyn rusqlite::ToSql, source],
+        )?;
+    }
+    // A group merged into itself shows under its own name again
+    tx.execute(
+        "DELETE FROM group_aliases WHERE channel_list_id = ?1 AND source_group = display_name",
+        [channel_list_id],
+    )?;
+    tx.commit()
+}
+
+pub fn add_rule(
+    conn: &Connection,
+    channel_list_id: i64,
+    pattern: &str,
+    match_field: &str,
+    target_group: &str,
+) -> Result<GroupRule, String> {
+    Regex::new(pattern).map_err(|e| format!("Invalid pattern: {}", e))?;
+    if !matches!(match_field, "name" | "group") {
+        return Err("Rules match on \"name\" or \"group\"".to_string());
+    }
+    if target_group.trim().is_empty() {
+        return Err("Target group cannot be empty".to_string());
+    }
+    conn.execute(
+        "INSERT INTO group_rules (channel_list_id, pattern, match_field, target_group, position)
+         VALUES (?1, ?2, ?3, ?4, (SELECT COALESCE(MAX(position), -1) + 1 FROM group_rules WHERE channel_list_id = ?1))",
+        rusqlite::params![channel_list_id, pattern, match_field, target_group.trim()],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+    conn.query_row(
+        "SELECT id, channel_list_id, pattern, match_field, target_group, position FROM group_rules WHERE id = ?1",
+        [id],
+        |row| {
+            Ok(GroupRule {
+                id: row.get(0)?,
+                channel_list_id: row.get(1)?,
+                pattern: row.get(2)?,
+                match_field: row.get(3)?,
+                target_group: row.get(4)?,
+                position: row.get(5)?,
+            })
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_group_layout(
+    state: State<DbState>,
+    channel_list_id: i64,
+) -> Result<GroupLayout, String> {
+    load_group_layout(&state.reader(), channel_list_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_group_order(
+    state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    channel_list_id: i64,
+    groups: Vec<String>,
+) -> Result<(), String> {
+    let mut db = state.db.lock().unwrap();
+    save_group_order(&mut db, channel_list_id, &groups).map_err(|e| e.to_string())?;
+    drop(db);
+    invalidate_channel_cache(cache_state)
+}
+
+#[tauri::command]
+pub fn set_group_hidden(
+    state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    channel_list_id: i64,
+    group_name: String,
+    hidden: bool,
+) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    save_group_hidden(&db, channel_list_id, &group_name, hidden).map_err(|e| e.to_string())?;
+    drop(db);
+    invalidate_channel_cache(cache_state)
+}
+
+#[tauri::command]
+pub fn rename_group(
+    state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    channel_list_id: i64,
+    group_name: String,
+    new_name: String,
+) -> Result<(), String> {
+    merge_groups(
+        state,
+        cache_state,
+        channel_list_id,
+        vec![group_name],
+        new_name,
+    )
+}
+
+#[tauri::command]
+pub fn merge_groups(
+    state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    channel_list_id: i64,
+    groups: Vec<String>,
+    target: String,
+) -> Result<(), String> {
+    let target = target.trim();
+    if target.is_empty() {
+        return Err("Group name cannot be empty".to_string());
+    }
+    let mut db = state.db.lock().unwrap();
+    merge_groups_into(&mut db, channel_list_id, &groups, target).map_err(|e| e.to_string())?;
+    drop(db);
+    invalidate_channel_cache(cache_state)
+}
+
+/// Shows a source group under its own name again.
+#[tauri::command]
+pub fn remove_group_alias(
+    state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    channel_list_id: i64,
+    source_group: String,
+) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.execute(
+        "DELETE FROM group_aliases WHERE channel_list_id = ?1 AND source_group = ?2",
+        rusqlite::params![channel_list_id, source_group],
+    )
+    .map_err(|e| e.to_string())?;
+    drop(db);
+    invalidate_channel_cache(cache_state)
+}
+
+#[tauri::command]
+pub fn add_group_rule(
+    state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    channel_list_id: i64,
+    pattern: String,
+    match_field: String,
+    target_group: String,
+) -> Result<GroupRule, String> {
+    let db = state.db.lock().unwrap();
+    let rule = add_rule(&db, channel_list_id, &pattern, &match_field, &target_group)?;
+    drop(db);
+    invalidate_channel_cache(cache_state)?;
+    Ok(rule)
+}
+
+#[tauri::command]
+pub fn delete_group_rule(
+    state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    id: i64,
+) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.execute("DELETE FROM group_rules WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    drop(db);
+    invalidate_channel_cache(cache_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, create_test_db};
+
+    fn channel(name: &str, group: &str) -> Channel {
+        test_support::channel(name, &format!("http://example.com/{}.m3u8", name), group)
+    }
+
+    fn shown(conn: &Connection, channels: &[Channel]) -> Vec<(String, String)> {
+        let mut channels = channels.to_vec();
+        apply_group_layout(conn, None, &mut channels);
+        channels
+            .into_iter()
+            .map(|c| (c.name, c.group_title))
+            .collect()
+    }
+
+    fn pairs(values: &[(&str, &str)]) -> Vec<(String, String)> {
+        values
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_groups_follow_saved_order() {
+        let mut conn = create_test_db();
+        let groups = || ["News", "Sports", "Kids", "News"].map(String::from);
+        assert_eq!(
+            order_groups(&conn, Some(1), groups()),
+            vec!["Kids", "News", "Sports"]
+        );
+
+        save_group_order(&mut conn, 1, &["Sports".to_string(), "News".to_string()]).unwrap();
+        assert_eq!(
+            order_groups(&conn, Some(1), groups()),
+            vec!["Sports", "News", "Kids"]
+        );
+        assert_eq!(
+            load_group_layout(&conn, 1).unwrap().order,
+            vec!["Sports", "News"]
+        );
+    }
+
+    #[test]
+    fn test_merge_rename_and_hide() {
+        let mut conn = create_test_db();
+        let channels = [
+            channel("a", "UK | News"),
+            channel("b", "US | News"),
+            channel("c", "Sports"),
+            channel("d", "XXX"),
+        ];
+
+        merge_groups_into(
+            &mut conn,
+            1,
+            &["UK | News".into(), "US | News".into()],
+            "News",
+        )
+        .unwrap();
+        merge_groups_into(&mut conn, 1, &["News".into()], "World News").unwrap();
+        save_group_hidden(&conn, 1, "XXX", true).unwrap();
+
+        assert_eq!(
+            shown(&conn, &channels),
+            pairs(&[("a", "World News"), ("b", "World News"), ("c", "Sports")])
+        );
+        let aliases = load_group_layout(&conn, 1).unwrap().aliases;
+        assert!(aliases.iter().all(|a| a.display_name == "World News"));
+    }
+
+    #[test]
+    fn test_merge_keeps_target_selection() {
+        let mut conn = create_test_db();
+        conn.execute_batch(
+            "INSERT INTO group_selections (channel_list_id, group_name, is_enabled) VALUES
+                (1, 'News', 1), (1, 'UK | News', 0), (1, 'US News', 0);",
+        )
+        .unwrap();
+
+        merge_groups_into(&mut conn, 1, &["UK | News".into()], "News").unwrap();
+        merge_groups_into(&mut conn, 1, &["US News".into()], "World").unwrap();
+
+        let mut stmt = conn
+            .prepare("SELECT group_name, is_enabled FROM group_selections ORDER BY group_name")
+            .unwrap();
+        let selections: Vec<(String, bool)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            selections,
+            vec![("News".to_string(), true), ("World".to_string(), false)]
+        );
+    }
+
+    #[test]
+    fn test_rules_move_channels() {
+        let conn = create_test_db();
+        let channels = [
+            channel("BBC One HD", "Entertainment"),
+            channel("ESPN", "US Sports"),
+            channel("Cartoons", "Kids"),
+        ];
+
+        add_rule(&conn, 1, r"(?i)\bHD$", "name", "HD").unwrap();
+        add_rule(&conn, 1, "Sports", "group", "Sport").unwrap();
+        assert!(add_rule(&conn, 1, "(", "name", "Broken").is_err());
+        assert!(add_rule(&conn, 1, "x", "url", "Broken").is_err());
+
+        assert_eq!(
+            shown(&conn, &channels),
+            pairs(&[
+                ("BBC One HD", "HD"),
+                ("ESPN", "Sport"),
+                ("Cartoons", "Kids")
+            ])
+        );
+        assert!(has_group_mapping(&conn, 1));
+
+        // Renaming a rule's target group carries the rule along
+        let mut conn = conn;
+        merge_groups_into(&mut conn, 1, &["Sport".into()], "Sports").unwrap();
+        let rules = load_group_layout(&conn, 1).unwrap().rules;
+        assert_eq!(rules[1].target_group, "Sports");
+    }
+}
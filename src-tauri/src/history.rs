@@ -1,30 +1,36 @@
-use crate::m3u_parser::Channel;
+use crate::m3u_parser::{http_options_from_json, http_options_to_json, Channel};
 use crate::state::DbState;
+use rusqlite::Connection;
 use tauri::{AppHandle, Emitter, State};
 
-#[tauri::command]
-pub fn get_history(state: State<DbState>) -> Result<Vec<Channel>, String> {
-    let db = state.db.lock().unwrap();
-    let mut stmt = db.prepare("SELECT name, logo, url, group_title, tvg_id, resolution, extra_info FROM history ORDER BY timestamp DESC LIMIT 20").map_err(|e| e.to_string())?;
-    let channel_iter = stmt
-        .query_map([], |row| {
-            Ok(Channel {
-                name: row.get(0)?,
-                logo: row.get(1)?,
-                url: row.get(2)?,
-                group_title: row.get(3)?,
-                tvg_id: row.get(4)?,
-                resolution: row.get(5)?,
-                extra_info: row.get(6)?,
-            })
+pub fn add_to_history(conn: &Connection, channel: &Channel) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT OR REPLACE INTO history (name, logo, url, group_title, tvg_id, resolution, extra_info, http_options, timestamp) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, CURRENT_TIMESTAMP)",
+        rusqlite::params![channel.name, channel.logo, channel.url, channel.group_title, channel.tvg_id, channel.resolution, channel.extra_info, http_options_to_json(&channel.http_options)],
+    )
+}
+
+pub fn load_history(conn: &Connection) -> rusqlite::Result<Vec<Channel>> {
+    let mut stmt = conn.prepare("SELECT name, logo, url, group_title, tvg_id, resolution, extra_info, http_options FROM history ORDER BY timestamp DESC LIMIT 20")?;
+    let channel_iter = stmt.query_map([], |row| {
+        Ok(Channel {
+            name: row.get(0)?,
+            logo: row.get(1)?,
+            url: row.get(2)?,
+            group_title: row.get(3)?,
+            tvg_id: row.get(4)?,
+            resolution: row.get(5)?,
+            extra_info: row.get(6)?,
+            http_options: http_options_from_json(row.get(7)?),
+            source_list_id: None,
         })
-        .map_err(|e| e.to_string())?;
+    })?;
+    channel_iter.collect()
+}
 
-    let mut channels = Vec::new();
-    for channel in channel_iter {
-        channels.push(channel.map_err(|e| e.to_string())?);
-    }
-    Ok(channels)
+#[tauri::command]
+pub fn get_history(state: State<DbState>) -> Result<Vec<Channel>, String> {
+    load_history(&state.reader()).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -35,8 +41,7 @@ pub async fn get_history_async(
     // Emit start
     let _ = app_handle.emit("history_loading", "Loading history...");
 
-    // Use blocking version for now
-    let result = get_history(state);
+    let result = state.read(load_history).await;
 
     // Emit completion
     let _ = app_handle.emit("history_loading", "History loaded!");
@@ -1,9 +1,9 @@
+use crate::http_client::{proxied_blocking_builder, proxied_builder, ProxiedClient};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tauri::Manager;
 use tokio::fs as async_fs;
 use tokio::sync::{Mutex, Semaphore};
 
@@ -18,12 +18,13 @@ mod tests {
         let cache_dir = temp_dir.join("test_cache");
         let _ = fs::create_dir_all(&cache_dir);
         
-        let client = reqwest::Client::new();
+        let (client, blocking_client) = image_clients();
         let cache = ImageCache {
             cache_dir: cache_dir.clone(),
             download_queue: Arc::new(Mutex::new(HashMap::new())),
             download_semaphore: Arc::new(Semaphore::new(5)),
             client,
+            blocking_client,
         };
         
         // Test with different URLs
@@ -52,12 +53,13 @@ mod tests {
         let cache_dir = temp_dir.join("test_cache");
         let _ = fs::create_dir_all(&cache_dir);
         
-        let client = reqwest::Client::new();
+        let (client, blocking_client) = image_clients();
         let cache = ImageCache {
             cache_dir: cache_dir.clone(),
             download_queue: Arc::new(Mutex::new(HashMap::new())),
             download_semaphore: Arc::new(Semaphore::new(5)),
             client,
+            blocking_client,
         };
         
         // Test supported extensions
@@ -83,12 +85,13 @@ mod tests {
         let cache_dir = temp_dir.join("test_cache");
         let _ = fs::create_dir_all(&cache_dir);
         
-        let client = reqwest::Client::new();
+        let (client, blocking_client) = image_clients();
         let cache = ImageCache {
             cache_dir: cache_dir.clone(),
             download_queue: Arc::new(Mutex::new(HashMap::new())),
             download_semaphore: Arc::new(Semaphore::new(5)),
             client,
+            blocking_client,
         };
         
         let url = "http://example.com/image.jpg";
@@ -98,11 +101,13 @@ mod tests {
         assert_eq!(filename1, filename2);
         
         // Should be deterministic across different cache instances
+        let (client, blocking_client) = image_clients();
         let cache2 = ImageCache {
             cache_dir: cache_dir.clone(),
             download_queue: Arc::new(Mutex::new(HashMap::new())),
             download_semaphore: Arc::new(Semaphore::new(5)),
-            client: reqwest::Client::new(),
+            client,
+            blocking_client,
         };
         
         let filename3 = cache2.url_to_filename(url);
@@ -115,12 +120,13 @@ mod tests {
         let cache_dir = temp_dir.join("test_cache_existing");
         let _ = fs::create_dir_all(&cache_dir);
         
-        let client = reqwest::Client::new();
+        let (client, blocking_client) = image_clients();
         let cache = ImageCache {
             cache_dir: cache_dir.clone(),
             download_queue: Arc::new(Mutex::new(HashMap::new())),
             download_semaphore: Arc::new(Semaphore::new(5)),
             client,
+            blocking_client,
         };
         
         let url = "http://example.com/test.jpg";
@@ -196,12 +202,13 @@ mod tests {
         let cache_dir = temp_dir.join("test_cache");
         let _ = fs::create_dir_all(&cache_dir);
         
-        let client = reqwest::Client::new();
+        let (client, blocking_client) = image_clients();
         let cache = ImageCache {
             cache_dir: cache_dir.clone(),
             download_queue: Arc::new(Mutex::new(HashMap::new())),
             download_semaphore: Arc::new(Semaphore::new(5)),
             client,
+            blocking_client,
         };
         
         // Test URLs with special characters
@@ -238,12 +245,13 @@ mod tests {
             let cache_dir = temp_dir.join("test_cache_nonexistent");
             let _ = fs::create_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // This will fail since we can't actually download in tests
@@ -257,12 +265,13 @@ mod tests {
             let cache_dir = temp_dir.join("test_cache");
             let _ = fs::create_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // Test with malformed URLs
@@ -298,12 +307,13 @@ mod tests {
             let cache_dir = temp_dir.join("test_cache");
             let _ = fs::create_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // Create a very long URL
@@ -324,12 +334,13 @@ mod tests {
             let cache_dir = temp_dir.join("test_cache");
             let _ = fs::create_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // Test with Unicode characters in URLs
@@ -362,12 +373,13 @@ mod tests {
             // Ensure directory doesn't exist
             let _ = fs::remove_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // Should handle nonexistent directory gracefully
@@ -383,12 +395,13 @@ mod tests {
             // Ensure directory doesn't exist
             let _ = fs::remove_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // Should return 0 for nonexistent directory
@@ -403,12 +416,13 @@ mod tests {
             let cache_dir = temp_dir.join("test_cache_sizes");
             let _ = fs::create_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // Create files of different sizes
@@ -433,12 +447,13 @@ mod tests {
             let cache_dir = temp_dir.join("test_cache");
             let _ = fs::create_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // Generate many similar URLs to test hash collision resistance
@@ -462,12 +477,13 @@ mod tests {
             let cache_dir = temp_dir.join("test cache with spaces & symbols!");
             let _ = fs::create_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             let url = "http://example.com/test.jpg";
@@ -517,12 +533,13 @@ mod tests {
             let cache_dir = temp_dir.join("test_cache");
             let _ = fs::create_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // Test edge cases
@@ -555,12 +572,13 @@ mod tests {
             let cache_dir = temp_dir.join("test_cache");
             let _ = fs::create_dir_all(&cache_dir);
             
-            let client = reqwest::Client::new();
+            let (client, blocking_client) = image_clients();
             let cache = ImageCache {
                 cache_dir: cache_dir.clone(),
                 download_queue: Arc::new(Mutex::new(HashMap::new())),
                 download_semaphore: Arc::new(Semaphore::new(5)),
                 client,
+                blocking_client,
             };
             
             // Test various extension edge cases
@@ -601,28 +619,45 @@ pub struct ImageCache {
     // Async download management
     download_queue: Arc<Mutex<HashMap<String, DownloadStatus>>>,
     download_semaphore: Arc<Semaphore>, // Limit concurrent downloads
-    client: reqwest::Client,
+    client: ProxiedClient<reqwest::Client>,
+    blocking_client: ProxiedClient<reqwest::blocking::Client>,
 }
 
-impl ImageCache {
-    pub fn new(app_handle: &tauri::AppHandle) -> Result<Self, Box<dyn std::error::Error>> {
-        let app_data_dir = app_handle.path().app_data_dir()?;
-        let cache_dir = app_data_dir.join("image_cache");
+// HTTP clients with reasonable timeouts, rebuilt when the proxy settings change
+fn image_clients() -> (
+    ProxiedClient<reqwest::Client>,
+    ProxiedClient<reqwest::blocking::Client>,
+) {
+    let client = ProxiedClient::new(|settings| {
+        proxied_builder(settings)?
+            .timeout(std::time::Duration::from_secs(30))
+            .user_agent("tollo/1.0")
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))
+    });
+    let blocking_client = ProxiedClient::new(|settings| {
+        proxied_blocking_builder(settings)?
+            .timeout(std::time::Duration::from_secs(30))
+            .user_agent("tollo/1.0")
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))
+    });
+    (client, blocking_client)
+}
 
+impl ImageCache {
+    pub fn new(cache_dir: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
         // Create cache directory if it doesn't exist
         fs::create_dir_all(&cache_dir)?;
 
-        // Create HTTP client with reasonable timeouts
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .user_agent("tollo/1.0")
-            .build()?;
+        let (client, blocking_client) = image_clients();
 
         Ok(ImageCache {
             cache_dir,
             download_queue: Arc::new(Mutex::new(HashMap::new())),
             download_semaphore: Arc::new(Semaphore::new(5)), // Max 5 concurrent downloads
             client,
+            blocking_client,
         })
     }
 
@@ -670,7 +705,7 @@ impl ImageCache {
         file_path: &PathBuf,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Download the image
-        let response = reqwest::blocking::get(url)?;
+        let response = self.blocking_client.current()?.get(url).send()?;
 
         // Check if the response is successful
         if !response.status().is_success() {
@@ -793,7 +828,7 @@ impl ImageCache {
         let _permit = self.download_semaphore.acquire().await?;
 
         // Download the image
-        let response = self.client.get(url).send().await?;
+        let response = self.client.current()?.get(url).send().await?;
 
         // Check if the response is successful
         if !response.status().is_success() {
@@ -1,6 +1,6 @@
 use crate::channels::*;
 use crate::favorites::*;
-use crate::m3u_parser::Channel;
+use crate::m3u_parser::{Channel, StreamHttpOptions};
 use crate::settings::*;
 use crate::state::{ChannelCacheState, DbState};
 use rusqlite::Connection;
@@ -19,7 +19,8 @@ fn create_test_db() -> Connection {
             group_title TEXT NOT NULL,
             tvg_id TEXT NOT NULL,
             resolution TEXT NOT NULL,
-            extra_info TEXT NOT NULL
+            extra_info TEXT NOT NULL,
+            http_options TEXT
         )",
         [],
     ).unwrap();
@@ -34,24 +35,13 @@ fn create_test_db() -> Connection {
             tvg_id TEXT NOT NULL,
             resolution TEXT NOT NULL,
             extra_info TEXT NOT NULL,
+            http_options TEXT,
             timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
         )",
         [],
     ).unwrap();
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS channels (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            logo TEXT NOT NULL,
-            url TEXT NOT NULL,
-            group_title TEXT NOT NULL,
-            tvg_id TEXT NOT NULL,
-            resolution TEXT NOT NULL,
-            extra_info TEXT NOT NULL
-        )",
-        [],
-    ).unwrap();
+    conn.execute_batch(crate::migrations::CHANNELS_SCHEMA).unwrap();
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS settings (
@@ -122,6 +112,8 @@ fn create_test_channel() -> Channel {
         tvg_id: "test123".to_string(),
         resolution: "1080p".to_string(),
         extra_info: "Test extra info".to_string(),
+        http_options: None,
+        source_list_id: None,
     }
 }
 
@@ -144,9 +136,7 @@ impl<T> From<T> for MockState<T> {
 // Settings Command Tests
 #[test]
 fn test_get_player_command() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     let result = get_player_command(unsafe { std::mem::transmute(&state) });
@@ -156,9 +146,7 @@ fn test_get_player_command() {
 
 #[test]
 fn test_set_player_command() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     let result = set_player_command(unsafe { std::mem::transmute(&state) }, "vlc".to_string());
@@ -172,9 +160,7 @@ fn test_set_player_command() {
 
 #[test]
 fn test_get_cache_duration() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     let result = get_cache_duration(unsafe { std::mem::transmute(&state) });
@@ -184,9 +170,7 @@ fn test_get_cache_duration() {
 
 #[test]
 fn test_set_cache_duration() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     let result = set_cache_duration(unsafe { std::mem::transmute(&state) }, 48);
@@ -200,9 +184,7 @@ fn test_set_cache_duration() {
 
 #[test]
 fn test_get_enable_preview() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     let result = get_enable_preview(unsafe { std::mem::transmute(&state) });
@@ -212,9 +194,7 @@ fn test_get_enable_preview() {
 
 #[test]
 fn test_set_enable_preview() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     let result = set_enable_preview(unsafe { std::mem::transmute(&state) }, false);
@@ -229,9 +209,7 @@ fn test_set_enable_preview() {
 // Favorites Command Tests
 #[test]
 fn test_add_favorite() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     let channel = create_test_channel();
     
@@ -244,11 +222,27 @@ fn test_add_favorite() {
     assert_eq!(favorites.unwrap().len(), 1);
 }
 
+#[test]
+fn test_favorite_keeps_http_options() {
+    let db_state = DbState::new(create_test_db());
+    let state = MockState::from(db_state);
+    let mut channel = create_test_channel();
+    channel.http_options = Some(StreamHttpOptions {
+        user_agent: Some("TestAgent/1.0".to_string()),
+        referrer: Some("http://example.com/".to_string()),
+        headers: vec![("X-Token".to_string(), "abc".to_string())],
+    });
+
+    let result = add_favorite(unsafe { std::mem::transmute(&state) }, channel.clone());
+    assert!(result.is_ok());
+
+    let favorites = get_favorites(unsafe { std::mem::transmute(&state) }).unwrap();
+    assert_eq!(favorites[0].http_options, channel.http_options);
+}
+
 #[test]
 fn test_remove_favorite() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     let channel = create_test_channel();
     
@@ -268,9 +262,7 @@ fn test_remove_favorite() {
 
 #[test]
 fn test_get_favorites_empty() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     let result = get_favorites(unsafe { std::mem::transmute(&state) });
@@ -280,9 +272,7 @@ fn test_get_favorites_empty() {
 
 #[test]
 fn test_get_favorites_with_data() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     let channel = create_test_channel();
     
@@ -300,9 +290,7 @@ fn test_get_favorites_with_data() {
 
 #[test]
 fn test_add_duplicate_favorite() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     let channel = create_test_channel();
     
@@ -346,9 +334,7 @@ fn test_settings_commands_with_empty_database() {
     let conn = Connection::open_in_memory().unwrap();
     // Don't create the settings table to test error handling
     
-    let db_state = DbState {
-        db: Mutex::new(conn),
-    };
+    let db_state = DbState::new(conn);
     let state = MockState::from(db_state);
     
     let result = get_player_command(unsafe { std::mem::transmute(&state) });
@@ -357,9 +343,7 @@ fn test_settings_commands_with_empty_database() {
 
 #[test]
 fn test_remove_nonexistent_favorite() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     let result = remove_favorite(unsafe { std::mem::transmute(&state) }, "Nonexistent Channel".to_string());
@@ -381,6 +365,8 @@ fn test_search_integration() {
             tvg_id: "bbc1".to_string(),
             resolution: "1080p".to_string(),
             extra_info: "HD".to_string(),
+            http_options: None,
+            source_list_id: None,
         },
         Channel {
             name: "CNN International".to_string(),
@@ -390,6 +376,8 @@ fn test_search_integration() {
             tvg_id: "cnn1".to_string(),
             resolution: "720p".to_string(),
             extra_info: "".to_string(),
+            http_options: None,
+            source_list_id: None,
         },
     ];
     
@@ -406,9 +394,7 @@ fn test_search_integration() {
 // Test command state interactions
 #[test]
 fn test_settings_persistence() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     // Test multiple setting changes
@@ -437,9 +423,7 @@ fn test_settings_persistence() {
 
 #[test]
 fn test_favorites_workflow() {
-    let db_state = DbState {
-        db: Mutex::new(create_test_db()),
-    };
+    let db_state = DbState::new(create_test_db());
     let state = MockState::from(db_state);
     
     // Create multiple channels
@@ -451,6 +435,8 @@ fn test_favorites_workflow() {
         tvg_id: "ch1".to_string(),
         resolution: "1080p".to_string(),
         extra_info: "".to_string(),
+        http_options: None,
+        source_list_id: None,
     };
     
     let channel2 = Channel {
@@ -461,6 +447,8 @@ fn test_favorites_workflow() {
         tvg_id: "ch2".to_string(),
         resolution: "720p".to_string(),
         extra_info: "HD".to_string(),
+        http_options: None,
+        source_list_id: None,
     };
     
     // Add both channels as favorites
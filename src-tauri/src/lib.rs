@@ -1,43 +1,75 @@
+mod backup;
+mod channel_overrides;
 mod channels;
 pub mod database;
+mod db_pool;
 mod error;
+mod failover;
 mod favorites;
 mod filters;
+pub mod fts_search;
 pub mod fuzzy_search;
 mod groups;
 mod history;
+mod http_client;
 pub mod image_cache;
 mod image_cache_api;
 pub mod m3u_parser;
 mod m3u_parser_helpers;
+pub mod migrations;
+mod multiview;
+pub mod paths;
+mod play_sessions;
+mod playback;
+mod player;
+mod player_failures;
 mod playlists;
+mod relay;
 pub mod search;
 mod settings;
 mod state;
+mod stream_health;
+mod stream_inspector;
 mod utils;
 
 #[cfg(test)]
 mod integration_tests;
+#[cfg(test)]
+mod test_support;
 
 use error::{Result, TolloError};
 use image_cache::ImageCache;
+use playback::PlaybackState;
 use playlists::FetchState;
+use relay::RelayState;
 use state::{ChannelCacheState, DbState, ImageCacheState};
+use stream_health::HealthScanState;
 use std::sync::{Arc, Mutex};
 use tauri::Manager;
 
 // Import all the command functions from their respective modules
+use backup::*;
+use channel_overrides::*;
 use channels::*;
+use failover::*;
 use favorites::*;
 use filters::*;
 use groups::*;
 use history::*;
+use http_client::*;
 use image_cache_api::*;
+use multiview::*;
+use play_sessions::*;
+use playback::*;
+use player_failures::*;
 use playlists::*;
+use relay::*;
 use search::*;
 use settings::*;
+use stream_health::*;
+use stream_inspector::*;
 
-fn initialize_application() -> Result<(rusqlite::Connection, Vec<m3u_parser::Channel>)> {
+fn initialize_application() -> Result<(DbState, Vec<m3u_parser::Channel>)> {
     let mut db_connection = database::initialize_database()
         .map_err(|e| TolloError::database_init(format!("Database initialization failed: {}", e)))?;
 
@@ -46,21 +78,35 @@ fn initialize_application() -> Result<(rusqlite::Connection, Vec<m3u_parser::Cha
         println!("Warning: Channel list cleanup failed: {}", e);
     }
 
+    http_client::init_proxy_settings(&db_connection);
+
     let channels = m3u_parser::get_channels(&mut db_connection, None);
-    database::populate_channels(&mut db_connection, &channels)
-        .map_err(|e| TolloError::database_init(format!("Failed to populate channels: {}", e)))?;
+    if let Some(list_id) = database::resolve_channel_list_id(&db_connection, None) {
+        database::populate_channels_with_changes(&mut db_connection, list_id, &channels)
+            .map_err(|e| {
+                TolloError::database_init(format!("Failed to populate channels: {}", e))
+            })?;
+    }
 
-    Ok((db_connection, channels))
+    let db_path = database::database_path()?;
+    let db_state = DbState::with_readers(db_connection, &db_path, db_pool::READER_CONNECTIONS)
+        .map_err(|e| TolloError::database_init(format!("Failed to open reader connections: {}", e)))?;
+
+    Ok((db_state, channels))
 }
 
-fn setup_image_cache(app: &tauri::App) -> Result<ImageCache> {
-    ImageCache::new(app.handle())
+fn setup_image_cache() -> Result<ImageCache> {
+    ImageCache::new(paths::data_paths().image_cache_dir())
         .map_err(|e| TolloError::internal(format!("Failed to initialize image cache: {}", e)))
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let (db_connection, _channels) = match initialize_application() {
+    let data_paths = paths::DataPaths::from_env();
+    println!("Using data directory: {}", data_paths.root().display());
+    paths::init_data_paths(data_paths);
+
+    let (db_state, _channels) = match initialize_application() {
         Ok(result) => result,
         Err(e) => {
             eprintln!("Fatal error during application initialization: {}", e);
@@ -70,15 +116,17 @@ pub fn run() {
     };
 
     tauri::Builder::default()
-        .manage(DbState {
-            db: Mutex::new(db_connection),
-        })
+        .manage(db_state)
         .manage(ChannelCacheState {
             cache: Mutex::new(None),
         })
         .manage(FetchState::new())
+        .manage(HealthScanState::new())
+        .manage(PlaybackState::new())
+        .manage(RelayState::new())
+        .manage(MultiviewState::new())
         .setup(|app| {
-            let image_cache = match setup_image_cache(app) {
+            let image_cache = match setup_image_cache() {
                 Ok(cache) => cache,
                 Err(e) => {
                     eprintln!("Failed to initialize image cache: {}", e);
@@ -88,6 +136,7 @@ pub fn run() {
             app.manage(ImageCacheState {
                 cache: Arc::new(image_cache),
             });
+            start_refresh_scheduler(app.handle().clone());
             Ok(())
         })
         .plugin(tauri_plugin_opener::init())
@@ -113,6 +162,11 @@ pub fn run() {
             remove_favorite_async,
             get_favorites_async,
             get_history_async,
+            // Channel override commands
+            get_channel_overrides,
+            set_channel_override,
+            bulk_edit_channel_overrides,
+            reset_channel_overrides,
             // Settings commands
             get_player_command,
             set_player_command,
@@ -126,6 +180,8 @@ pub fn run() {
             set_show_controls,
             get_autoplay,
             set_autoplay,
+            get_hide_dead_channels,
+            set_hide_dead_channels,
             // Playlist commands
             get_channel_lists,
             add_channel_list,
@@ -134,6 +190,19 @@ pub fn run() {
             validate_and_add_channel_list,
             delete_channel_list,
             update_channel_list,
+            set_channel_list_refresh_interval,
+            get_playlist_changes,
+            get_flagged_favorites,
+            dismiss_favorite_flag,
+            get_virtual_list,
+            create_virtual_list,
+            update_virtual_list,
+            get_playlist_request_config,
+            set_playlist_request_config,
+            get_channel_list_mirrors,
+            set_channel_list_mirrors,
+            get_fetch_retry_policy,
+            set_fetch_retry_policy,
             start_channel_list_selection,
             start_channel_list_selection_async,
             // Async playlist commands
@@ -141,6 +210,7 @@ pub fn run() {
             validate_and_add_channel_list_async,
             get_playlist_fetch_status,
             get_all_playlist_fetch_status,
+            cancel_playlist_fetch,
             // Image cache commands (sync)
             get_cached_image,
             clear_image_cache,
@@ -157,10 +227,59 @@ pub fn run() {
             sync_channel_list_groups,
             enable_all_groups,
             disable_all_groups,
+            get_group_layout,
+            set_group_order,
+            set_group_hidden,
+            rename_group,
+            merge_groups,
+            remove_group_alias,
+            add_group_rule,
+            delete_group_rule,
             // Filter commands
             save_filter,
             get_saved_filters,
             delete_saved_filter,
+            // Stream health commands
+            start_channel_health_scan,
+            get_channel_health,
+            get_channel_health_scan_status,
+            // Stream inspection commands
+            inspect_channel_stream,
+            get_channel_variants,
+            // Failover commands
+            get_channel_sources,
+            set_channel_source_order,
+            // Playback session commands
+            set_playback_queue,
+            get_playback_session,
+            clear_playback_session,
+            play_next,
+            play_previous,
+            // Stream relay commands
+            get_use_stream_relay,
+            set_use_stream_relay,
+            get_relay_stats,
+            // Proxy commands
+            get_proxy_settings,
+            set_proxy_settings,
+            // Player failure log commands
+            get_player_failures,
+            clear_player_failures,
+            // Multiview commands
+            start_multiview,
+            get_multiview_groups,
+            close_multiview,
+            // Watch statistics commands
+            get_play_sessions,
+            get_most_watched_channels,
+            get_most_watched_groups,
+            get_watch_time,
+            get_watch_summary,
+            export_play_sessions,
+            // Backup commands
+            create_backup,
+            inspect_backup,
+            restore_backup,
         ])
         .run(tauri::generate_context!())
         .map_err(|e| {
@@ -1,11 +1,11 @@
+use crate::paths::data_paths;
+use crate::playlists::{load_virtual_channels, refresh_playlist_blocking};
 use chrono::Utc;
 use regex::Regex;
-use reqwest;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fs;
-use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Channel {
@@ -16,9 +16,112 @@ pub struct Channel {
     pub tvg_id: String,
     pub resolution: String,
     pub extra_info: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_options: Option<StreamHttpOptions>,
+    /// The member list a channel of a virtual list came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_list_id: Option<i32>,
 }
 
-fn parse_m3u_content(m3u_content: &str) -> Vec<Channel> {
+/// Request options a provider attaches to a stream with `#EXTVLCOPT`, `#EXTHTTP`
+/// or `#KODIPROP` lines between `#EXTINF` and the URL.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct StreamHttpOptions {
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub referrer: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+}
+
+impl StreamHttpOptions {
+    fn set_header(&mut self, name: &str, value: &str) {
+        let value = value.trim().to_string();
+        match name.trim().to_lowercase().as_str() {
+            "user-agent" => self.user_agent = Some(value),
+            "referer" | "referrer" => self.referrer = Some(value),
+            _ => self.headers.push((name.trim().to_string(), value)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.user_agent.is_none() && self.referrer.is_none() && self.headers.is_empty()
+    }
+
+    /// Sends these options with `request`.
+    pub fn apply_to(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(ref user_agent) = self.user_agent {
+            request = request.header(reqwest::header::USER_AGENT, user_agent);
+        }
+        if let Some(ref referrer) = self.referrer {
+            request = request.header(reqwest::header::REFERER, referrer);
+        }
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        request
+    }
+}
+
+/// Serializes stream options for the `http_options` column of favorites and history.
+pub fn http_options_to_json(options: &Option<StreamHttpOptions>) -> Option<String> {
+    options
+        .as_ref()
+        .and_then(|options| serde_json::to_string(options).ok())
+}
+
+pub fn http_options_from_json(json: Option<String>) -> Option<StreamHttpOptions> {
+    json.and_then(|json| serde_json::from_str(&json).ok())
+}
+
+/// Applies a stream option line to `options`. Returns false for lines that are
+/// not stream options, so callers can tell them apart from unexpected tags.
+pub fn parse_stream_option(line: &str, options: &mut StreamHttpOptions) -> bool {
+    if let Some(option) = line.strip_prefix("#EXTVLCOPT:") {
+        if let Some((key, value)) = option.split_once('=') {
+            match key.trim().to_lowercase().as_str() {
+                "http-user-agent" => options.set_header("User-Agent", value),
+                "http-referrer" | "http-referer" => options.set_header("Referer", value),
+                "http-cookie" => options.set_header("Cookie", value),
+                "http-origin" => options.set_header("Origin", value),
+                _ => {}
+            }
+        }
+        true
+    } else if let Some(json) = line.strip_prefix("#EXTHTTP:") {
+        if let Ok(serde_json::Value::Object(headers)) = serde_json::from_str(json) {
+            for (name, value) in headers {
+                if let Some(value) = value.as_str() {
+                    options.set_header(&name, value);
+                }
+            }
+        }
+        true
+    } else if let Some(property) = line.strip_prefix("#KODIPROP:") {
+        if let Some(headers) = property.strip_prefix("inputstream.adaptive.stream_headers=") {
+            for header in headers.split('&') {
+                if let Some((name, value)) = header.split_once('=') {
+                    options.set_header(name, value);
+                }
+            }
+        }
+        true
+    } else {
+        false
+    }
+}
+
+pub fn parse_m3u_content(m3u_content: &str) -> Vec<Channel> {
+    parse_m3u_content_until(m3u_content, || false).unwrap_or_default()
+}
+
+/// Parses like `parse_m3u_content`, giving up with `None` as soon as
+/// `is_cancelled` returns true.
+pub fn parse_m3u_content_until(
+    m3u_content: &str,
+    is_cancelled: impl Fn() -> bool,
+) -> Option<Vec<Channel>> {
     let mut channels = Vec::new();
     let re_resolution = Regex::new(r"(\d+p)").unwrap();
     let re_extra_info = Regex::new(r"\[(.*?)\]").unwrap();
@@ -33,6 +136,10 @@ fn parse_m3u_content(m3u_content: &str) -> Vec<Channel> {
 
     while let Some(line) = lines.next() {
         if line.starts_with("#EXTINF") {
+            if is_cancelled() {
+                println!("M3U parsing cancelled after {} channels", parsed_channels);
+                return None;
+            }
             extinf_count += 1;
             let name = line
                 .split(',')
@@ -73,7 +180,11 @@ fn parse_m3u_content(m3u_content: &str) -> Vec<Channel> {
                 .and_then(|c| c.get(1))
                 .map_or_else(|| "".to_string(), |m| m.as_str().to_string());
 
-            if let Some(url_line) = lines.next() {
+            let mut http_options = StreamHttpOptions::default();
+            for url_line in lines.by_ref() {
+                if parse_stream_option(url_line, &mut http_options) {
+                    continue;
+                }
                 if !url_line.starts_with('#') {
                     channels.push(Channel {
                         name,
@@ -83,14 +194,14 @@ fn parse_m3u_content(m3u_content: &str) -> Vec<Channel> {
                         tvg_id,
                         resolution,
                         extra_info,
+                        http_options: (!http_options.is_empty()).then_some(http_options),
+                        source_list_id: None,
                     });
                     parsed_channels += 1;
                 } else {
-                    // Only warn for unexpected non-URL lines (but skip common M3U options)
-                    if !url_line.starts_with("#EXTVLCOPT") && !url_line.starts_with("#KODIPROP") {
-                        println!("Warning: Expected URL line but got: {}", url_line);
-                    }
+                    println!("Warning: Expected URL line but got: {}", url_line);
                 }
+                break;
             }
 
             // Log progress only for very large files
@@ -107,7 +218,7 @@ fn parse_m3u_content(m3u_content: &str) -> Vec<Channel> {
         "M3U parsing complete: {} EXTINF lines found, {} channels parsed",
         extinf_count, parsed_channels
     );
-    channels
+    Some(channels)
 }
 
 // New async version with progress callback
@@ -175,8 +286,12 @@ where
                 .and_then(|c| c.get(1))
                 .map_or_else(|| "".to_string(), |m| m.as_str().to_string());
 
-            if let Some(url_line) = lines.next() {
+            let mut http_options = StreamHttpOptions::default();
+            for url_line in lines.by_ref() {
                 current_line += 1;
+                if parse_stream_option(url_line, &mut http_options) {
+                    continue;
+                }
                 if !url_line.starts_with('#') {
                     channels.push(Channel {
                         name,
@@ -186,9 +301,12 @@ where
                         tvg_id,
                         resolution,
                         extra_info,
+                        http_options: (!http_options.is_empty()).then_some(http_options),
+                        source_list_id: None,
                     });
                     parsed_channels += 1;
                 }
+                break;
             }
 
             // Update progress every 1000 channels or 5% of total lines
@@ -212,13 +330,17 @@ where
 }
 
 pub fn get_channels(conn: &mut Connection, id: Option<i32>) -> Vec<Channel> {
+    if let Some(channels) = load_virtual_channels(conn, id) {
+        return channels;
+    }
+
     let query = if let Some(list_id) = id {
         format!(
-            "SELECT id, source, filepath, last_fetched FROM channel_lists WHERE id = {}",
+            "SELECT id, source, filepath, last_fetched, refresh_interval_hours FROM channel_lists WHERE id = {}",
             list_id
         )
     } else {
-        "SELECT id, source, filepath, last_fetched FROM channel_lists WHERE is_default = 1"
+        "SELECT id, source, filepath, last_fetched, refresh_interval_hours FROM channel_lists WHERE is_default = 1"
             .to_string()
     };
 
@@ -230,6 +352,7 @@ pub fn get_channels(conn: &mut Connection, id: Option<i32>) -> Vec<Channel> {
         let source: String = row.get(1).unwrap();
         let filepath: Option<String> = row.get(2).unwrap();
         let last_fetched: Option<i64> = row.get(3).unwrap();
+        let refresh_interval_hours: Option<i64> = row.get(4).unwrap();
 
         let cache_duration_hours: i64 = conn
             .query_row(
@@ -241,10 +364,10 @@ pub fn get_channels(conn: &mut Connection, id: Option<i32>) -> Vec<Channel> {
 
         let now = Utc::now().timestamp();
 
+        // Lists with a refresh interval are kept current by the scheduler
         if let (Some(fp), Some(lf)) = (filepath, last_fetched) {
-            if now - lf < cache_duration_hours * 3600 {
-                let data_dir = dirs::data_dir().unwrap().join("tollo");
-                let channel_lists_dir = data_dir.join("channel_lists");
+            if refresh_interval_hours.is_some() || now - lf < cache_duration_hours * 3600 {
+                let channel_lists_dir = data_paths().channel_lists_dir();
                 if let Ok(content) = fs::read_to_string(channel_lists_dir.join(fp)) {
                     return parse_m3u_content(&content);
                 }
@@ -252,28 +375,11 @@ pub fn get_channels(conn: &mut Connection, id: Option<i32>) -> Vec<Channel> {
         }
 
         if source.starts_with("http") {
-            if let Ok(content) = reqwest::blocking::get(&source).and_then(|resp| resp.text()) {
-                let data_dir = dirs::data_dir().unwrap().join("tollo");
-                let channel_lists_dir = data_dir.join("channel_lists");
-                let _ = fs::create_dir_all(&channel_lists_dir);
-                let filename = format!("{}.m3u", Uuid::new_v4());
-                let new_filepath = channel_lists_dir.join(&filename);
-                if fs::write(&new_filepath, &content).is_ok() {
-                    conn.execute(
-                        "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2 WHERE id = ?3",
-                        &[
-                            &filename as &dyn rusqlite::ToSql,
-                            &now as &dyn rusqlite::ToSql,
-                            &id as &dyn rusqlite::ToSql,
-                        ],
-                    )
-                    .unwrap();
-                    return parse_m3u_content(&content);
-                }
+            if let Ok(content) = refresh_playlist_blocking(conn, id, &source) {
+                return parse_m3u_content(&content);
             }
         } else {
-            let data_dir = dirs::data_dir().unwrap().join("tollo");
-            let channel_lists_dir = data_dir.join("channel_lists");
+            let channel_lists_dir = data_paths().channel_lists_dir();
             if let Ok(content) = fs::read_to_string(channel_lists_dir.join(&source)) {
                 return parse_m3u_content(&content);
             }
@@ -296,11 +402,11 @@ where
 
     let query = if let Some(list_id) = id {
         format!(
-            "SELECT id, source, filepath, last_fetched FROM channel_lists WHERE id = {}",
+            "SELECT id, source, filepath, last_fetched, refresh_interval_hours FROM channel_lists WHERE id = {}",
             list_id
         )
     } else {
-        "SELECT id, source, filepath, last_fetched FROM channel_lists WHERE is_default = 1"
+        "SELECT id, source, filepath, last_fetched, refresh_interval_hours FROM channel_lists WHERE is_default = 1"
             .to_string()
     };
 
@@ -312,6 +418,7 @@ where
         let source: String = row.get(1).unwrap();
         let filepath: Option<String> = row.get(2).unwrap();
         let last_fetched: Option<i64> = row.get(3).unwrap();
+        let refresh_interval_hours: Option<i64> = row.get(4).unwrap();
 
         let cache_duration_hours: i64 = conn
             .query_row(
@@ -325,11 +432,11 @@ where
 
         progress_callback(0.1, "Checking cache...".to_string(), 0);
 
+        // Lists with a refresh interval are kept current by the scheduler
         if let (Some(fp), Some(lf)) = (filepath, last_fetched) {
-            if now - lf < cache_duration_hours * 3600 {
+            if refresh_interval_hours.is_some() || now - lf < cache_duration_hours * 3600 {
                 progress_callback(0.2, "Loading from cache...".to_string(), 0);
-                let data_dir = dirs::data_dir().unwrap().join("tollo");
-                let channel_lists_dir = data_dir.join("channel_lists");
+                let channel_lists_dir = data_paths().channel_lists_dir();
                 if let Ok(content) = fs::read_to_string(channel_lists_dir.join(fp)) {
                     progress_callback(0.3, "Parsing cached M3U content...".to_string(), 0);
                     return parse_m3u_content_with_progress(&content, progress_callback);
@@ -339,31 +446,13 @@ where
 
         if source.starts_with("http") {
             progress_callback(0.2, "Downloading playlist...".to_string(), 0);
-            if let Ok(content) = reqwest::blocking::get(&source).and_then(|resp| resp.text()) {
-                progress_callback(0.4, "Saving to cache...".to_string(), 0);
-                let data_dir = dirs::data_dir().unwrap().join("tollo");
-                let channel_lists_dir = data_dir.join("channel_lists");
-                let _ = fs::create_dir_all(&channel_lists_dir);
-                let filename = format!("{}.m3u", Uuid::new_v4());
-                let new_filepath = channel_lists_dir.join(&filename);
-                if fs::write(&new_filepath, &content).is_ok() {
-                    conn.execute(
-                        "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2 WHERE id = ?3",
-                        &[
-                            &filename as &dyn rusqlite::ToSql,
-                            &now as &dyn rusqlite::ToSql,
-                            &id as &dyn rusqlite::ToSql,
-                        ],
-                    )
-                    .unwrap();
-                }
+            if let Ok(content) = refresh_playlist_blocking(conn, id, &source) {
                 progress_callback(0.5, "Parsing M3U content...".to_string(), 0);
                 return parse_m3u_content_with_progress(&content, progress_callback);
             }
         } else {
             progress_callback(0.2, "Loading from file...".to_string(), 0);
-            let data_dir = dirs::data_dir().unwrap().join("tollo");
-            let channel_lists_dir = data_dir.join("channel_lists");
+            let channel_lists_dir = data_paths().channel_lists_dir();
             if let Ok(content) = fs::read_to_string(channel_lists_dir.join(&source)) {
                 progress_callback(0.3, "Parsing M3U content...".to_string(), 0);
                 return parse_m3u_content_with_progress(&content, progress_callback);
@@ -496,9 +585,70 @@ http://example.com/stream1.m3u8
 http://example.com/stream2.m3u8"#;
 
         let channels = parse_m3u_content(m3u_content);
-        // Based on the parser logic, EXTVLCOPT and KODIPROP lines are consumed instead of URLs
-        // so no valid channels will be parsed
-        assert_eq!(channels.len(), 0);
+        // EXTVLCOPT and KODIPROP lines are read as stream options and the URL follows them
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].url, "http://example.com/stream1.m3u8");
+        assert_eq!(
+            channels[0].http_options.as_ref().unwrap().referrer.as_deref(),
+            Some("http://example.com")
+        );
+        assert_eq!(channels[1].url, "http://example.com/stream2.m3u8");
+        assert_eq!(channels[1].http_options, None);
+    }
+
+    #[test]
+    fn test_parse_m3u_content_with_http_options() {
+        let m3u_content = r#"#EXTM3U
+#EXTINF:-1 tvg-id="test1" group-title="Sports",Test Channel 1
+#EXTVLCOPT:http-user-agent=TestAgent/1.0
+#EXTVLCOPT:http-referrer=http://referrer.example.com/
+#EXTHTTP:{"cookie":"session=abc","X-Token":"123"}
+http://example.com/stream1.m3u8
+#EXTINF:-1 tvg-id="test2" group-title="News",Test Channel 2
+#KODIPROP:inputstream.adaptive.stream_headers=User-Agent=KodiAgent&Authorization=Bearer xyz
+http://example.com/stream2.m3u8"#;
+
+        let channels = parse_m3u_content(m3u_content);
+        assert_eq!(channels.len(), 2);
+
+        let options = channels[0].http_options.as_ref().unwrap();
+        assert_eq!(options.user_agent.as_deref(), Some("TestAgent/1.0"));
+        assert_eq!(
+            options.referrer.as_deref(),
+            Some("http://referrer.example.com/")
+        );
+        assert!(options
+            .headers
+            .contains(&("cookie".to_string(), "session=abc".to_string())));
+        assert!(options
+            .headers
+            .contains(&("X-Token".to_string(), "123".to_string())));
+
+        let options = channels[1].http_options.as_ref().unwrap();
+        assert_eq!(options.user_agent.as_deref(), Some("KodiAgent"));
+        assert_eq!(
+            options.headers,
+            vec![("Authorization".to_string(), "Bearer xyz".to_string())]
+        );
+
+        // The progress parser reads the same options
+        let with_progress = parse_m3u_content_with_progress(m3u_content, |_, _, _| {});
+        assert_eq!(with_progress, channels);
+    }
+
+    #[test]
+    fn test_parse_m3u_content_until_cancelled() {
+        let m3u_content = "#EXTM3U\n#EXTINF:-1,One\nhttp://example.com/1\n#EXTINF:-1,Two\nhttp://example.com/2\n";
+        let parsed = std::cell::Cell::new(0);
+        let channels = parse_m3u_content_until(m3u_content, || {
+            parsed.set(parsed.get() + 1);
+            parsed.get() > 1
+        });
+        assert!(channels.is_none());
+
+        let channels = parse_m3u_content_until(m3u_content, || false).unwrap();
+        assert_eq!(channels, parse_m3u_content(m3u_content));
+        assert_eq!(channels.len(), 2);
     }
 
     #[test]
@@ -622,6 +772,8 @@ http://example.com/stream2.m3u8"#;
             tvg_id: "test123".to_string(),
             resolution: "1080p".to_string(),
             extra_info: "HD".to_string(),
+            http_options: None,
+            source_list_id: None,
         };
 
         assert_eq!(channel.name, "Test Channel");
@@ -643,6 +795,8 @@ http://example.com/stream2.m3u8"#;
             tvg_id: "test123".to_string(),
             resolution: "1080p".to_string(),
             extra_info: "HD".to_string(),
+            http_options: None,
+            source_list_id: None,
         };
 
         let cloned_channel = channel.clone();
@@ -676,6 +830,30 @@ http://example.com/stream2.m3u8"#;
         assert_eq!(channels[99].group_title, "Group4");
     }
 
+    #[test]
+    fn test_scheduled_list_serves_stored_file() {
+        use crate::migrations::run_migrations;
+        use crate::playlists::store_playlist_file;
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn, None).unwrap();
+        let filename =
+            store_playlist_file("#EXTM3U\n#EXTINF:-1,News\nhttp://example.com/news\n").unwrap();
+        // Fetched long ago from a source that is down: the scheduler owns the refresh
+        conn.execute(
+            "INSERT INTO channel_lists (id, name, source, filepath, last_fetched, refresh_interval_hours)
+                VALUES (1, 'Scheduled', 'http://127.0.0.1:9/list.m3u', ?1, 0, 6)",
+            [&filename],
+        )
+        .unwrap();
+
+        let channels = get_channels(&mut conn, Some(1));
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].name, "News");
+
+        let _ = fs::remove_file(data_paths().channel_list_file(&filename));
+    }
+
     // Property-based tests for M3U parsing
     mod property_tests {
         use super::*;
@@ -1,20 +1,31 @@
-use crate::m3u_parser::Channel;
+use crate::m3u_parser::{parse_m3u_content_until, Channel};
+use crate::paths::data_paths;
+use crate::playlists::refresh_playlist_unlocked;
+use crate::state::DbState;
 use chrono;
-use dirs;
-use regex;
-use reqwest;
 use rusqlite;
-use uuid;
+use std::cell::Cell;
+
+/// Where the content of a list is read from.
+pub enum M3uSource {
+    /// The stored copy, or a local file, already read.
+    Content(String),
+    /// A remote list whose stored copy is missing or stale.
+    Remote { id: i32, source: String },
+}
 
-// Helper function to get M3U content without parsing
-pub fn get_m3u_content(conn: &mut rusqlite::Connection, id: Option<i32>) -> Result<String, String> {
+// Looks up list `id` and reads its content when no download is needed
+pub fn locate_m3u_content(
+    conn: &rusqlite::Connection,
+    id: Option<i32>,
+) -> Result<M3uSource, String> {
     let query = if let Some(list_id) = id {
         format!(
-            "SELECT id, source, filepath, last_fetched FROM channel_lists WHERE id = {}",
+            "SELECT id, source, filepath, last_fetched, refresh_interval_hours FROM channel_lists WHERE id = {}",
             list_id
         )
     } else {
-        "SELECT id, source, filepath, last_fetched FROM channel_lists WHERE is_default = 1"
+        "SELECT id, source, filepath, last_fetched, refresh_interval_hours FROM channel_lists WHERE is_default = 1"
             .to_string()
     };
 
@@ -26,6 +37,7 @@ pub fn get_m3u_content(conn: &mut rusqlite::Connection, id: Option<i32>) -> Resu
         let source: String = row.get(1).map_err(|e| e.to_string())?;
         let filepath: Option<String> = row.get(2).map_err(|e| e.to_string())?;
         let last_fetched: Option<i64> = row.get(3).map_err(|e| e.to_string())?;
+        let refresh_interval_hours: Option<i64> = row.get(4).map_err(|e| e.to_string())?;
 
         let cache_duration_hours: i64 = conn
             .query_row(
@@ -37,55 +49,24 @@ pub fn get_m3u_content(conn: &mut rusqlite::Connection, id: Option<i32>) -> Resu
 
         let now = chrono::Utc::now().timestamp();
 
-        // Check if we have cached content
+        // Check if we have cached content. Lists with a refresh interval are
+        // kept current by the scheduler, so their cached file is served as is.
         if let (Some(fp), Some(lf)) = (filepath, last_fetched) {
-            if now - lf < cache_duration_hours * 3600 {
-                let data_dir = dirs::data_dir().unwrap().join("tollo");
-                let channel_lists_dir = data_dir.join("channel_lists");
+            if refresh_interval_hours.is_some() || now - lf < cache_duration_hours * 3600 {
+                let channel_lists_dir = data_paths().channel_lists_dir();
                 if let Ok(content) = std::fs::read_to_string(channel_lists_dir.join(fp)) {
-                    return Ok(content);
+                    return Ok(M3uSource::Content(content));
                 }
             }
         }
 
         // Fetch from source
         if source.starts_with("http") {
-            let client = reqwest::blocking::Client::builder()
-                .timeout(std::time::Duration::from_secs(120))
-                .build()
-                .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-            
-            let content = client
-                .get(&source)
-                .header("User-Agent", "Mozilla/5.0")
-                .send()
-                .map_err(|e| format!("Failed to fetch playlist: {}", e))?
-                .text()
-                .map_err(|e| format!("Failed to read response: {}", e))?;
-
-            // Save to cache
-            let data_dir = dirs::data_dir().unwrap().join("tollo");
-            let channel_lists_dir = data_dir.join("channel_lists");
-            let _ = std::fs::create_dir_all(&channel_lists_dir);
-            let filename = format!("{}.m3u", uuid::Uuid::new_v4());
-            let new_filepath = channel_lists_dir.join(&filename);
-            if std::fs::write(&new_filepath, &content).is_ok() {
-                let _ = conn.execute(
-                    "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2 WHERE id = ?3",
-                    &[
-                        &filename as &dyn rusqlite::ToSql,
-                        &now as &dyn rusqlite::ToSql,
-                        &id as &dyn rusqlite::ToSql,
-                    ],
-                );
-            }
-
-            return Ok(content);
+            return Ok(M3uSource::Remote { id, source });
         } else {
-            let data_dir = dirs::data_dir().unwrap().join("tollo");
-            let channel_lists_dir = data_dir.join("channel_lists");
+            let channel_lists_dir = data_paths().channel_lists_dir();
             if let Ok(content) = std::fs::read_to_string(channel_lists_dir.join(&source)) {
-                return Ok(content);
+                return Ok(M3uSource::Content(content));
             }
         }
     }
@@ -93,101 +74,51 @@ pub fn get_m3u_content(conn: &mut rusqlite::Connection, id: Option<i32>) -> Resu
     Err("No channel list found".to_string())
 }
 
-// Helper function to parse M3U content with progress
-pub fn parse_m3u_with_progress<F>(m3u_content: &str, progress_callback: F) -> Vec<Channel>
+// Helper function to get M3U content without parsing. The lookup uses a reader
+// connection and a download, when needed, holds no connection at all.
+pub fn get_m3u_content(db_state: &DbState, id: Option<i32>) -> Result<String, String> {
+    let located = locate_m3u_content(&db_state.reader(), id)?;
+    match located {
+        M3uSource::Content(content) => Ok(content),
+        M3uSource::Remote { id, source } => refresh_playlist_unlocked(db_state, id, &source),
+    }
+}
+
+/// Parses `m3u_content` like `parse_m3u_content_until`, reporting progress
+/// every 1000 channels. None when `is_cancelled` stopped it.
+pub fn parse_m3u_with_progress<F>(
+    m3u_content: &str,
+    is_cancelled: impl Fn() -> bool,
+    progress_callback: F,
+) -> Option<Vec<Channel>>
 where
     F: Fn(f32, String, usize),
 {
-    let mut channels = Vec::new();
-    let re_resolution = regex::Regex::new(r"(\d+p)").unwrap();
-    let re_extra_info = regex::Regex::new(r"\[(.*?)\]").unwrap();
-
-    // Count total lines for progress calculation
-    let total_lines = m3u_content.lines().count();
-    let mut current_line = 0;
-    let mut extinf_count = 0;
-    let mut parsed_channels = 0;
+    let total_entries = m3u_content
+        .lines()
+        .filter(|line| line.starts_with("#EXTINF"))
+        .count();
+    let entries = Cell::new(0usize);
 
     progress_callback(0.0, "Starting M3U parsing...".to_string(), 0);
-
-    let mut lines = m3u_content.lines().peekable();
-
-    while let Some(line) = lines.next() {
-        current_line += 1;
-
-        if line.starts_with("#EXTINF") {
-            extinf_count += 1;
-            let name = line
-                .split(',')
-                .nth(1)
-                .unwrap_or_default()
-                .trim()
-                .to_string();
-            let logo = line
-                .split("tvg-logo=\"")
-                .nth(1)
-                .unwrap_or_default()
-                .split('"')
-                .next()
-                .unwrap_or_default()
-                .to_string();
-            let group_title = line
-                .split("group-title=\"")
-                .nth(1)
-                .unwrap_or_default()
-                .split('"')
-                .next()
-                .unwrap_or_default()
-                .to_string();
-            let tvg_id = line
-                .split("tvg-id=\"")
-                .nth(1)
-                .unwrap_or_default()
-                .split('"')
-                .next()
-                .unwrap_or_default()
-                .to_string();
-            let resolution = re_resolution
-                .captures(&name)
-                .and_then(|c| c.get(1))
-                .map_or_else(|| "".to_string(), |m| m.as_str().to_string());
-            let extra_info = re_extra_info
-                .captures(&name)
-                .and_then(|c| c.get(1))
-                .map_or_else(|| "".to_string(), |m| m.as_str().to_string());
-
-            if let Some(url_line) = lines.next() {
-                current_line += 1;
-                if !url_line.starts_with('#') {
-                    channels.push(Channel {
-                        name,
-                        logo,
-                        url: url_line.to_string(),
-                        group_title,
-                        tvg_id,
-                        resolution,
-                        extra_info,
-                    });
-                    parsed_channels += 1;
-                }
-            }
-
-            // Update progress every 1000 channels or 5% of total lines
-            if parsed_channels % 1000 == 0 || current_line % (total_lines / 20).max(1) == 0 {
-                let progress = (current_line as f32) / (total_lines as f32);
-                let message = format!(
-                    "Parsed {} channels ({} EXTINF entries)",
-                    parsed_channels, extinf_count
-                );
-                progress_callback(progress, message, parsed_channels);
-            }
+    // Called before each EXTINF entry is parsed
+    let channels = parse_m3u_content_until(m3u_content, || {
+        let parsed = entries.get();
+        if parsed > 0 && parsed.is_multiple_of(1000) {
+            progress_callback(
+                parsed as f32 / total_entries as f32,
+                format!("Parsed {} of {} EXTINF entries", parsed, total_entries),
+                parsed,
+            );
         }
-    }
+        entries.set(parsed + 1);
+        is_cancelled()
+    })?;
 
     progress_callback(
         1.0,
-        format!("Parsing complete! {} channels parsed", parsed_channels),
-        parsed_channels,
+        format!("Parsing complete! {} channels parsed", channels.len()),
+        channels.len(),
     );
-    channels
+    Some(channels)
 }
@@ -0,0 +1,1013 @@
+use crate::error::{Result, TolloError};
+use rusqlite::{Connection, Result as RusqliteResult, Transaction};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One step of the schema history. Steps run in order, each in its own
+/// transaction, and bump `PRAGMA user_version` to `version` when they commit.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    /// Drops, rebuilds or rewrites existing data; the database file is backed up first
+    pub destructive: bool,
+    pub up: fn(&Transaction) -> RusqliteResult<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "baseline schema",
+        destructive: false,
+        up: baseline_schema,
+    },
+    Migration {
+        version: 2,
+        description: "per-list channel storage",
+        destructive: true,
+        up: per_list_channels,
+    },
+    Migration {
+        version: 3,
+        description: "prefix and trigram channel search indexes",
+        destructive: false,
+        up: channel_search_indexes,
+    },
+    Migration {
+        version: 4,
+        description: "per-list automatic refresh interval",
+        destructive: false,
+        up: list_refresh_intervals,
+    },
+    Migration {
+        version: 5,
+        description: "playlist HTTP validators and content hash",
+        destructive: false,
+        up: playlist_validators,
+    },
+    Migration {
+        version: 6,
+        description: "playlist change log and favorite flags",
+        destructive: false,
+        up: playlist_change_log,
+    },
+    Migration {
+        version: 7,
+        description: "channel overrides",
+        destructive: false,
+        up: channel_overrides,
+    },
+    Migration {
+        version: 8,
+        description: "group order, aliases and rules",
+        destructive: false,
+        up: group_layout,
+    },
+    Migration {
+        version: 9,
+        description: "virtual channel lists",
+        destructive: false,
+        up: virtual_lists,
+    },
+    Migration {
+        version: 10,
+        description: "playlist request configs",
+        destructive: false,
+        up: playlist_request_configs,
+    },
+    Migration {
+        version: 11,
+        description: "proxy settings",
+        destructive: false,
+        up: proxy_settings,
+    },
+    Migration {
+        version: 12,
+        description: "fetch retries and mirrors",
+        destructive: false,
+        up: fetch_retries_and_mirrors,
+    },
+];
+
+/// Channel rows belong to a list and are keyed by a stable identity hash.
+/// The triggers keep the external-content FTS index in step with the table.
+pub(crate) const CHANNELS_SCHEMA: &str = "
+    CREATE TABLE channels (
+        id INTEGER PRIMARY KEY,
+        channel_list_id INTEGER NOT NULL,
+        identity TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        logo TEXT NOT NULL,
+        url TEXT NOT NULL,
+        group_title TEXT NOT NULL,
+        tvg_id TEXT NOT NULL,
+        resolution TEXT NOT NULL,
+        extra_info TEXT NOT NULL,
+        http_options TEXT,
+        UNIQUE (channel_list_id, identity),
+        FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+    );
+    CREATE INDEX idx_channels_list_position ON channels(channel_list_id, position);
+    CREATE VIRTUAL TABLE channels_fts USING fts5(name, group_title, content='channels', content_rowid='id');
+    CREATE TRIGGER channels_fts_insert AFTER INSERT ON channels BEGIN
+        INSERT INTO channels_fts(rowid, name, group_title) VALUES (new.id, new.name, new.group_title);
+    END;
+    CREATE TRIGGER channels_fts_delete AFTER DELETE ON channels BEGIN
+        INSERT INTO channels_fts(channels_fts, rowid, name, group_title) VALUES ('delete', old.id, old.name, old.group_title);
+    END;
+    CREATE TRIGGER channels_fts_update AFTER UPDATE OF name, group_title ON channels BEGIN
+        INSERT INTO channels_fts(channels_fts, rowid, name, group_title) VALUES ('delete', old.id, old.name, old.group_title);
+        INSERT INTO channels_fts(rowid, name, group_title) VALUES (new.id, new.name, new.group_title);
+    END;
+";
+
+pub fn schema_version(conn: &Connection) -> RusqliteResult<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+pub fn column_exists(conn: &Connection, table: &str, column: &str) -> RusqliteResult<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        [table, column],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> RusqliteResult<()> {
+    if !column_exists(conn, table, column)? {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// Brings the database up to the latest schema version. `db_path` is where
+/// backups are written before destructive steps; in-memory databases pass `None`.
+pub fn run_migrations(conn: &mut Connection, db_path: Option<&Path>) -> Result<u32> {
+    apply_migrations(conn, MIGRATIONS, db_path)
+}
+
+fn apply_migrations(
+    conn: &mut Connection,
+    migrations: &[Migration],
+    db_path: Option<&Path>,
+) -> Result<u32> {
+    let mut version = schema_version(conn)?;
+    let latest = migrations.last().map(|m| m.version).unwrap_or(0);
+    if version > latest {
+        return Err(TolloError::database_migration(format!(
+            "database schema version {} is newer than this version of Tollo supports ({})",
+            version, latest
+        )));
+    }
+
+    let current = version;
+    let mut backed_up = false;
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        if migration.destructive && !backed_up {
+            if let Some(path) = db_path {
+                if has_tables(conn)? {
+                    let backup = backup_database(conn, path, version)?;
+                    println!("Backed up database to {}", backup.display());
+                }
+            }
+            backed_up = true;
+        }
+
+        let failed = |e: rusqlite::Error| {
+            TolloError::database_migration(format!(
+                "migration {} ({}) failed: {}",
+                migration.version, migration.description, e
+            ))
+        };
+        let tx = conn.transaction().map_err(failed)?;
+        (migration.up)(&tx).map_err(failed)?;
+        tx.pragma_update(None, "user_version", migration.version)
+            .map_err(failed)?;
+        tx.commit().map_err(failed)?;
+
+        println!(
+            "Applied database migration {}: {}",
+            migration.version, migration.description
+        );
+        version = migration.version;
+    }
+
+    Ok(version)
+}
+
+fn has_tables(conn: &Connection) -> RusqliteResult<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// Copies the database next to `db_path` as `<file>.v<version>.bak`.
+pub fn backup_database(conn: &Connection, db_path: &Path, version: u32) -> Result<PathBuf> {
+    let file_name = db_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "database.sqlite".to_string());
+    let backup_path = db_path.with_file_name(format!("{}.v{}.bak", file_name, version));
+
+    if backup_path.exists() {
+        fs::remove_file(&backup_path).map_err(|e| {
+            TolloError::database_migration(format!(
+                "could not replace old backup {}: {}",
+                backup_path.display(),
+                e
+            ))
+        })?;
+    }
+    // VACUUM INTO writes a consistent copy even while the database is open
+    conn.execute(
+        "VACUUM INTO ?1",
+        [backup_path.to_string_lossy().to_string()],
+    )
+    .map_err(|e| {
+        TolloError::database_migration(format!(
+            "could not back up database to {}: {}",
+            backup_path.display(),
+            e
+        ))
+    })?;
+    Ok(backup_path)
+}
+
+// Version 1 covers every schema created before versioning, when tables were
+// created if missing and columns patched in on start-up. It is idempotent so it
+// can bring any of those databases, or an empty one, to the same shape.
+fn baseline_schema(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS favorites (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            logo TEXT NOT NULL,
+            url TEXT NOT NULL,
+            group_title TEXT NOT NULL,
+            tvg_id TEXT NOT NULL,
+            resolution TEXT NOT NULL,
+            extra_info TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS history (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            logo TEXT NOT NULL,
+            url TEXT NOT NULL,
+            group_title TEXT NOT NULL,
+            tvg_id TEXT NOT NULL,
+            resolution TEXT NOT NULL,
+            extra_info TEXT NOT NULL,
+            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS channels (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            logo TEXT NOT NULL,
+            url TEXT NOT NULL,
+            group_title TEXT NOT NULL,
+            tvg_id TEXT NOT NULL,
+            resolution TEXT NOT NULL,
+            extra_info TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS channels_fts USING fts5(name, content='channels', content_rowid='id')",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            id INTEGER PRIMARY KEY,
+            player_command TEXT NOT NULL,
+            cache_duration_hours INTEGER NOT NULL DEFAULT 24,
+            enable_preview BOOLEAN NOT NULL DEFAULT 1,
+            mute_on_start BOOLEAN NOT NULL DEFAULT 0,
+            show_controls BOOLEAN NOT NULL DEFAULT 1,
+            autoplay BOOLEAN NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    // Columns added to settings after the table was first released
+    add_column_if_missing(
+        tx,
+        "settings",
+        "enable_preview",
+        "BOOLEAN NOT NULL DEFAULT 1",
+    )?;
+    add_column_if_missing(
+        tx,
+        "settings",
+        "mute_on_start",
+        "BOOLEAN NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(
+        tx,
+        "settings",
+        "show_controls",
+        "BOOLEAN NOT NULL DEFAULT 1",
+    )?;
+    add_column_if_missing(tx, "settings", "autoplay", "BOOLEAN NOT NULL DEFAULT 0")?;
+    add_column_if_missing(
+        tx,
+        "settings",
+        "hide_dead_channels",
+        "BOOLEAN NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(
+        tx,
+        "settings",
+        "use_stream_relay",
+        "BOOLEAN NOT NULL DEFAULT 0",
+    )?;
+    // Stream request options (user agent, referrer, headers) stored as JSON
+    add_column_if_missing(tx, "favorites", "http_options", "TEXT")?;
+    add_column_if_missing(tx, "history", "http_options", "TEXT")?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS channel_lists (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            source TEXT NOT NULL,
+            filepath TEXT,
+            last_fetched INTEGER,
+            is_default BOOLEAN NOT NULL DEFAULT 0,
+            CONSTRAINT is_default_check CHECK (is_default IN (0, 1))
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_one_default_list ON channel_lists (is_default) WHERE is_default = 1",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS group_selections (
+            channel_list_id INTEGER NOT NULL,
+            group_name TEXT NOT NULL,
+            is_enabled BOOLEAN NOT NULL DEFAULT 1,
+            PRIMARY KEY (channel_list_id, group_name),
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS saved_filters (
+            channel_list_id INTEGER NOT NULL,
+            slot_number INTEGER NOT NULL CHECK (slot_number >= 0 AND slot_number <= 9),
+            search_query TEXT NOT NULL DEFAULT '',
+            selected_group TEXT,
+            name TEXT NOT NULL DEFAULT '',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (channel_list_id, slot_number),
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS channel_health (
+            channel_list_id INTEGER NOT NULL,
+            url TEXT NOT NULL,
+            status TEXT NOT NULL,
+            http_status INTEGER,
+            latency_ms INTEGER,
+            last_checked INTEGER NOT NULL,
+            error TEXT,
+            PRIMARY KEY (channel_list_id, url),
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS stream_variants (
+            channel_list_id INTEGER NOT NULL,
+            channel_url TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            url TEXT NOT NULL,
+            bandwidth INTEGER,
+            width INTEGER,
+            height INTEGER,
+            codecs TEXT,
+            frame_rate REAL,
+            inspected_at INTEGER NOT NULL,
+            PRIMARY KEY (channel_list_id, channel_url, position),
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS channel_source_order (
+            channel_list_id INTEGER NOT NULL,
+            equivalence_key TEXT NOT NULL,
+            url TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (channel_list_id, equivalence_key, url),
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS player_failures (
+            id INTEGER PRIMARY KEY,
+            channel_name TEXT NOT NULL,
+            channel_url TEXT NOT NULL,
+            stream_url TEXT NOT NULL,
+            category TEXT NOT NULL,
+            message TEXT NOT NULL,
+            exit_code INTEGER,
+            log_excerpt TEXT NOT NULL,
+            occurred_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_player_failures_channel_url ON player_failures(channel_url)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS play_sessions (
+            id INTEGER PRIMARY KEY,
+            channel_name TEXT NOT NULL,
+            channel_url TEXT NOT NULL,
+            group_title TEXT NOT NULL,
+            player_pid INTEGER,
+            started_at INTEGER NOT NULL,
+            ended_at INTEGER,
+            duration_seconds INTEGER
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_play_sessions_started_at ON play_sessions(started_at)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+// The old table kept one row per channel name across all lists. Its rows are
+// derived from the playlists and are stored again the next time a list loads.
+fn per_list_channels(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute_batch(
+        "DROP TABLE IF EXISTS channels_fts;
+         DROP TABLE IF EXISTS channels;",
+    )?;
+    tx.execute_batch(CHANNELS_SCHEMA)
+}
+
+// The word index gains prefix indexes for short queries, and a trigram index
+// answers substring queries. Both are rebuilt from the channels table.
+fn channel_search_indexes(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute_batch(
+        "DROP TABLE IF EXISTS channels_fts;
+         CREATE VIRTUAL TABLE channels_fts USING fts5(name, group_title, content='channels', content_rowid='id', prefix='1 2');
+         INSERT INTO channels_fts(channels_fts) VALUES ('rebuild');
+         CREATE VIRTUAL TABLE channels_trigram USING fts5(name, group_title, content='channels', content_rowid='id', tokenize='trigram');
+         INSERT INTO channels_trigram(channels_trigram) VALUES ('rebuild');
+         CREATE TRIGGER channels_trigram_insert AFTER INSERT ON channels BEGIN
+             INSERT INTO channels_trigram(rowid, name, group_title) VALUES (new.id, new.name, new.group_title);
+         END;
+         CREATE TRIGGER channels_trigram_delete AFTER DELETE ON channels BEGIN
+             INSERT INTO channels_trigram(channels_trigram, rowid, name, group_title) VALUES ('delete', old.id, old.name, old.group_title);
+         END;
+         CREATE TRIGGER channels_trigram_update AFTER UPDATE OF name, group_title ON channels BEGIN
+             INSERT INTO channels_trigram(channels_trigram, rowid, name, group_title) VALUES ('delete', old.id, old.name, old.group_title);
+             INSERT INTO channels_trigram(rowid, name, group_title) VALUES (new.id, new.name, new.group_title);
+         END;",
+    )
+}
+
+// NULL leaves the list to be refreshed by hand
+fn list_refresh_intervals(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute(
+        "ALTER TABLE channel_lists ADD COLUMN refresh_interval_hours INTEGER",
+        [],
+    )?;
+    Ok(())
+}
+
+// ETag and Last-Modified from the last download, for conditional requests
+fn playlist_validators(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute_batch(
+        "ALTER TABLE channel_lists ADD COLUMN etag TEXT;
+         ALTER TABLE channel_lists ADD COLUMN last_modified TEXT;
+         ALTER TABLE channel_lists ADD COLUMN content_hash TEXT;",
+    )
+}
+
+// One row per refresh that changed a list, with the diff as JSON. Flags mark
+// favorites whose channel a refresh removed or changed.
+fn playlist_change_log(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE playlist_changes (
+            id INTEGER PRIMARY KEY,
+            channel_list_id INTEGER NOT NULL,
+            changed_at INTEGER NOT NULL,
+            details TEXT NOT NULL,
+            flagged_favorites TEXT NOT NULL DEFAULT '[]',
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        );
+        CREATE INDEX idx_playlist_changes_list ON playlist_changes(channel_list_id, changed_at);
+        CREATE TABLE favorite_flags (
+            favorite_name TEXT PRIMARY KEY,
+            channel_list_id INTEGER NOT NULL,
+            reason TEXT NOT NULL,
+            replacement_name TEXT,
+            replacement_url TEXT,
+            flagged_at INTEGER NOT NULL,
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        );",
+    )
+}
+
+// Edits to channels keyed by identity, so a refresh of the playlist keeps them.
+// NULL fields keep the value from the playlist.
+fn channel_overrides(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute(
+        "CREATE TABLE channel_overrides (
+            channel_list_id INTEGER NOT NULL,
+            identity TEXT NOT NULL,
+            original_name TEXT NOT NULL,
+            original_url TEXT NOT NULL,
+            name TEXT,
+            group_title TEXT,
+            logo TEXT,
+            tvg_id TEXT,
+            hidden BOOLEAN NOT NULL DEFAULT 0,
+            updated_at INTEGER NOT NULL,
+            PRIMARY KEY (channel_list_id, identity),
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+// How a list's groups are shown: order and hidden flag per shown group,
+// aliases from source groups to shown names, and regex rules that move
+// channels into a group.
+fn group_layout(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE group_layout (
+            channel_list_id INTEGER NOT NULL,
+            group_name TEXT NOT NULL,
+            position INTEGER,
+            hidden BOOLEAN NOT NULL DEFAULT 0,
+            PRIMARY KEY (channel_list_id, group_name),
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        );
+        CREATE TABLE group_aliases (
+            channel_list_id INTEGER NOT NULL,
+            source_group TEXT NOT NULL,
+            display_name TEXT NOT NULL,
+            PRIMARY KEY (channel_list_id, source_group),
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        );
+        CREATE TABLE group_rules (
+            id INTEGER PRIMARY KEY,
+            channel_list_id INTEGER NOT NULL,
+            pattern TEXT NOT NULL,
+            match_field TEXT NOT NULL CHECK (match_field IN ('name', 'group')),
+            target_group TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        );",
+    )
+}
+
+// A virtual list is a channel_lists row combining the channels of its members
+fn virtual_lists(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE virtual_lists (
+            channel_list_id INTEGER PRIMARY KEY,
+            filters TEXT NOT NULL DEFAULT '{}',
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        );
+        CREATE TABLE virtual_list_members (
+            virtual_list_id INTEGER NOT NULL,
+            member_list_id INTEGER NOT NULL,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (virtual_list_id, member_list_id),
+            FOREIGN KEY (virtual_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE,
+            FOREIGN KEY (member_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        );",
+    )
+}
+
+// Request settings are encrypted as a whole, so the table only holds the sealed blob
+fn playlist_request_configs(tx: &Transaction) -> RusqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE playlist_request_configs (
+            channel_list_id INTEGER PRIMARY KEY,
+            config BLOB NOT NULL,
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        );",
+    )
+}
+
+fn proxy_settings(tx: &Transaction) -> RusqliteResult<()> {
+    add_column_if_missing(tx, "settings", "proxy_url", "TEXT")?;
+    add_column_if_missing(tx, "settings", "no_proxy", "TEXT")?;
+    add_column_if_missing(
+        tx,
+        "settings",
+        "proxy_player",
+        "BOOLEAN NOT NULL DEFAULT 0",
+    )
+}
+
+fn fetch_retries_and_mirrors(tx: &Transaction) -> RusqliteResult<()> {
+    add_column_if_missing(
+        tx,
+        "settings",
+        "fetch_max_attempts",
+        "INTEGER NOT NULL DEFAULT 3",
+    )?;
+    add_column_if_missing(
+        tx,
+        "settings",
+        "fetch_retry_delay_ms",
+        "INTEGER NOT NULL DEFAULT 1000",
+    )?;
+    tx.execute_batch(
+        "CREATE TABLE channel_list_mirrors (
+            channel_list_id INTEGER NOT NULL,
+            position INTEGER NOT NULL,
+            url TEXT NOT NULL,
+            PRIMARY KEY (channel_list_id, position),
+            FOREIGN KEY (channel_list_id) REFERENCES channel_lists(id) ON DELETE CASCADE
+        );",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn latest_version() -> u32 {
+        MIGRATIONS.last().unwrap().version
+    }
+
+    // Schemas users may have on disk from before versioning, oldest first
+
+    // First release: settings held only the player command and cache duration
+    const SCHEMA_INITIAL: &str = "
+        CREATE TABLE favorites (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, logo TEXT NOT NULL, url TEXT NOT NULL, group_title TEXT NOT NULL, tvg_id TEXT NOT NULL, resolution TEXT NOT NULL, extra_info TEXT NOT NULL);
+        CREATE TABLE history (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, logo TEXT NOT NULL, url TEXT NOT NULL, group_title TEXT NOT NULL, tvg_id TEXT NOT NULL, resolution TEXT NOT NULL, extra_info TEXT NOT NULL, timestamp DATETIME DEFAULT CURRENT_TIMESTAMP);
+        CREATE TABLE channels (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, logo TEXT NOT NULL, url TEXT NOT NULL, group_title TEXT NOT NULL, tvg_id TEXT NOT NULL, resolution TEXT NOT NULL, extra_info TEXT NOT NULL);
+        CREATE TABLE settings (id INTEGER PRIMARY KEY, player_command TEXT NOT NULL, cache_duration_hours INTEGER NOT NULL DEFAULT 24);
+        CREATE TABLE channel_lists (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, source TEXT NOT NULL, filepath TEXT, last_fetched INTEGER, is_default BOOLEAN NOT NULL DEFAULT 0, CONSTRAINT is_default_check CHECK (is_default IN (0, 1)));
+        INSERT INTO settings (id, player_command, cache_duration_hours) VALUES (1, 'vlc', 12);
+        INSERT INTO favorites (name, logo, url, group_title, tvg_id, resolution, extra_info) VALUES ('News', '', 'http://example.com/news.m3u8', 'News', '', '', '');
+        INSERT INTO channel_lists (name, source, is_default) VALUES ('iptv-org', 'https://iptv-org.github.io/iptv/index.m3u', 1);
+    ";
+
+    // Player settings, group selections and saved filters, before stream health
+    const SCHEMA_SAVED_FILTERS: &str = "
+        CREATE TABLE favorites (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, logo TEXT NOT NULL, url TEXT NOT NULL, group_title TEXT NOT NULL, tvg_id TEXT NOT NULL, resolution TEXT NOT NULL, extra_info TEXT NOT NULL);
+        CREATE TABLE history (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, logo TEXT NOT NULL, url TEXT NOT NULL, group_title TEXT NOT NULL, tvg_id TEXT NOT NULL, resolution TEXT NOT NULL, extra_info TEXT NOT NULL, timestamp DATETIME DEFAULT CURRENT_TIMESTAMP);
+        CREATE TABLE channels (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, logo TEXT NOT NULL, url TEXT NOT NULL, group_title TEXT NOT NULL, tvg_id TEXT NOT NULL, resolution TEXT NOT NULL, extra_info TEXT NOT NULL);
+        CREATE VIRTUAL TABLE channels_fts USING fts5(name, content='channels', content_rowid='id');
+        CREATE TABLE settings (id INTEGER PRIMARY KEY, player_command TEXT NOT NULL, cache_duration_hours INTEGER NOT NULL DEFAULT 24, enable_preview BOOLEAN NOT NULL DEFAULT 1, mute_on_start BOOLEAN NOT NULL DEFAULT 0, show_controls BOOLEAN NOT NULL DEFAULT 1, autoplay BOOLEAN NOT NULL DEFAULT 0);
+        CREATE TABLE channel_lists (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, source TEXT NOT NULL, filepath TEXT, last_fetched INTEGER, is_default BOOLEAN NOT NULL DEFAULT 0, CONSTRAINT is_default_check CHECK (is_default IN (0, 1)));
+        CREATE UNIQUE INDEX idx_one_default_list ON channel_lists (is_default) WHERE is_default = 1;
+        CREATE TABLE group_selections (channel_list_id INTEGER NOT NULL, group_name TEXT NOT NULL, is_enabled BOOLEAN NOT NULL DEFAULT 1, PRIMARY KEY (channel_list_id, group_name));
+        CREATE TABLE saved_filters (channel_list_id INTEGER NOT NULL, slot_number INTEGER NOT NULL, search_query TEXT NOT NULL DEFAULT '', selected_group TEXT, name TEXT NOT NULL DEFAULT '', created_at DATETIME DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (channel_list_id, slot_number));
+        INSERT INTO settings (id, player_command, cache_duration_hours, enable_preview, mute_on_start, show_controls, autoplay) VALUES (1, 'vlc', 12, 0, 1, 1, 0);
+        INSERT INTO favorites (name, logo, url, group_title, tvg_id, resolution, extra_info) VALUES ('News', '', 'http://example.com/news.m3u8', 'News', '', '', '');
+        INSERT INTO channel_lists (name, source, is_default) VALUES ('iptv-org', 'https://iptv-org.github.io/iptv/index.m3u', 1);
+        INSERT INTO group_selections (channel_list_id, group_name, is_enabled) VALUES (1, 'News', 0);
+    ";
+
+    // Stream health and relay settings, before request options were stored
+    const SCHEMA_STREAM_HEALTH: &str = "
+        CREATE TABLE favorites (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, logo TEXT NOT NULL, url TEXT NOT NULL, group_title TEXT NOT NULL, tvg_id TEXT NOT NULL, resolution TEXT NOT NULL, extra_info TEXT NOT NULL);
+        CREATE TABLE history (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, logo TEXT NOT NULL, url TEXT NOT NULL, group_title TEXT NOT NULL, tvg_id TEXT NOT NULL, resolution TEXT NOT NULL, extra_info TEXT NOT NULL, timestamp DATETIME DEFAULT CURRENT_TIMESTAMP);
+        CREATE TABLE channels (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, logo TEXT NOT NULL, url TEXT NOT NULL, group_title TEXT NOT NULL, tvg_id TEXT NOT NULL, resolution TEXT NOT NULL, extra_info TEXT NOT NULL);
+        CREATE VIRTUAL TABLE channels_fts USING fts5(name, content='channels', content_rowid='id');
+        CREATE TABLE settings (id INTEGER PRIMARY KEY, player_command TEXT NOT NULL, cache_duration_hours INTEGER NOT NULL DEFAULT 24, enable_preview BOOLEAN NOT NULL DEFAULT 1, mute_on_start BOOLEAN NOT NULL DEFAULT 0, show_controls BOOLEAN NOT NULL DEFAULT 1, autoplay BOOLEAN NOT NULL DEFAULT 0, hide_dead_channels BOOLEAN NOT NULL DEFAULT 0);
+        CREATE TABLE channel_lists (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, source TEXT NOT NULL, filepath TEXT, last_fetched INTEGER, is_default BOOLEAN NOT NULL DEFAULT 0, CONSTRAINT is_default_check CHECK (is_default IN (0, 1)));
+        CREATE UNIQUE INDEX idx_one_default_list ON channel_lists (is_default) WHERE is_default = 1;
+        CREATE TABLE group_selections (channel_list_id INTEGER NOT NULL, group_name TEXT NOT NULL, is_enabled BOOLEAN NOT NULL DEFAULT 1, PRIMARY KEY (channel_list_id, group_name));
+        CREATE TABLE saved_filters (channel_list_id INTEGER NOT NULL, slot_number INTEGER NOT NULL, search_query TEXT NOT NULL DEFAULT '', selected_group TEXT, name TEXT NOT NULL DEFAULT '', created_at DATETIME DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (channel_list_id, slot_number));
+        CREATE TABLE channel_health (channel_list_id INTEGER NOT NULL, url TEXT NOT NULL, status TEXT NOT NULL, http_status INTEGER, latency_ms INTEGER, last_checked INTEGER NOT NULL, error TEXT, PRIMARY KEY (channel_list_id, url));
+        INSERT INTO settings (id, player_command, cache_duration_hours, enable_preview, mute_on_start, show_controls, autoplay, hide_dead_channels) VALUES (1, 'vlc', 12, 0, 1, 1, 0, 1);
+        INSERT INTO favorites (name, logo, url, group_title, tvg_id, resolution, extra_info) VALUES ('News', '', 'http://example.com/news.m3u8', 'News', '', '', '');
+        INSERT INTO channel_lists (name, source, is_default) VALUES ('iptv-org', 'https://iptv-org.github.io/iptv/index.m3u', 1);
+        INSERT INTO channel_health (channel_list_id, url, status, last_checked) VALUES (1, 'http://example.com/news.m3u8', 'dead', 0);
+    ";
+
+    const HISTORICAL_SCHEMAS: &[&str] = &[
+        "",
+        SCHEMA_INITIAL,
+        SCHEMA_SAVED_FILTERS,
+        SCHEMA_STREAM_HEALTH,
+    ];
+
+    fn table_exists(conn: &Connection, table: &str) -> bool {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE name = ?1",
+            [table],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            > 0
+    }
+
+    fn assert_latest_schema(conn: &Connection) {
+        assert_eq!(schema_version(conn).unwrap(), latest_version());
+        for table in [
+            "favorites",
+            "history",
+            "channels",
+            "channels_fts",
+            "settings",
+            "channel_lists",
+            "group_selections",
+            "saved_filters",
+            "channel_health",
+            "stream_variants",
+            "channel_source_order",
+            "player_failures",
+            "play_sessions",
+            "channels_trigram",
+            "playlist_changes",
+            "favorite_flags",
+            "channel_overrides",
+            "group_layout",
+            "group_aliases",
+            "group_rules",
+            "virtual_lists",
+            "virtual_list_members",
+            "playlist_request_configs",
+            "channel_list_mirrors",
+        ] {
+            assert!(table_exists(conn, table), "missing table {}", table);
+        }
+        for (table, column) in [
+            ("settings", "enable_preview"),
+            ("settings", "autoplay"),
+            ("settings", "hide_dead_channels"),
+            ("settings", "use_stream_relay"),
+            ("settings", "proxy_url"),
+            ("settings", "proxy_player"),
+            ("settings", "fetch_max_attempts"),
+            ("favorites", "http_options"),
+            ("history", "http_options"),
+            ("channels", "channel_list_id"),
+            ("channels", "identity"),
+            ("channel_lists", "refresh_interval_hours"),
+            ("channel_lists", "etag"),
+            ("channel_lists", "content_hash"),
+        ] {
+            assert!(
+                column_exists(conn, table, column).unwrap(),
+                "missing column {}.{}",
+                table,
+                column
+            );
+        }
+    }
+
+    #[test]
+    fn test_migrates_every_historical_schema() {
+        for schema in HISTORICAL_SCHEMAS {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(schema).unwrap();
+
+            assert_eq!(run_migrations(&mut conn, None).unwrap(), latest_version());
+            assert_latest_schema(&conn);
+
+            if schema.is_empty() {
+                continue;
+            }
+            // Existing data survives the upgrade
+            let (player, cache_hours): (String, i64) = conn
+                .query_row(
+                    "SELECT player_command, cache_duration_hours FROM settings WHERE id = 1",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!((player.as_str(), cache_hours), ("vlc", 12));
+            let favorite: (String, Option<String>) = conn
+                .query_row("SELECT name, http_options FROM favorites", [], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .unwrap();
+            assert_eq!(favorite, ("News".to_string(), None));
+        }
+    }
+
+    #[test]
+    fn test_per_list_channels_migration() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("database.sqlite");
+        let mut conn = Connection::open(&db_path).unwrap();
+        apply_migrations(&mut conn, &MIGRATIONS[..1], Some(&db_path)).unwrap();
+        conn.execute(
+            "INSERT INTO channel_lists (id, name, source) VALUES (1, 'iptv-org', 'http://example.com')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO channels (name, logo, url, group_title, tvg_id, resolution, extra_info)
+             VALUES ('News', '', 'http://example.com/news.m3u8', 'News', '', '', '')",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(
+            run_migrations(&mut conn, Some(&db_path)).unwrap(),
+            latest_version()
+        );
+        assert!(dir.path().join("database.sqlite.v1.bak").exists());
+
+        // Old rows are dropped and stored again per list the next time a list loads
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM channels", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+        conn.execute(
+            "INSERT INTO channels (channel_list_id, identity, position, name, logo, url, group_title, tvg_id, resolution, extra_info)
+             VALUES (1, 'a', 0, 'News', '', 'http://example.com/news.m3u8', 'News', '', '', '')",
+            [],
+        )
+        .unwrap();
+        let matches: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM channels_fts WHERE channels_fts MATCH 'news'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(matches, 1);
+    }
+
+    #[test]
+    fn test_search_indexes_cover_existing_channels() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply_migrations(&mut conn, &MIGRATIONS[..2], None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO channel_lists (id, name, source) VALUES (1, 'iptv-org', 'http://example.com');
+             INSERT INTO channels (channel_list_id, identity, position, name, logo, url, group_title, tvg_id, resolution, extra_info)
+             VALUES (1, 'a', 0, 'Eurosport', '', 'http://example.com/es.m3u8', 'Sports', '', '', '');",
+        )
+        .unwrap();
+
+        run_migrations(&mut conn, None).unwrap();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(
+            count("SELECT COUNT(*) FROM channels_trigram WHERE channels_trigram MATCH '\"rosp\"'"),
+            1
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM channels_fts WHERE channels_fts MATCH 'eu*'"),
+            1
+        );
+    }
+
+    #[test]
+    fn test_migrations_are_idempotent() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA_STREAM_HEALTH).unwrap();
+        run_migrations(&mut conn, None).unwrap();
+        assert_eq!(run_migrations(&mut conn, None).unwrap(), latest_version());
+
+        let hide_dead: bool = conn
+            .query_row("SELECT hide_dead_channels FROM settings", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(hide_dead);
+    }
+
+    #[test]
+    fn test_migration_versions_are_ordered() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version);
+        }
+        assert_eq!(MIGRATIONS[0].version, 1);
+    }
+
+    #[test]
+    fn test_rejects_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+
+        let error = run_migrations(&mut conn, None).unwrap_err();
+        assert!(matches!(error, TolloError::DatabaseMigration { .. }));
+        assert!(error.to_string().contains("newer"));
+    }
+
+    fn create_items_table(tx: &Transaction) -> RusqliteResult<()> {
+        tx.execute_batch(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+             INSERT INTO items (name) VALUES ('first');",
+        )
+    }
+
+    fn rename_items_column(tx: &Transaction) -> RusqliteResult<()> {
+        tx.execute_batch(
+            "CREATE TABLE items_new (id INTEGER PRIMARY KEY, title TEXT NOT NULL);
+             INSERT INTO items_new (id, title) SELECT id, name FROM items;
+             DROP TABLE items;
+             ALTER TABLE items_new RENAME TO items;",
+        )
+    }
+
+    fn broken_step(tx: &Transaction) -> RusqliteResult<()> {
+        tx.execute("INSERT INTO items (title) VALUES ('half done')", [])?;
+        tx.execute("INSERT INTO missing_table VALUES (1)", [])?;
+        Ok(())
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            description: "create items",
+            destructive: false,
+            up: create_items_table,
+        },
+        Migration {
+            version: 2,
+            description: "rename name to title",
+            destructive: true,
+            up: rename_items_column,
+        },
+        Migration {
+            version: 3,
+            description: "broken step",
+            destructive: false,
+            up: broken_step,
+        },
+    ];
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        let error = apply_migrations(&mut conn, TEST_MIGRATIONS, None).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("migration 3 (broken step) failed"));
+
+        // Earlier steps stay applied; the failed one leaves no trace
+        assert_eq!(schema_version(&conn).unwrap(), 2);
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert!(column_exists(&conn, "items", "title").unwrap());
+    }
+
+    #[test]
+    fn test_backup_before_destructive_migration() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("database.sqlite");
+        let mut conn = Connection::open(&db_path).unwrap();
+
+        apply_migrations(&mut conn, &TEST_MIGRATIONS[..2], Some(&db_path)).unwrap();
+        // The backup holds the state right before the destructive step
+        let backup_path = dir.path().join("database.sqlite.v1.bak");
+        assert!(backup_path.exists());
+
+        let backup = Connection::open(&backup_path).unwrap();
+        assert_eq!(schema_version(&backup).unwrap(), 1);
+        assert!(column_exists(&backup, "items", "name").unwrap());
+        assert!(column_exists(&conn, "items", "title").unwrap());
+    }
+}
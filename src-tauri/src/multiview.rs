@@ -0,0 +1,570 @@
+use crate::http_client::current_proxy_settings;
+use crate::m3u_parser::Channel;
+use crate::player::{self, is_mpv, is_vlc};
+use crate::player_failures::OutputBuffer;
+use crate::relay::RelayState;
+use crate::state::DbState;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Child;
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+// Used when the frontend does not pass a screen size and no monitor can be detected
+const FALLBACK_SCREEN_WIDTH: u32 = 1920;
+const FALLBACK_SCREEN_HEIGHT: u32 = 1080;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MultiviewLayout {
+    #[serde(rename = "2x1")]
+    SideBySide,
+    #[serde(rename = "2x2")]
+    Grid,
+    #[serde(rename = "1+3")]
+    MainPlusThree,
+}
+
+impl MultiviewLayout {
+    pub fn tile_count(&self) -> usize {
+        match self {
+            MultiviewLayout::SideBySide => 2,
+            MultiviewLayout::Grid | MultiviewLayout::MainPlusThree => 4,
+        }
+    }
+}
+
+/// Separate player windows per tile, or one mpv compositing all streams.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiviewMode {
+    Windows,
+    Single,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScreenArea {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tile {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Offset and size of part `index` when `total` is split in `parts`; the last part takes the remainder
+fn split(total: u32, parts: u32, index: u32) -> (u32, u32) {
+    let size = total / parts;
+    let offset = size * index;
+    if index == parts - 1 {
+        (offset, total - offset)
+    } else {
+        (offset, size)
+    }
+}
+
+pub fn compute_tiles(layout: MultiviewLayout, screen: ScreenArea) -> Vec<Tile> {
+    let tile = |x: u32, y: u32, width: u32, height: u32| Tile {
+        x: screen.x + x as i32,
+        y: screen.y + y as i32,
+        width,
+        height,
+    };
+
+    match layout {
+        MultiviewLayout::SideBySide => (0..2)
+            .map(|column| {
+                let (x, width) = split(screen.width, 2, column);
+                tile(x, 0, width, screen.height)
+            })
+            .collect(),
+        MultiviewLayout::Grid => (0..4)
+            .map(|index| {
+                let (x, width) = split(screen.width, 2, index % 2);
+                let (y, height) = split(screen.height, 2, index / 2);
+                tile(x, y, width, height)
+            })
+            .collect(),
+        MultiviewLayout::MainPlusThree => {
+            // Main stream on the left two thirds, three small ones stacked on the right
+            let main_width = screen.width * 2 / 3;
+            let side_width = screen.width - main_width;
+            let mut tiles = vec![tile(0, 0, main_width, screen.height)];
+            tiles.extend((0..3).map(|row| {
+                let (y, height) = split(screen.height, 3, row);
+                tile(main_width, y, side_width, height)
+            }));
+            tiles
+        }
+    }
+}
+
+/// Window placement flags for one tile. Only the first tile keeps its audio.
+pub fn tile_args(command: &str, tile: &Tile, primary: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    if is_mpv(command) {
+        args.push(format!(
+            "--geometry={}x{}+{}+{}",
+            tile.width,
+            tile.height,
+            tile.x.max(0),
+            tile.y.max(0)
+        ));
+        args.push("--no-border".to_string());
+        args.push("--keepaspect-window=no".to_string());
+        if !primary {
+            args.push("--mute=yes".to_string());
+        }
+    } else if is_vlc(command) {
+        args.push("--no-embedded-video".to_string());
+        args.push("--no-video-deco".to_string());
+        args.push(format!("--video-x={}", tile.x));
+        args.push(format!("--video-y={}", tile.y));
+        args.push(format!("--width={}", tile.width));
+        args.push(format!("--height={}", tile.height));
+        if !primary {
+            args.push("--no-audio".to_string());
+        }
+    }
+    args
+}
+
+/// Whether the streams go through the relay. A single mpv sends one set of
+/// request options for all its streams, so there any stream with options of
+/// its own has the relay send them instead.
+pub fn needs_relay(use_relay: bool, mode: MultiviewMode, channels: &[Channel]) -> bool {
+    use_relay
+        || (mode == MultiviewMode::Single
+            && channels.iter().any(|channel| {
+                channel
+                    .http_options
+                    .as_ref()
+                    .is_some_and(|options| !options.is_empty())
+            }))
+}
+
+/// Builds an mpv `--lavfi-complex` graph that scales each input into its tile
+/// and stacks them into one picture. Audio stays out of the graph, so mpv's
+/// normal track selection plays the first stream's audio when it has any.
+pub fn lavfi_complex_filter(tiles: &[Tile], screen: ScreenArea) -> String {
+    let scale = |index: usize, tile: &Tile, output: &str| {
+        format!(
+            "[vid{n}]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1[{output}]",
+            n = index + 1,
+            w = tile.width,
+            h = tile.height,
+            output = output
+        )
+    };
+
+    // xstack needs at least two inputs
+    if let [tile] = tiles {
+        return scale(0, tile, "vo");
+    }
+
+    let mut filter = String::new();
+    for (index, tile) in tiles.iter().enumerate() {
+        filter.push_str(&scale(index, tile, &format!("v{}", index + 1)));
+        filter.push(';');
+    }
+
+    let inputs: String = (1..=tiles.len()).map(|n| format!("[v{}]", n)).collect();
+    let positions: Vec<String> = tiles
+        .iter()
+        .map(|tile| format!("{}_{}", tile.x - screen.x, tile.y - screen.y))
+        .collect();
+    filter.push_str(&format!(
+        "{}xstack=inputs={}:layout={}:fill=black[vo]",
+        inputs,
+        tiles.len(),
+        positions.join("|")
+    ));
+    filter
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiviewGroupInfo {
+    pub id: String,
+    pub layout: MultiviewLayout,
+    pub mode: MultiviewMode,
+    pub channels: Vec<String>,
+    pub started_at: i64,
+    pub running_players: usize,
+}
+
+struct MultiviewGroup {
+    info: MultiviewGroupInfo,
+    children: Vec<Child>,
+}
+
+pub struct MultiviewState {
+    groups: Mutex<HashMap<String, MultiviewGroup>>,
+}
+
+impl MultiviewState {
+    pub fn new() -> Self {
+        Self {
+            groups: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+fn kill_children(children: &mut Vec<Child>) {
+    for mut child in children.drain(..) {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+fn resolve_screen(
+    app_handle: &AppHandle,
+    screen_width: Option<u32>,
+    screen_height: Option<u32>,
+) -> ScreenArea {
+    if let (Some(width), Some(height)) = (screen_width, screen_height) {
+        return ScreenArea {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+    }
+    match app_handle.primary_monitor() {
+        Ok(Some(monitor)) => ScreenArea {
+            x: monitor.position().x,
+            y: monitor.position().y,
+            width: monitor.size().width,
+            height: monitor.size().height,
+        },
+        _ => ScreenArea {
+            x: 0,
+            y: 0,
+            width: FALLBACK_SCREEN_WIDTH,
+            height: FALLBACK_SCREEN_HEIGHT,
+        },
+    }
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_multiview(
+    app_handle: AppHandle,
+    db_state: State<'_, DbState>,
+    relay_state: State<'_, RelayState>,
+    multiview_state: State<'_, MultiviewState>,
+    channels: Vec<Channel>,
+    layout: MultiviewLayout,
+    mode: Option<MultiviewMode>,
+    screen_width: Option<u32>,
+    screen_height: Option<u32>,
+) -> Result<MultiviewGroupInfo, String> {
+    if channels.is_empty() {
+        return Err("Select at least one channel for multiview".to_string());
+    }
+    if channels.len() > layout.tile_count() {
+        return Err(format!(
+            "Layout fits at most {} channels",
+            layout.tile_count()
+        ));
+    }
+
+    let (player_command, use_relay) = {
+        let db = db_state.reader();
+        (
+            player::get_player_command(&db),
+            player::use_stream_relay(&db),
+        )
+    };
+    let (command, mut args) = player::split_player_command(&player_command);
+    let mode = mode.unwrap_or(MultiviewMode::Windows);
+    if mode == MultiviewMode::Single && !is_mpv(&command) {
+        return Err("Single-window multiview requires mpv as the player".to_string());
+    }
+    let use_relay = needs_relay(use_relay, mode, &channels);
+    if !use_relay {
+        args.extend(player::proxy_args(&command, &current_proxy_settings()));
+    }
+
+    let screen = resolve_screen(&app_handle, screen_width, screen_height);
+    let tiles = compute_tiles(layout, screen);
+
+    let mut urls = Vec::with_capacity(channels.len());
+    for channel in &channels {
+        urls.push(if use_relay {
+            relay_state.register(channel, &channel.url).await?
+        } else {
+            channel.url.clone()
+        });
+    }
+
+    let mut children = Vec::new();
+    match mode {
+        MultiviewMode::Windows => {
+            for (index, (channel, url)) in channels.iter().zip(&urls).enumerate() {
+                let mut tile_launch_args = args.clone();
+                tile_launch_args.extend(tile_args(&command, &tiles[index], index == 0));
+                if let (false, Some(options)) = (use_relay, &channel.http_options) {
+                    tile_launch_args.extend(player::http_option_args(&command, options));
+                }
+
+                match player::spawn_player(&command, &tile_launch_args, url) {
+                    Ok(mut child) => {
+                        OutputBuffer::capture(&mut child);
+                        children.push(child);
+                    }
+                    Err(e) => {
+                        kill_children(&mut children);
+                        return Err(format!("Failed to launch video player: {}", e));
+                    }
+                }
+            }
+        }
+        MultiviewMode::Single => {
+            let used_tiles = &tiles[..channels.len()];
+            let mut single_args = args.clone();
+            single_args.push(format!(
+                "--geometry={}x{}+{}+{}",
+                screen.width,
+                screen.height,
+                screen.x.max(0),
+                screen.y.max(0)
+            ));
+            single_args.push(format!(
+                "--lavfi-complex={}",
+                lavfi_complex_filter(used_tiles, screen)
+            ));
+            single_args.extend(
+                urls[1..]
+                    .iter()
+                    .map(|url| format!("--external-file={}", url)),
+            );
+
+            let mut child = player::spawn_player(&command, &single_args, &urls[0])
+                .map_err(|e| format!("Failed to launch video player: {}", e))?;
+            OutputBuffer::capture(&mut child);
+            children.push(child);
+        }
+    }
+
+    let info = MultiviewGroupInfo {
+        id: Uuid::new_v4().to_string(),
+        layout,
+        mode,
+        channels: channels.iter().map(|c| c.name.clone()).collect(),
+        started_at: Utc::now().timestamp(),
+        running_players: children.len(),
+    };
+    println!(
+        "Started multiview {} with {} channels",
+        info.id,
+        info.channels.len()
+    );
+
+    multiview_state.groups.lock().unwrap().insert(
+        info.id.clone(),
+        MultiviewGroup {
+            info: info.clone(),
+            children,
+        },
+    );
+    Ok(info)
+}
+
+#[tauri::command]
+pub fn get_multiview_groups(
+    multiview_state: State<MultiviewState>,
+) -> Result<Vec<MultiviewGroupInfo>, String> {
+    let mut groups = multiview_state.groups.lock().unwrap();
+
+    // Forget players the user already closed, and groups with none left
+    for group in groups.values_mut() {
+        group
+            .children
+            .retain_mut(|child| matches!(child.try_wait(), Ok(None)));
+        group.info.running_players = group.children.len();
+    }
+    groups.retain(|_, group| !group.children.is_empty());
+
+    let mut infos: Vec<MultiviewGroupInfo> = groups.values().map(|g| g.info.clone()).collect();
+    infos.sort_by_key(|info| info.started_at);
+    Ok(infos)
+}
+
+#[tauri::command]
+pub fn close_multiview(multiview_state: State<MultiviewState>, id: String) -> Result<(), String> {
+    let group = multiview_state.groups.lock().unwrap().remove(&id);
+    match group {
+        Some(mut group) => {
+            kill_children(&mut group.children);
+            Ok(())
+        }
+        None => Err("Multiview group not found".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::m3u_parser::StreamHttpOptions;
+    use crate::test_support::channel;
+
+    const SCREEN: ScreenArea = ScreenArea {
+        x: 0,
+        y: 0,
+        width: 1921,
+        height: 1081,
+    };
+
+    // Tiles must cover the whole screen without overlapping
+    fn assert_covers_screen(tiles: &[Tile], screen: ScreenArea) {
+        let area: u64 = tiles.iter().map(|t| t.width as u64 * t.height as u64).sum();
+        assert_eq!(area, screen.width as u64 * screen.height as u64);
+        for tile in tiles {
+            assert!(tile.x >= screen.x && tile.y >= screen.y);
+            assert!(tile.x + tile.width as i32 <= screen.x + screen.width as i32);
+            assert!(tile.y + tile.height as i32 <= screen.y + screen.height as i32);
+        }
+    }
+
+    #[test]
+    fn test_side_by_side_tiles() {
+        let tiles = compute_tiles(MultiviewLayout::SideBySide, SCREEN);
+        assert_eq!(tiles.len(), 2);
+        assert_eq!(
+            tiles[0],
+            Tile {
+                x: 0,
+                y: 0,
+                width: 960,
+                height: 1081
+            }
+        );
+        assert_eq!(
+            tiles[1],
+            Tile {
+                x: 960,
+                y: 0,
+                width: 961,
+                height: 1081
+            }
+        );
+        assert_covers_screen(&tiles, SCREEN);
+    }
+
+    #[test]
+    fn test_grid_tiles() {
+        let tiles = compute_tiles(MultiviewLayout::Grid, SCREEN);
+        assert_eq!(tiles.len(), 4);
+        assert_eq!((tiles[3].x, tiles[3].y), (960, 540));
+        assert_covers_screen(&tiles, SCREEN);
+    }
+
+    #[test]
+    fn test_main_plus_three_tiles() {
+        let tiles = compute_tiles(MultiviewLayout::MainPlusThree, SCREEN);
+        assert_eq!(tiles.len(), 4);
+        assert_eq!(tiles[0].width, 1280);
+        assert_eq!(tiles[0].height, 1081);
+        assert!(tiles[1..].iter().all(|t| t.x == 1280 && t.width == 641));
+        assert_covers_screen(&tiles, SCREEN);
+    }
+
+    #[test]
+    fn test_tiles_follow_monitor_position() {
+        let screen = ScreenArea {
+            x: 1920,
+            y: 0,
+            width: 2560,
+            height: 1440,
+        };
+        let tiles = compute_tiles(MultiviewLayout::Grid, screen);
+        assert_eq!((tiles[0].x, tiles[1].x), (1920, 3200));
+        assert_covers_screen(&tiles, screen);
+    }
+
+    #[test]
+    fn test_tile_args() {
+        let tile = Tile {
+            x: 960,
+            y: 540,
+            width: 960,
+            height: 540,
+        };
+
+        let args = tile_args("mpv", &tile, false);
+        assert!(args.contains(&"--geometry=960x540+960+540".to_string()));
+        assert!(args.contains(&"--mute=yes".to_string()));
+        assert!(!tile_args("mpv", &tile, true).contains(&"--mute=yes".to_string()));
+
+        let args = tile_args("vlc", &tile, true);
+        assert!(args.contains(&"--video-x=960".to_string()));
+        assert!(args.contains(&"--height=540".to_string()));
+
+        assert!(tile_args("ffplay", &tile, true).is_empty());
+    }
+
+    #[test]
+    fn test_single_window_relays_streams_with_options() {
+        let plain = channel("News", "http://example.com/news.m3u8", "");
+        let protected = Channel {
+            http_options: Some(StreamHttpOptions {
+                referrer: Some("http://example.com/".to_string()),
+                ..Default::default()
+            }),
+            ..channel("Sport", "http://example.com/sport.m3u8", "")
+        };
+
+        let mixed = [plain.clone(), protected];
+        assert!(!needs_relay(
+            false,
+            MultiviewMode::Single,
+            std::slice::from_ref(&plain)
+        ));
+        assert!(needs_relay(false, MultiviewMode::Single, &mixed));
+        // Separate windows each get their own player flags
+        assert!(!needs_relay(false, MultiviewMode::Windows, &mixed));
+        assert!(needs_relay(true, MultiviewMode::Windows, &[plain]));
+    }
+
+    #[test]
+    fn test_lavfi_complex_filter() {
+        let screen = ScreenArea {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+        let tiles = compute_tiles(MultiviewLayout::SideBySide, screen);
+        let filter = lavfi_complex_filter(&tiles, screen);
+
+        assert!(filter.starts_with("[vid1]scale=960:1080:"));
+        assert!(filter.contains("[vid2]scale=960:1080:"));
+        assert!(filter.contains("[v1][v2]xstack=inputs=2:layout=0_0|960_0"));
+        assert!(filter.ends_with("[vo]"));
+        assert!(!filter.contains("aid"));
+
+        // A single channel is only scaled, xstack rejects one input
+        let filter = lavfi_complex_filter(&tiles[..1], screen);
+        assert!(filter.starts_with("[vid1]scale=960:1080:"));
+        assert!(filter.ends_with("setsar=1[vo]"));
+        assert!(!filter.contains("xstack"));
+    }
+
+    #[test]
+    fn test_layout_serialization() {
+        assert_eq!(
+            serde_json::to_string(&MultiviewLayout::MainPlusThree).unwrap(),
+            r#""1+3""#
+        );
+        let layout: MultiviewLayout = serde_json::from_str(r#""2x2""#).unwrap();
+        assert_eq!(layout, MultiviewLayout::Grid);
+        assert_eq!(layout.tile_count(), 4);
+    }
+}
@@ -0,0 +1,223 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Overrides the data directory: `--data-dir <path>` or `--data-dir=<path>`.
+pub const DATA_DIR_FLAG: &str = "--data-dir";
+/// Keeps all data in a `data` directory next to the executable.
+pub const PORTABLE_FLAG: &str = "--portable";
+pub const DATA_DIR_ENV: &str = "TOLLO_DATA_DIR";
+/// A file with this name next to the executable turns on portable mode without a flag.
+pub const PORTABLE_MARKER: &str = "tollo.portable";
+
+const PORTABLE_DIR_NAME: &str = "data";
+
+static DATA_PATHS: OnceLock<DataPaths> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataLocation {
+    /// The platform data directory, e.g. `~/.local/share/tollo`.
+    Default,
+    /// Given on the command line or in `TOLLO_DATA_DIR`.
+    Custom,
+    Portable,
+}
+
+/// Where the database, downloaded channel lists and cached images are stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataPaths {
+    root: PathBuf,
+    location: DataLocation,
+}
+
+impl DataPaths {
+    pub fn new(root: impl Into<PathBuf>, location: DataLocation) -> Self {
+        Self {
+            root: root.into(),
+            location,
+        }
+    }
+
+    /// Picks the data directory. In order: the `--data-dir` flag, the
+    /// `--portable` flag, `TOLLO_DATA_DIR`, a portable marker next to the
+    /// executable, then the platform data directory.
+    pub fn resolve<I>(args: I, env_dir: Option<String>, exe_dir: Option<&Path>) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut flag_dir = None;
+        let mut portable = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == DATA_DIR_FLAG {
+                flag_dir = args.next();
+            } else if let Some(dir) = arg.strip_prefix("--data-dir=") {
+                flag_dir = Some(dir.to_string());
+            } else if arg == PORTABLE_FLAG {
+                portable = true;
+            }
+        }
+
+        let portable_root = || {
+            exe_dir
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join(PORTABLE_DIR_NAME)
+        };
+
+        if let Some(dir) = flag_dir.filter(|dir| !dir.trim().is_empty()) {
+            return Self::new(dir, DataLocation::Custom);
+        }
+        if portable {
+            return Self::new(portable_root(), DataLocation::Portable);
+        }
+        if let Some(dir) = env_dir.filter(|dir| !dir.trim().is_empty()) {
+            return Self::new(dir, DataLocation::Custom);
+        }
+        if exe_dir.is_some_and(|dir| dir.join(PORTABLE_MARKER).exists()) {
+            return Self::new(portable_root(), DataLocation::Portable);
+        }
+        match dirs::data_dir() {
+            Some(dir) => Self::new(dir.join("tollo"), DataLocation::Default),
+            // Without a platform data directory, keep the data with the program
+            None => Self::new(portable_root(), DataLocation::Portable),
+        }
+    }
+
+    /// Resolves from the process arguments, environment and executable location.
+    pub fn from_env() -> Self {
+        let exe_dir = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf));
+        Self::resolve(
+            env::args().skip(1),
+            env::var(DATA_DIR_ENV).ok(),
+            exe_dir.as_deref(),
+        )
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn location(&self) -> DataLocation {
+        self.location
+    }
+
+    pub fn database_file(&self) -> PathBuf {
+        self.root.join("database.sqlite")
+    }
+
+    pub fn channel_lists_dir(&self) -> PathBuf {
+        self.root.join("channel_lists")
+    }
+
+    pub fn channel_list_file(&self, filename: &str) -> PathBuf {
+        self.channel_lists_dir().join(filename)
+    }
+
+    pub fn image_cache_dir(&self) -> PathBuf {
+        self.root.join("image_cache")
+    }
+
+    /// Key that encrypts stored playlist credentials.
+    pub fn secret_key_file(&self) -> PathBuf {
+        self.root.join("secret.key")
+    }
+}
+
+/// Sets the data paths for the rest of the process. Returns false if they were
+/// already in use, in which case the earlier paths stay.
+pub fn init_data_paths(paths: DataPaths) -> bool {
+    DATA_PATHS.set(paths).is_ok()
+}
+
+/// The data paths of this process. Unit tests get a scratch directory so they
+/// never touch the user's data.
+pub fn data_paths() -> &'static DataPaths {
+    DATA_PATHS.get_or_init(|| {
+        if cfg!(test) {
+            let root = env::temp_dir().join(format!("tollo-test-{}", std::process::id()));
+            DataPaths::new(root, DataLocation::Custom)
+        } else {
+            DataPaths::from_env()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_data_dir_flag() {
+        let paths = DataPaths::resolve(args(&["--data-dir", "/srv/tollo"]), None, None);
+        assert_eq!(paths.root(), Path::new("/srv/tollo"));
+        assert_eq!(paths.location(), DataLocation::Custom);
+
+        let paths = DataPaths::resolve(
+            args(&["--data-dir=/srv/tollo", "--portable"]),
+            Some("/env/tollo".to_string()),
+            None,
+        );
+        assert_eq!(paths.root(), Path::new("/srv/tollo"));
+    }
+
+    #[test]
+    fn test_portable_flag_wins_over_environment() {
+        let exe_dir = TempDir::new().unwrap();
+        let paths = DataPaths::resolve(
+            args(&["--portable"]),
+            Some("/env/tollo".to_string()),
+            Some(exe_dir.path()),
+        );
+        assert_eq!(paths.root(), exe_dir.path().join("data"));
+        assert_eq!(paths.location(), DataLocation::Portable);
+    }
+
+    #[test]
+    fn test_environment_variable() {
+        let paths = DataPaths::resolve(args(&[]), Some("/env/tollo".to_string()), None);
+        assert_eq!(paths.root(), Path::new("/env/tollo"));
+
+        // An empty variable is ignored
+        let paths = DataPaths::resolve(args(&[]), Some(" ".to_string()), None);
+        assert_ne!(paths.root(), Path::new(" "));
+    }
+
+    #[test]
+    fn test_portable_marker() {
+        let exe_dir = TempDir::new().unwrap();
+        let paths = DataPaths::resolve(args(&[]), None, Some(exe_dir.path()));
+        assert_ne!(paths.location(), DataLocation::Portable);
+
+        std::fs::write(exe_dir.path().join(PORTABLE_MARKER), "").unwrap();
+        let paths = DataPaths::resolve(args(&["--unrelated"]), None, Some(exe_dir.path()));
+        assert_eq!(paths.root(), exe_dir.path().join("data"));
+        assert_eq!(paths.location(), DataLocation::Portable);
+    }
+
+    #[test]
+    fn test_everything_lives_under_the_root() {
+        let paths = DataPaths::new("/srv/tollo", DataLocation::Custom);
+        assert_eq!(
+            paths.database_file(),
+            Path::new("/srv/tollo/database.sqlite")
+        );
+        assert_eq!(
+            paths.channel_list_file("list.m3u"),
+            Path::new("/srv/tollo/channel_lists/list.m3u")
+        );
+        assert_eq!(paths.image_cache_dir(), Path::new("/srv/tollo/image_cache"));
+    }
+
+    #[test]
+    fn test_tests_do_not_use_the_user_directory() {
+        assert!(data_paths().root().starts_with(env::temp_dir()));
+    }
+}
@@ -0,0 +1,524 @@
+use crate::m3u_parser::Channel;
+use crate::state::DbState;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::process::Child;
+use tauri::{AppHandle, Manager, State};
+
+const DEFAULT_STATS_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaySession {
+    pub id: i64,
+    pub channel_name: String,
+    pub channel_url: String,
+    pub group_title: String,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    pub duration_seconds: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelWatchStats {
+    pub channel_name: String,
+    pub channel_url: String,
+    pub group_title: String,
+    pub sessions: i64,
+    pub total_seconds: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupWatchStats {
+    pub group_title: String,
+    pub sessions: i64,
+    pub total_seconds: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchPeriod {
+    Day,
+    Week,
+}
+
+impl WatchPeriod {
+    fn strftime_format(&self) -> &'static str {
+        match self {
+            WatchPeriod::Day => "%Y-%m-%d",
+            WatchPeriod::Week => "%Y-W%W",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchTimeBucket {
+    /// `YYYY-MM-DD` for days, `YYYY-Www` for weeks, in local time
+    pub period: String,
+    pub sessions: i64,
+    pub total_seconds: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchSummary {
+    pub sessions: i64,
+    pub total_seconds: i64,
+    pub average_session_seconds: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+pub fn start_play_session(
+    conn: &Connection,
+    channel: &Channel,
+    player_pid: Option<u32>,
+    started_at: i64,
+) -> RusqliteResult<i64> {
+    conn.execute(
+        "INSERT INTO play_sessions (channel_name, channel_url, group_title, player_pid, started_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            channel.name,
+            channel.url,
+            channel.group_title,
+            player_pid,
+            started_at
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Closes the open sessions of a player process. Only sessions started since
+/// `launched_at` are touched, so a reused pid from an earlier run is left alone.
+pub fn end_player_sessions(
+    conn: &Connection,
+    player_pid: u32,
+    launched_at: i64,
+    ended_at: i64,
+) -> RusqliteResult<usize> {
+    conn.execute(
+        "UPDATE play_sessions SET ended_at = ?1, duration_seconds = MAX(?1 - started_at, 0)
+         WHERE player_pid = ?2 AND ended_at IS NULL AND started_at >= ?3",
+        params![ended_at, player_pid, launched_at],
+    )
+}
+
+fn end_play_session(conn: &Connection, id: i64, ended_at: i64) -> RusqliteResult<()> {
+    conn.execute(
+        "UPDATE play_sessions SET ended_at = ?1, duration_seconds = MAX(?1 - started_at, 0)
+         WHERE id = ?2",
+        params![ended_at, id],
+    )?;
+    Ok(())
+}
+
+/// Ends the session of the running player and starts one for `channel` in the
+/// same process, for channel changes made over IPC without a relaunch.
+pub fn switch_play_session(conn: &Connection, channel: &Channel, now: i64) -> RusqliteResult<()> {
+    let open: Option<(i64, u32)> = conn
+        .query_row(
+            "SELECT id, player_pid FROM play_sessions
+             WHERE ended_at IS NULL AND player_pid IS NOT NULL
+             ORDER BY started_at DESC, id DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    if let Some((id, player_pid)) = open {
+        end_play_session(conn, id, now)?;
+        start_play_session(conn, channel, Some(player_pid), now)?;
+    }
+    Ok(())
+}
+
+/// Logs a session for a player launched at `launched_at` and closes it once
+/// the process exits. A player that already exited, successfully, within the
+/// launch window gets a finished session.
+pub fn track_player_session(
+    app_handle: &AppHandle,
+    channel: &Channel,
+    child: Option<Child>,
+    launched_at: i64,
+) {
+    let db_state = app_handle.state::<DbState>();
+    let Some(mut child) = child else {
+        let db = db_state.db.lock().unwrap();
+        let recorded = start_play_session(&db, channel, None, launched_at)
+            .and_then(|id| end_play_session(&db, id, Utc::now().timestamp()));
+        if let Err(e) = recorded {
+            eprintln!("Warning: Failed to record play session: {}", e);
+        }
+        return;
+    };
+
+    let player_pid = child.id();
+    {
+        let db = db_state.db.lock().unwrap();
+        if let Err(e) = start_play_session(&db, channel, Some(player_pid), launched_at) {
+            eprintln!("Warning: Failed to record play session: {}", e);
+            return;
+        }
+    }
+
+    let app_handle = app_handle.clone();
+    std::thread::spawn(move || {
+        let _ = child.wait();
+        let db_state = app_handle.state::<DbState>();
+        let db = db_state.db.lock().unwrap();
+        if let Err(e) = end_player_sessions(&db, player_pid, launched_at, Utc::now().timestamp()) {
+            eprintln!("Warning: Failed to close play session: {}", e);
+        }
+    });
+}
+
+pub fn load_play_sessions(
+    conn: &Connection,
+    since: Option<i64>,
+) -> RusqliteResult<Vec<PlaySession>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, channel_name, channel_url, group_title, started_at, ended_at, duration_seconds
+         FROM play_sessions WHERE started_at >= ?1 ORDER BY started_at, id",
+    )?;
+    let sessions = stmt
+        .query_map([since.unwrap_or(0)], |row| {
+            Ok(PlaySession {
+                id: row.get(0)?,
+                channel_name: row.get(1)?,
+                channel_url: row.get(2)?,
+                group_title: row.get(3)?,
+                started_at: row.get(4)?,
+                ended_at: row.get(5)?,
+                duration_seconds: row.get(6)?,
+            })
+        })?
+        .collect();
+    sessions
+}
+
+// Statistics only count finished sessions; a running player has no duration yet
+pub fn most_watched_channels(
+    conn: &Connection,
+    since: Option<i64>,
+    limit: usize,
+) -> RusqliteResult<Vec<ChannelWatchStats>> {
+    let mut stmt = conn.prepare(
+        "SELECT channel_name, channel_url, MAX(group_title), COUNT(*), SUM(duration_seconds) AS total
+         FROM play_sessions
+         WHERE duration_seconds IS NOT NULL AND started_at >= ?1
+         GROUP BY channel_name, channel_url
+         ORDER BY total DESC, channel_name
+         LIMIT ?2",
+    )?;
+    let stats = stmt
+        .query_map(params![since.unwrap_or(0), limit as i64], |row| {
+            Ok(ChannelWatchStats {
+                channel_name: row.get(0)?,
+                channel_url: row.get(1)?,
+                group_title: row.get(2)?,
+                sessions: row.get(3)?,
+                total_seconds: row.get(4)?,
+            })
+        })?
+        .collect();
+    stats
+}
+
+pub fn most_watched_groups(
+    conn: &Connection,
+    since: Option<i64>,
+    limit: usize,
+) -> RusqliteResult<Vec<GroupWatchStats>> {
+    let mut stmt = conn.prepare(
+        "SELECT group_title, COUNT(*), SUM(duration_seconds) AS total
+         FROM play_sessions
+         WHERE duration_seconds IS NOT NULL AND started_at >= ?1
+         GROUP BY group_title
+         ORDER BY total DESC, group_title
+         LIMIT ?2",
+    )?;
+    let stats = stmt
+        .query_map(params![since.unwrap_or(0), limit as i64], |row| {
+            Ok(GroupWatchStats {
+                group_title: row.get(0)?,
+                sessions: row.get(1)?,
+                total_seconds: row.get(2)?,
+            })
+        })?
+        .collect();
+    stats
+}
+
+pub fn watch_time_by_period(
+    conn: &Connection,
+    period: WatchPeriod,
+    since: Option<i64>,
+) -> RusqliteResult<Vec<WatchTimeBucket>> {
+    let mut stmt = conn.prepare(
+        "SELECT strftime(?1, started_at, 'unixepoch', 'localtime') AS period,
+                COUNT(*), SUM(duration_seconds)
+         FROM play_sessions
+         WHERE duration_seconds IS NOT NULL AND started_at >= ?2
+         GROUP BY period
+         ORDER BY period",
+    )?;
+    let buckets = stmt
+        .query_map(
+            params![period.strftime_format(), since.unwrap_or(0)],
+            |row| {
+                Ok(WatchTimeBucket {
+                    period: row.get(0)?,
+                    sessions: row.get(1)?,
+                    total_seconds: row.get(2)?,
+                })
+            },
+        )?
+        .collect();
+    buckets
+}
+
+pub fn watch_summary(conn: &Connection, since: Option<i64>) -> RusqliteResult<WatchSummary> {
+    conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(duration_seconds), 0), COALESCE(AVG(duration_seconds), 0.0)
+         FROM play_sessions
+         WHERE duration_seconds IS NOT NULL AND started_at >= ?1",
+        [since.unwrap_or(0)],
+        |row| {
+            Ok(WatchSummary {
+                sessions: row.get(0)?,
+                total_seconds: row.get(1)?,
+                average_session_seconds: row.get(2)?,
+            })
+        },
+    )
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn sessions_to_csv(sessions: &[PlaySession]) -> String {
+    let mut csv = String::from(
+        "id,channel_name,channel_url,group_title,started_at,ended_at,duration_seconds\n",
+    );
+    for session in sessions {
+        let optional = |value: Option<i64>| value.map(|v| v.to_string()).unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            session.id,
+            csv_field(&session.channel_name),
+            csv_field(&session.channel_url),
+            csv_field(&session.group_title),
+            session.started_at,
+            optional(session.ended_at),
+            optional(session.duration_seconds)
+        ));
+    }
+    csv
+}
+
+#[tauri::command]
+pub fn get_play_sessions(
+    state: State<DbState>,
+    since: Option<i64>,
+) -> Result<Vec<PlaySession>, String> {
+    let db = state.reader();
+    load_play_sessions(&db, since).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_most_watched_channels(
+    state: State<DbState>,
+    since: Option<i64>,
+    limit: Option<usize>,
+) -> Result<Vec<ChannelWatchStats>, String> {
+    let db = state.reader();
+    most_watched_channels(&db, since, limit.unwrap_or(DEFAULT_STATS_LIMIT))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_most_watched_groups(
+    state: State<DbState>,
+    since: Option<i64>,
+    limit: Option<usize>,
+) -> Result<Vec<GroupWatchStats>, String> {
+    let db = state.reader();
+    most_watched_groups(&db, since, limit.unwrap_or(DEFAULT_STATS_LIMIT)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_watch_time(
+    state: State<DbState>,
+    period: WatchPeriod,
+    since: Option<i64>,
+) -> Result<Vec<WatchTimeBucket>, String> {
+    let db = state.reader();
+    watch_time_by_period(&db, period, since).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_watch_summary(
+    state: State<DbState>,
+    since: Option<i64>,
+) -> Result<WatchSummary, String> {
+    let db = state.reader();
+    watch_summary(&db, since).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn export_play_sessions(
+    state: State<DbState>,
+    path: String,
+    format: ExportFormat,
+    since: Option<i64>,
+) -> Result<usize, String> {
+    let sessions = {
+        let db = state.reader();
+        load_play_sessions(&db, since).map_err(|e| e.to_string())?
+    };
+
+    let content = match format {
+        ExportFormat::Csv => sessions_to_csv(&sessions),
+        ExportFormat::Json => serde_json::to_string_pretty(&sessions).map_err(|e| e.to_string())?,
+    };
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    Ok(sessions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{channel, migrated_db};
+
+    const DAY: i64 = 24 * 60 * 60;
+    // Noon UTC, so local-time bucketing stays on the same date in most time zones
+    const BASE: i64 = 1_700_000_000 - 1_700_000_000 % DAY + DAY / 2;
+
+    fn create_channel(name: &str, group: &str) -> Channel {
+        channel(name, &format!("http://example.com/{}.m3u8", name), group)
+    }
+
+    fn add_finished_session(conn: &Connection, channel: &Channel, started_at: i64, seconds: i64) {
+        start_play_session(conn, channel, Some(100), started_at).unwrap();
+        end_player_sessions(conn, 100, started_at, started_at + seconds).unwrap();
+    }
+
+    #[test]
+    fn test_session_lifecycle() {
+        let conn = migrated_db();
+        let news = create_channel("News", "News");
+        start_play_session(&conn, &news, Some(42), BASE).unwrap();
+
+        // A stale open session from an earlier run with the same pid is not closed
+        start_play_session(&conn, &news, Some(42), BASE - DAY).unwrap();
+
+        assert_eq!(end_player_sessions(&conn, 42, BASE, BASE + 90).unwrap(), 1);
+        let sessions = load_play_sessions(&conn, Some(BASE)).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].ended_at, Some(BASE + 90));
+        assert_eq!(sessions[0].duration_seconds, Some(90));
+    }
+
+    #[test]
+    fn test_switch_play_session() {
+        let conn = migrated_db();
+        start_play_session(&conn, &create_channel("News", "News"), Some(7), BASE).unwrap();
+        switch_play_session(&conn, &create_channel("Sports", "Sports"), BASE + 60).unwrap();
+        end_player_sessions(&conn, 7, BASE, BASE + 100).unwrap();
+
+        let sessions = load_play_sessions(&conn, None).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].duration_seconds, Some(60));
+        assert_eq!(sessions[1].channel_name, "Sports");
+        assert_eq!(sessions[1].duration_seconds, Some(40));
+    }
+
+    #[test]
+    fn test_most_watched_channels_and_groups() {
+        let conn = migrated_db();
+        let news = create_channel("News", "News");
+        let sports = create_channel("Sports", "Sports");
+        let weather = create_channel("Weather", "News");
+        add_finished_session(&conn, &news, BASE, 100);
+        add_finished_session(&conn, &news, BASE + 200, 100);
+        add_finished_session(&conn, &sports, BASE + 400, 150);
+        add_finished_session(&conn, &weather, BASE + 600, 120);
+        // Still running, so not counted
+        start_play_session(&conn, &sports, Some(1), BASE + 800).unwrap();
+
+        let channels = most_watched_channels(&conn, None, 2).unwrap();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].channel_name, "News");
+        assert_eq!((channels[0].sessions, channels[0].total_seconds), (2, 200));
+        assert_eq!(channels[1].channel_name, "Sports");
+
+        let groups = most_watched_groups(&conn, None, 10).unwrap();
+        assert_eq!(groups[0].group_title, "News");
+        assert_eq!((groups[0].sessions, groups[0].total_seconds), (3, 320));
+        assert_eq!(groups[1].total_seconds, 150);
+
+        let recent = most_watched_channels(&conn, Some(BASE + 300), 10).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert!(recent.iter().all(|c| c.channel_name != "News"));
+    }
+
+    #[test]
+    fn test_watch_time_and_summary() {
+        let conn = migrated_db();
+        let news = create_channel("News", "News");
+        add_finished_session(&conn, &news, BASE, 60);
+        add_finished_session(&conn, &news, BASE + 60, 120);
+        add_finished_session(&conn, &news, BASE + 3 * DAY, 30);
+
+        let days = watch_time_by_period(&conn, WatchPeriod::Day, None).unwrap();
+        assert_eq!(days.len(), 2);
+        assert_eq!((days[0].sessions, days[0].total_seconds), (2, 180));
+        assert_eq!(days[1].total_seconds, 30);
+
+        let weeks = watch_time_by_period(&conn, WatchPeriod::Week, None).unwrap();
+        assert_eq!(weeks.iter().map(|w| w.total_seconds).sum::<i64>(), 210);
+        assert!(weeks[0].period.contains("-W"));
+
+        let summary = watch_summary(&conn, None).unwrap();
+        assert_eq!(summary.sessions, 3);
+        assert_eq!(summary.total_seconds, 210);
+        assert_eq!(summary.average_session_seconds, 70.0);
+
+        let empty = watch_summary(&migrated_db(), None).unwrap();
+        assert_eq!(empty.sessions, 0);
+        assert_eq!(empty.average_session_seconds, 0.0);
+    }
+
+    #[test]
+    fn test_sessions_to_csv() {
+        let sessions = vec![PlaySession {
+            id: 1,
+            channel_name: "News, \"Live\"".to_string(),
+            channel_url: "http://example.com/news.m3u8".to_string(),
+            group_title: "News".to_string(),
+            started_at: BASE,
+            ended_at: None,
+            duration_seconds: None,
+        }];
+        let csv = sessions_to_csv(&sessions);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            format!(
+                "1,\"News, \"\"Live\"\"\",http://example.com/news.m3u8,News,{},,",
+                BASE
+            )
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use crate::channels::play_channel;
+use crate::history::add_to_history;
+use crate::m3u_parser::Channel;
+use crate::play_sessions::switch_play_session;
+use crate::player::{mpv_command, mpv_loadfile_command, use_stream_relay};
+use crate::player_failures::PlayerFailure;
+use crate::relay::RelayState;
+use crate::state::{ChannelCacheState, DbState};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+/// Where the channels being zapped through came from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum PlaybackSource {
+    Search { query: String },
+    Group { name: String },
+    Favorites,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaybackSession {
+    pub source: PlaybackSource,
+    pub channels: Vec<Channel>,
+    pub current_index: usize,
+}
+
+impl PlaybackSession {
+    /// Moves `offset` channels forward or backward, wrapping at both ends.
+    pub fn step(&mut self, offset: isize) -> Option<&Channel> {
+        if self.channels.is_empty() {
+            return None;
+        }
+        let len = self.channels.len() as isize;
+        self.current_index = (self.current_index as isize + offset).rem_euclid(len) as usize;
+        self.channels.get(self.current_index)
+    }
+
+    /// Points the session at the channel with `url`, returning false if it is not queued.
+    pub fn sync_to_url(&mut self, url: &str) -> bool {
+        match self.channels.iter().position(|c| c.url == url) {
+            Some(index) => {
+                self.current_index = index;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlaybackSessionInfo {
+    pub source: PlaybackSource,
+    pub current_index: usize,
+    pub total: usize,
+    pub current_channel: Option<Channel>,
+}
+
+pub struct PlaybackState {
+    pub session: Mutex<Option<PlaybackSession>>,
+    // IPC server of the mpv instance launched last, if the player is mpv
+    pub ipc_path: Mutex<Option<PathBuf>>,
+}
+
+impl PlaybackState {
+    pub fn new() -> Self {
+        Self {
+            session: Mutex::new(None),
+            ipc_path: Mutex::new(None),
+        }
+    }
+
+    /// Records a successful launch so zapping can reach the new player instance.
+    pub fn player_started(&self, ipc_path: Option<PathBuf>, url: &str) {
+        *self.ipc_path.lock().unwrap() = ipc_path;
+        if let Some(ref mut session) = *self.session.lock().unwrap() {
+            session.sync_to_url(url);
+        }
+    }
+}
+
+#[tauri::command]
+pub fn set_playback_queue(
+    playback_state: State<PlaybackState>,
+    source: PlaybackSource,
+    channels: Vec<Channel>,
+    current_index: Option<usize>,
+) -> Result<(), String> {
+    let current_index = current_index.unwrap_or(0);
+    if !channels.is_empty() && current_index >= channels.len() {
+        return Err("Current index is out of range".to_string());
+    }
+
+    *playback_state.session.lock().unwrap() = Some(PlaybackSession {
+        source,
+        channels,
+        current_index,
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_playback_session(
+    playback_state: State<PlaybackState>,
+) -> Result<Option<PlaybackSessionInfo>, String> {
+    let session = playback_state.session.lock().unwrap();
+    Ok(session.as_ref().map(|session| PlaybackSessionInfo {
+        source: session.source.clone(),
+        current_index: session.current_index,
+        total: session.channels.len(),
+        current_channel: session.channels.get(session.current_index).cloned(),
+    }))
+}
+
+#[tauri::command]
+pub fn clear_playback_session(playback_state: State<PlaybackState>) -> Result<(), String> {
+    *playback_state.session.lock().unwrap() = None;
+    Ok(())
+}
+
+async fn play_step(
+    app_handle: AppHandle,
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    playback_state: State<'_, PlaybackState>,
+    relay_state: State<'_, RelayState>,
+    offset: isize,
+) -> Result<Channel, PlayerFailure> {
+    let ipc_path = playback_state.ipc_path.lock().unwrap().clone();
+
+    // The user may have zapped inside mpv itself, so ask it what is playing first
+    if let Some(ref path) = ipc_path {
+        if let Ok(Value::String(url)) = mpv_command(path, json!(["get_property", "path"])).await {
+            // Relayed streams report the loopback URL; map it back to the channel
+            let url = relay_state.upstream_url(&url).unwrap_or(url);
+            if let Some(ref mut session) = *playback_state.session.lock().unwrap() {
+                session.sync_to_url(&url);
+            }
+        }
+    }
+
+    let channel = {
+        let mut session = playback_state.session.lock().unwrap();
+        let session = session
+            .as_mut()
+            .ok_or_else(|| PlayerFailure::from("No playback session".to_string()))?;
+        session
+            .step(offset)
+            .cloned()
+            .ok_or_else(|| PlayerFailure::from("Playback queue is empty".to_string()))?
+    };
+
+    if let Some(ref path) = ipc_path {
+        let use_relay = {
+            let db = db_state.reader();
+            use_stream_relay(&db)
+        };
+        // The relay applies the request options itself
+        let loadfile = if use_relay {
+            let load_url = relay_state.register(&channel, &channel.url).await?;
+            mpv_loadfile_command(&load_url, None)
+        } else {
+            mpv_loadfile_command(&channel.url, channel.http_options.as_ref())
+        };
+
+        match mpv_command(path, loadfile).await {
+            Ok(_) => {
+                let db = db_state.db.lock().unwrap();
+                if let Err(e) = add_to_history(&db, &channel) {
+                    eprintln!("Warning: Failed to add channel to history: {}", e);
+                }
+                if let Err(e) = switch_play_session(&db, &channel, Utc::now().timestamp()) {
+                    eprintln!("Warning: Failed to record play session: {}", e);
+                }
+                return Ok(channel);
+            }
+            Err(e) => {
+                // The player was closed or is not mpv anymore; fall back to a fresh launch
+                println!("mpv IPC unavailable, relaunching player: {}", e);
+                *playback_state.ipc_path.lock().unwrap() = None;
+            }
+        }
+    }
+
+    play_channel(
+        app_handle,
+        db_state,
+        cache_state,
+        playback_state,
+        relay_state,
+        channel.clone(),
+        None,
+    )
+    .await?;
+    Ok(channel)
+}
+
+#[tauri::command]
+pub async fn play_next(
+    app_handle: AppHandle,
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    playback_state: State<'_, PlaybackState>,
+    relay_state: State<'_, RelayState>,
+) -> Result<Channel, PlayerFailure> {
+    play_step(
+        app_handle,
+        db_state,
+        cache_state,
+        playback_state,
+        relay_state,
+        1,
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn play_previous(
+    app_handle: AppHandle,
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    playback_state: State<'_, PlaybackState>,
+    relay_state: State<'_, RelayState>,
+) -> Result<Channel, PlayerFailure> {
+    play_step(
+        app_handle,
+        db_state,
+        cache_state,
+        playback_state,
+        relay_state,
+        -1,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::channel;
+
+    fn create_session(count: usize) -> PlaybackSession {
+        PlaybackSession {
+            source: PlaybackSource::Group {
+                name: "News".to_string(),
+            },
+            channels: (0..count)
+                .map(|i| {
+                    channel(
+                        &format!("Channel {}", i),
+                        &format!("http://example.com/{}.m3u8", i),
+                        "News",
+                    )
+                })
+                .collect(),
+            current_index: 0,
+        }
+    }
+
+    #[test]
+    fn test_step_wraps_in_both_directions() {
+        let mut session = create_session(3);
+        assert_eq!(session.step(1).unwrap().name, "Channel 1");
+        assert_eq!(session.step(1).unwrap().name, "Channel 2");
+        assert_eq!(session.step(1).unwrap().name, "Channel 0");
+        assert_eq!(session.step(-1).unwrap().name, "Channel 2");
+        assert_eq!(session.current_index, 2);
+    }
+
+    #[test]
+    fn test_step_on_empty_session() {
+        let mut session = create_session(0);
+        assert!(session.step(1).is_none());
+        assert!(session.step(-1).is_none());
+    }
+
+    #[test]
+    fn test_sync_to_url() {
+        let mut session = create_session(4);
+        assert!(session.sync_to_url("http://example.com/2.m3u8"));
+        assert_eq!(session.step(1).unwrap().name, "Channel 3");
+
+        // Unknown URLs (e.g. a failover source) leave the position unchanged
+        assert!(!session.sync_to_url("http://elsewhere.com/live.m3u8"));
+        assert_eq!(session.current_index, 3);
+    }
+
+    #[test]
+    fn test_player_started_syncs_session() {
+        let state = PlaybackState::new();
+        *state.session.lock().unwrap() = Some(create_session(3));
+
+        state.player_started(
+            Some(PathBuf::from("/tmp/mpv.sock")),
+            "http://example.com/1.m3u8",
+        );
+        assert_eq!(
+            state
+                .session
+                .lock()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .current_index,
+            1
+        );
+        assert_eq!(
+            *state.ipc_path.lock().unwrap(),
+            Some(PathBuf::from("/tmp/mpv.sock"))
+        );
+    }
+
+    #[test]
+    fn test_playback_source_serialization() {
+        let source = PlaybackSource::Search {
+            query: "bbc".to_string(),
+        };
+        let json = serde_json::to_string(&source).unwrap();
+        assert_eq!(json, r#"{"kind":"search","query":"bbc"}"#);
+
+        let favorites: PlaybackSource = serde_json::from_str(r#"{"kind":"favorites"}"#).unwrap();
+        assert_eq!(favorites, PlaybackSource::Favorites);
+    }
+}
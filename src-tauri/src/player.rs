@@ -0,0 +1,456 @@
+use crate::http_client::ProxySettings;
+use crate::m3u_parser::StreamHttpOptions;
+use crate::player_failures::OutputBuffer;
+use rusqlite::Connection;
+use serde_json::{json, Value};
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::time;
+
+// How long a player gets to fail before we consider the launch successful
+const EARLY_EXIT_WINDOW_MS: u64 = 3000;
+const IPC_TIMEOUT_SECONDS: u64 = 2;
+const IPC_REQUEST_ID: u64 = 1;
+const MPV_IPC_ARG: &str = "--input-ipc-server=";
+
+#[derive(Debug)]
+pub enum LaunchError {
+    /// The player binary could not be started at all
+    Spawn(std::io::Error),
+    /// The player started but exited with an error inside the early exit window
+    EarlyExit {
+        code: Option<i32>,
+        output: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LaunchError::Spawn(e) => write!(f, "Failed to launch video player: {}", e),
+            LaunchError::EarlyExit {
+                code: Some(code), ..
+            } => {
+                write!(f, "Player failed to play the channel (exit code: {})", code)
+            }
+            LaunchError::EarlyExit { code: None, .. } => {
+                write!(f, "Player failed to play the channel")
+            }
+        }
+    }
+}
+
+pub fn get_player_command(conn: &Connection) -> String {
+    conn.query_row(
+        "SELECT player_command FROM settings WHERE id = 1",
+        [],
+        |row| row.get(0),
+    )
+    .unwrap_or_else(|_| crate::settings::detect_default_player())
+}
+
+/// Splits a configured player command line into the binary and its arguments.
+pub fn split_player_command(player_command: &str) -> (String, Vec<String>) {
+    let mut command_parts = player_command.split_whitespace();
+    let command = command_parts
+        .next()
+        .map(str::to_string)
+        .unwrap_or_else(crate::settings::detect_default_player);
+    let args = command_parts.map(str::to_string).collect();
+    (command, args)
+}
+
+/// Whether streams should be routed through the local relay instead of opened directly.
+pub fn use_stream_relay(conn: &Connection) -> bool {
+    conn.query_row(
+        "SELECT use_stream_relay FROM settings WHERE id = 1",
+        [],
+        |row| row.get(0),
+    )
+    .unwrap_or(false)
+}
+
+/// Command line flags that pass a channel's request options to players that support them.
+/// Players without such flags need the stream relay instead.
+pub fn http_option_args(command: &str, options: &StreamHttpOptions) -> Vec<String> {
+    let mut args = Vec::new();
+    if is_mpv(command) {
+        if let Some(ref user_agent) = options.user_agent {
+            args.push(format!("--user-agent={}", user_agent));
+        }
+        if let Some(ref referrer) = options.referrer {
+            args.push(format!("--referrer={}", referrer));
+        }
+        if !options.headers.is_empty() {
+            let fields: Vec<String> = options
+                .headers
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect();
+            args.push(format!("--http-header-fields={}", fields.join(",")));
+        }
+    } else if is_vlc(command) {
+        if let Some(ref user_agent) = options.user_agent {
+            args.push(format!("--http-user-agent={}", user_agent));
+        }
+        if let Some(ref referrer) = options.referrer {
+            args.push(format!("--http-referrer={}", referrer));
+        }
+    }
+    args
+}
+
+/// mpv's own user agent, restored for channels that don't set one.
+const MPV_DEFAULT_USER_AGENT: &str = "libmpv";
+
+/// An mpv `loadfile` command that replaces the current stream. The request
+/// options go along as per-file options, and are reset when the channel has
+/// none, so headers from the launch or an earlier channel are never reused.
+pub fn mpv_loadfile_command(url: &str, options: Option<&StreamHttpOptions>) -> Value {
+    let default_options = StreamHttpOptions::default();
+    let options = options.unwrap_or(&default_options);
+    let header_fields: Vec<String> = options
+        .headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect();
+    json!({
+        "name": "loadfile",
+        "url": url,
+        "flags": "replace",
+        "options": {
+            "user-agent": options.user_agent.as_deref().unwrap_or(MPV_DEFAULT_USER_AGENT),
+            "referrer": options.referrer.as_deref().unwrap_or_default(),
+            "http-header-fields": header_fields.join(","),
+        },
+    })
+}
+
+/// Flags passing the proxy to players that take one. Only HTTP proxies are
+/// passed on, since neither mpv nor VLC can use SOCKS for streams.
+pub fn proxy_args(command: &str, settings: &ProxySettings) -> Vec<String> {
+    match settings.proxy_url() {
+        Some(url)
+            if settings.proxy_player
+                && url.starts_with("http://")
+                && (is_mpv(command) || is_vlc(command)) =>
+        {
+            vec![format!("--http-proxy={}", url)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+pub fn spawn_player(command: &str, args: &[String], url: &str) -> std::io::Result<Child> {
+    #[cfg(target_os = "windows")]
+    let spawn_result = Command::new(command)
+        .args(args)
+        .arg(url)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .creation_flags(0x08000000) // CREATE_NO_WINDOW flag to hide CMD window
+        .spawn();
+
+    #[cfg(not(target_os = "windows"))]
+    let spawn_result = Command::new(command)
+        .args(args)
+        .arg(url)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    spawn_result
+}
+
+/// Spawns the player and waits briefly to see whether it exits quickly, which
+/// indicates the stream could not be played. Returns the child if it is still running.
+pub async fn launch_player(
+    command: &str,
+    args: &[String],
+    url: &str,
+) -> Result<Option<Child>, LaunchError> {
+    let mut child = spawn_player(command, args, url).map_err(|e| {
+        eprintln!("Failed to launch video player '{}': {}", command, e);
+        LaunchError::Spawn(e)
+    })?;
+    let output = OutputBuffer::capture(&mut child);
+
+    time::sleep(Duration::from_millis(EARLY_EXIT_WINDOW_MS)).await;
+
+    match child.try_wait() {
+        Ok(Some(exit_status)) => {
+            if exit_status.success() {
+                println!("Player exited successfully for stream: {}", url);
+                Ok(None)
+            } else {
+                eprintln!(
+                    "Player exited with error for stream: {} (exit code: {:?})",
+                    url,
+                    exit_status.code()
+                );
+                output.wait_closed(Duration::from_millis(500)).await;
+                Err(LaunchError::EarlyExit {
+                    code: exit_status.code(),
+                    output: output.lines(),
+                })
+            }
+        }
+        Ok(None) => Ok(Some(child)),
+        Err(e) => {
+            // If we can't check the status, assume it's working
+            eprintln!("Failed to check player status for stream {}: {}", url, e);
+            Ok(None)
+        }
+    }
+}
+
+pub fn is_mpv(command: &str) -> bool {
+    Path::new(command)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().eq_ignore_ascii_case("mpv"))
+        .unwrap_or(false)
+}
+
+pub fn is_vlc(command: &str) -> bool {
+    Path::new(command)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().eq_ignore_ascii_case("vlc"))
+        .unwrap_or(false)
+}
+
+fn mpv_ipc_path() -> PathBuf {
+    #[cfg(target_os = "windows")]
+    let path = PathBuf::from(format!(r"\\.\pipe\tollo-mpv-{}", std::process::id()));
+
+    #[cfg(not(target_os = "windows"))]
+    let path = std::env::temp_dir().join(format!("tollo-mpv-{}.sock", std::process::id()));
+
+    path
+}
+
+/// Makes sure an mpv launch exposes a JSON IPC server and returns its path.
+/// A server configured by the user in the player command is reused.
+pub fn prepare_mpv_ipc(command: &str, args: &mut Vec<String>) -> Option<PathBuf> {
+    if !is_mpv(command) {
+        return None;
+    }
+    if let Some(existing) = args.iter().find_map(|arg| arg.strip_prefix(MPV_IPC_ARG)) {
+        return Some(PathBuf::from(existing));
+    }
+
+    let path = mpv_ipc_path();
+    args.push(format!("{}{}", MPV_IPC_ARG, path.display()));
+    Some(path)
+}
+
+async fn send_ipc_request<S>(stream: S, command: &Value) -> Result<Value, String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let request = json!({ "command": command, "request_id": IPC_REQUEST_ID });
+    writer
+        .write_all(format!("{}\n", request).as_bytes())
+        .await
+        .map_err(|e| format!("Failed to write to mpv IPC: {}", e))?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .map_err(|e| format!("Failed to read from mpv IPC: {}", e))?
+    {
+        // mpv interleaves property-change and playback events with replies
+        let Ok(response) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if response.get("request_id").and_then(Value::as_u64) != Some(IPC_REQUEST_ID) {
+            continue;
+        }
+
+        return match response.get("error").and_then(Value::as_str) {
+            Some("success") => Ok(response.get("data").cloned().unwrap_or(Value::Null)),
+            Some(error) => Err(format!("mpv rejected the command: {}", error)),
+            None => Err("Malformed mpv IPC response".to_string()),
+        };
+    }
+
+    Err("mpv closed the IPC connection".to_string())
+}
+
+/// Sends a single command to a running mpv over its JSON IPC server.
+pub async fn mpv_command(ipc_path: &Path, command: Value) -> Result<Value, String> {
+    let request = async {
+        #[cfg(target_os = "windows")]
+        let stream = tokio::net::windows::named_pipe::ClientOptions::new()
+            .open(ipc_path)
+            .map_err(|e| format!("Failed to connect to mpv IPC: {}", e))?;
+
+        #[cfg(not(target_os = "windows"))]
+        let stream = tokio::net::UnixStream::connect(ipc_path)
+            .await
+            .map_err(|e| format!("Failed to connect to mpv IPC: {}", e))?;
+
+        send_ipc_request(stream, &command).await
+    };
+
+    time::timeout(Duration::from_secs(IPC_TIMEOUT_SECONDS), request)
+        .await
+        .map_err(|_| "mpv IPC request timed out".to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_player_command() {
+        let (command, args) = split_player_command("mpv --fs --volume=50");
+        assert_eq!(command, "mpv");
+        assert_eq!(args, vec!["--fs", "--volume=50"]);
+    }
+
+    #[test]
+    fn test_is_mpv() {
+        assert!(is_mpv("mpv"));
+        assert!(is_mpv("/usr/local/bin/mpv"));
+        assert!(is_mpv("MPV.exe"));
+        assert!(!is_mpv("vlc"));
+        assert!(!is_mpv("mpv-wrapper"));
+    }
+
+    #[test]
+    fn test_prepare_mpv_ipc() {
+        let mut args = vec!["--fs".to_string()];
+        let path = prepare_mpv_ipc("mpv", &mut args).unwrap();
+        assert_eq!(args.len(), 2);
+        assert_eq!(args[1], format!("{}{}", MPV_IPC_ARG, path.display()));
+
+        // A user-configured server is reused rather than added twice
+        let mut args = vec!["--input-ipc-server=/tmp/custom.sock".to_string()];
+        let path = prepare_mpv_ipc("mpv", &mut args).unwrap();
+        assert_eq!(path, PathBuf::from("/tmp/custom.sock"));
+        assert_eq!(args.len(), 1);
+
+        let mut args = Vec::new();
+        assert!(prepare_mpv_ipc("vlc", &mut args).is_none());
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn test_http_option_args() {
+        let options = StreamHttpOptions {
+            user_agent: Some("Agent/1.0".to_string()),
+            referrer: Some("http://example.com/".to_string()),
+            headers: vec![("X-Token".to_string(), "abc".to_string())],
+        };
+
+        assert_eq!(
+            http_option_args("mpv", &options),
+            vec![
+                "--user-agent=Agent/1.0",
+                "--referrer=http://example.com/",
+                "--http-header-fields=X-Token: abc",
+            ]
+        );
+        assert_eq!(
+            http_option_args("vlc", &options),
+            vec![
+                "--http-user-agent=Agent/1.0",
+                "--http-referrer=http://example.com/",
+            ]
+        );
+        assert!(http_option_args("ffplay", &options).is_empty());
+    }
+
+    #[test]
+    fn test_mpv_loadfile_command() {
+        let options = StreamHttpOptions {
+            user_agent: Some("Agent/1.0".to_string()),
+            referrer: None,
+            headers: vec![("Cookie".to_string(), "session=abc".to_string())],
+        };
+        let command = mpv_loadfile_command("http://example.com/a.m3u8", Some(&options));
+        assert_eq!(command["url"], "http://example.com/a.m3u8");
+        assert_eq!(command["flags"], "replace");
+        assert_eq!(command["options"]["user-agent"], "Agent/1.0");
+        assert_eq!(command["options"]["referrer"], "");
+        assert_eq!(command["options"]["http-header-fields"], "Cookie: session=abc");
+
+        // A channel without options clears whatever the previous one set
+        let command = mpv_loadfile_command("http://example.com/b.m3u8", None);
+        assert_eq!(command["options"]["user-agent"], MPV_DEFAULT_USER_AGENT);
+        assert_eq!(command["options"]["http-header-fields"], "");
+    }
+
+    #[test]
+    fn test_proxy_args() {
+        let mut settings = ProxySettings {
+            proxy_url: Some("http://proxy.local:3128".to_string()),
+            no_proxy: None,
+            proxy_player: true,
+        };
+        assert_eq!(
+            proxy_args("mpv", &settings),
+            vec!["--http-proxy=http://proxy.local:3128"]
+        );
+        assert_eq!(
+            proxy_args("vlc", &settings),
+            vec!["--http-proxy=http://proxy.local:3128"]
+        );
+        assert!(proxy_args("ffplay", &settings).is_empty());
+
+        settings.proxy_url = Some("socks5://proxy.local:1080".to_string());
+        assert!(proxy_args("mpv", &settings).is_empty());
+
+        settings.proxy_url = Some("http://proxy.local:3128".to_string());
+        settings.proxy_player = false;
+        assert!(proxy_args("mpv", &settings).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_mpv_command_skips_events() {
+        let path =
+            std::env::temp_dir().join(format!("tollo-test-ipc-{}.sock", uuid::Uuid::new_v4()));
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = tokio::io::split(stream);
+            let mut lines = BufReader::new(reader).lines();
+            let request: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            assert_eq!(request["command"], json!(["get_property", "path"]));
+
+            writer
+                .write_all(b"{\"event\":\"playback-restart\"}\n")
+                .await
+                .unwrap();
+            writer
+                .write_all(b"{\"data\":\"http://example.com/a.m3u8\",\"request_id\":1,\"error\":\"success\"}\n")
+                .await
+                .unwrap();
+        });
+
+        let result = mpv_command(&path, json!(["get_property", "path"])).await;
+        server.await.unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(result.unwrap(), json!("http://example.com/a.m3u8"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_mpv_command_without_server_fails() {
+        let path =
+            std::env::temp_dir().join(format!("tollo-test-missing-{}.sock", uuid::Uuid::new_v4()));
+        assert!(mpv_command(&path, json!(["get_property", "path"]))
+            .await
+            .is_err());
+    }
+}
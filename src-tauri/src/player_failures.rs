@@ -0,0 +1,471 @@
+use crate::player::LaunchError;
+use crate::state::DbState;
+use chrono::Utc;
+use regex::Regex;
+use rusqlite::{Connection, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::process::Child;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tauri::State;
+
+// Lines of player output kept per launch
+const OUTPUT_BUFFER_LINES: usize = 200;
+// Lines of output returned with a failure and stored in the failure log
+const LOG_EXCERPT_LINES: usize = 20;
+// Failures kept per channel in the failure log
+const MAX_FAILURES_PER_CHANNEL: i64 = 20;
+
+// Stream URLs the player echoes back, which can contain anything
+static RE_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[a-zA-Z][\w+.-]*://\S+").unwrap());
+
+/// Ring buffer holding the most recent stdout and stderr lines of a player.
+pub struct OutputBuffer {
+    lines: Mutex<VecDeque<String>>,
+    open_streams: AtomicUsize,
+}
+
+impl OutputBuffer {
+    fn new() -> Self {
+        Self {
+            lines: Mutex::new(VecDeque::with_capacity(OUTPUT_BUFFER_LINES)),
+            open_streams: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes the child's piped stdout and stderr and drains them on background threads.
+    /// The pipes must keep being read for as long as the player runs, or it would block.
+    pub fn capture(child: &mut Child) -> Arc<Self> {
+        let buffer = Arc::new(Self::new());
+        if let Some(stdout) = child.stdout.take() {
+            buffer.spawn_reader(stdout);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            buffer.spawn_reader(stderr);
+        }
+        buffer
+    }
+
+    fn spawn_reader<R: Read + Send + 'static>(self: &Arc<Self>, reader: R) {
+        self.open_streams.fetch_add(1, Ordering::SeqCst);
+        let buffer = self.clone();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            let mut line = Vec::new();
+            while let Ok(read) = reader.read_until(b'\n', &mut line) {
+                if read == 0 {
+                    break;
+                }
+                buffer.push(String::from_utf8_lossy(&line).trim_end().to_string());
+                line.clear();
+            }
+            buffer.open_streams.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    fn push(&self, line: String) {
+        if line.is_empty() {
+            return;
+        }
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == OUTPUT_BUFFER_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Waits until both pipes reached end of file, so output of an exited player is complete.
+    pub async fn wait_closed(&self, timeout: Duration) {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.open_streams.load(Ordering::SeqCst) > 0 && tokio::time::Instant::now() < deadline
+        {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCategory {
+    Forbidden,
+    NotFound,
+    CodecUnsupported,
+    TlsError,
+    Timeout,
+    PlayerNotFound,
+    Unknown,
+}
+
+impl FailureCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureCategory::Forbidden => "forbidden",
+            FailureCategory::NotFound => "not_found",
+            FailureCategory::CodecUnsupported => "codec_unsupported",
+            FailureCategory::TlsError => "tls_error",
+            FailureCategory::Timeout => "timeout",
+            FailureCategory::PlayerNotFound => "player_not_found",
+            FailureCategory::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "forbidden" => FailureCategory::Forbidden,
+            "not_found" => FailureCategory::NotFound,
+            "codec_unsupported" => FailureCategory::CodecUnsupported,
+            "tls_error" => FailureCategory::TlsError,
+            "timeout" => FailureCategory::Timeout,
+            "player_not_found" => FailureCategory::PlayerNotFound,
+            _ => FailureCategory::Unknown,
+        }
+    }
+
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            FailureCategory::Forbidden => "The server refused access to the stream (HTTP 403).",
+            FailureCategory::NotFound => "The stream was not found on the server (HTTP 404).",
+            FailureCategory::CodecUnsupported => "The player does not support the stream's codec.",
+            FailureCategory::TlsError => {
+                "A secure connection to the stream could not be established."
+            }
+            FailureCategory::Timeout => "The stream did not respond in time.",
+            FailureCategory::PlayerNotFound => {
+                "The video player could not be found. Please check the player command."
+            }
+            FailureCategory::Unknown => "Player failed to play the channel",
+        }
+    }
+}
+
+/// Structured error returned to the frontend when playback fails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerFailure {
+    pub category: FailureCategory,
+    pub message: String,
+    pub exit_code: Option<i32>,
+    pub log_excerpt: Vec<String>,
+    pub channel_name: String,
+    pub stream_url: String,
+    pub occurred_at: i64,
+}
+
+impl PlayerFailure {
+    pub fn from_launch_error(error: &LaunchError, channel_name: &str, stream_url: &str) -> Self {
+        let category = classify_failure(error);
+        let (exit_code, output) = match error {
+            LaunchError::Spawn(e) => (None, vec![e.to_string()]),
+            LaunchError::EarlyExit { code, output } => (*code, output.clone()),
+        };
+        let excerpt_start = output.len().saturating_sub(LOG_EXCERPT_LINES);
+
+        Self {
+            category,
+            message: category.user_message().to_string(),
+            exit_code,
+            log_excerpt: output[excerpt_start..].to_vec(),
+            channel_name: channel_name.to_string(),
+            stream_url: stream_url.to_string(),
+            occurred_at: Utc::now().timestamp(),
+        }
+    }
+}
+
+/// Errors that happen before the player is involved keep their message as is.
+impl From<String> for PlayerFailure {
+    fn from(message: String) -> Self {
+        Self {
+            category: FailureCategory::Unknown,
+            message,
+            exit_code: None,
+            log_excerpt: Vec::new(),
+            channel_name: String::new(),
+            stream_url: String::new(),
+            occurred_at: Utc::now().timestamp(),
+        }
+    }
+}
+
+// The player's own messages, without the lines announcing the stream and
+// with any URL it echoes removed
+fn error_text(output: &[String]) -> String {
+    output
+        .iter()
+        .filter(|line| !line.trim_start().starts_with("Playing:"))
+        .map(|line| RE_URL.replace_all(line, "").to_lowercase())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn classify_output(output: &[String]) -> FailureCategory {
+    let text = error_text(output);
+    let contains_any = |patterns: &[&str]| patterns.iter().any(|p| text.contains(p));
+
+    // Most specific first: codec and TLS messages often also mention "not found" or "error"
+    if contains_any(&[
+        "tls handshake",
+        "ssl handshake",
+        "tls: error",
+        "ssl routines",
+        "certificate verify failed",
+        "certificate has expired",
+        "self signed certificate",
+        "self-signed certificate",
+        "unable to get local issuer certificate",
+    ]) {
+        FailureCategory::TlsError
+    } else if contains_any(&["http error 403", "server returned 403", "403 forbidden"]) {
+        FailureCategory::Forbidden
+    } else if contains_any(&[
+        "unsupported codec",
+        "codec not supported",
+        "no decoder",
+        "decoder not found",
+        "could not find codec",
+        "unknown codec",
+        "failed to initialize a decoder",
+    ]) {
+        FailureCategory::CodecUnsupported
+    } else if contains_any(&[
+        "http error 404",
+        "server returned 404",
+        "404 not found",
+        "no such file or directory",
+    ]) {
+        FailureCategory::NotFound
+    } else if contains_any(&["timed out", "timeout was reached", "connection timeout"]) {
+        FailureCategory::Timeout
+    } else {
+        FailureCategory::Unknown
+    }
+}
+
+pub fn classify_failure(error: &LaunchError) -> FailureCategory {
+    match error {
+        LaunchError::Spawn(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            FailureCategory::PlayerNotFound
+        }
+        LaunchError::Spawn(_) => FailureCategory::Unknown,
+        LaunchError::EarlyExit { output, .. } => classify_output(output),
+    }
+}
+
+pub fn record_player_failure(
+    conn: &Connection,
+    channel_url: &str,
+    failure: &PlayerFailure,
+) -> RusqliteResult<()> {
+    conn.execute(
+        "INSERT INTO player_failures (channel_name, channel_url, stream_url, category, message, exit_code, log_excerpt, occurred_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        rusqlite::params![
+            failure.channel_name,
+            channel_url,
+            failure.stream_url,
+            failure.category.as_str(),
+            failure.message,
+            failure.exit_code,
+            failure.log_excerpt.join("\n"),
+            failure.occurred_at,
+        ],
+    )?;
+    conn.execute(
+        "DELETE FROM player_failures WHERE channel_url = ?1 AND id NOT IN (SELECT id FROM player_failures WHERE channel_url = ?1 ORDER BY id DESC LIMIT ?2)",
+        rusqlite::params![channel_url, MAX_FAILURES_PER_CHANNEL],
+    )?;
+    Ok(())
+}
+
+pub fn load_player_failures(
+    conn: &Connection,
+    channel_url: &str,
+) -> RusqliteResult<Vec<PlayerFailure>> {
+    let mut stmt = conn.prepare(
+        "SELECT category, message, exit_code, log_excerpt, channel_name, stream_url, occurred_at FROM player_failures WHERE channel_url = ?1 ORDER BY id DESC",
+    )?;
+    let failure_iter = stmt.query_map([channel_url], |row| {
+        let category: String = row.get(0)?;
+        let log_excerpt: String = row.get(3)?;
+        Ok(PlayerFailure {
+            category: FailureCategory::parse(&category),
+            message: row.get(1)?,
+            exit_code: row.get(2)?,
+            log_excerpt: log_excerpt.lines().map(str::to_string).collect(),
+            channel_name: row.get(4)?,
+            stream_url: row.get(5)?,
+            occurred_at: row.get(6)?,
+        })
+    })?;
+
+    let mut failures = Vec::new();
+    for failure in failure_iter {
+        failures.push(failure?);
+    }
+    Ok(failures)
+}
+
+#[tauri::command]
+pub fn get_player_failures(
+    db_state: State<DbState>,
+    channel_url: String,
+) -> Result<Vec<PlayerFailure>, String> {
+    let db = db_state.reader();
+    load_player_failures(&db, &channel_url).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn clear_player_failures(
+    db_state: State<DbState>,
+    channel_url: Option<String>,
+) -> Result<(), String> {
+    let db = db_state.db.lock().unwrap();
+    match channel_url {
+        Some(url) => db.execute("DELETE FROM player_failures WHERE channel_url = ?1", [url]),
+        None => db.execute("DELETE FROM player_failures", []),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::migrated_db;
+
+    fn early_exit(output: &[&str]) -> LaunchError {
+        LaunchError::EarlyExit {
+            code: Some(2),
+            output: output.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_classify_player_output() {
+        let cases = [
+            (
+                "[ffmpeg] https: HTTP error 403 Forbidden",
+                FailureCategory::Forbidden,
+            ),
+            (
+                "[ffmpeg] http: HTTP error 404 Not Found",
+                FailureCategory::NotFound,
+            ),
+            (
+                "[ffmpeg] tls: error:0A000086:SSL routines::certificate verify failed",
+                FailureCategory::TlsError,
+            ),
+            (
+                "main input error: VLC is unable to open the MRL: Connection timed out",
+                FailureCategory::Timeout,
+            ),
+            (
+                "Could not find codec parameters for stream 0 (Video: hevc): unknown codec",
+                FailureCategory::CodecUnsupported,
+            ),
+            (
+                "Exiting... (Errors when loading file)",
+                FailureCategory::Unknown,
+            ),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(
+                classify_failure(&early_exit(&["Playing: http://example.com", line])),
+                expected,
+                "line: {}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_classify_ignores_echoed_urls() {
+        let url = "http://tls.example.com/404/ssl-timeout-403.m3u8";
+        let opening = format!("[ffmpeg] Opening '{}' for reading", url);
+        let playing = format!("Playing: {}", url);
+
+        let error = early_exit(&[&playing, &opening, "Exiting... (Errors when loading file)"]);
+        assert_eq!(classify_failure(&error), FailureCategory::Unknown);
+
+        let error = early_exit(&[
+            &playing,
+            &opening,
+            "[ffmpeg] https: HTTP error 403 Forbidden",
+        ]);
+        assert_eq!(classify_failure(&error), FailureCategory::Forbidden);
+    }
+
+    #[test]
+    fn test_classify_missing_player() {
+        let error = LaunchError::Spawn(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(classify_failure(&error), FailureCategory::PlayerNotFound);
+
+        let error = LaunchError::Spawn(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        assert_eq!(classify_failure(&error), FailureCategory::Unknown);
+    }
+
+    #[test]
+    fn test_failure_keeps_log_tail() {
+        let output: Vec<String> = (0..50).map(|i| format!("line {}", i)).collect();
+        let error = LaunchError::EarlyExit {
+            code: Some(1),
+            output,
+        };
+        let failure = PlayerFailure::from_launch_error(&error, "News", "http://example.com/a");
+
+        assert_eq!(failure.log_excerpt.len(), LOG_EXCERPT_LINES);
+        assert_eq!(failure.log_excerpt.last().unwrap(), "line 49");
+        assert_eq!(failure.exit_code, Some(1));
+    }
+
+    #[test]
+    fn test_failure_log_is_bounded_per_channel() {
+        let conn = migrated_db();
+        let failure = PlayerFailure::from_launch_error(
+            &early_exit(&["HTTP error 403 Forbidden"]),
+            "News",
+            "http://example.com/a",
+        );
+
+        for _ in 0..(MAX_FAILURES_PER_CHANNEL + 5) {
+            record_player_failure(&conn, "http://example.com/a", &failure).unwrap();
+        }
+        record_player_failure(&conn, "http://example.com/b", &failure).unwrap();
+
+        let failures = load_player_failures(&conn, "http://example.com/a").unwrap();
+        assert_eq!(failures.len(), MAX_FAILURES_PER_CHANNEL as usize);
+        assert_eq!(failures[0].category, FailureCategory::Forbidden);
+        assert_eq!(failures[0].log_excerpt, vec!["HTTP error 403 Forbidden"]);
+        assert_eq!(
+            load_player_failures(&conn, "http://example.com/b")
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_output_buffer_captures_both_streams() {
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg("echo to-stdout; echo to-stderr 1>&2")
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let buffer = OutputBuffer::capture(&mut child);
+        child.wait().unwrap();
+        buffer.wait_closed(Duration::from_secs(2)).await;
+
+        let lines = buffer.lines();
+        assert!(lines.contains(&"to-stdout".to_string()));
+        assert!(lines.contains(&"to-stderr".to_string()));
+    }
+}
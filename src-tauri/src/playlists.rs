@@ -1,11 +1,23 @@
 // Module declarations
+mod changes;
+mod conditional;
 mod crud;
 mod fetch;
 mod legacy;
+mod request_config;
+mod retry;
+mod scheduler;
 mod types;
+mod virtual_lists;
 
 // Re-export all public items from the sub-modules
+pub use changes::*;
+pub use conditional::*;
 pub use crud::*;
 pub use fetch::*;
 pub use legacy::*;
+pub use request_config::*;
+pub use retry::*;
+pub use scheduler::*;
 pub use types::*;
+pub use virtual_lists::*;
@@ -0,0 +1,535 @@
+use crate::channel_overrides::carry_over_overrides;
+use crate::database::{store_channels, ChannelSyncStats};
+use crate::m3u_parser::Channel;
+use crate::state::DbState;
+use rusqlite::{Connection, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use tauri::{AppHandle, Emitter, State};
+
+pub const PLAYLIST_CHANGES_EVENT: &str = "playlist_changes";
+
+/// Change log entries kept per list; older ones are dropped.
+const CHANGE_LOG_LIMIT: i64 = 50;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelSnapshot {
+    pub name: String,
+    pub url: String,
+    pub group_title: String,
+}
+
+impl From<&Channel> for ChannelSnapshot {
+    fn from(channel: &Channel) -> Self {
+        Self {
+            name: channel.name.clone(),
+            url: channel.url.clone(),
+            group_title: channel.group_title.clone(),
+        }
+    }
+}
+
+/// A channel that kept its URL under a new name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenamedChannel {
+    pub url: String,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+/// A channel that kept its name but moved to a new URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MovedChannel {
+    pub name: String,
+    pub old_url: String,
+    pub new_url: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistDiff {
+    pub added: Vec<ChannelSnapshot>,
+    pub removed: Vec<ChannelSnapshot>,
+    pub renamed: Vec<RenamedChannel>,
+    pub url_changed: Vec<MovedChannel>,
+    pub groups_added: Vec<String>,
+    pub groups_removed: Vec<String>,
+}
+
+impl PlaylistDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.url_changed.is_empty()
+            && self.groups_added.is_empty()
+            && self.groups_removed.is_empty()
+    }
+}
+
+/// One refresh that changed a list, as stored and sent to the frontend.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistChangeLog {
+    pub id: i64,
+    pub channel_list_id: i64,
+    pub changed_at: i64,
+    pub changes: PlaylistDiff,
+    /// Favorites this refresh flagged
+    pub flagged_favorites: Vec<String>,
+}
+
+/// A favorite whose channel was removed or changed by a refresh.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlaggedFavorite {
+    pub favorite_name: String,
+    pub channel_list_id: i64,
+    /// "removed", "renamed" or "url_changed"
+    pub reason: String,
+    pub replacement_name: Option<String>,
+    pub replacement_url: Option<String>,
+    pub flagged_at: i64,
+}
+
+fn to_sql_error(e: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(Box::new(e))
+}
+
+fn from_sql_error(column: usize, e: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, Box::new(e))
+}
+
+/// The channels stored for a list, in playlist order.
+pub fn load_snapshot(
+    conn: &Connection,
+    channel_list_id: i64,
+) -> RusqliteResult<Vec<ChannelSnapshot>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, url, group_title FROM channels WHERE channel_list_id = ?1 ORDER BY position",
+    )?;
+    let rows = stmt.query_map([channel_list_id], |row| {
+        Ok(ChannelSnapshot {
+            name: row.get(0)?,
+            url: row.get(1)?,
+            group_title: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// Pairs entries of `old` and `new` with equal keys, first come first served.
+// Returns the pairs and what is left on each side, in order.
+fn pair_by<'a, K, F>(
+    old: Vec<&'a ChannelSnapshot>,
+    new: Vec<&'a ChannelSnapshot>,
+    key: F,
+) -> (
+    Vec<(&'a ChannelSnapshot, &'a ChannelSnapshot)>,
+    Vec<&'a ChannelSnapshot>,
+    Vec<&'a ChannelSnapshot>,
+)
+where
+    K: Hash + Eq,
+    F: Fn(&'a ChannelSnapshot) -> K,
+{
+    let mut by_key: HashMap<K, VecDeque<usize>> = HashMap::new();
+    for (index, channel) in old.iter().enumerate() {
+        by_key.entry(key(channel)).or_default().push_back(index);
+    }
+    let mut taken = vec![false; old.len()];
+    let mut pairs = Vec::new();
+    let mut new_left = Vec::new();
+    for channel in new {
+        match by_key.get_mut(&key(channel)).and_then(VecDeque::pop_front) {
+            Some(index) => {
+                taken[index] = true;
+                pairs.push((old[index], channel));
+            }
+            None => new_left.push(channel),
+        }
+    }
+    let old_left = old
+        .into_iter()
+        .zip(taken)
+        .filter(|(_, taken)| !taken)
+        .map(|(channel, _)| channel)
+        .collect();
+    (pairs, old_left, new_left)
+}
+
+/// What changed between the stored snapshot of a list and its new channels.
+/// Entries with the same name and URL are unchanged; of the rest, a shared
+/// URL makes a rename and a shared name a URL change.
+pub fn diff_channels(previous: &[ChannelSnapshot], current: &[Channel]) -> PlaylistDiff {
+    let current: Vec<ChannelSnapshot> = current.iter().map(ChannelSnapshot::from).collect();
+
+    let (_, old, new) = pair_by(previous.iter().collect(), current.iter().collect(), |c| {
+        (c.name.as_str(), c.url.as_str())
+    });
+    let (renamed, old, new) = pair_by(old, new, |c| c.url.as_str());
+    let (moved, old, new) = pair_by(old, new, |c| c.name.as_str());
+
+    let groups = |channels: &[ChannelSnapshot]| -> BTreeSet<String> {
+        channels
+            .iter()
+            .filter(|c| !c.group_title.is_empty())
+            .map(|c| c.group_title.clone())
+            .collect()
+    };
+    let (old_groups, new_groups) = (groups(previous), groups(&current));
+
+    PlaylistDiff {
+        added: new.into_iter().cloned().collect(),
+        removed: old.into_iter().cloned().collect(),
+        renamed: renamed
+            .into_iter()
+            .map(|(old, new)| RenamedChannel {
+                url: new.url.clone(),
+                old_name: old.name.clone(),
+                new_name: new.name.clone(),
+            })
+            .collect(),
+        url_changed: moved
+            .into_iter()
+            .map(|(old, new)| MovedChannel {
+                name: new.name.clone(),
+                old_url: old.url.clone(),
+                new_url: new.url.clone(),
+            })
+            .collect(),
+        groups_added: new_groups.difference(&old_groups).cloned().collect(),
+        groups_removed: old_groups.difference(&new_groups).cloned().collect(),
+    }
+}
+
+/// Flags favorites pointing at channels the diff removed, renamed or moved,
+/// and clears flags of favorites whose URL came back. Returns the names flagged.
+pub fn flag_favorites(
+    conn: &Connection,
+    channel_list_id: i64,
+    diff: &PlaylistDiff,
+    flagged_at: i64,
+) -> RusqliteResult<Vec<String>> {
+    let favorites: Vec<(String, String)> = {
+        let mut stmt = conn.prepare("SELECT name, url FROM favorites ORDER BY name")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<RusqliteResult<_>>()?
+    };
+
+    let restored: HashSet<&str> = diff
+        .added
+        .iter()
+        .map(|c| c.url.as_str())
+        .chain(diff.url_changed.iter().map(|c| c.new_url.as_str()))
+        .collect();
+    let mut flags: HashMap<&str, (&str, Option<&str>, Option<&str>)> = HashMap::new();
+    for channel in &diff.removed {
+        flags.insert(&channel.url, ("removed", None, None));
+    }
+    for channel in &diff.renamed {
+        flags.insert(&channel.url, ("renamed", Some(&channel.new_name), None));
+    }
+    for channel in &diff.url_changed {
+        flags.insert(
+            &channel.old_url,
+            ("url_changed", Some(&channel.name), Some(&channel.new_url)),
+        );
+    }
+
+    let mut flagged = Vec::new();
+    for (name, url) in &favorites {
+        if let Some((reason, replacement_name, replacement_url)) = flags.get(url.as_str()) {
+            conn.execute(
+                "INSERT OR REPLACE INTO favorite_flags (favorite_name, channel_list_id, reason, replacement_name, replacement_url, flagged_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![name, channel_list_id, reason, replacement_name, replacement_url, flagged_at],
+            )?;
+            flagged.push(name.clone());
+        } else if restored.contains(url.as_str()) {
+            conn.execute(
+                "DELETE FROM favorite_flags WHERE favorite_name = ?1 AND channel_list_id = ?2",
+                rusqlite::params![name, channel_list_id],
+            )?;
+        }
+    }
+    Ok(flagged)
+}
+
+/// Stores the diff of a refresh, flags affected favorites and trims the log.
+pub fn record_changes(
+    conn: &Connection,
+    channel_list_id: i64,
+    diff: &PlaylistDiff,
+    changed_at: i64,
+) -> RusqliteResult<PlaylistChangeLog> {
+    let flagged_favorites = flag_favorites(conn, channel_list_id, diff, changed_at)?;
+    conn.execute(
+        "INSERT INTO playlist_changes (channel_list_id, changed_at, details, flagged_favorites) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![
+            channel_list_id,
+            changed_at,
+            serde_json::to_string(diff).map_err(to_sql_error)?,
+            serde_json::to_string(&flagged_favorites).map_err(to_sql_error)?,
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+    conn.execute(
+        "DELETE FROM playlist_changes WHERE channel_list_id = ?1 AND id NOT IN (
+            SELECT id FROM playlist_changes WHERE channel_list_id = ?1 ORDER BY changed_at DESC, id DESC LIMIT ?2
+        )",
+        rusqlite::params![channel_list_id, CHANGE_LOG_LIMIT],
+    )?;
+    Ok(PlaylistChangeLog {
+        id,
+        channel_list_id,
+        changed_at,
+        changes: diff.clone(),
+        flagged_favorites,
+    })
+}
+
+/// Stores the channels of a list like `store_channels` and logs what changed
+/// since the stored snapshot, moving overrides along with renamed and moved
+/// channels. A list stored for the first time has no log entry.
+pub fn store_channels_with_changes(
+    conn: &Connection,
+    channel_list_id: i64,
+    channels: &[Channel],
+    now: i64,
+) -> RusqliteResult<(ChannelSyncStats, Option<PlaylistChangeLog>)> {
+    let previous = load_snapshot(conn, channel_list_id)?;
+    let stats = store_channels(conn, channel_list_id, channels)?;
+    if previous.is_empty() || stats == ChannelSyncStats::default() {
+        return Ok((stats, None));
+    }
+    let diff = diff_channels(&previous, channels);
+    if diff.is_empty() {
+        return Ok((stats, None));
+    }
+    carry_over_overrides(conn, channel_list_id, channels, &diff)?;
+    let log = record_changes(conn, channel_list_id, &diff, now)?;
+    Ok((stats, Some(log)))
+}
+
+pub fn emit_playlist_changes(app_handle: &AppHandle, log: &PlaylistChangeLog) {
+    if let Err(e) = app_handle.emit(PLAYLIST_CHANGES_EVENT, log) {
+        eprintln!("Warning: Failed to emit playlist changes: {}", e);
+    }
+}
+
+/// The change log of a list, newest first.
+pub fn load_change_log(
+    conn: &Connection,
+    channel_list_id: i64,
+    limit: i64,
+) -> RusqliteResult<Vec<PlaylistChangeLog>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, channel_list_id, changed_at, details, flagged_favorites FROM playlist_changes
+         WHERE channel_list_id = ?1 ORDER BY changed_at DESC, id DESC LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![channel_list_id, limit], |row| {
+        let details: String = row.get(3)?;
+        let flagged: String = row.get(4)?;
+        Ok(PlaylistChangeLog {
+            id: row.get(0)?,
+            channel_list_id: row.get(1)?,
+            changed_at: row.get(2)?,
+            changes: serde_json::from_str(&details).map_err(|e| from_sql_error(3, e))?,
+            flagged_favorites: serde_json::from_str(&flagged).map_err(|e| from_sql_error(4, e))?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Flags of favorites that still exist, newest first.
+pub fn load_flagged_favorites(conn: &Connection) -> RusqliteResult<Vec<FlaggedFavorite>> {
+    let mut stmt = conn.prepare(
+        "SELECT f.favorite_name, f.channel_list_id, f.reason, f.replacement_name, f.replacement_url, f.flagged_at
+         FROM favorite_flags f JOIN favorites ON favorites.name = f.favorite_name
+         ORDER BY f.flagged_at DESC, f.favorite_name",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(FlaggedFavorite {
+            favorite_name: row.get(0)?,
+            channel_list_id: row.get(1)?,
+            reason: row.get(2)?,
+            replacement_name: row.get(3)?,
+            replacement_url: row.get(4)?,
+            flagged_at: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+#[tauri::command]
+pub fn get_playlist_changes(
+    state: State<DbState>,
+    id: i32,
+    limit: Option<i64>,
+) -> Result<Vec<PlaylistChangeLog>, String> {
+    let limit = limit.unwrap_or(CHANGE_LOG_LIMIT).clamp(1, CHANGE_LOG_LIMIT);
+    load_change_log(&state.reader(), id as i64, limit).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_flagged_favorites(state: State<DbState>) -> Result<Vec<FlaggedFavorite>, String> {
+    load_flagged_favorites(&state.reader()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn dismiss_favorite_flag(state: State<DbState>, name: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.execute(
+        "DELETE FROM favorite_flags WHERE favorite_name = ?1",
+        [&name],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{channel, create_test_db};
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn snapshot(channels: &[Channel]) -> Vec<ChannelSnapshot> {
+        channels.iter().map(ChannelSnapshot::from).collect()
+    }
+
+    fn add_favorite(conn: &Connection, name: &str, url: &str) {
+        conn.execute(
+            "INSERT INTO favorites (name, logo, url, group_title, tvg_id, resolution, extra_info) VALUES (?1, '', ?2, '', '', '', '')",
+            [name, url],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_diff_classifies_changes() {
+        let previous = snapshot(&[
+            channel("News", "http://a/news", "News"),
+            channel("Sport", "http://a/sport", "Sports"),
+            channel("Movies", "http://a/movies", "Film"),
+            channel("Kids", "http://a/kids", "Kids"),
+        ]);
+        let current = [
+            channel("News", "http://a/news", "News"),
+            channel("Sport HD", "http://a/sport", "Sports"),
+            channel("Movies", "http://b/movies", "Film"),
+            channel("Music", "http://a/music", "Music"),
+        ];
+
+        let diff = diff_channels(&previous, &current);
+        assert_eq!(diff.added, snapshot(&[current[3].clone()]));
+        assert_eq!(diff.removed, vec![previous[3].clone()]);
+        assert_eq!(
+            diff.renamed,
+            vec![RenamedChannel {
+                url: "http://a/sport".to_string(),
+                old_name: "Sport".to_string(),
+                new_name: "Sport HD".to_string(),
+            }]
+        );
+        assert_eq!(diff.url_changed[0].old_url, "http://a/movies");
+        assert_eq!(diff.url_changed[0].new_url, "http://b/movies");
+        assert_eq!(diff.groups_added, vec!["Music".to_string()]);
+        assert_eq!(diff.groups_removed, vec!["Kids".to_string()]);
+    }
+
+    #[test]
+    fn test_diff_ignores_order_and_counts_repeats() {
+        let previous = snapshot(&[
+            channel("A", "http://a/1", "G"),
+            channel("B", "http://a/2", "G"),
+        ]);
+        let reordered = [
+            channel("B", "http://a/2", "G"),
+            channel("A", "http://a/1", "G"),
+        ];
+        assert!(diff_channels(&previous, &reordered).is_empty());
+
+        let repeated = [
+            channel("A", "http://a/1", "G"),
+            channel("A", "http://a/1", "G"),
+            channel("B", "http://a/2", "G"),
+        ];
+        let diff = diff_channels(&previous, &repeated);
+        assert_eq!(diff.added.len(), 1);
+        assert!(diff.removed.is_empty());
+    }
+
+    #[test]
+    fn test_store_logs_changes_and_flags_favorites() {
+        let conn = create_test_db();
+        add_favorite(&conn, "Fav sport", "http://a/sport");
+        add_favorite(&conn, "Fav kids", "http://a/kids");
+        add_favorite(&conn, "Fav news", "http://a/news");
+
+        let first = [
+            channel("News", "http://a/news", "News"),
+            channel("Sport", "http://a/sport", "Sports"),
+            channel("Kids", "http://a/kids", "Kids"),
+        ];
+        // The first time a list is stored there is nothing to compare with
+        let (_, log) = store_channels_with_changes(&conn, 1, &first, NOW).unwrap();
+        assert!(log.is_none());
+
+        let second = [
+            channel("News", "http://a/news", "News"),
+            channel("Sport", "http://b/sport", "Sports"),
+        ];
+        let (_, log) = store_channels_with_changes(&conn, 1, &second, NOW + 1).unwrap();
+        let log = log.unwrap();
+        assert_eq!(log.changes.removed.len(), 1);
+        assert_eq!(log.changes.url_changed.len(), 1);
+        assert_eq!(log.flagged_favorites, vec!["Fav kids", "Fav sport"]);
+
+        let flags = load_flagged_favorites(&conn).unwrap();
+        let sport = flags
+            .iter()
+            .find(|f| f.favorite_name == "Fav sport")
+            .unwrap();
+        assert_eq!(sport.reason, "url_changed");
+        assert_eq!(sport.replacement_url.as_deref(), Some("http://b/sport"));
+
+        // Storing the same channels again logs nothing
+        let (_, log) = store_channels_with_changes(&conn, 1, &second, NOW + 2).unwrap();
+        assert!(log.is_none());
+        assert_eq!(load_change_log(&conn, 1, 10).unwrap().len(), 1);
+
+        // A channel coming back clears its flag
+        let third = [
+            channel("News", "http://a/news", "News"),
+            channel("Sport", "http://b/sport", "Sports"),
+            channel("Kids", "http://a/kids", "Kids"),
+        ];
+        store_channels_with_changes(&conn, 1, &third, NOW + 3).unwrap();
+        let names: Vec<String> = load_flagged_favorites(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.favorite_name)
+            .collect();
+        assert_eq!(names, vec!["Fav sport"]);
+
+        let log = load_change_log(&conn, 1, 10).unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].changes.added.len(), 1);
+        assert_eq!(log[1].changed_at, NOW + 1);
+    }
+
+    #[test]
+    fn test_change_log_is_trimmed() {
+        let conn = create_test_db();
+        let diff = PlaylistDiff {
+            groups_added: vec!["News".to_string()],
+            ..Default::default()
+        };
+        for i in 0..CHANGE_LOG_LIMIT + 5 {
+            record_changes(&conn, 1, &diff, NOW + i).unwrap();
+        }
+        let log = load_change_log(&conn, 1, 1000).unwrap();
+        assert_eq!(log.len() as i64, CHANGE_LOG_LIMIT);
+        assert_eq!(log[0].changed_at, NOW + CHANGE_LOG_LIMIT + 4);
+    }
+}
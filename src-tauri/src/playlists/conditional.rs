@@ -0,0 +1,755 @@
+use crate::paths::data_paths;
+use crate::playlists::retry::PlaylistFetchPlan;
+use crate::playlists::types::{TransferMeter, TransferSender};
+use crate::state::DbState;
+use chrono::Utc;
+use reqwest::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::StatusCode;
+use rusqlite::{Connection, Result as RusqliteResult};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What is known about the stored copy of a list from its last download.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaylistValidators {
+    pub filepath: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_hash: Option<String>,
+}
+
+impl PlaylistValidators {
+    /// The validators of list `id`. They only count while the file they
+    /// describe is on disk, since a 304 leaves nothing else to read.
+    pub fn load(conn: &Connection, id: i32) -> RusqliteResult<Self> {
+        let validators = conn.query_row(
+            "SELECT filepath, etag, last_modified, content_hash FROM channel_lists WHERE id = ?1",
+            [id],
+            |row| {
+                Ok(Self {
+                    filepath: row.get(0)?,
+                    etag: row.get(1)?,
+                    last_modified: row.get(2)?,
+                    content_hash: row.get(3)?,
+                })
+            },
+        )?;
+        match &validators.filepath {
+            Some(filepath) if data_paths().channel_list_file(filepath).is_file() => Ok(validators),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn request_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let valid =
+            |value: &Option<String>| value.as_deref().and_then(|v| HeaderValue::from_str(v).ok());
+        if let Some(etag) = valid(&self.etag) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = valid(&self.last_modified) {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+        headers
+    }
+
+    /// Reads the stored copy, after the server answered 304.
+    pub fn read_stored(&self) -> Result<String, String> {
+        let filepath = self
+            .filepath
+            .as_ref()
+            .ok_or_else(|| "No stored playlist to reuse".to_string())?;
+        fs::read_to_string(data_paths().channel_list_file(filepath))
+            .map_err(|e| format!("Failed to read stored playlist: {}", e))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PlaylistDownload {
+    NotModified,
+    Content {
+        body: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        /// The `.part` file the body was streamed into, if any.
+        file: Option<PartialFile>,
+    },
+}
+
+/// A download streamed into a `.part` file in `channel_lists/`. It is
+/// removed when dropped, unless `persist` renamed it into place first.
+#[derive(Debug, PartialEq)]
+pub struct PartialFile {
+    path: PathBuf,
+}
+
+impl PartialFile {
+    fn create() -> io::Result<(Self, File)> {
+        let channel_lists_dir = data_paths().channel_lists_dir();
+        fs::create_dir_all(&channel_lists_dir)?;
+        let path = channel_lists_dir.join(format!("{}.m3u.part", Uuid::new_v4()));
+        let file = File::create(&path)?;
+        Ok((Self { path }, file))
+    }
+
+    /// Renames the file into place and returns its new name.
+    pub fn persist(&self) -> io::Result<String> {
+        let path = self.path.with_extension("");
+        rename_durably(&self.path, &path)?;
+        Ok(path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default())
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistChange {
+    /// The server answered 304.
+    NotModified,
+    /// The server sent the playlist again, byte for byte the stored one.
+    Unchanged,
+    Changed,
+}
+
+impl PlaylistDownload {
+    pub fn change(&self, validators: &PlaylistValidators) -> PlaylistChange {
+        match self {
+            PlaylistDownload::NotModified => PlaylistChange::NotModified,
+            PlaylistDownload::Content { body, .. }
+                if validators.content_hash.as_deref() == Some(content_hash(body).as_str()) =>
+            {
+                PlaylistChange::Unchanged
+            }
+            PlaylistDownload::Content { .. } => PlaylistChange::Changed,
+        }
+    }
+}
+
+/// A failed download, and whether trying again may help.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchFailure {
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl FetchFailure {
+    fn request(context: &str, error: reqwest::Error) -> Self {
+        Self {
+            message: format!("{}: {}", context, error),
+            retryable: !error.is_builder(),
+        }
+    }
+
+    // Another try won't make room on the disk
+    fn save(error: io::Error) -> Self {
+        Self {
+            message: format!("Failed to save: {}", error),
+            retryable: false,
+        }
+    }
+
+    // Server errors, timeouts and rate limiting are worth another try
+    fn status(context: &str, status: StatusCode) -> Self {
+        Self {
+            message: format!("{}: server returned {}", context, status),
+            retryable: status.is_server_error()
+                || status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+impl From<FetchFailure> for String {
+    fn from(failure: FetchFailure) -> Self {
+        failure.message
+    }
+}
+
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+fn header_string(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Downloads `url` chunk by chunk, publishing the bytes received to `transfer`.
+pub async fn download_playlist(
+    client: &reqwest::Client,
+    url: &str,
+    validators: &PlaylistValidators,
+    transfer: &TransferSender,
+) -> Result<PlaylistDownload, FetchFailure> {
+    let mut response = client
+        .get(url)
+        .headers(validators.request_headers())
+        .timeout(DOWNLOAD_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| FetchFailure::request("Failed to fetch", e))?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(PlaylistDownload::NotModified);
+    }
+    if !response.status().is_success() {
+        return Err(FetchFailure::status("Failed to fetch", response.status()));
+    }
+    let etag = header_string(response.headers(), ETAG);
+    let last_modified = header_string(response.headers(), LAST_MODIFIED);
+    let mut meter = TransferMeter::new(transfer, response.content_length());
+    let (partial, file) = PartialFile::create().map_err(FetchFailure::save)?;
+    let mut file = tokio::fs::File::from_std(file);
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| FetchFailure::request("Failed to read", e))?
+    {
+        file.write_all(&chunk).await.map_err(FetchFailure::save)?;
+        meter.add(chunk.len());
+    }
+    file.sync_all().await.map_err(FetchFailure::save)?;
+    drop(file);
+    meter.finish();
+    let bytes = tokio::fs::read(&partial.path)
+        .await
+        .map_err(FetchFailure::save)?;
+    let body = String::from_utf8(bytes)
+        .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+    Ok(PlaylistDownload::Content {
+        body,
+        etag,
+        last_modified,
+        file: Some(partial),
+    })
+}
+
+pub fn download_playlist_blocking(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    validators: &PlaylistValidators,
+) -> Result<PlaylistDownload, FetchFailure> {
+    let response = client
+        .get(url)
+        .headers(validators.request_headers())
+        .timeout(DOWNLOAD_TIMEOUT)
+        .send()
+        .map_err(|e| FetchFailure::request("Failed to fetch playlist", e))?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(PlaylistDownload::NotModified);
+    }
+    if !response.status().is_success() {
+        return Err(FetchFailure::status(
+            "Failed to fetch playlist",
+            response.status(),
+        ));
+    }
+    let etag = header_string(response.headers(), ETAG);
+    let last_modified = header_string(response.headers(), LAST_MODIFIED);
+    let body = response
+        .text()
+        .map_err(|e| FetchFailure::request("Failed to read response", e))?;
+    Ok(PlaylistDownload::Content {
+        body,
+        etag,
+        last_modified,
+        file: None,
+    })
+}
+
+/// Bumps `last_fetched` for a fetch that kept the stored file, taking any new
+/// validators the server sent with an identical body.
+pub fn record_unchanged_fetch(
+    conn: &Connection,
+    id: i32,
+    fetched_at: i64,
+    download: &PlaylistDownload,
+) -> RusqliteResult<()> {
+    let (etag, last_modified) = match download {
+        PlaylistDownload::Content {
+            etag,
+            last_modified,
+            ..
+        } => (etag.as_deref(), last_modified.as_deref()),
+        PlaylistDownload::NotModified => (None, None),
+    };
+    conn.execute(
+        "UPDATE channel_lists SET last_fetched = ?1, etag = COALESCE(?2, etag), last_modified = COALESCE(?3, last_modified) WHERE id = ?4",
+        rusqlite::params![fetched_at, etag, last_modified, id],
+    )?;
+    Ok(())
+}
+
+/// Points list `id` at a newly written playlist file and stores its validators.
+pub fn record_new_file(
+    conn: &Connection,
+    id: i32,
+    filename: &str,
+    fetched_at: i64,
+    download: &PlaylistDownload,
+) -> RusqliteResult<()> {
+    let PlaylistDownload::Content {
+        body,
+        etag,
+        last_modified,
+        ..
+    } = download
+    else {
+        return Ok(());
+    };
+    conn.execute(
+        "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2, etag = ?3, last_modified = ?4, content_hash = ?5 WHERE id = ?6",
+        rusqlite::params![filename, fetched_at, etag, last_modified, content_hash(body), id],
+    )?;
+    Ok(())
+}
+
+/// Points list `id` at a newly written playlist file like `record_new_file`,
+/// then removes the file it used before. When the update fails the new file
+/// is removed instead, so either way only the file in use is left.
+pub fn replace_playlist_file(
+    conn: &Connection,
+    id: i32,
+    filename: &str,
+    fetched_at: i64,
+    download: &PlaylistDownload,
+) -> Result<(), String> {
+    let previous = conn
+        .query_row(
+            "SELECT filepath FROM channel_lists WHERE id = ?1",
+            [id],
+            |row| row.get::<_, Option<String>>(0),
+        )
+        .and_then(|previous| {
+            record_new_file(conn, id, filename, fetched_at, download)?;
+            Ok(previous)
+        });
+    match previous {
+        Ok(previous) => {
+            if let Some(previous) = previous.filter(|previous| previous != filename) {
+                let _ = fs::remove_file(data_paths().channel_list_file(&previous));
+            }
+            Ok(())
+        }
+        Err(e) => {
+            let _ = fs::remove_file(data_paths().channel_list_file(filename));
+            Err(format!("Failed to update: {}", e))
+        }
+    }
+}
+
+/// Writes playlist content under a new file name in `channel_lists/`.
+pub fn store_playlist_file(content: &str) -> Result<String, String> {
+    let channel_lists_dir = data_paths().channel_lists_dir();
+    fs::create_dir_all(&channel_lists_dir)
+        .map_err(|e| format!("Failed to create directory: {}", e))?;
+    let filename = format!("{}.m3u", Uuid::new_v4());
+    write_atomically(&channel_lists_dir.join(&filename), content.as_bytes())
+        .map_err(|e| format!("Failed to save: {}", e))?;
+    Ok(filename)
+}
+
+/// Stores a download under a new file name in `channel_lists/`, renaming
+/// the `.part` file it was streamed into when there is one.
+pub fn store_download(download: &PlaylistDownload) -> Result<String, String> {
+    match download {
+        PlaylistDownload::Content {
+            file: Some(file), ..
+        } => file.persist().map_err(|e| format!("Failed to save: {}", e)),
+        _ => store_playlist_file(download_body(download)),
+    }
+}
+
+/// Writes `path` through a `.part` file renamed into place, so a crash
+/// leaves either no file or a complete one. Both the content and the rename
+/// are flushed to disk before returning. Startup cleanup removes leftover
+/// `.part` files.
+pub fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let result = File::create(&partial)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| rename_durably(Path::new(&partial), path));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+// Renames `from` to `to` and flushes the directory entry
+fn rename_durably(from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to)?;
+    match to.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => sync_dir(dir),
+        None => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+// Directories can't be opened for syncing here
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Fetches list `id` from `source`, or its mirrors, for the blocking load paths. The stored
+/// file is reused, and left untouched, when the server answers 304 or sends
+/// the same content again. Each URL is tried once, since the caller holds
+/// `conn` throughout.
+pub fn refresh_playlist_blocking(
+    conn: &Connection,
+    id: i32,
+    source: &str,
+) -> Result<String, String> {
+    let validators = PlaylistValidators::load(conn, id).map_err(|e| e.to_string())?;
+    let download = PlaylistFetchPlan::load(conn, id, source)?
+        .without_retries()
+        .download_blocking(&validators)?;
+    let new_file = store_changed_playlist(&validators, &download)?;
+    record_refresh(conn, id, &validators, download, new_file)
+}
+
+/// Like `refresh_playlist_blocking`, but the download runs without holding a
+/// connection; the writer is only taken, through `DbState::write`, to record
+/// the result. Blocks the calling thread, so it must not run on the runtime.
+pub fn refresh_playlist_unlocked(
+    db_state: &DbState,
+    id: i32,
+    source: &str,
+) -> Result<String, String> {
+    let (validators, plan) = {
+        let conn = db_state.reader();
+        (
+            PlaylistValidators::load(&conn, id).map_err(|e| e.to_string())?,
+            PlaylistFetchPlan::load(&conn, id, source)?,
+        )
+    };
+    let download = plan.download_blocking(&validators)?;
+    let new_file = store_changed_playlist(&validators, &download)?;
+    tauri::async_runtime::block_on(
+        db_state.write(move |conn| Ok(record_refresh(conn, id, &validators, download, new_file))),
+    )?
+}
+
+// Writes a download that differs from the stored copy to a new file
+fn store_changed_playlist(
+    validators: &PlaylistValidators,
+    download: &PlaylistDownload,
+) -> Result<Option<String>, String> {
+    match download.change(validators) {
+        PlaylistChange::Changed => store_download(download).map(Some),
+        _ => Ok(None),
+    }
+}
+
+// Records a refresh and returns the playlist content
+fn record_refresh(
+    conn: &Connection,
+    id: i32,
+    validators: &PlaylistValidators,
+    download: PlaylistDownload,
+    new_file: Option<String>,
+) -> Result<String, String> {
+    let now = Utc::now().timestamp();
+    match new_file {
+        Some(filename) => {
+            replace_playlist_file(conn, id, &filename, now, &download)?;
+            Ok(into_body(download))
+        }
+        None if download == PlaylistDownload::NotModified => {
+            let content = validators.read_stored()?;
+            let _ = record_unchanged_fetch(conn, id, now, &download);
+            Ok(content)
+        }
+        None => {
+            let _ = record_unchanged_fetch(conn, id, now, &download);
+            Ok(into_body(download))
+        }
+    }
+}
+
+pub fn download_body(download: &PlaylistDownload) -> &str {
+    match download {
+        PlaylistDownload::Content { body, .. } => body,
+        PlaylistDownload::NotModified => "",
+    }
+}
+
+fn into_body(download: PlaylistDownload) -> String {
+    match download {
+        PlaylistDownload::Content { body, .. } => body,
+        PlaylistDownload::NotModified => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::migrated_db;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    const PLAYLIST: &str = "#EXTM3U\n#EXTINF:-1,News\nhttp://example.com/news.m3u8\n";
+
+    // Serves PLAYLIST with an ETag, answering 304 when the request carries it.
+    // Returns the server URL and the request heads it received.
+    fn spawn_playlist_server() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut buffer = [0u8; 4096];
+                let read = stream.read(&mut buffer).unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..read]).to_lowercase();
+                let response = if request.contains("if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                } else {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Wed, 01 Jan 2025 00:00:00 GMT\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        PLAYLIST.len(),
+                        PLAYLIST
+                    )
+                };
+                seen.lock().unwrap().push(request);
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (format!("http://{}/list.m3u", address), requests)
+    }
+
+    fn create_test_db() -> Connection {
+        let conn = migrated_db();
+        conn.execute(
+            "INSERT INTO channel_lists (id, name, source) VALUES (1, 'Test', 'http://example.com')",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn list_row(conn: &Connection) -> (Option<String>, Option<i64>, Option<String>) {
+        conn.query_row(
+            "SELECT filepath, last_fetched, etag FROM channel_lists WHERE id = 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_change_detection() {
+        let validators = PlaylistValidators {
+            content_hash: Some(content_hash(PLAYLIST)),
+            ..Default::default()
+        };
+        let download = |body: &str| PlaylistDownload::Content {
+            body: body.to_string(),
+            etag: None,
+            last_modified: None,
+            file: None,
+        };
+
+        assert_eq!(
+            PlaylistDownload::NotModified.change(&validators),
+            PlaylistChange::NotModified
+        );
+        assert_eq!(
+            download(PLAYLIST).change(&validators),
+            PlaylistChange::Unchanged
+        );
+        assert_eq!(
+            download("#EXTM3U\n").change(&validators),
+            PlaylistChange::Changed
+        );
+        assert_eq!(
+            download(PLAYLIST).change(&PlaylistValidators::default()),
+            PlaylistChange::Changed
+        );
+    }
+
+    #[test]
+    fn test_validators_need_the_stored_file() {
+        let conn = create_test_db();
+        conn.execute(
+            "UPDATE channel_lists SET filepath = 'missing.m3u', etag = '\"v1\"' WHERE id = 1",
+            [],
+        )
+        .unwrap();
+
+        let validators = PlaylistValidators::load(&conn, 1).unwrap();
+        assert_eq!(validators, PlaylistValidators::default());
+        assert!(validators.request_headers().is_empty());
+    }
+
+    #[test]
+    fn test_conditional_refresh_reuses_stored_file() {
+        let (url, requests) = spawn_playlist_server();
+        let conn = create_test_db();
+
+        // First fetch stores the file and its validators
+        let content = refresh_playlist_blocking(&conn, 1, &url).unwrap();
+        assert_eq!(content, PLAYLIST);
+        let (filepath, _, etag) = list_row(&conn);
+        let filepath = filepath.unwrap();
+        assert_eq!(etag.as_deref(), Some("\"v1\""));
+
+        // The next one sends them and gets a 304
+        conn.execute("UPDATE channel_lists SET last_fetched = 0 WHERE id = 1", [])
+            .unwrap();
+        let content = refresh_playlist_blocking(&conn, 1, &url).unwrap();
+        assert_eq!(content, PLAYLIST);
+        let (unchanged_filepath, last_fetched, _) = list_row(&conn);
+        assert_eq!(unchanged_filepath.as_deref(), Some(filepath.as_str()));
+        assert!(last_fetched.unwrap() > 0);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-modified-since: wed, 01 jan 2025"));
+
+        let _ = fs::remove_file(data_paths().channel_list_file(&filepath));
+    }
+
+    #[tokio::test]
+    async fn test_download_reports_bytes() {
+        let (url, _requests) = spawn_playlist_server();
+        let (transfer, updates) = tokio::sync::watch::channel(None);
+        let download = download_playlist(
+            &reqwest::Client::new(),
+            &url,
+            &PlaylistValidators::default(),
+            &transfer,
+        )
+        .await
+        .unwrap();
+        assert_eq!(download_body(&download), PLAYLIST);
+
+        let progress = updates.borrow().unwrap();
+        assert_eq!(progress.bytes_downloaded, PLAYLIST.len() as u64);
+        assert_eq!(progress.total_bytes, Some(PLAYLIST.len() as u64));
+        assert_eq!(progress.eta_seconds.unwrap_or(0.0), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_download_streams_into_part_file() {
+        let (url, _requests) = spawn_playlist_server();
+        let (transfer, _updates) = tokio::sync::watch::channel(None);
+        let client = reqwest::Client::new();
+        let validators = PlaylistValidators::default();
+        let download = || download_playlist(&client, &url, &validators, &transfer);
+
+        let stored = download().await.unwrap();
+        let filename = store_download(&stored).unwrap();
+        drop(stored);
+        let path = data_paths().channel_list_file(&filename);
+        assert_eq!(fs::read_to_string(&path).unwrap(), PLAYLIST);
+
+        // A download that is never stored leaves nothing behind
+        let discarded = download().await.unwrap();
+        let PlaylistDownload::Content {
+            file: Some(partial),
+            ..
+        } = &discarded
+        else {
+            panic!("the download was not streamed to a file");
+        };
+        let partial = partial.path.clone();
+        assert!(partial.is_file());
+        drop(discarded);
+        assert!(!partial.exists());
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("list.m3u");
+        write_atomically(&path, PLAYLIST.as_bytes()).unwrap();
+        write_atomically(&path, b"#EXTM3U\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "#EXTM3U\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // A failed write leaves nothing behind
+        let missing = dir.path().join("missing").join("list.m3u");
+        assert!(write_atomically(&missing, PLAYLIST.as_bytes()).is_err());
+        assert!(!dir.path().join("missing").exists());
+    }
+
+    #[test]
+    fn test_replace_playlist_file() {
+        let conn = create_test_db();
+        let download = PlaylistDownload::Content {
+            body: PLAYLIST.to_string(),
+            etag: None,
+            last_modified: None,
+            file: None,
+        };
+        let first = store_playlist_file(PLAYLIST).unwrap();
+        replace_playlist_file(&conn, 1, &first, 1, &download).unwrap();
+        let second = store_playlist_file(PLAYLIST).unwrap();
+        replace_playlist_file(&conn, 1, &second, 2, &download).unwrap();
+        assert_eq!(list_row(&conn).0, Some(second.clone()));
+        assert!(!data_paths().channel_list_file(&first).exists());
+        assert!(data_paths().channel_list_file(&second).exists());
+
+        // A list deleted in the meantime keeps nothing
+        let orphan = store_playlist_file(PLAYLIST).unwrap();
+        assert!(replace_playlist_file(&conn, 2, &orphan, 3, &download).is_err());
+        assert!(!data_paths().channel_list_file(&orphan).exists());
+
+        let _ = fs::remove_file(data_paths().channel_list_file(&second));
+    }
+
+    #[test]
+    fn test_identical_content_keeps_file() {
+        let (url, _requests) = spawn_playlist_server();
+        let conn = create_test_db();
+        let filename = store_playlist_file(PLAYLIST).unwrap();
+        // Stored by an earlier version, without validators the server recognises
+        conn.execute(
+            "UPDATE channel_lists SET filepath = ?1, content_hash = ?2 WHERE id = 1",
+            [&filename, &content_hash(PLAYLIST)],
+        )
+        .unwrap();
+
+        refresh_playlist_blocking(&conn, 1, &url).unwrap();
+        let (filepath, last_fetched, etag) = list_row(&conn);
+        assert_eq!(filepath, Some(filename.clone()));
+        assert!(last_fetched.is_some());
+        assert_eq!(etag.as_deref(), Some("\"v1\""));
+
+        let _ = fs::remove_file(data_paths().channel_list_file(&filename));
+    }
+}
@@ -1,14 +1,23 @@
 use crate::channels::invalidate_channel_cache;
+use crate::database::PER_LIST_TABLES;
+use crate::paths::data_paths;
 use crate::playlists::fetch::refresh_channel_list_async;
+use crate::playlists::scheduler::MIN_REFRESH_INTERVAL_HOURS;
 use crate::playlists::types::FetchState;
+use crate::playlists::virtual_lists::{forget_virtual_list, is_virtual_list, VIRTUAL_LIST_SOURCE};
 use crate::state::{ChannelCacheState, ChannelList, DbState};
+use rusqlite::{Connection, Result as RusqliteResult};
 use tauri::{AppHandle, State};
 
 #[tauri::command]
 pub fn get_channel_lists(state: State<DbState>) -> Result<Vec<ChannelList>, String> {
-    let db = state.db.lock().unwrap();
+    let db = state.reader();
     let mut stmt = db
-        .prepare("SELECT id, name, source, is_default, filepath, last_fetched FROM channel_lists")
+        .prepare(
+            "SELECT id, name, source, is_default, filepath, last_fetched, refresh_interval_hours,
+                    EXISTS (SELECT 1 FROM virtual_lists v WHERE v.channel_list_id = channel_lists.id)
+             FROM channel_lists",
+        )
         .map_err(|e| e.to_string())?;
     let list_iter = stmt
         .query_map([], |row| {
@@ -19,6 +28,8 @@ pub fn get_channel_lists(state: State<DbState>) -> Result<Vec<ChannelList>, Stri
                 is_default: row.get(3)?,
                 filepath: row.get(4)?,
                 last_fetched: row.get(5)?,
+                refresh_interval_hours: row.get(6)?,
+                is_virtual: row.get(7)?,
             })
         })
         .map_err(|e| e.to_string())?;
@@ -41,6 +52,36 @@ pub fn add_channel_list(state: State<DbState>, name: String, source: String) ->
     Ok(())
 }
 
+#[tauri::command]
+pub fn set_channel_list_refresh_interval(
+    state: State<DbState>,
+    id: i32,
+    hours: Option<i64>,
+) -> Result<(), String> {
+    if let Some(hours) = hours {
+        if hours < MIN_REFRESH_INTERVAL_HOURS {
+            return Err(format!(
+                "Refresh interval must be at least {} hour(s)",
+                MIN_REFRESH_INTERVAL_HOURS
+            ));
+        }
+    }
+    let db = state.db.lock().unwrap();
+    if hours.is_some() && is_virtual_list(&db, id as i64) {
+        return Err("Virtual lists follow the refresh schedule of their members".to_string());
+    }
+    let updated = db
+        .execute(
+            "UPDATE channel_lists SET refresh_interval_hours = ?1 WHERE id = ?2",
+            rusqlite::params![hours, id],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err("Channel list not found".to_string());
+    }
+    Ok(())
+}
+
 #[tauri::command]
 pub fn set_default_channel_list(state: State<DbState>, id: i32) -> Result<(), String> {
     let mut db = state.db.lock().unwrap();
@@ -62,13 +103,34 @@ pub fn delete_channel_list(
     cache_state: State<ChannelCacheState>,
     id: i32,
 ) -> Result<(), String> {
-    let db = db_state.db.lock().unwrap();
-    db.execute("DELETE FROM channel_lists WHERE id = ?1", &[&id])
-        .map_err(|e| e.to_string())?;
+    let mut db = db_state.db.lock().unwrap();
+    discard_channel_list(&mut db, id)?;
     invalidate_channel_cache(cache_state)?;
     Ok(())
 }
 
+/// Deletes list `id` and everything stored for it in one transaction, e.g.
+/// when adding it failed after its row was inserted.
+pub fn discard_channel_list(conn: &mut Connection, id: i32) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    delete_channel_list_rows(&tx, id).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Deletes list `id` with everything stored for it, so a list that later
+/// reuses the id starts out empty. Run it inside a transaction.
+pub fn delete_channel_list_rows(conn: &Connection, id: i32) -> RusqliteResult<()> {
+    for table in PER_LIST_TABLES {
+        conn.execute(
+            &format!("DELETE FROM {} WHERE channel_list_id = ?1", table),
+            [id],
+        )?;
+    }
+    forget_virtual_list(conn, id as i64)?;
+    conn.execute("DELETE FROM channel_lists WHERE id = ?1", [id])?;
+    Ok(())
+}
+
 #[tauri::command]
 pub fn update_channel_list(
     db_state: State<DbState>,
@@ -77,13 +139,33 @@ pub fn update_channel_list(
     name: String,
     source: String,
 ) -> Result<(), String> {
-    let db = db_state.db.lock().unwrap();
-    db.execute(
+    {
+        let db = db_state.db.lock().unwrap();
+        save_channel_list_details(&db, id, &name, &source)?;
+    }
+    invalidate_channel_cache(cache_state)?;
+    Ok(())
+}
+
+// Virtual lists keep the marker source, and no other list may take it on
+fn save_channel_list_details(
+    conn: &Connection,
+    id: i32,
+    name: &str,
+    source: &str,
+) -> Result<(), String> {
+    let is_virtual = is_virtual_list(conn, id as i64);
+    if is_virtual && source != VIRTUAL_LIST_SOURCE {
+        return Err("The source of a virtual list cannot be changed".to_string());
+    }
+    if !is_virtual && source == VIRTUAL_LIST_SOURCE {
+        return Err(format!("'{}' is not a valid playlist source", source));
+    }
+    conn.execute(
         "UPDATE channel_lists SET name = ?1, source = ?2 WHERE id = ?3",
-        &[&name, &source, &id.to_string()],
+        rusqlite::params![name, source, id],
     )
     .map_err(|e| e.to_string())?;
-    invalidate_channel_cache(cache_state)?;
     Ok(())
 }
 
@@ -103,7 +185,12 @@ pub async fn start_channel_list_selection_async(
 ) -> Result<(), String> {
     // Check if the playlist needs to be refreshed based on cache settings
     let needs_refresh = {
-        let db = db_state.db.lock().unwrap();
+        let db = db_state.reader();
+
+        // Virtual lists load their members on demand
+        if is_virtual_list(&db, id as i64) {
+            return Ok(());
+        }
         
         // Get cache duration and current time
         let cache_duration_hours: i64 = db
@@ -177,9 +264,7 @@ pub async fn start_channel_list_selection_async(
                         true // Cache is expired, need refresh
                     } else {
                         // Cache is not expired, but validate the cached file
-                        let data_dir = dirs::data_dir().unwrap().join("tollo");
-                        let channel_lists_dir = data_dir.join("channel_lists");
-                        let cached_file_path = channel_lists_dir.join(&cached_file);
+                        let cached_file_path = data_paths().channel_list_file(&cached_file);
                     
                         // Check if cached file exists and is not empty
                         match std::fs::metadata(&cached_file_path) {
@@ -217,3 +302,100 @@ pub async fn start_channel_list_selection_async(
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::populate_channels;
+    use crate::test_support::{channel, migrated_db};
+
+    #[test]
+    fn test_update_keeps_virtual_source_to_virtual_lists() {
+        let conn = migrated_db();
+        conn.execute_batch(
+            "INSERT INTO channel_lists (id, name, source) VALUES (1, 'Plain', 'http://example.com/a.m3u');
+             INSERT INTO channel_lists (id, name, source) VALUES (2, 'Both', 'virtual');
+             INSERT INTO virtual_lists (channel_list_id) VALUES (2);",
+        )
+        .unwrap();
+
+        assert!(save_channel_list_details(&conn, 1, "Plain", VIRTUAL_LIST_SOURCE).is_err());
+        assert!(save_channel_list_details(&conn, 2, "Both", "http://example.com/b.m3u").is_err());
+        save_channel_list_details(&conn, 1, "Renamed", "http://example.com/c.m3u").unwrap();
+        save_channel_list_details(&conn, 2, "Combined", VIRTUAL_LIST_SOURCE).unwrap();
+
+        let lists: Vec<(String, String)> = conn
+            .prepare("SELECT name, source FROM channel_lists ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            lists,
+            vec![
+                (
+                    "Renamed".to_string(),
+                    "http://example.com/c.m3u".to_string()
+                ),
+                ("Combined".to_string(), VIRTUAL_LIST_SOURCE.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delete_channel_list_rows() {
+        let mut conn = migrated_db();
+        conn.execute_batch(
+            "INSERT INTO channel_lists (id, name, source) VALUES (1, 'Old', 'http://example.com/a.m3u');
+             INSERT INTO channel_lists (id, name, source) VALUES (2, 'Kept', 'http://example.com/b.m3u');
+             INSERT INTO channel_lists (id, name, source) VALUES (3, 'Both', 'virtual');
+             INSERT INTO group_layout (channel_list_id, group_name, position) VALUES (1, 'News', 0);
+             INSERT INTO channel_list_mirrors (channel_list_id, position, url) VALUES (1, 0, 'http://mirror.example.com/a.m3u');
+             INSERT INTO virtual_list_members (virtual_list_id, member_list_id, position) VALUES (3, 1, 0);
+             INSERT INTO group_layout (channel_list_id, group_name, position) VALUES (2, 'Sports', 0);",
+        )
+        .unwrap();
+        let channel = channel("News", "http://example.com/news", "News");
+        populate_channels(&mut conn, 1, &[channel]).unwrap();
+
+        let tx = conn.transaction().unwrap();
+        delete_channel_list_rows(&tx, 1).unwrap();
+        tx.commit().unwrap();
+
+        for table in PER_LIST_TABLES {
+            let count: i64 = conn
+                .query_row(
+                    &format!("SELECT COUNT(*) FROM {} WHERE channel_list_id = 1", table),
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(count, 0, "{} still has rows of the deleted list", table);
+        }
+        let members: i64 = conn
+            .query_row("SELECT COUNT(*) FROM virtual_list_members", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(members, 0);
+        let fts: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM channels_fts WHERE channels_fts MATCH 'News'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(fts, 0);
+
+        // Other lists keep their data
+        let kept: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM group_layout WHERE channel_list_id = 2",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(kept, 1);
+    }
+}
@@ -1,13 +1,23 @@
 use crate::channels::invalidate_channel_cache;
-use crate::playlists::types::{emit_progress, FetchState, PlaylistFetchStatus};
+use crate::paths::data_paths;
+use crate::playlists::conditional::{
+    download_body, record_unchanged_fetch, replace_playlist_file, store_download,
+    store_playlist_file, PlaylistChange, PlaylistDownload, PlaylistValidators,
+};
+use crate::playlists::crud::{delete_channel_list_rows, discard_channel_list};
+use crate::playlists::request_config::{save_request_config, PlaylistRequestConfig};
+use crate::playlists::retry::{save_mirrors, PlaylistFetchPlan};
+use crate::playlists::types::{
+    emit_progress, emit_stopped, report_transfer, FetchOperation, FetchState, PlaylistFetchStatus,
+};
+use crate::playlists::virtual_lists::{load_virtual_list, VIRTUAL_LIST_SOURCE};
 use crate::state::{ChannelCacheState, DbState};
 use chrono::Utc;
-use dirs;
-use reqwest;
-use rusqlite;
+use rusqlite::{self, OptionalExtension};
 use std::fs;
 use tauri::{AppHandle, State};
-use uuid::Uuid;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
 
 #[tauri::command]
 pub async fn refresh_channel_list_async(
@@ -16,10 +26,45 @@ pub async fn refresh_channel_list_async(
     cache_state: State<'_, ChannelCacheState>,
     fetch_state: State<'_, FetchState>,
     id: i32,
+) -> Result<(), String> {
+    refresh_channel_list(app_handle, db_state, cache_state, fetch_state, id, None).await
+}
+
+async fn refresh_channel_list(
+    app_handle: AppHandle,
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    fetch_state: State<'_, FetchState>,
+    id: i32,
+    parent: Option<&CancellationToken>,
+) -> Result<(), String> {
+    let operation = fetch_state.begin(id, parent);
+    let result = refresh_list_source(
+        app_handle.clone(),
+        db_state,
+        cache_state,
+        fetch_state.clone(),
+        id,
+        &operation,
+    )
+    .await;
+    if let Err(error) = &result {
+        emit_stopped(&app_handle, &fetch_state, &operation, error).await;
+    }
+    result
+}
+
+async fn refresh_list_source(
+    app_handle: AppHandle,
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    fetch_state: State<'_, FetchState>,
+    id: i32,
+    operation: &FetchOperation,
 ) -> Result<(), String> {
     // Get the source URL from database
     let source = {
-        let db = db_state.db.lock().unwrap();
+        let db = db_state.reader();
         db.query_row(
             "SELECT source FROM channel_lists WHERE id = ?1",
             &[&id],
@@ -28,12 +73,33 @@ pub async fn refresh_channel_list_async(
         .map_err(|_| "Channel list not found".to_string())?
     };
 
+    if source == VIRTUAL_LIST_SOURCE {
+        return refresh_virtual_list(
+            app_handle,
+            db_state,
+            cache_state,
+            fetch_state,
+            id,
+            operation,
+        )
+        .await;
+    }
+
     // Handle both HTTP and file sources
     if source.starts_with("http") {
         // HTTP source - download and cache
     } else {
         // File source - read from local filesystem
-        return refresh_file_playlist(app_handle, db_state, cache_state, fetch_state, id, source).await;
+        return refresh_file_playlist(
+            app_handle,
+            db_state,
+            cache_state,
+            fetch_state,
+            id,
+            source,
+            operation,
+        )
+        .await;
     }
 
     // Emit starting status
@@ -43,10 +109,11 @@ pub async fn refresh_channel_list_async(
         PlaylistFetchStatus {
             id,
             status: "starting".to_string(),
-            progress: 0.0,
+            progress: Some(0.0),
             message: "Initializing refresh...".to_string(),
             channel_count: None,
             error: None,
+            transfer: None,
         },
     )
     .await;
@@ -58,23 +125,36 @@ pub async fn refresh_channel_list_async(
         PlaylistFetchStatus {
             id,
             status: "fetching".to_string(),
-            progress: 0.2,
+            progress: None,
             message: "Downloading playlist...".to_string(),
             channel_count: None,
             error: None,
+            transfer: None,
         },
     )
     .await;
 
-    // Fetch the playlist
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&source)
-        .header("User-Agent", "Mozilla/5.0")
-        .timeout(std::time::Duration::from_secs(120))
-        .send()
-        .await
-        .map_err(|e| format!("Failed to fetch: {}", e))?;
+    // Fetch the playlist, conditionally if the stored copy is still on disk,
+    // falling back to the list's mirrors
+    let (validators, plan) = db_state
+        .read(move |conn| {
+            Ok((
+                PlaylistValidators::load(conn, id)?,
+                PlaylistFetchPlan::load(conn, id, &source),
+            ))
+        })
+        .await?;
+    let plan = plan?;
+    let (transfer, updates) = watch::channel(None);
+    let download = operation
+        .run(report_transfer(
+            &app_handle,
+            &fetch_state,
+            id,
+            updates,
+            plan.download(&validators, &transfer),
+        ))
+        .await?;
 
     // Emit processing status
     emit_progress(
@@ -83,19 +163,47 @@ pub async fn refresh_channel_list_async(
         PlaylistFetchStatus {
             id,
             status: "processing".to_string(),
-            progress: 0.6,
+            progress: None,
             message: "Processing playlist content...".to_string(),
             channel_count: None,
             error: None,
+            transfer: None,
         },
     )
     .await;
 
-    let content = response
-        .text()
-        .await
-        .map_err(|e| format!("Failed to read: {}", e))?;
+    let now = Utc::now().timestamp();
+    let change = download.change(&validators);
+    if change != PlaylistChange::Changed {
+        // Nothing new: keep the stored file and the cached channels
+        let content = match change {
+            PlaylistChange::NotModified => validators.read_stored().unwrap_or_default(),
+            _ => download_body(&download).to_string(),
+        };
+        let channel_count = count_channels(&content);
+        db_state
+            .write(move |conn| record_unchanged_fetch(conn, id, now, &download))
+            .await
+            .map_err(|e| format!("Failed to update: {}", e))?;
+
+        emit_progress(
+            &app_handle,
+            &fetch_state,
+            PlaylistFetchStatus {
+                id,
+                status: "completed".to_string(),
+                progress: Some(1.0),
+                message: "Playlist is already up to date".to_string(),
+                channel_count: Some(channel_count),
+                error: None,
+                transfer: None,
+            },
+        )
+        .await;
+        return Ok(());
+    }
 
+    let content = download_body(&download);
     if content.trim().is_empty() || !content.trim_start().starts_with("#EXTM3U") {
         let error_msg = "Invalid M3U playlist".to_string();
         emit_progress(
@@ -104,21 +212,19 @@ pub async fn refresh_channel_list_async(
             PlaylistFetchStatus {
                 id,
                 status: "error".to_string(),
-                progress: 0.0,
+                progress: Some(0.0),
                 message: "Failed to process playlist".to_string(),
                 channel_count: None,
                 error: Some(error_msg.clone()),
+                transfer: None,
             },
         )
         .await;
         return Err(error_msg);
     }
 
-    // Count channels
-    let channel_count = content
-        .lines()
-        .filter(|line| line.starts_with("#EXTINF:"))
-        .count();
+    let channel_count = count_channels(content);
+    operation.check()?;
 
     // Emit saving status
     emit_progress(
@@ -127,36 +233,22 @@ pub async fn refresh_channel_list_async(
         PlaylistFetchStatus {
             id,
             status: "saving".to_string(),
-            progress: 0.8,
+            progress: None,
             message: "Saving playlist...".to_string(),
             channel_count: Some(channel_count),
             error: None,
+            transfer: None,
         },
     )
     .await;
 
     // Save to file
-    let data_dir = dirs::data_dir().unwrap().join("tollo/channel_lists");
-    fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create directory: {}", e))?;
-    let filename = format!("{}.m3u", Uuid::new_v4());
-    let filepath = data_dir.join(&filename);
-
-    fs::write(&filepath, &content).map_err(|e| format!("Failed to save: {}", e))?;
+    let filename = store_download(&download)?;
 
-    // Update database
-    let now = Utc::now().timestamp();
-    {
-        let db = db_state.db.lock().unwrap();
-        db.execute(
-            "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2 WHERE id = ?3",
-            &[
-                &filename as &dyn rusqlite::ToSql,
-                &now as &dyn rusqlite::ToSql,
-                &id as &dyn rusqlite::ToSql,
-            ],
-        )
-        .map_err(|e| format!("Failed to update: {}", e))?;
-    }
+    // Update database, dropping the file it replaces
+    db_state
+        .write(move |conn| Ok(replace_playlist_file(conn, id, &filename, now, &download)))
+        .await??;
 
     // Invalidate cache
     invalidate_channel_cache(cache_state)?;
@@ -168,10 +260,11 @@ pub async fn refresh_channel_list_async(
         PlaylistFetchStatus {
             id,
             status: "completed".to_string(),
-            progress: 1.0,
+            progress: Some(1.0),
             message: "Playlist refreshed successfully".to_string(),
             channel_count: Some(channel_count),
             error: None,
+            transfer: None,
         },
     )
     .await;
@@ -179,6 +272,80 @@ pub async fn refresh_channel_list_async(
     Ok(())
 }
 
+// Refreshing a virtual list refreshes each of its members
+async fn refresh_virtual_list(
+    app_handle: AppHandle,
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    fetch_state: State<'_, FetchState>,
+    id: i32,
+    operation: &FetchOperation,
+) -> Result<(), String> {
+    let list = db_state
+        .read(move |conn| load_virtual_list(conn, id as i64))
+        .await?
+        .ok_or_else(|| "Channel list not found".to_string())?;
+
+    let mut failures = Vec::new();
+    for member_id in list.member_ids {
+        // Cancelling the virtual list cancels the member being refreshed
+        let refresh = Box::pin(refresh_channel_list(
+            app_handle.clone(),
+            db_state.clone(),
+            cache_state.clone(),
+            fetch_state.clone(),
+            member_id as i32,
+            Some(operation.token()),
+        ));
+        if let Err(e) = refresh.await {
+            failures.push(format!("list {}: {}", member_id, e));
+        }
+        if operation.is_cancelled() {
+            break;
+        }
+    }
+
+    invalidate_channel_cache(cache_state)?;
+    operation.check()?;
+
+    let error = (!failures.is_empty())
+        .then(|| format!("Failed to refresh member lists: {}", failures.join("; ")));
+    emit_progress(
+        &app_handle,
+        &fetch_state,
+        PlaylistFetchStatus {
+            id,
+            status: if error.is_some() {
+                "error"
+            } else {
+                "completed"
+            }
+            .to_string(),
+            progress: Some(1.0),
+            message: match error {
+                Some(_) => "Some member lists failed to refresh".to_string(),
+                None => "Member lists refreshed successfully".to_string(),
+            },
+            channel_count: None,
+            error: error.clone(),
+            transfer: None,
+        },
+    )
+    .await;
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn count_channels(content: &str) -> usize {
+    content
+        .lines()
+        .filter(|line| line.starts_with("#EXTINF:"))
+        .count()
+}
+
 #[tauri::command]
 pub async fn validate_and_add_channel_list_async(
     app_handle: AppHandle,
@@ -187,8 +354,11 @@ pub async fn validate_and_add_channel_list_async(
     fetch_state: State<'_, FetchState>,
     name: String,
     source: String,
+    request_config: Option<PlaylistRequestConfig>,
+    mirrors: Option<Vec<String>>,
 ) -> Result<i32, String> {
     let clean_name = name.trim();
+    let request_config = request_config.unwrap_or_default();
     let clean_source = source.trim();
 
     if clean_name.is_empty() || clean_source.is_empty() {
@@ -197,36 +367,102 @@ pub async fn validate_and_add_channel_list_async(
 
     // First, add the list to get an ID
     let list_id = {
-        let db = db_state.db.lock().unwrap();
-
-        // Check if already exists
-        let existing: i64 = db
-            .query_row(
-                "SELECT COUNT(*) FROM channel_lists WHERE name = ?1",
-                [clean_name],
-                |row| row.get(0),
-            )
-            .map_err(|e| e.to_string())?;
-        if existing > 0 {
-            return Err(format!("Channel list '{}' already exists", clean_name));
-        }
+        let name = clean_name.to_string();
+        let source = clean_source.to_string();
+        let mirrors = mirrors.unwrap_or_default();
+        db_state
+            .write(move |db| {
+                // Check if already exists
+                let existing: i64 = db.query_row(
+                    "SELECT COUNT(*) FROM channel_lists WHERE name = ?1",
+                    [&name],
+                    |row| row.get(0),
+                )?;
+                if existing > 0 {
+                    return Ok(Err(format!("Channel list '{}' already exists", name)));
+                }
+
+                // Insert the new list
+                db.execute(
+                    "INSERT INTO channel_lists (name, source) VALUES (?1, ?2)",
+                    [&name, &source],
+                )?;
+
+                // Get the ID
+                let list_id = db.query_row(
+                    "SELECT id FROM channel_lists WHERE name = ?1 AND source = ?2",
+                    [&name, &source],
+                    |row| row.get::<_, i32>(0),
+                )?;
+                let saved = save_request_config(db, list_id, &request_config)
+                    .and_then(|_| save_mirrors(db, list_id, &mirrors));
+                if let Err(error) = saved {
+                    return Ok(discard_channel_list(db, list_id).and(Err(error)));
+                }
+                Ok(Ok(list_id))
+            })
+            .await??
+    };
 
-        // Insert the new list
-        db.execute(
-            "INSERT INTO channel_lists (name, source) VALUES (?1, ?2)",
-            &[&clean_name, &clean_source],
-        )
-        .map_err(|e| e.to_string())?;
+    let operation = fetch_state.begin(list_id, None);
+    let result = load_new_list(
+        &app_handle,
+        db_state.clone(),
+        cache_state,
+        fetch_state.clone(),
+        list_id,
+        clean_source,
+        &operation,
+    )
+    .await;
+    if let Err(error) = &result {
+        // Nothing of a list that failed to load, or was cancelled, is kept
+        if let Err(e) = discard_new_list(&db_state, list_id).await {
+            eprintln!(
+                "Failed to remove list {} after a failed add: {}",
+                list_id, e
+            );
+        }
+        emit_stopped(&app_handle, &fetch_state, &operation, error).await;
+    }
+    result.map(|_| list_id)
+}
 
-        // Get the ID
-        db.query_row(
-            "SELECT id FROM channel_lists WHERE name = ?1 AND source = ?2",
-            [clean_name, clean_source],
-            |row| row.get::<_, i32>(0),
-        )
-        .map_err(|e| e.to_string())?
-    };
+// Deletes a list that was just added, with its request settings, mirrors and
+// any playlist file already stored for it
+async fn discard_new_list(db_state: &DbState, list_id: i32) -> Result<(), String> {
+    let filepath = db_state
+        .write(move |conn| {
+            let filepath: Option<String> = conn
+                .query_row(
+                    "SELECT filepath FROM channel_lists WHERE id = ?1",
+                    [list_id],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .flatten();
+            let tx = conn.transaction()?;
+            delete_channel_list_rows(&tx, list_id)?;
+            tx.commit()?;
+            Ok(filepath)
+        })
+        .await?;
+    if let Some(filepath) = filepath {
+        let _ = fs::remove_file(data_paths().channel_list_file(&filepath));
+    }
+    Ok(())
+}
 
+// Fetches and stores the playlist of a list that was just added
+async fn load_new_list(
+    app_handle: &AppHandle,
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    fetch_state: State<'_, FetchState>,
+    list_id: i32,
+    clean_source: &str,
+    operation: &FetchOperation,
+) -> Result<(), String> {
     // Process both HTTP and file sources
     if clean_source.starts_with("http") {
         if !clean_source.starts_with("http://") && !clean_source.starts_with("https://") {
@@ -235,76 +471,83 @@ pub async fn validate_and_add_channel_list_async(
 
         // Emit starting status
         emit_progress(
-            &app_handle,
+            app_handle,
             &fetch_state,
             PlaylistFetchStatus {
                 id: list_id,
                 status: "starting".to_string(),
-                progress: 0.0,
+                progress: Some(0.0),
                 message: "Validating playlist...".to_string(),
                 channel_count: None,
                 error: None,
+                transfer: None,
             },
         )
         .await;
 
         // Emit fetching status
         emit_progress(
-            &app_handle,
+            app_handle,
             &fetch_state,
             PlaylistFetchStatus {
                 id: list_id,
                 status: "fetching".to_string(),
-                progress: 0.2,
+                progress: None,
                 message: "Downloading playlist...".to_string(),
                 channel_count: None,
                 error: None,
+                transfer: None,
             },
         )
         .await;
 
-        // Fetch the playlist
-        let client = reqwest::Client::new();
-        let response = client
-            .get(clean_source)
-            .header("User-Agent", "Mozilla/5.0")
-            .timeout(std::time::Duration::from_secs(120))
-            .send()
-            .await
-            .map_err(|e| format!("Failed to connect: {}", e))?;
+        // Fetch the playlist, falling back to its mirrors
+        let source = clean_source.to_string();
+        let plan = db_state
+            .read(move |conn| Ok(PlaylistFetchPlan::load(conn, list_id, &source)))
+            .await??;
+        let (transfer, updates) = watch::channel(None);
+        let download = operation
+            .run(report_transfer(
+                app_handle,
+                &fetch_state,
+                list_id,
+                updates,
+                plan.download(&PlaylistValidators::default(), &transfer),
+            ))
+            .await?;
 
         // Emit processing status
         emit_progress(
-            &app_handle,
+            app_handle,
             &fetch_state,
             PlaylistFetchStatus {
                 id: list_id,
                 status: "processing".to_string(),
-                progress: 0.6,
+                progress: None,
                 message: "Processing playlist content...".to_string(),
                 channel_count: None,
                 error: None,
+                transfer: None,
             },
         )
         .await;
 
-        let content = response
-            .text()
-            .await
-            .map_err(|e| format!("Failed to read: {}", e))?;
+        let content = download_body(&download);
 
         if content.trim().is_empty() || !content.trim_start().starts_with("#EXTM3U") {
             let error_msg = "Invalid M3U playlist".to_string();
             emit_progress(
-                &app_handle,
+                app_handle,
                 &fetch_state,
                 PlaylistFetchStatus {
                     id: list_id,
                     status: "error".to_string(),
-                    progress: 0.0,
+                    progress: Some(0.0),
                     message: "Failed to validate playlist".to_string(),
                     channel_count: None,
                     error: Some(error_msg.clone()),
+                    transfer: None,
                 },
             )
             .await;
@@ -319,98 +562,81 @@ pub async fn validate_and_add_channel_list_async(
         if channel_count == 0 {
             let error_msg = "No channels found".to_string();
             emit_progress(
-                &app_handle,
+                app_handle,
                 &fetch_state,
                 PlaylistFetchStatus {
                     id: list_id,
                     status: "error".to_string(),
-                    progress: 0.0,
+                    progress: Some(0.0),
                     message: "No channels found in playlist".to_string(),
                     channel_count: None,
                     error: Some(error_msg.clone()),
+                    transfer: None,
                 },
             )
             .await;
             return Err(error_msg);
         }
+        operation.check()?;
 
         // Emit saving status
         emit_progress(
-            &app_handle,
+            app_handle,
             &fetch_state,
             PlaylistFetchStatus {
                 id: list_id,
                 status: "saving".to_string(),
-                progress: 0.8,
+                progress: None,
                 message: "Saving playlist...".to_string(),
                 channel_count: Some(channel_count),
                 error: None,
+                transfer: None,
             },
         )
         .await;
 
         // Save the playlist
-        let data_dir = dirs::data_dir().unwrap().join("tollo/channel_lists");
-        fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create directory: {}", e))?;
-        let filename = format!("{}.m3u", Uuid::new_v4());
-        let filepath = data_dir.join(&filename);
-
-        fs::write(&filepath, &content).map_err(|e| format!("Failed to save: {}", e))?;
+        let filename = store_download(&download)?;
 
         // Update database with file info
         let now = Utc::now().timestamp();
-        {
-            let db = db_state.db.lock().unwrap();
-            db.execute(
-                "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2 WHERE id = ?3",
-                &[
-                    &filename as &dyn rusqlite::ToSql,
-                    &now as &dyn rusqlite::ToSql,
-                    &list_id as &dyn rusqlite::ToSql,
-                ],
-            )
-            .map_err(|e| format!("Failed to update: {}", e))?;
-        }
+        db_state
+            .write(move |conn| {
+                Ok(replace_playlist_file(
+                    conn, list_id, &filename, now, &download,
+                ))
+            })
+            .await??;
 
         // Invalidate cache
         invalidate_channel_cache(cache_state)?;
 
         // Emit completed status
         emit_progress(
-            &app_handle,
+            app_handle,
             &fetch_state,
             PlaylistFetchStatus {
                 id: list_id,
                 status: "completed".to_string(),
-                progress: 1.0,
+                progress: Some(1.0),
                 message: "Playlist added successfully".to_string(),
                 channel_count: Some(channel_count),
                 error: None,
+                transfer: None,
             },
         )
         .await;
     } else {
         // Handle file sources
         if !std::path::Path::new(clean_source).exists() {
-            // Delete the playlist entry since the file doesn't exist
-            let db = db_state.db.lock().unwrap();
-            let _ = db.execute("DELETE FROM channel_lists WHERE id = ?1", [list_id]);
             return Err(format!("File '{}' does not exist", clean_source));
         }
 
         // Read and validate the file
         let content = fs::read_to_string(clean_source)
-            .map_err(|e| {
-                // Delete the playlist entry since we can't read the file
-                let db = db_state.db.lock().unwrap();
-                let _ = db.execute("DELETE FROM channel_lists WHERE id = ?1", [list_id]);
-                format!("Failed to read file '{}': {}", clean_source, e)
-            })?;
+            .map_err(|e| format!("Failed to read file '{}': {}", clean_source, e))?;
 
         if content.trim().is_empty() || !content.trim_start().starts_with("#EXTM3U") {
-            // Delete the playlist entry since the file is invalid
-            let db = db_state.db.lock().unwrap();
-            let _ = db.execute("DELETE FROM channel_lists WHERE id = ?1", [list_id]);
             return Err("Invalid M3U playlist file".to_string());
         }
 
@@ -420,40 +646,34 @@ pub async fn validate_and_add_channel_list_async(
             .count();
 
         if channel_count == 0 {
-            // Delete the playlist entry since no channels were found
-            let db = db_state.db.lock().unwrap();
-            let _ = db.execute("DELETE FROM channel_lists WHERE id = ?1", [list_id]);
             return Err("No channels found in playlist file".to_string());
         }
+        operation.check()?;
 
         // Save the file content to cache
-        let data_dir = dirs::data_dir().unwrap().join("tollo/channel_lists");
-        fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create directory: {}", e))?;
-        let filename = format!("{}.m3u", Uuid::new_v4());
-        let filepath = data_dir.join(&filename);
-
-        fs::write(&filepath, &content).map_err(|e| format!("Failed to save: {}", e))?;
+        let filename = store_playlist_file(&content)?;
+        let download = PlaylistDownload::Content {
+            body: content,
+            etag: None,
+            last_modified: None,
+            file: None,
+        };
 
         // Update database with file info
         let now = Utc::now().timestamp();
-        {
-            let db = db_state.db.lock().unwrap();
-            db.execute(
-                "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2 WHERE id = ?3",
-                &[
-                    &filename as &dyn rusqlite::ToSql,
-                    &now as &dyn rusqlite::ToSql,
-                    &list_id as &dyn rusqlite::ToSql,
-                ],
-            )
-            .map_err(|e| format!("Failed to update: {}", e))?;
-        }
+        db_state
+            .write(move |conn| {
+                Ok(replace_playlist_file(
+                    conn, list_id, &filename, now, &download,
+                ))
+            })
+            .await??;
 
         // Invalidate cache
         invalidate_channel_cache(cache_state)?;
     }
 
-    Ok(list_id)
+    Ok(())
 }
 
 #[tauri::command]
@@ -465,6 +685,15 @@ pub async fn get_playlist_fetch_status(
     Ok(operations.get(&id).cloned())
 }
 
+// Stops the running refresh or import of list `id`, returning false if there is none
+#[tauri::command]
+pub async fn cancel_playlist_fetch(
+    fetch_state: State<'_, FetchState>,
+    id: i32,
+) -> Result<bool, String> {
+    Ok(fetch_state.cancel(id))
+}
+
 #[tauri::command]
 pub async fn get_all_playlist_fetch_status(
     fetch_state: State<'_, FetchState>,
@@ -480,6 +709,7 @@ async fn refresh_file_playlist(
     fetch_state: State<'_, FetchState>,
     id: i32,
     source: String,
+    operation: &FetchOperation,
 ) -> Result<(), String> {
     // Emit starting status
     emit_progress(
@@ -488,10 +718,11 @@ async fn refresh_file_playlist(
         PlaylistFetchStatus {
             id,
             status: "starting".to_string(),
-            progress: 0.0,
+            progress: Some(0.0),
             message: "Reading file playlist...".to_string(),
             channel_count: None,
             error: None,
+            transfer: None,
         },
     )
     .await;
@@ -503,10 +734,11 @@ async fn refresh_file_playlist(
         PlaylistFetchStatus {
             id,
             status: "processing".to_string(),
-            progress: 0.4,
+            progress: None,
             message: "Processing playlist content...".to_string(),
             channel_count: None,
             error: None,
+            transfer: None,
         },
     )
     .await;
@@ -523,10 +755,11 @@ async fn refresh_file_playlist(
             PlaylistFetchStatus {
                 id,
                 status: "error".to_string(),
-                progress: 0.0,
+                progress: Some(0.0),
                 message: "Failed to process playlist".to_string(),
                 channel_count: None,
                 error: Some(error_msg.clone()),
+                transfer: None,
             },
         )
         .await;
@@ -538,6 +771,7 @@ async fn refresh_file_playlist(
         .lines()
         .filter(|line| line.starts_with("#EXTINF:"))
         .count();
+    operation.check()?;
 
     // Emit saving status
     emit_progress(
@@ -546,36 +780,29 @@ async fn refresh_file_playlist(
         PlaylistFetchStatus {
             id,
             status: "saving".to_string(),
-            progress: 0.8,
+            progress: None,
             message: "Updating cached playlist...".to_string(),
             channel_count: Some(channel_count),
             error: None,
+            transfer: None,
         },
     )
     .await;
 
     // Save to cache file
-    let data_dir = dirs::data_dir().unwrap().join("tollo/channel_lists");
-    fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create directory: {}", e))?;
-    let filename = format!("{}.m3u", Uuid::new_v4());
-    let filepath = data_dir.join(&filename);
+    let filename = store_playlist_file(&content)?;
 
-    fs::write(&filepath, &content).map_err(|e| format!("Failed to save: {}", e))?;
-
-    // Update database
+    // Update database, dropping the file it replaces
     let now = Utc::now().timestamp();
-    {
-        let db = db_state.db.lock().unwrap();
-        db.execute(
-            "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2 WHERE id = ?3",
-            &[
-                &filename as &dyn rusqlite::ToSql,
-                &now as &dyn rusqlite::ToSql,
-                &id as &dyn rusqlite::ToSql,
-            ],
-        )
-        .map_err(|e| format!("Failed to update: {}", e))?;
-    }
+    let download = PlaylistDownload::Content {
+        body: content,
+        etag: None,
+        last_modified: None,
+        file: None,
+    };
+    db_state
+        .write(move |conn| Ok(replace_playlist_file(conn, id, &filename, now, &download)))
+        .await??;
 
     // Invalidate cache
     invalidate_channel_cache(cache_state)?;
@@ -587,10 +814,11 @@ async fn refresh_file_playlist(
         PlaylistFetchStatus {
             id,
             status: "completed".to_string(),
-            progress: 1.0,
+            progress: Some(1.0),
             message: "File playlist refreshed successfully".to_string(),
             channel_count: Some(channel_count),
             error: None,
+            transfer: None,
         },
     )
     .await;
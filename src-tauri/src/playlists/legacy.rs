@@ -1,8 +1,11 @@
 use crate::channels::invalidate_channel_cache;
+use crate::paths::data_paths;
+use crate::playlists::crud::discard_channel_list;
+use crate::playlists::request_config::{
+    load_request_config, save_request_config, PlaylistRequestConfig,
+};
 use crate::state::{ChannelCacheState, DbState};
 use chrono::Utc;
-use dirs;
-use reqwest;
 use rusqlite;
 use std::fs;
 use tauri::State;
@@ -25,10 +28,9 @@ pub fn refresh_channel_list(
         .map_err(|_| "Channel list not found")?;
 
     if source.starts_with("http") {
-        let client = reqwest::blocking::Client::new();
+        let client = load_request_config(&db, id)?.blocking_client()?;
         let response = client
             .get(&source)
-            .header("User-Agent", "Mozilla/5.0")
             .timeout(std::time::Duration::from_secs(30))
             .send()
             .map_err(|e| format!("Failed to fetch: {}", e))?;
@@ -40,7 +42,7 @@ pub fn refresh_channel_list(
             return Err("Invalid M3U playlist".to_string());
         }
 
-        let data_dir = dirs::data_dir().unwrap().join("tollo/channel_lists");
+        let data_dir = data_paths().channel_lists_dir();
         fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create directory: {}", e))?;
         let filename = format!("{}.m3u", Uuid::new_v4());
         let filepath = data_dir.join(&filename);
@@ -69,8 +71,10 @@ pub fn validate_and_add_channel_list(
     cache_state: State<ChannelCacheState>,
     name: String,
     source: String,
+    request_config: Option<PlaylistRequestConfig>,
 ) -> Result<i32, String> {
     let clean_name = name.trim();
+    let request_config = request_config.unwrap_or_default();
     let clean_source = source.trim();
 
     if clean_name.is_empty() || clean_source.is_empty() {
@@ -82,10 +86,9 @@ pub fn validate_and_add_channel_list(
             return Err("Invalid URL format".to_string());
         }
 
-        let client = reqwest::blocking::Client::new();
+        let client = request_config.blocking_client()?;
         let response = client
             .get(clean_source)
-            .header("User-Agent", "Mozilla/5.0")
             .timeout(std::time::Duration::from_secs(30))
             .send()
             .map_err(|e| format!("Failed to connect: {}", e))?;
@@ -106,7 +109,7 @@ pub fn validate_and_add_channel_list(
         }
     }
 
-    let db = db_state.db.lock().unwrap();
+    let mut db = db_state.db.lock().unwrap();
     let existing: i64 = db
         .query_row(
             "SELECT COUNT(*) FROM channel_lists WHERE name = ?1",
@@ -131,38 +134,55 @@ pub fn validate_and_add_channel_list(
         )
         .map_err(|e| e.to_string())?;
 
-    if clean_source.starts_with("http") {
-        let client = reqwest::blocking::Client::new();
-        let response = client
-            .get(clean_source)
-            .header("User-Agent", "Mozilla/5.0")
-            .timeout(std::time::Duration::from_secs(30))
-            .send()
-            .map_err(|e| format!("Failed to re-fetch: {}", e))?;
-        let content = response
-            .text()
-            .map_err(|e| format!("Failed to read: {}", e))?;
-
-        let data_dir = dirs::data_dir().unwrap().join("tollo/channel_lists");
-        fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create directory: {}", e))?;
-        let filename = format!("{}.m3u", Uuid::new_v4());
-        let filepath = data_dir.join(&filename);
-
-        fs::write(&filepath, &content).map_err(|e| format!("Failed to save: {}", e))?;
-
-        let now = Utc::now().timestamp();
-        db.execute(
-            "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2 WHERE id = ?3",
-            &[
-                &filename as &dyn rusqlite::ToSql,
-                &now as &dyn rusqlite::ToSql,
-                &list_id as &dyn rusqlite::ToSql,
-            ],
-        )
-        .map_err(|e| format!("Failed to update: {}", e))?;
+    // A list that fails to load is removed again, request settings included
+    let stored = save_request_config(&db, list_id, &request_config)
+        .and_then(|_| store_new_list(&db, list_id, clean_source, &request_config));
+    if let Err(error) = stored {
+        discard_channel_list(&mut db, list_id)?;
+        return Err(error);
+    }
 
+    if clean_source.starts_with("http") {
         invalidate_channel_cache(cache_state)?;
     }
-
     Ok(list_id)
 }
+
+// Downloads the playlist of a list that was just added
+fn store_new_list(
+    db: &rusqlite::Connection,
+    list_id: i32,
+    clean_source: &str,
+    request_config: &PlaylistRequestConfig,
+) -> Result<(), String> {
+    if !clean_source.starts_with("http") {
+        return Ok(());
+    }
+    let client = request_config.blocking_client()?;
+    let response = client
+        .get(clean_source)
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .map_err(|e| format!("Failed to re-fetch: {}", e))?;
+    let content = response
+        .text()
+        .map_err(|e| format!("Failed to read: {}", e))?;
+
+    let data_dir = data_paths().channel_lists_dir();
+    fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+    let filename = format!("{}.m3u", Uuid::new_v4());
+    let filepath = data_dir.join(&filename);
+
+    fs::write(&filepath, &content).map_err(|e| format!("Failed to save: {}", e))?;
+
+    let now = Utc::now().timestamp();
+    db.execute(
+        "UPDATE channel_lists SET filepath = ?1, last_fetched = ?2 WHERE id = ?3",
+        rusqlite::params![filename, now, list_id],
+    )
+    .map_err(|e| {
+        let _ = fs::remove_file(&filepath);
+        format!("Failed to update: {}", e)
+    })?;
+    Ok(())
+}
@@ -0,0 +1,502 @@
+use crate::http_client::current_proxy_settings;
+use crate::paths::data_paths;
+use crate::state::DbState;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE, USER_AGENT};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::sync::Mutex;
+use tauri::State;
+
+/// Sent when a list does not configure its own user agent.
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0";
+
+const SECRET_KEY_LEN: usize = 32;
+
+// Loaded once per process; the file is created on first use
+static SECRET_KEY: Mutex<Option<[u8; SECRET_KEY_LEN]>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: String,
+}
+
+/// How the playlist of a list is requested. Stored encrypted, since most of it
+/// is credentials.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaylistRequestConfig {
+    pub user_agent: Option<String>,
+    pub basic_auth: Option<BasicAuth>,
+    /// Used when no basic auth is set
+    pub bearer_token: Option<String>,
+    pub headers: Vec<(String, String)>,
+    /// `Cookie` header value, e.g. `session=abc; lang=en`
+    pub cookies: Option<String>,
+    pub skip_tls_verification: bool,
+    /// Proxy for this list instead of the global one
+    pub proxy_url: Option<String>,
+    /// Fetch this list directly even when a global proxy is set
+    pub bypass_proxy: bool,
+}
+
+/// What the frontend is shown of a list's request settings. Secrets are only
+/// reported as set; once stored they are not sent back.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RequestConfigSummary {
+    pub user_agent: Option<String>,
+    pub username: Option<String>,
+    pub has_password: bool,
+    pub has_bearer_token: bool,
+    /// Names of the custom headers; their values may be credentials too
+    pub headers: Vec<String>,
+    pub has_cookies: bool,
+    pub skip_tls_verification: bool,
+    pub proxy_url: Option<String>,
+    pub bypass_proxy: bool,
+}
+
+impl From<&PlaylistRequestConfig> for RequestConfigSummary {
+    fn from(config: &PlaylistRequestConfig) -> Self {
+        RequestConfigSummary {
+            user_agent: config.user_agent.clone(),
+            username: config.basic_auth.as_ref().map(|auth| auth.username.clone()),
+            has_password: config
+                .basic_auth
+                .as_ref()
+                .is_some_and(|auth| !auth.password.is_empty()),
+            has_bearer_token: non_empty(&config.bearer_token).is_some(),
+            headers: config
+                .headers
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect(),
+            has_cookies: non_empty(&config.cookies).is_some(),
+            skip_tls_verification: config.skip_tls_verification,
+            proxy_url: config.proxy_url.clone(),
+            bypass_proxy: config.bypass_proxy,
+        }
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn header_value(value: &str, sensitive: bool) -> Result<HeaderValue, String> {
+    let mut value =
+        HeaderValue::from_str(value.trim()).map_err(|_| "Invalid header value".to_string())?;
+    value.set_sensitive(sensitive);
+    Ok(value)
+}
+
+impl PlaylistRequestConfig {
+    /// Headers sent with every playlist request. Authentication set here wins
+    /// over an `Authorization` header in `headers`.
+    pub fn header_map(&self) -> Result<HeaderMap, String> {
+        let mut headers = HeaderMap::new();
+        let user_agent = non_empty(&self.user_agent).unwrap_or(DEFAULT_USER_AGENT);
+        headers.insert(USER_AGENT, header_value(user_agent, false)?);
+
+        for (name, value) in &self.headers {
+            let header = HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| format!("Invalid header name '{}'", name))?;
+            headers.insert(header, header_value(value, false)?);
+        }
+
+        if let Some(auth) = &self.basic_auth {
+            let credentials = STANDARD.encode(format!("{}:{}", auth.username, auth.password));
+            headers.insert(
+                AUTHORIZATION,
+                header_value(&format!("Basic {}", credentials), true)?,
+            );
+        } else if let Some(token) = non_empty(&self.bearer_token) {
+            headers.insert(
+                AUTHORIZATION,
+                header_value(&format!("Bearer {}", token), true)?,
+            );
+        }
+
+        if let Some(cookies) = non_empty(&self.cookies) {
+            headers.insert(COOKIE, header_value(cookies, true)?);
+        }
+        Ok(headers)
+    }
+
+    /// Takes the secrets left out of an update from the stored settings. An
+    /// empty password, or no bearer token or cookies, keeps the stored one;
+    /// an empty token or cookie string removes it.
+    fn keep_stored_secrets(&mut self, stored: &PlaylistRequestConfig) {
+        if let (Some(auth), Some(stored_auth)) = (&mut self.basic_auth, &stored.basic_auth) {
+            if auth.password.is_empty() {
+                auth.password = stored_auth.password.clone();
+            }
+        }
+        for (value, stored) in [
+            (&mut self.bearer_token, &stored.bearer_token),
+            (&mut self.cookies, &stored.cookies),
+        ] {
+            if value.is_none() {
+                value.clone_from(stored);
+            }
+            if non_empty(value).is_none() {
+                *value = None;
+            }
+        }
+        // Headers are sent back by name only, so an empty value keeps the stored one
+        for (name, value) in self.headers.iter_mut() {
+            if value.is_empty() {
+                if let Some((_, stored)) = stored.headers.iter().find(|(n, _)| n == name) {
+                    value.clone_from(stored);
+                }
+            }
+        }
+    }
+
+    /// The proxy this list is fetched through, `None` when it has none.
+    fn proxy(&self) -> Result<Option<reqwest::Proxy>, String> {
+        if self.bypass_proxy {
+            return Ok(None);
+        }
+        let settings = current_proxy_settings();
+        match non_empty(&self.proxy_url) {
+            Some(url) => settings.proxy_to(url).map(Some),
+            None => settings.proxy(),
+        }
+    }
+
+    pub fn client(&self) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder()
+            .default_headers(self.header_map()?)
+            .danger_accept_invalid_certs(self.skip_tls_verification);
+        builder = match self.proxy()? {
+            Some(proxy) => builder.proxy(proxy),
+            None if self.bypass_proxy => builder.no_proxy(),
+            None => builder,
+        };
+        builder
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))
+    }
+
+    pub fn blocking_client(&self) -> Result<reqwest::blocking::Client, String> {
+        let mut builder = reqwest::blocking::Client::builder()
+            .default_headers(self.header_map()?)
+            .danger_accept_invalid_certs(self.skip_tls_verification);
+        builder = match self.proxy()? {
+            Some(proxy) => builder.proxy(proxy),
+            None if self.bypass_proxy => builder.no_proxy(),
+            None => builder,
+        };
+        builder
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))
+    }
+}
+
+fn read_or_create_key() -> Result<[u8; SECRET_KEY_LEN], String> {
+    let path = data_paths().secret_key_file();
+    match fs::read(&path) {
+        Ok(bytes) => {
+            return bytes
+                .try_into()
+                .map_err(|_| format!("Secret key '{}' is corrupt", path.display()));
+        }
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            return Err(format!("Failed to read secret key: {}", e));
+        }
+        Err(_) => {}
+    }
+
+    let mut key = [0u8; SECRET_KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| "Failed to generate secret key".to_string())?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(&path)
+        .map_err(|e| format!("Failed to create secret key: {}", e))?;
+    file.write_all(&key)
+        .map_err(|e| format!("Failed to write secret key: {}", e))?;
+    Ok(key)
+}
+
+fn secret_key() -> Result<LessSafeKey, String> {
+    let mut cached = SECRET_KEY.lock().unwrap();
+    let bytes = match *cached {
+        Some(bytes) => bytes,
+        None => *cached.insert(read_or_create_key()?),
+    };
+    let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| "Invalid secret key")?;
+    Ok(LessSafeKey::new(key))
+}
+
+// The list id is authenticated with the data so a config can't be moved to another list
+fn seal(key: &LessSafeKey, list_id: i32, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| "Failed to generate nonce".to_string())?;
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(list_id.to_le_bytes()),
+        &mut sealed,
+    )
+    .map_err(|_| "Failed to encrypt request settings".to_string())?;
+
+    let mut blob = nonce.to_vec();
+    blob.extend_from_slice(&sealed);
+    Ok(blob)
+}
+
+fn open(key: &LessSafeKey, list_id: i32, blob: &[u8]) -> Result<Vec<u8>, String> {
+    if blob.len() < NONCE_LEN {
+        return Err("Stored request settings are corrupt".to_string());
+    }
+    let (nonce, sealed) = blob.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| "Stored request settings are corrupt".to_string())?;
+    let mut buffer = sealed.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(list_id.to_le_bytes()), &mut buffer)
+        .map_err(|_| "Failed to decrypt request settings".to_string())?;
+    Ok(plaintext.to_vec())
+}
+
+/// Seals the stored settings of list `from` again for list `to`, or returns
+/// them as they are when the id stays. Fails for settings sealed by another
+/// installation, whose key this one does not have.
+pub fn reseal_request_config(blob: &[u8], from: i32, to: i32) -> Result<Vec<u8>, String> {
+    let key = secret_key()?;
+    let plaintext = open(&key, from, blob)?;
+    if from == to {
+        return Ok(blob.to_vec());
+    }
+    seal(&key, to, &plaintext)
+}
+
+/// The request settings of list `id`, the defaults when it has none.
+pub fn load_request_config(conn: &Connection, id: i32) -> Result<PlaylistRequestConfig, String> {
+    let blob: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT config FROM playlist_request_configs WHERE channel_list_id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some(blob) = blob else {
+        return Ok(PlaylistRequestConfig::default());
+    };
+    let plaintext = open(&secret_key()?, id, &blob)?;
+    serde_json::from_slice(&plaintext).map_err(|e| e.to_string())
+}
+
+/// Stores the request settings of list `id`; the defaults remove them.
+pub fn save_request_config(
+    conn: &Connection,
+    id: i32,
+    config: &PlaylistRequestConfig,
+) -> Result<(), String> {
+    if *config == PlaylistRequestConfig::default() {
+        return delete_request_config(conn, id).map_err(|e| e.to_string());
+    }
+    config.header_map()?;
+    config.proxy()?;
+    let plaintext = serde_json::to_vec(config).map_err(|e| e.to_string())?;
+    let blob = seal(&secret_key()?, id, &plaintext)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO playlist_request_configs (channel_list_id, config) VALUES (?1, ?2)",
+        rusqlite::params![id, blob],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn delete_request_config(conn: &Connection, id: i32) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM playlist_request_configs WHERE channel_list_id = ?1",
+        [id],
+    )?;
+    Ok(())
+}
+
+/// Stores `config` for list `id`, keeping the stored secrets it leaves out.
+pub fn update_request_config(
+    conn: &Connection,
+    id: i32,
+    mut config: PlaylistRequestConfig,
+) -> Result<(), String> {
+    config.keep_stored_secrets(&load_request_config(conn, id)?);
+    save_request_config(conn, id, &config)
+}
+
+#[tauri::command]
+pub fn get_playlist_request_config(
+    state: State<DbState>,
+    id: i32,
+) -> Result<RequestConfigSummary, String> {
+    let config = load_request_config(&state.reader(), id)?;
+    Ok(RequestConfigSummary::from(&config))
+}
+
+#[tauri::command]
+pub fn set_playlist_request_config(
+    state: State<DbState>,
+    id: i32,
+    config: Option<PlaylistRequestConfig>,
+) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    update_request_config(&db, id, config.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::migrated_db;
+
+    fn create_test_db() -> Connection {
+        let conn = migrated_db();
+        conn.execute(
+            "INSERT INTO channel_lists (id, name, source) VALUES (1, 'Private', 'https://example.com/list.m3u')",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn private_config() -> PlaylistRequestConfig {
+        PlaylistRequestConfig {
+            user_agent: Some("VLC/3.0".to_string()),
+            basic_auth: Some(BasicAuth {
+                username: "user".to_string(),
+                password: "secret".to_string(),
+            }),
+            bearer_token: Some("ignored".to_string()),
+            headers: vec![("X-Provider".to_string(), "tollo".to_string())],
+            cookies: Some("session=abc".to_string()),
+            skip_tls_verification: true,
+            proxy_url: Some("http://proxy.local:3128".to_string()),
+            bypass_proxy: false,
+        }
+    }
+
+    #[test]
+    fn test_header_map() {
+        let headers = PlaylistRequestConfig::default().header_map().unwrap();
+        assert_eq!(headers[USER_AGENT], DEFAULT_USER_AGENT);
+        assert!(headers.get(AUTHORIZATION).is_none());
+
+        let headers = private_config().header_map().unwrap();
+        assert_eq!(headers[USER_AGENT], "VLC/3.0");
+        assert_eq!(headers[AUTHORIZATION], "Basic dXNlcjpzZWNyZXQ=");
+        assert_eq!(headers["x-provider"], "tollo");
+        assert_eq!(headers[COOKIE], "session=abc");
+
+        let bearer = PlaylistRequestConfig {
+            bearer_token: Some("token".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(bearer.header_map().unwrap()[AUTHORIZATION], "Bearer token");
+
+        let invalid = PlaylistRequestConfig {
+            headers: vec![("Bad Header".to_string(), "x".to_string())],
+            ..Default::default()
+        };
+        assert!(invalid.header_map().is_err());
+    }
+
+    #[test]
+    fn test_config_is_stored_encrypted() {
+        let conn = create_test_db();
+        assert_eq!(
+            load_request_config(&conn, 1).unwrap(),
+            PlaylistRequestConfig::default()
+        );
+
+        save_request_config(&conn, 1, &private_config()).unwrap();
+        let blob: Vec<u8> = conn
+            .query_row(
+                "SELECT config FROM playlist_request_configs WHERE channel_list_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!String::from_utf8_lossy(&blob).contains("secret"));
+        assert_eq!(load_request_config(&conn, 1).unwrap(), private_config());
+
+        // Sealed for list 1, so it does not open as another list's settings
+        assert!(open(&secret_key().unwrap(), 2, &blob).is_err());
+
+        save_request_config(&conn, 1, &PlaylistRequestConfig::default()).unwrap();
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM playlist_request_configs", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_update_keeps_stored_secrets() {
+        let conn = create_test_db();
+        save_request_config(&conn, 1, &private_config()).unwrap();
+
+        let summary = RequestConfigSummary::from(&load_request_config(&conn, 1).unwrap());
+        assert_eq!(summary.username.as_deref(), Some("user"));
+        assert!(summary.has_password && summary.has_bearer_token && summary.has_cookies);
+        assert_eq!(summary.headers, vec!["X-Provider"]);
+        let json = serde_json::to_string(&summary).unwrap();
+        assert!(!json.contains("secret") && !json.contains("session=abc"));
+        assert!(!json.contains("tollo"));
+
+        // Secrets left out of an update stay as they are
+        let update = PlaylistRequestConfig {
+            user_agent: Some("mpv".to_string()),
+            basic_auth: Some(BasicAuth {
+                username: "user".to_string(),
+                password: String::new(),
+            }),
+            bearer_token: None,
+            headers: vec![("X-Provider".to_string(), String::new())],
+            cookies: None,
+            ..private_config()
+        };
+        update_request_config(&conn, 1, update).unwrap();
+        let stored = load_request_config(&conn, 1).unwrap();
+        assert_eq!(stored.user_agent.as_deref(), Some("mpv"));
+        assert_eq!(stored.basic_auth, private_config().basic_auth);
+        assert_eq!(stored.bearer_token.as_deref(), Some("ignored"));
+        assert_eq!(stored.cookies.as_deref(), Some("session=abc"));
+        assert_eq!(stored.headers, private_config().headers);
+
+        // New values replace them and empty ones remove them
+        let update = PlaylistRequestConfig {
+            basic_auth: None,
+            bearer_token: Some("token".to_string()),
+            cookies: Some(String::new()),
+            ..stored
+        };
+        update_request_config(&conn, 1, update).unwrap();
+        let stored = load_request_config(&conn, 1).unwrap();
+        assert_eq!(stored.basic_auth, None);
+        assert_eq!(stored.bearer_token.as_deref(), Some("token"));
+        assert_eq!(stored.cookies, None);
+    }
+}
@@ -0,0 +1,545 @@
+use crate::channels::invalidate_channel_cache;
+use crate::playlists::conditional::{
+    download_playlist, download_playlist_blocking, FetchFailure, PlaylistDownload,
+    PlaylistValidators,
+};
+use crate::playlists::request_config::{load_request_config, PlaylistRequestConfig};
+use crate::playlists::types::TransferSender;
+use crate::state::{ChannelCacheState, DbState};
+use ring::rand::{SecureRandom, SystemRandom};
+use rusqlite::{Connection, OptionalExtension, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::State;
+
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 1000;
+
+const MAX_ATTEMPTS_LIMIT: u32 = 10;
+const MAX_RETRY_DELAY_MS: u64 = 30_000;
+
+/// How often a playlist URL is tried before moving on to the next mirror.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Attempts per URL, the first one included
+    pub max_attempts: u32,
+    /// Delay before the first retry; it doubles with every further one
+    pub base_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay_ms: DEFAULT_RETRY_DELAY_MS,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn load(conn: &Connection) -> RusqliteResult<Self> {
+        let policy = conn
+            .query_row(
+                "SELECT fetch_max_attempts, fetch_retry_delay_ms FROM settings WHERE id = 1",
+                [],
+                |row| {
+                    Ok(Self {
+                        max_attempts: row.get(0)?,
+                        base_delay_ms: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(policy.unwrap_or_default())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_ATTEMPTS_LIMIT).contains(&self.max_attempts) {
+            return Err(format!(
+                "Attempts must be between 1 and {}",
+                MAX_ATTEMPTS_LIMIT
+            ));
+        }
+        if self.base_delay_ms > MAX_RETRY_DELAY_MS {
+            return Err(format!(
+                "Retry delay can be at most {} ms",
+                MAX_RETRY_DELAY_MS
+            ));
+        }
+        Ok(())
+    }
+
+    /// Wait before retry number `retry`, counting from 1. Half of it is random so
+    /// lists that failed together don't all retry at the same moment.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(16);
+        let full = self
+            .base_delay_ms
+            .saturating_mul(1 << exponent)
+            .min(MAX_RETRY_DELAY_MS);
+        let half = full / 2;
+        Duration::from_millis(half + jitter(full - half))
+    }
+}
+
+fn jitter(max: u64) -> u64 {
+    let mut bytes = [0u8; 8];
+    if max == 0 || SystemRandom::new().fill(&mut bytes).is_err() {
+        return 0;
+    }
+    u64::from_le_bytes(bytes) % (max + 1)
+}
+
+/// Mirror URLs of list `id`, in the order they are tried after its source.
+pub fn load_mirrors(conn: &Connection, id: i32) -> RusqliteResult<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT url FROM channel_list_mirrors WHERE channel_list_id = ?1 ORDER BY position",
+    )?;
+    let mirrors = stmt
+        .query_map([id], |row| row.get(0))?
+        .collect::<RusqliteResult<Vec<String>>>()?;
+    Ok(mirrors)
+}
+
+pub fn save_mirrors(conn: &mut Connection, id: i32, mirrors: &[String]) -> Result<(), String> {
+    let mirrors: Vec<&str> = mirrors
+        .iter()
+        .map(|url| url.trim())
+        .filter(|url| !url.is_empty())
+        .collect();
+    if let Some(url) = mirrors
+        .iter()
+        .find(|url| !url.starts_with("http://") && !url.starts_with("https://"))
+    {
+        return Err(format!("Mirror '{}' is not an http(s) URL", url));
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "DELETE FROM channel_list_mirrors WHERE channel_list_id = ?1",
+        [id],
+    )
+    .map_err(|e| e.to_string())?;
+    for (position, url) in mirrors.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO channel_list_mirrors (channel_list_id, position, url) VALUES (?1, ?2, ?3)",
+            rusqlite::params![id, position as i64, url],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// The source followed by its mirrors, each URL once.
+pub fn fetch_urls(source: &str, mirrors: &[String]) -> Vec<String> {
+    let mut urls = vec![source.to_string()];
+    for mirror in mirrors {
+        if !urls.contains(mirror) {
+            urls.push(mirror.clone());
+        }
+    }
+    urls
+}
+
+// None means: give up on this URL and try the next one
+fn next_delay(
+    policy: &RetryPolicy,
+    url: &str,
+    attempt: u32,
+    failure: &FetchFailure,
+) -> Option<Duration> {
+    if !failure.retryable || attempt >= policy.max_attempts {
+        return None;
+    }
+    let delay = policy.delay(attempt);
+    println!(
+        "Fetching {} failed (attempt {}/{}), retrying in {} ms: {}",
+        url,
+        attempt,
+        policy.max_attempts,
+        delay.as_millis(),
+        failure.message
+    );
+    Some(delay)
+}
+
+// The conditional headers describe the copy the source sent. A mirror is asked
+// without them, and the validators it answers with are not kept either, so
+// they are never sent to the source later.
+fn validators_for<'a>(
+    index: usize,
+    validators: &'a PlaylistValidators,
+    none: &'a PlaylistValidators,
+) -> &'a PlaylistValidators {
+    if index == 0 {
+        validators
+    } else {
+        none
+    }
+}
+
+fn from_url(index: usize, download: PlaylistDownload) -> PlaylistDownload {
+    match download {
+        PlaylistDownload::Content { body, file, .. } if index > 0 => PlaylistDownload::Content {
+            body,
+            etag: None,
+            last_modified: None,
+            file,
+        },
+        download => download,
+    }
+}
+
+fn combined_failure(failures: Vec<String>) -> String {
+    match failures.len() {
+        1 => failures.into_iter().next().unwrap_or_default(),
+        _ => format!("All mirrors failed: {}", failures.join("; ")),
+    }
+}
+
+/// Downloads from the first of `urls` that answers, retrying transient
+/// failures of each with backoff before moving on to the next. `validators`
+/// are only sent to the first URL, the source.
+pub async fn download_with_retries(
+    client: &reqwest::Client,
+    urls: &[String],
+    validators: &PlaylistValidators,
+    policy: &RetryPolicy,
+    transfer: &TransferSender,
+) -> Result<PlaylistDownload, String> {
+    let mut failures = Vec::new();
+    let no_validators = PlaylistValidators::default();
+    for (index, url) in urls.iter().enumerate() {
+        let validators = validators_for(index, validators, &no_validators);
+        let mut attempt = 1;
+        loop {
+            match download_playlist(client, url, validators, transfer).await {
+                Ok(download) => return Ok(from_url(index, download)),
+                Err(failure) => match next_delay(policy, url, attempt, &failure) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => {
+                        failures.push(failure.message);
+                        break;
+                    }
+                },
+            }
+            attempt += 1;
+        }
+    }
+    Err(combined_failure(failures))
+}
+
+pub fn download_with_retries_blocking(
+    client: &reqwest::blocking::Client,
+    urls: &[String],
+    validators: &PlaylistValidators,
+    policy: &RetryPolicy,
+) -> Result<PlaylistDownload, String> {
+    let mut failures = Vec::new();
+    let no_validators = PlaylistValidators::default();
+    for (index, url) in urls.iter().enumerate() {
+        let validators = validators_for(index, validators, &no_validators);
+        let mut attempt = 1;
+        loop {
+            match download_playlist_blocking(client, url, validators) {
+                Ok(download) => return Ok(from_url(index, download)),
+                Err(failure) => match next_delay(policy, url, attempt, &failure) {
+                    Some(delay) => std::thread::sleep(delay),
+                    None => {
+                        failures.push(failure.message);
+                        break;
+                    }
+                },
+            }
+            attempt += 1;
+        }
+    }
+    Err(combined_failure(failures))
+}
+
+/// How list `id` is downloaded: its request settings, URLs and retry policy.
+pub struct PlaylistFetchPlan {
+    pub request_config: PlaylistRequestConfig,
+    pub urls: Vec<String>,
+    pub policy: RetryPolicy,
+}
+
+impl PlaylistFetchPlan {
+    pub fn load(conn: &Connection, id: i32, source: &str) -> Result<Self, String> {
+        let mirrors = load_mirrors(conn, id).map_err(|e| e.to_string())?;
+        Ok(Self {
+            request_config: load_request_config(conn, id)?,
+            urls: fetch_urls(source, &mirrors),
+            policy: RetryPolicy::load(conn).map_err(|e| e.to_string())?,
+        })
+    }
+
+    /// The same plan trying every URL once, for callers that hold a
+    /// connection and must not wait between attempts.
+    pub fn without_retries(self) -> Self {
+        Self {
+            policy: RetryPolicy {
+                max_attempts: 1,
+                ..self.policy
+            },
+            ..self
+        }
+    }
+
+    pub async fn download(
+        &self,
+        validators: &PlaylistValidators,
+        transfer: &TransferSender,
+    ) -> Result<PlaylistDownload, String> {
+        let client = self.request_config.client()?;
+        download_with_retries(&client, &self.urls, validators, &self.policy, transfer).await
+    }
+
+    pub fn download_blocking(
+        &self,
+        validators: &PlaylistValidators,
+    ) -> Result<PlaylistDownload, String> {
+        let client = self.request_config.blocking_client()?;
+        download_with_retries_blocking(&client, &self.urls, validators, &self.policy)
+    }
+}
+
+#[tauri::command]
+pub fn get_fetch_retry_policy(state: State<DbState>) -> Result<RetryPolicy, String> {
+    RetryPolicy::load(&state.reader()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_fetch_retry_policy(state: State<DbState>, policy: RetryPolicy) -> Result<(), String> {
+    policy.validate()?;
+    let db = state.db.lock().unwrap();
+    db.execute(
+        "UPDATE settings SET fetch_max_attempts = ?1, fetch_retry_delay_ms = ?2 WHERE id = 1",
+        rusqlite::params![policy.max_attempts, policy.base_delay_ms],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_channel_list_mirrors(state: State<DbState>, id: i32) -> Result<Vec<String>, String> {
+    load_mirrors(&state.reader(), id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_channel_list_mirrors(
+    db_state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    id: i32,
+    mirrors: Vec<String>,
+) -> Result<(), String> {
+    {
+        let mut db = db_state.db.lock().unwrap();
+        save_mirrors(&mut db, id, &mirrors)?;
+    }
+    invalidate_channel_cache(cache_state)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::migrated_db;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    const PLAYLIST: &str = "#EXTM3U\n#EXTINF:-1,News\nhttp://example.com/news.m3u8\n";
+
+    // Answers with each of `statuses` in turn, serving PLAYLIST on 200.
+    // Returns the server URL and the number of requests it received.
+    fn spawn_server(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/list.m3u", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let mut buffer = [0u8; 4096];
+                let _ = stream.read(&mut buffer);
+                counter.fetch_add(1, Ordering::SeqCst);
+                let body = if status == 200 { PLAYLIST } else { "" };
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    fn quick_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay_ms: 1,
+        }
+    }
+
+    fn body(download: PlaylistDownload) -> String {
+        match download {
+            PlaylistDownload::Content { body, .. } => body,
+            PlaylistDownload::NotModified => String::new(),
+        }
+    }
+
+    #[test]
+    fn test_delay_backs_off_with_jitter() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay_ms: 1000,
+        };
+        for _ in 0..20 {
+            let first = policy.delay(1).as_millis();
+            assert!((500..=1000).contains(&first), "{}", first);
+            let third = policy.delay(3).as_millis();
+            assert!((2000..=4000).contains(&third), "{}", third);
+            let capped = policy.delay(12).as_millis() as u64;
+            assert!(capped <= MAX_RETRY_DELAY_MS);
+        }
+        assert!(RetryPolicy::default().validate().is_ok());
+        assert!(quick_policy(0).validate().is_err());
+        assert!(quick_policy(MAX_ATTEMPTS_LIMIT + 1).validate().is_err());
+    }
+
+    #[test]
+    fn test_retries_transient_failures() {
+        let (url, requests) = spawn_server(vec![503, 500, 200]);
+        let client = reqwest::blocking::Client::new();
+        let download = download_with_retries_blocking(
+            &client,
+            &[url],
+            &PlaylistValidators::default(),
+            &quick_policy(3),
+        )
+        .unwrap();
+        assert_eq!(body(download), PLAYLIST);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_falls_back_to_mirrors() {
+        // A 404 is not retried, the mirror is tried straight away
+        let (primary, primary_requests) = spawn_server(vec![404]);
+        let (mirror, _) = spawn_server(vec![200]);
+        let client = reqwest::blocking::Client::new();
+        let urls = fetch_urls(&primary, &[mirror.clone(), primary.clone()]);
+        assert_eq!(urls, vec![primary.clone(), mirror]);
+
+        let download = download_with_retries_blocking(
+            &client,
+            &urls,
+            &PlaylistValidators::default(),
+            &quick_policy(3),
+        )
+        .unwrap();
+        assert_eq!(body(download), PLAYLIST);
+        assert_eq!(primary_requests.load(Ordering::SeqCst), 1);
+
+        let (failing, _) = spawn_server(vec![500, 500]);
+        let error = download_with_retries_blocking(
+            &client,
+            &[failing],
+            &PlaylistValidators::default(),
+            &quick_policy(2),
+        )
+        .unwrap_err();
+        assert!(error.contains("500"), "{}", error);
+    }
+
+    // Answers once with `status`, an ETag and PLAYLIST, keeping the request head
+    fn spawn_recording_server(status: u16) -> (String, Arc<Mutex<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/list.m3u", listener.local_addr().unwrap());
+        let request = Arc::new(Mutex::new(String::new()));
+        let recorded = request.clone();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 4096];
+            let read = stream.read(&mut buffer).unwrap_or(0);
+            *recorded.lock().unwrap() = String::from_utf8_lossy(&buffer[..read]).to_lowercase();
+            let body = if status == 200 { PLAYLIST } else { "" };
+            let response = format!(
+                "HTTP/1.1 {} Status\r\nETag: \"mirror\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        (url, request)
+    }
+
+    #[test]
+    fn test_mirrors_get_no_validators() {
+        let (primary, primary_request) = spawn_recording_server(404);
+        let (mirror, mirror_request) = spawn_recording_server(200);
+        let validators = PlaylistValidators {
+            filepath: Some("stored.m3u".to_string()),
+            etag: Some("\"source\"".to_string()),
+            last_modified: Some("Wed, 01 Jan 2025 00:00:00 GMT".to_string()),
+            content_hash: None,
+        };
+
+        let download = download_with_retries_blocking(
+            &reqwest::blocking::Client::new(),
+            &[primary, mirror],
+            &validators,
+            &quick_policy(1),
+        )
+        .unwrap();
+        assert!(primary_request.lock().unwrap().contains("if-none-match"));
+        let mirror_request = mirror_request.lock().unwrap();
+        assert!(!mirror_request.contains("if-none-match"));
+        assert!(!mirror_request.contains("if-modified-since"));
+        // The mirror's own validators are not stored for the source either
+        assert_eq!(
+            download,
+            PlaylistDownload::Content {
+                body: PLAYLIST.to_string(),
+                etag: None,
+                last_modified: None,
+                file: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_save_mirrors() {
+        let mut conn = migrated_db();
+        conn.execute(
+            "INSERT INTO channel_lists (id, name, source) VALUES (1, 'Test', 'http://example.com/a.m3u')",
+            [],
+        )
+        .unwrap();
+
+        let mirrors = vec![
+            "http://mirror-one.example/a.m3u".to_string(),
+            " ".to_string(),
+            "https://mirror-two.example/a.m3u".to_string(),
+        ];
+        save_mirrors(&mut conn, 1, &mirrors).unwrap();
+        assert_eq!(
+            load_mirrors(&conn, 1).unwrap(),
+            vec![
+                "http://mirror-one.example/a.m3u",
+                "https://mirror-two.example/a.m3u"
+            ]
+        );
+
+        assert!(save_mirrors(&mut conn, 1, &["/local/file.m3u".to_string()]).is_err());
+        assert_eq!(load_mirrors(&conn, 1).unwrap().len(), 2);
+
+        save_mirrors(&mut conn, 1, &[]).unwrap();
+        assert!(load_mirrors(&conn, 1).unwrap().is_empty());
+    }
+}
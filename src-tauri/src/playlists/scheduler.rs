@@ -0,0 +1,507 @@
+use crate::channel_overrides::apply_channel_overrides;
+use crate::groups::apply_group_layout;
+use crate::m3u_parser::{parse_m3u_content_until, Channel};
+use crate::paths::data_paths;
+use crate::playlists::changes::{
+    emit_playlist_changes, store_channels_with_changes, PlaylistChangeLog,
+};
+use crate::playlists::conditional::{
+    download_body, record_new_file, record_unchanged_fetch, store_download, PlaylistChange,
+    PlaylistDownload, PlaylistValidators,
+};
+use crate::playlists::retry::PlaylistFetchPlan;
+use crate::playlists::types::{
+    cancelled_error, emit_progress, emit_stopped, report_transfer, FetchOperation, FetchState,
+    PlaylistFetchStatus, TransferSender,
+};
+use crate::search::clear_advanced_cache;
+use crate::state::{ChannelCacheState, DbState};
+use crate::stream_inspector::apply_inspected_resolutions;
+use chrono::Utc;
+use rusqlite::{Connection, Result as RusqliteResult};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Manager};
+use tokio::sync::watch;
+
+pub const MIN_REFRESH_INTERVAL_HOURS: i64 = 1;
+
+/// How often the scheduler looks for lists that are due.
+const SCHEDULER_TICK: Duration = Duration::from_secs(60);
+
+/// A failed refresh is retried after this long rather than on every tick.
+const RETRY_AFTER_SECONDS: i64 = 15 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledRefresh {
+    pub id: i32,
+    pub source: String,
+    pub is_default: bool,
+}
+
+/// Lists with a refresh interval whose last fetch is at least that old.
+pub fn due_refreshes(conn: &Connection, now: i64) -> RusqliteResult<Vec<ScheduledRefresh>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, source, is_default FROM channel_lists
+         WHERE refresh_interval_hours IS NOT NULL
+           AND (last_fetched IS NULL OR last_fetched + refresh_interval_hours * 3600 <= ?1)
+         ORDER BY id",
+    )?;
+    let lists = stmt
+        .query_map([now], |row| {
+            Ok(ScheduledRefresh {
+                id: row.get(0)?,
+                source: row.get(1)?,
+                is_default: row.get(2)?,
+            })
+        })?
+        .collect::<RusqliteResult<Vec<_>>>()?;
+    Ok(lists)
+}
+
+/// Points the list at its new playlist file and stores its channels in one
+/// transaction. Returns the file the list used before and the change log entry
+/// if the channels changed.
+pub fn commit_refresh(
+    conn: &mut Connection,
+    id: i32,
+    filename: &str,
+    fetched_at: i64,
+    download: &PlaylistDownload,
+    channels: &[Channel],
+) -> RusqliteResult<(Option<String>, Option<PlaylistChangeLog>)> {
+    let tx = conn.transaction()?;
+    let previous: Option<String> = tx.query_row(
+        "SELECT filepath FROM channel_lists WHERE id = ?1",
+        [id],
+        |row| row.get(0),
+    )?;
+    record_new_file(&tx, id, filename, fetched_at, download)?;
+    let (_, changes) = store_channels_with_changes(&tx, id as i64, channels, fetched_at)?;
+    tx.commit()?;
+    Ok((previous, changes))
+}
+
+fn is_in_progress(status: &PlaylistFetchStatus) -> bool {
+    !matches!(status.status.as_str(), "completed" | "error" | "cancelled")
+}
+
+async fn fetch_source(
+    source: &str,
+    validators: &PlaylistValidators,
+    plan: &PlaylistFetchPlan,
+    transfer: &TransferSender,
+) -> Result<PlaylistDownload, String> {
+    if source.starts_with("http") {
+        plan.download(validators, transfer).await
+    } else {
+        let body = tokio::fs::read_to_string(source)
+            .await
+            .map_err(|e| format!("Failed to read file '{}': {}", source, e))?;
+        Ok(PlaylistDownload::Content {
+            body,
+            etag: None,
+            last_modified: None,
+            file: None,
+        })
+    }
+}
+
+fn count_stored_channels(conn: &Connection, id: i32) -> RusqliteResult<usize> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM channels WHERE channel_list_id = ?1",
+        [id],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count as usize)
+}
+
+async fn report(
+    app_handle: &AppHandle,
+    id: i32,
+    status: &str,
+    progress: Option<f32>,
+    message: &str,
+    channel_count: Option<usize>,
+    error: Option<String>,
+) {
+    let fetch_state = app_handle.state::<FetchState>();
+    emit_progress(
+        app_handle,
+        &fetch_state,
+        PlaylistFetchStatus {
+            id,
+            status: status.to_string(),
+            progress,
+            message: message.to_string(),
+            channel_count,
+            error,
+            transfer: None,
+        },
+    )
+    .await;
+}
+
+/// Downloads and parses a list, and only then replaces its stored file,
+/// channels and cached copy. Until then the previous data stays in use. A
+/// list the server reports as unchanged keeps all three. Returns the channel
+/// count and whether the list changed.
+async fn refresh_list(
+    app_handle: &AppHandle,
+    list: &ScheduledRefresh,
+    operation: &FetchOperation,
+) -> Result<(usize, bool), String> {
+    let id = list.id;
+    let db_state = app_handle.state::<DbState>();
+    let source = list.source.clone();
+    let (validators, plan) = db_state
+        .read(move |conn| {
+            Ok((
+                PlaylistValidators::load(conn, id)?,
+                PlaylistFetchPlan::load(conn, id, &source),
+            ))
+        })
+        .await?;
+    let plan = plan?;
+    report(
+        app_handle,
+        id,
+        "fetching",
+        None,
+        "Scheduled refresh: downloading playlist...",
+        None,
+        None,
+    )
+    .await;
+    let (transfer, updates) = watch::channel(None);
+    let fetch_state = app_handle.state::<FetchState>();
+    let download = operation
+        .run(report_transfer(
+            app_handle,
+            &fetch_state,
+            id,
+            updates,
+            fetch_source(&list.source, &validators, &plan, &transfer),
+        ))
+        .await?;
+    let now = Utc::now().timestamp();
+    if download.change(&validators) != PlaylistChange::Changed {
+        let channel_count = db_state
+            .write(move |conn| {
+                record_unchanged_fetch(conn, id, now, &download)?;
+                count_stored_channels(conn, id)
+            })
+            .await?;
+        return Ok((channel_count, false));
+    }
+
+    let content = download_body(&download).to_string();
+    if content.trim().is_empty() || !content.trim_start().starts_with("#EXTM3U") {
+        return Err("Invalid M3U playlist".to_string());
+    }
+
+    report(
+        app_handle,
+        id,
+        "processing",
+        None,
+        "Scheduled refresh: parsing playlist...",
+        None,
+        None,
+    )
+    .await;
+    let token = operation.token().clone();
+    let channels = tokio::task::spawn_blocking(move || {
+        parse_m3u_content_until(&content, || token.is_cancelled())
+    })
+    .await
+    .map_err(|e| format!("Parsing failed: {}", e))?;
+    let channels = channels.ok_or_else(cancelled_error)?;
+    if channels.is_empty() {
+        return Err("No channels found in playlist".to_string());
+    }
+
+    report(
+        app_handle,
+        id,
+        "saving",
+        None,
+        "Scheduled refresh: saving playlist...",
+        Some(channels.len()),
+        None,
+    )
+    .await;
+    // The file only appears once flushed to disk, so storing it runs to the end
+    let (filename, download) =
+        tokio::task::spawn_blocking(move || (store_download(&download), download))
+            .await
+            .map_err(|e| format!("Failed to save: {}", e))?;
+    let filename = filename?;
+    let channel_lists_dir = data_paths().channel_lists_dir();
+    let new_path = channel_lists_dir.join(&filename);
+    if let Err(e) = operation.check() {
+        // Don't leave an unused file behind
+        let _ = tokio::fs::remove_file(&new_path).await;
+        return Err(e);
+    }
+
+    let stored_filename = filename.clone();
+    let committed = db_state
+        .write(move |conn| {
+            let (previous, changes) =
+                commit_refresh(conn, id, &stored_filename, now, &download, &channels)?;
+            let mut channels = channels;
+            apply_inspected_resolutions(conn, Some(id), &mut channels);
+            apply_channel_overrides(conn, Some(id), &mut channels);
+            apply_group_layout(conn, Some(id), &mut channels);
+            Ok((previous, changes, channels))
+        })
+        .await;
+    let (previous, changes, channels) = match committed {
+        Ok(committed) => committed,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&new_path).await;
+            return Err(e);
+        }
+    };
+    if let Some(previous) = previous.filter(|previous| *previous != filename) {
+        let _ = tokio::fs::remove_file(channel_lists_dir.join(previous)).await;
+    }
+    if let Some(changes) = &changes {
+        emit_playlist_changes(app_handle, changes);
+    }
+
+    let channel_count = channels.len();
+    swap_cached_channels(app_handle, list, channels);
+    Ok((channel_count, true))
+}
+
+// The cache holds at most one list, keyed by the id it was requested with
+fn swap_cached_channels(app_handle: &AppHandle, list: &ScheduledRefresh, channels: Vec<Channel>) {
+    let cache_state = app_handle.state::<ChannelCacheState>();
+    let mut cache = cache_state.cache.lock().unwrap();
+    if let Some(cached) = cache.as_mut() {
+        let is_cached = match cached.channel_list_id {
+            Some(cached_id) => cached_id == list.id,
+            None => list.is_default,
+        };
+        if is_cached {
+            cached.channels = channels;
+            cached.last_updated = SystemTime::now();
+            clear_advanced_cache();
+        }
+    }
+}
+
+async fn run_due_refreshes(app_handle: &AppHandle, failed_at: &mut HashMap<i32, i64>) {
+    let now = Utc::now().timestamp();
+    let db_state = app_handle.state::<DbState>();
+    let due = match db_state.read(move |conn| due_refreshes(conn, now)).await {
+        Ok(due) => due,
+        Err(e) => {
+            eprintln!("Warning: Failed to look up scheduled refreshes: {}", e);
+            return;
+        }
+    };
+
+    for list in due {
+        if failed_at
+            .get(&list.id)
+            .is_some_and(|failed| now - failed < RETRY_AFTER_SECONDS)
+        {
+            continue;
+        }
+        // Leave lists alone while the user is refreshing them
+        {
+            let fetch_state = app_handle.state::<FetchState>();
+            let operations = fetch_state.operations.lock().await;
+            if operations.get(&list.id).is_some_and(is_in_progress) {
+                continue;
+            }
+        }
+
+        report(
+            app_handle,
+            list.id,
+            "starting",
+            Some(0.0),
+            "Scheduled refresh...",
+            None,
+            None,
+        )
+        .await;
+        let fetch_state = app_handle.state::<FetchState>();
+        let operation = fetch_state.begin(list.id, None);
+        match refresh_list(app_handle, &list, &operation).await {
+            Ok((channel_count, changed)) => {
+                failed_at.remove(&list.id);
+                let message = if changed {
+                    "Playlist refreshed on schedule"
+                } else {
+                    "Playlist is already up to date"
+                };
+                report(
+                    app_handle,
+                    list.id,
+                    "completed",
+                    Some(1.0),
+                    message,
+                    Some(channel_count),
+                    None,
+                )
+                .await;
+            }
+            Err(e) if operation.is_cancelled() => {
+                failed_at.insert(list.id, now);
+                emit_stopped(app_handle, &fetch_state, &operation, &e).await;
+            }
+            Err(e) => {
+                failed_at.insert(list.id, now);
+                report(
+                    app_handle,
+                    list.id,
+                    "error",
+                    Some(0.0),
+                    "Scheduled refresh failed, keeping the current playlist",
+                    None,
+                    Some(e),
+                )
+                .await;
+            }
+        }
+    }
+}
+
+/// Refreshes lists in the background on their own intervals.
+pub fn start_refresh_scheduler(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut failed_at = HashMap::new();
+        let mut ticker = tokio::time::interval(SCHEDULER_TICK);
+        loop {
+            ticker.tick().await;
+            run_due_refreshes(&app_handle, &mut failed_at).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{channel, migrated_db};
+
+    const HOUR: i64 = 3600;
+    const NOW: i64 = 1_700_000_000;
+
+    fn create_channel(name: &str) -> Channel {
+        channel(name, &format!("http://example.com/{}.m3u8", name), "News")
+    }
+
+    fn create_test_db() -> Connection {
+        let conn = migrated_db();
+        conn.execute(
+            "INSERT INTO channel_lists (id, name, source, is_default, filepath, last_fetched, refresh_interval_hours)
+             VALUES (1, 'Hourly', 'http://example.com/a.m3u', 1, 'old.m3u', ?1, 1),
+                    (2, 'Daily', 'http://example.com/b.m3u', 0, NULL, ?2, 24),
+                    (3, 'Manual', 'http://example.com/c.m3u', 0, NULL, NULL, NULL),
+                    (4, 'Never fetched', '/tmp/d.m3u', 0, NULL, NULL, 6)",
+            [NOW - 2 * HOUR, NOW - 2 * HOUR],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn download(body: &str) -> PlaylistDownload {
+        PlaylistDownload::Content {
+            body: body.to_string(),
+            etag: Some("\"v2\"".to_string()),
+            last_modified: None,
+            file: None,
+        }
+    }
+
+    fn due_ids(conn: &Connection, now: i64) -> Vec<i32> {
+        due_refreshes(conn, now)
+            .unwrap()
+            .into_iter()
+            .map(|list| list.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_due_refreshes_follow_each_list_interval() {
+        let conn = create_test_db();
+        assert_eq!(due_ids(&conn, NOW), vec![1, 4]);
+        assert_eq!(due_ids(&conn, NOW + 22 * HOUR), vec![1, 2, 4]);
+
+        let due = due_refreshes(&conn, NOW).unwrap();
+        assert!(due[0].is_default);
+        assert_eq!(due[1].source, "/tmp/d.m3u");
+    }
+
+    #[test]
+    fn test_commit_refresh() {
+        let mut conn = create_test_db();
+        let channels = vec![create_channel("a"), create_channel("b")];
+
+        let (previous, changes) = commit_refresh(
+            &mut conn,
+            1,
+            "new.m3u",
+            NOW,
+            &download("#EXTM3U"),
+            &channels,
+        )
+        .unwrap();
+        assert_eq!(previous, Some("old.m3u".to_string()));
+        // Nothing was stored for the list before, so there is nothing to compare
+        assert!(changes.is_none());
+
+        let (filepath, last_fetched, etag): (String, i64, String) = conn
+            .query_row(
+                "SELECT filepath, last_fetched, etag FROM channel_lists WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((filepath.as_str(), last_fetched), ("new.m3u", NOW));
+        assert_eq!(etag, "\"v2\"");
+        let stored: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM channels WHERE channel_list_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(stored, 2);
+        assert!(!due_ids(&conn, NOW).contains(&1));
+    }
+
+    #[test]
+    fn test_failed_commit_keeps_previous_data() {
+        let mut conn = create_test_db();
+        conn.execute(
+            "CREATE TRIGGER reject_channels BEFORE INSERT ON channels BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+            [],
+        )
+        .unwrap();
+
+        let channels = [create_channel("a")];
+        assert!(commit_refresh(
+            &mut conn,
+            1,
+            "new.m3u",
+            NOW,
+            &download("#EXTM3U"),
+            &channels
+        )
+        .is_err());
+        let filepath: String = conn
+            .query_row(
+                "SELECT filepath FROM channel_lists WHERE id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(filepath, "old.m3u");
+    }
+}
@@ -1,31 +1,271 @@
+use crate::error::TolloError;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Emitter, State};
-use tokio::sync::Mutex as AsyncMutex;
+use tokio::sync::{watch, Mutex as AsyncMutex};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PlaylistFetchStatus {
     pub id: i32,
-    pub status: String, // "starting", "fetching", "processing", "saving", "completed", "error"
-    pub progress: f32,  // 0.0 to 1.0
+    pub status: String, // "starting", "fetching", "processing", "saving", "completed", "error", "cancelled"
+    /// 0.0 to 1.0; `None` while a step can't be measured, e.g. a download
+    /// without `Content-Length`
+    pub progress: Option<f32>,
     pub message: String,
     pub channel_count: Option<usize>,
     pub error: Option<String>,
+    /// Byte counts of the download in progress
+    #[serde(default)]
+    pub transfer: Option<TransferProgress>,
 }
 
+/// How far a playlist download has come.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TransferProgress {
+    pub bytes_downloaded: u64,
+    /// From `Content-Length`, when the server sends one
+    pub total_bytes: Option<u64>,
+    pub bytes_per_second: f64,
+    pub eta_seconds: Option<f64>,
+}
+
+/// Publishes the latest transfer progress of a download; readers only see the newest.
+pub type TransferSender = watch::Sender<Option<TransferProgress>>;
+
+/// Throttle for transfer updates, so large downloads don't flood the frontend.
+const TRANSFER_UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Measures one download attempt and publishes its progress to a `TransferSender`.
+pub struct TransferMeter<'a> {
+    sender: &'a TransferSender,
+    total_bytes: Option<u64>,
+    bytes_downloaded: u64,
+    started: Instant,
+    last_update: Option<Instant>,
+}
+
+impl<'a> TransferMeter<'a> {
+    pub fn new(sender: &'a TransferSender, total_bytes: Option<u64>) -> Self {
+        let meter = Self {
+            sender,
+            total_bytes,
+            bytes_downloaded: 0,
+            started: Instant::now(),
+            last_update: None,
+        };
+        meter.publish();
+        meter
+    }
+
+    pub fn add(&mut self, bytes: usize) {
+        self.bytes_downloaded += bytes as u64;
+        let now = Instant::now();
+        if self
+            .last_update
+            .is_none_or(|last| now.duration_since(last) >= TRANSFER_UPDATE_INTERVAL)
+        {
+            self.last_update = Some(now);
+            self.publish();
+        }
+    }
+
+    /// Publishes the final count, whatever the throttle says.
+    pub fn finish(&self) {
+        self.publish();
+    }
+
+    pub fn progress(&self) -> TransferProgress {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let bytes_per_second = match elapsed > 0.0 {
+            true => self.bytes_downloaded as f64 / elapsed,
+            false => 0.0,
+        };
+        let eta_seconds = self
+            .total_bytes
+            .filter(|_| bytes_per_second > 0.0)
+            .map(|total| total.saturating_sub(self.bytes_downloaded) as f64 / bytes_per_second);
+        TransferProgress {
+            bytes_downloaded: self.bytes_downloaded,
+            total_bytes: self.total_bytes,
+            bytes_per_second,
+            eta_seconds,
+        }
+    }
+
+    fn publish(&self) {
+        self.sender.send_replace(Some(self.progress()));
+    }
+}
+
+// Every running operation on a list, e.g. a refresh and a channel load
+type CancellationTokens = Arc<Mutex<HashMap<i32, Vec<(u64, CancellationToken)>>>>;
+
 pub struct FetchState {
     pub operations: Arc<AsyncMutex<HashMap<i32, PlaylistFetchStatus>>>,
+    cancellations: CancellationTokens,
+    next_operation: AtomicU64,
 }
 
 impl FetchState {
     pub fn new() -> Self {
         Self {
             operations: Arc::new(AsyncMutex::new(HashMap::new())),
+            cancellations: Arc::new(Mutex::new(HashMap::new())),
+            next_operation: AtomicU64::new(0),
+        }
+    }
+
+    /// Registers a fetch of list `id` that `cancel` can stop. A fetch started
+    /// from within another one is also stopped when its `parent` is.
+    pub fn begin(&self, id: i32, parent: Option<&CancellationToken>) -> FetchOperation {
+        let token = match parent {
+            Some(parent) => parent.child_token(),
+            None => CancellationToken::new(),
+        };
+        let generation = self.next_operation.fetch_add(1, Ordering::Relaxed);
+        self.cancellations
+            .lock()
+            .unwrap()
+            .entry(id)
+            .or_default()
+            .push((generation, token.clone()));
+        FetchOperation {
+            id,
+            generation,
+            token,
+            cancellations: self.cancellations.clone(),
+        }
+    }
+
+    /// Cancels the running fetches of list `id`. Returns false if there are none.
+    pub fn cancel(&self, id: i32) -> bool {
+        match self.cancellations.lock().unwrap().get(&id) {
+            Some(tokens) => {
+                tokens.iter().for_each(|(_, token)| token.cancel());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A running fetch of one list. It stops being cancellable once dropped.
+pub struct FetchOperation {
+    id: i32,
+    generation: u64,
+    token: CancellationToken,
+    cancellations: CancellationTokens,
+}
+
+impl FetchOperation {
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Errors out once the fetch was cancelled.
+    pub fn check(&self) -> Result<(), String> {
+        match self.is_cancelled() {
+            true => Err(cancelled_error()),
+            false => Ok(()),
+        }
+    }
+
+    /// Runs `future` until it finishes or the fetch is cancelled, dropping it
+    /// in the latter case. Dropping a download aborts its request.
+    pub async fn run<T>(
+        &self,
+        future: impl Future<Output = Result<T, String>>,
+    ) -> Result<T, String> {
+        tokio::select! {
+            biased;
+            _ = self.token.cancelled() => Err(cancelled_error()),
+            result = future => result,
+        }
+    }
+}
+
+impl Drop for FetchOperation {
+    fn drop(&mut self) {
+        let mut cancellations = self.cancellations.lock().unwrap();
+        if let Some(tokens) = cancellations.get_mut(&self.id) {
+            tokens.retain(|(generation, _)| *generation != self.generation);
+            if tokens.is_empty() {
+                cancellations.remove(&self.id);
+            }
+        }
+    }
+}
+
+/// Drives `download` to completion, reporting each transfer update it
+/// publishes as a "fetching" status of list `id`.
+pub async fn report_transfer<T>(
+    app_handle: &AppHandle,
+    fetch_state: &State<'_, FetchState>,
+    id: i32,
+    mut updates: watch::Receiver<Option<TransferProgress>>,
+    download: impl Future<Output = T>,
+) -> T {
+    tokio::pin!(download);
+    loop {
+        tokio::select! {
+            biased;
+            result = &mut download => return result,
+            changed = updates.changed() => {
+                if changed.is_err() {
+                    return download.await;
+                }
+                let transfer = *updates.borrow_and_update();
+                if let Some(transfer) = transfer {
+                    emit_progress(app_handle, fetch_state, transfer_status(id, transfer)).await;
+                }
+            }
         }
     }
 }
 
+// The download's own share of its total, when the server sent one
+fn transfer_status(id: i32, transfer: TransferProgress) -> PlaylistFetchStatus {
+    let fraction = transfer
+        .total_bytes
+        .filter(|total| *total > 0)
+        .map(|total| (transfer.bytes_downloaded as f32 / total as f32).min(1.0));
+    let downloaded = megabytes(transfer.bytes_downloaded);
+    let message = match transfer.total_bytes {
+        Some(total) => format!(
+            "Downloading playlist... {} of {}",
+            downloaded,
+            megabytes(total)
+        ),
+        None => format!("Downloading playlist... {}", downloaded),
+    };
+    PlaylistFetchStatus {
+        id,
+        status: "fetching".to_string(),
+        progress: fraction,
+        message,
+        channel_count: None,
+        error: None,
+        transfer: Some(transfer),
+    }
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+pub fn cancelled_error() -> String {
+    TolloError::cancelled("playlist fetch").to_string()
+}
+
 // Helper function to emit progress events
 pub async fn emit_progress(
     app_handle: &AppHandle,
@@ -42,3 +282,166 @@ pub async fn emit_progress(
         eprintln!("Failed to emit playlist_fetch_status event: {}", e);
     }
 }
+
+// Makes a failed operation end in an error status, unless it already reported this error
+pub async fn emit_failure(
+    app_handle: &AppHandle,
+    fetch_state: &State<'_, FetchState>,
+    id: i32,
+    error: &str,
+) {
+    let reported = fetch_state
+        .operations
+        .lock()
+        .await
+        .get(&id)
+        .is_some_and(|status| status.status == "error" && status.error.as_deref() == Some(error));
+    if reported {
+        return;
+    }
+    emit_progress(
+        app_handle,
+        fetch_state,
+        PlaylistFetchStatus {
+            id,
+            status: "error".to_string(),
+            progress: Some(0.0),
+            message: "Failed to fetch playlist".to_string(),
+            channel_count: None,
+            error: Some(error.to_string()),
+            transfer: None,
+        },
+    )
+    .await;
+}
+
+// Ends a fetch in its final status: "cancelled" if it was stopped, "error" otherwise
+pub async fn emit_stopped(
+    app_handle: &AppHandle,
+    fetch_state: &State<'_, FetchState>,
+    operation: &FetchOperation,
+    error: &str,
+) {
+    if !operation.is_cancelled() {
+        emit_failure(app_handle, fetch_state, operation.id, error).await;
+        return;
+    }
+    emit_progress(
+        app_handle,
+        fetch_state,
+        PlaylistFetchStatus {
+            id: operation.id,
+            status: "cancelled".to_string(),
+            progress: Some(0.0),
+            message: "Playlist fetch cancelled".to_string(),
+            channel_count: None,
+            error: Some(cancelled_error()),
+            transfer: None,
+        },
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_running_fetch() {
+        let fetch_state = FetchState::new();
+        assert!(!fetch_state.cancel(1));
+
+        let operation = fetch_state.begin(1, None);
+        assert!(operation.check().is_ok());
+        assert!(fetch_state.cancel(1));
+        assert!(operation.is_cancelled());
+        assert_eq!(operation.check(), Err(cancelled_error()));
+
+        // Finished fetches can no longer be cancelled
+        drop(operation);
+        assert!(!fetch_state.cancel(1));
+    }
+
+    #[test]
+    fn test_newer_fetch_replaces_older_one() {
+        let fetch_state = FetchState::new();
+        let older = fetch_state.begin(1, None);
+        let newer = fetch_state.begin(1, None);
+        drop(older);
+
+        assert!(fetch_state.cancel(1));
+        assert!(newer.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancel_stops_every_operation_on_a_list() {
+        let fetch_state = FetchState::new();
+        let refresh = fetch_state.begin(1, None);
+        let load = fetch_state.begin(1, None);
+
+        assert!(fetch_state.cancel(1));
+        assert!(refresh.is_cancelled());
+        assert!(load.is_cancelled());
+
+        drop(refresh);
+        let next = fetch_state.begin(1, None);
+        drop(load);
+        assert!(fetch_state.cancel(1));
+        assert!(next.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancelling_parent_cancels_members() {
+        let fetch_state = FetchState::new();
+        let parent = fetch_state.begin(1, None);
+        let first = fetch_state.begin(2, Some(parent.token()));
+        let second = fetch_state.begin(3, Some(parent.token()));
+
+        // Cancelling a member leaves its parent running
+        fetch_state.cancel(2);
+        assert!(first.is_cancelled());
+        assert!(!parent.is_cancelled());
+        assert!(!second.is_cancelled());
+
+        fetch_state.cancel(1);
+        assert!(second.is_cancelled());
+    }
+
+    #[test]
+    fn test_transfer_meter() {
+        let (sender, updates) = watch::channel(None);
+        let mut meter = TransferMeter::new(&sender, Some(1000));
+        assert_eq!(updates.borrow().unwrap().bytes_downloaded, 0);
+
+        meter.add(400);
+        meter.add(100);
+        meter.finish();
+        let progress = updates.borrow().unwrap();
+        assert_eq!(progress.bytes_downloaded, 500);
+        assert_eq!(progress.total_bytes, Some(1000));
+        if progress.bytes_per_second > 0.0 {
+            let eta = progress.eta_seconds.unwrap();
+            assert!((eta - 500.0 / progress.bytes_per_second).abs() < 1e-6);
+        }
+
+        let status = transfer_status(1, progress);
+        assert!((status.progress.unwrap() - 0.5).abs() < 1e-6);
+        assert_eq!(status.message, "Downloading playlist... 0.0 MB of 0.0 MB");
+
+        // Without Content-Length there's no fraction and no ETA
+        let unknown = TransferMeter::new(&sender, None).progress();
+        assert_eq!(unknown.eta_seconds, None);
+        assert_eq!(transfer_status(1, unknown).progress, None);
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_at_cancellation() {
+        let fetch_state = FetchState::new();
+        let operation = fetch_state.begin(1, None);
+        assert_eq!(operation.run(async { Ok(5) }).await, Ok(5));
+
+        fetch_state.cancel(1);
+        let result: Result<(), String> = operation.run(std::future::pending()).await;
+        assert_eq!(result, Err(cancelled_error()));
+    }
+}
@@ -0,0 +1,498 @@
+use crate::channel_overrides::apply_channel_overrides;
+use crate::channels::invalidate_channel_cache;
+use crate::database::resolve_channel_list_id;
+use crate::groups::apply_group_layout;
+use crate::m3u_parser::{self, parse_m3u_content, Channel};
+use crate::m3u_parser_helpers::get_m3u_content;
+use crate::state::{ChannelCacheState, DbState};
+use crate::stream_inspector::apply_inspected_resolutions;
+use regex::Regex;
+use rusqlite::{Connection, OptionalExtension, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tauri::State;
+
+/// Source stored for virtual lists, which have no playlist of their own.
+pub const VIRTUAL_LIST_SOURCE: &str = "virtual";
+
+/// Narrows the channels a virtual list takes from its members.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VirtualListFilters {
+    /// Groups to keep, every group when empty
+    pub include_groups: Vec<String>,
+    pub exclude_groups: Vec<String>,
+    /// Regular expression a channel name has to match
+    pub name_pattern: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VirtualList {
+    pub id: i64,
+    pub name: String,
+    /// Member lists in priority order; the first one wins on duplicates
+    pub member_ids: Vec<i64>,
+    pub filters: VirtualListFilters,
+}
+
+struct CompiledFilters<'a> {
+    filters: &'a VirtualListFilters,
+    name_pattern: Option<Regex>,
+}
+
+impl<'a> CompiledFilters<'a> {
+    fn new(filters: &'a VirtualListFilters) -> Result<Self, String> {
+        let name_pattern = filters
+            .name_pattern
+            .as_deref()
+            .filter(|pattern| !pattern.trim().is_empty())
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| format!("Invalid pattern: {}", e))?;
+        Ok(Self {
+            filters,
+            name_pattern,
+        })
+    }
+
+    fn matches(&self, channel: &Channel) -> bool {
+        if !self.filters.include_groups.is_empty()
+            && !self.filters.include_groups.contains(&channel.group_title)
+        {
+            return false;
+        }
+        if self.filters.exclude_groups.contains(&channel.group_title) {
+            return false;
+        }
+        self.name_pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(&channel.name))
+    }
+}
+
+pub fn is_virtual_list(conn: &Connection, list_id: i64) -> bool {
+    conn.query_row(
+        "SELECT 1 FROM virtual_lists WHERE channel_list_id = ?1",
+        [list_id],
+        |_| Ok(()),
+    )
+    .is_ok()
+}
+
+pub fn load_virtual_list(conn: &Connection, list_id: i64) -> RusqliteResult<Option<VirtualList>> {
+    let Some((name, filters)) = conn
+        .query_row(
+            "SELECT l.name, v.filters FROM virtual_lists v
+             JOIN channel_lists l ON l.id = v.channel_list_id
+             WHERE v.channel_list_id = ?1",
+            [list_id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()?
+    else {
+        return Ok(None);
+    };
+
+    // Members deleted since the list was defined are left out
+    let mut stmt = conn.prepare(
+        "SELECT m.member_list_id FROM virtual_list_members m
+         JOIN channel_lists l ON l.id = m.member_list_id
+         WHERE m.virtual_list_id = ?1 ORDER BY m.position",
+    )?;
+    let member_ids = stmt
+        .query_map([list_id], |row| row.get(0))?
+        .collect::<RusqliteResult<Vec<i64>>>()?;
+
+    Ok(Some(VirtualList {
+        id: list_id,
+        name,
+        member_ids,
+        filters: serde_json::from_str(&filters).unwrap_or_default(),
+    }))
+}
+
+/// Combines member channels in member order, tagging each with the list it came
+/// from. Channels sharing a `tvg_id`, or a URL when they have none, are kept once.
+pub fn merge_member_channels(
+    members: Vec<(i64, Vec<Channel>)>,
+    filters: &VirtualListFilters,
+) -> Result<Vec<Channel>, String> {
+    let filters = CompiledFilters::new(filters)?;
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+    for (member_id, channels) in members {
+        for mut channel in channels {
+            if !filters.matches(&channel) {
+                continue;
+            }
+            let key = if channel.tvg_id.trim().is_empty() {
+                format!("url:{}", channel.url)
+            } else {
+                format!("tvg:{}", channel.tvg_id.trim())
+            };
+            if !seen.insert(key) {
+                continue;
+            }
+            channel.source_list_id = Some(member_id as i32);
+            merged.push(channel);
+        }
+    }
+    Ok(merged)
+}
+
+/// Channels of the virtual list `id` resolves to, or `None` for a normal list.
+/// Members are loaded with their own overrides and group layout applied.
+pub fn load_virtual_channels(conn: &mut Connection, id: Option<i32>) -> Option<Vec<Channel>> {
+    let list_id = resolve_channel_list_id(conn, id)?;
+    let list = load_virtual_list(conn, list_id).ok()??;
+
+    let mut members = Vec::with_capacity(list.member_ids.len());
+    for member_id in list.member_ids {
+        if is_virtual_list(conn, member_id) {
+            continue;
+        }
+        let channels = m3u_parser::get_channels(conn, Some(member_id as i32));
+        let channels = prepare_member_channels(conn, member_id, channels);
+        members.push((member_id, channels));
+    }
+    Some(merge_virtual_list(list_id, members, &list.filters))
+}
+
+/// Like `load_virtual_channels`, but members are read through reader
+/// connections and downloaded without holding the writer.
+pub fn load_virtual_channels_unlocked(
+    db_state: &DbState,
+    id: Option<i32>,
+) -> Result<Option<Vec<Channel>>, String> {
+    let found = {
+        let conn = db_state.reader();
+        match resolve_channel_list_id(&conn, id) {
+            Some(list_id) => load_virtual_list(&conn, list_id)
+                .map_err(|e| e.to_string())?
+                .map(|list| {
+                    let member_ids: Vec<i64> = list
+                        .member_ids
+                        .iter()
+                        .copied()
+                        .filter(|member_id| !is_virtual_list(&conn, *member_id))
+                        .collect();
+                    (list, member_ids)
+                }),
+            None => None,
+        }
+    };
+    let Some((list, member_ids)) = found else {
+        return Ok(None);
+    };
+
+    let mut members = Vec::with_capacity(member_ids.len());
+    for member_id in member_ids {
+        // A member that fails to load contributes no channels, as with get_channels
+        let channels = get_m3u_content(db_state, Some(member_id as i32))
+            .map(|content| parse_m3u_content(&content))
+            .unwrap_or_default();
+        let channels = prepare_member_channels(&db_state.reader(), member_id, channels);
+        members.push((member_id, channels));
+    }
+    Ok(Some(merge_virtual_list(list.id, members, &list.filters)))
+}
+
+fn prepare_member_channels(
+    conn: &Connection,
+    member_id: i64,
+    mut channels: Vec<Channel>,
+) -> Vec<Channel> {
+    let member = Some(member_id as i32);
+    apply_inspected_resolutions(conn, member, &mut channels);
+    apply_channel_overrides(conn, member, &mut channels);
+    apply_group_layout(conn, member, &mut channels);
+    channels
+}
+
+fn merge_virtual_list(
+    list_id: i64,
+    members: Vec<(i64, Vec<Channel>)>,
+    filters: &VirtualListFilters,
+) -> Vec<Channel> {
+    match merge_member_channels(members, filters) {
+        Ok(channels) => channels,
+        Err(e) => {
+            eprintln!("Failed to load virtual list {}: {}", list_id, e);
+            Vec::new()
+        }
+    }
+}
+
+fn validate_members(conn: &Connection, member_ids: &[i64]) -> Result<(), String> {
+    if member_ids.is_empty() {
+        return Err("A virtual list needs at least one member list".to_string());
+    }
+    let mut seen = HashSet::new();
+    for &member_id in member_ids {
+        if !seen.insert(member_id) {
+            return Err(format!("Channel list {} is listed twice", member_id));
+        }
+        let exists: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM channel_lists WHERE id = ?1)",
+                [member_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !exists {
+            return Err(format!("Channel list {} not found", member_id));
+        }
+        if is_virtual_list(conn, member_id) {
+            return Err("Virtual lists cannot be members of another virtual list".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Creates the virtual list when `list_id` is `None`, otherwise replaces the
+/// definition of an existing one.
+pub fn save_virtual_list(
+    conn: &mut Connection,
+    list_id: Option<i64>,
+    name: &str,
+    member_ids: &[i64],
+    filters: &VirtualListFilters,
+) -> Result<i64, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Name cannot be empty".to_string());
+    }
+    validate_members(conn, member_ids)?;
+    CompiledFilters::new(filters)?;
+    let filters = serde_json::to_string(filters).map_err(|e| e.to_string())?;
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let list_id = match list_id {
+        Some(list_id) => {
+            if !is_virtual_list(&tx, list_id) {
+                return Err("Virtual list not found".to_string());
+            }
+            if member_ids.contains(&list_id) {
+                return Err("A virtual list cannot contain itself".to_string());
+            }
+            tx.execute(
+                "UPDATE channel_lists SET name = ?1 WHERE id = ?2",
+                rusqlite::params![name, list_id],
+            )
+            .map_err(|e| e.to_string())?;
+            tx.execute(
+                "UPDATE virtual_lists SET filters = ?1 WHERE channel_list_id = ?2",
+                rusqlite::params![filters, list_id],
+            )
+            .map_err(|e| e.to_string())?;
+            tx.execute(
+                "DELETE FROM virtual_list_members WHERE virtual_list_id = ?1",
+                [list_id],
+            )
+            .map_err(|e| e.to_string())?;
+            list_id
+        }
+        None => {
+            tx.execute(
+                "INSERT INTO channel_lists (name, source) VALUES (?1, ?2)",
+                rusqlite::params![name, VIRTUAL_LIST_SOURCE],
+            )
+            .map_err(|e| e.to_string())?;
+            let list_id = tx.last_insert_rowid();
+            tx.execute(
+                "INSERT INTO virtual_lists (channel_list_id, filters) VALUES (?1, ?2)",
+                rusqlite::params![list_id, filters],
+            )
+            .map_err(|e| e.to_string())?;
+            list_id
+        }
+    };
+    for (position, member_id) in member_ids.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO virtual_list_members (virtual_list_id, member_list_id, position)
+             VALUES (?1, ?2, ?3)",
+            rusqlite::params![list_id, member_id, position as i64],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(list_id)
+}
+
+/// Removes virtual list definitions and memberships involving `list_id`.
+pub fn forget_virtual_list(conn: &Connection, list_id: i64) -> RusqliteResult<()> {
+    conn.execute(
+        "DELETE FROM virtual_list_members WHERE virtual_list_id = ?1 OR member_list_id = ?1",
+        [list_id],
+    )?;
+    conn.execute(
+        "DELETE FROM virtual_lists WHERE channel_list_id = ?1",
+        [list_id],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_virtual_list(state: State<DbState>, id: i64) -> Result<Option<VirtualList>, String> {
+    load_virtual_list(&state.reader(), id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn create_virtual_list(
+    state: State<DbState>,
+    name: String,
+    member_ids: Vec<i64>,
+    filters: Option<VirtualListFilters>,
+) -> Result<i64, String> {
+    let mut db = state.db.lock().unwrap();
+    save_virtual_list(
+        &mut db,
+        None,
+        &name,
+        &member_ids,
+        &filters.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+pub fn update_virtual_list(
+    db_state: State<DbState>,
+    cache_state: State<ChannelCacheState>,
+    id: i64,
+    name: String,
+    member_ids: Vec<i64>,
+    filters: Option<VirtualListFilters>,
+) -> Result<(), String> {
+    {
+        let mut db = db_state.db.lock().unwrap();
+        save_virtual_list(
+            &mut db,
+            Some(id),
+            &name,
+            &member_ids,
+            &filters.unwrap_or_default(),
+        )?;
+    }
+    invalidate_channel_cache(cache_state)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, migrated_db};
+
+    fn channel(name: &str, url: &str, group: &str, tvg_id: &str) -> Channel {
+        Channel {
+            tvg_id: tvg_id.to_string(),
+            ..test_support::channel(name, url, group)
+        }
+    }
+
+    fn create_test_db() -> Connection {
+        let conn = migrated_db();
+        conn.execute_batch(
+            "INSERT INTO channel_lists (id, name, source) VALUES (1, 'First', 'http://one.example');
+             INSERT INTO channel_lists (id, name, source) VALUES (2, 'Second', 'http://two.example');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_merge_deduplicates_by_tvg_id_then_url() {
+        let first = vec![
+            channel("News", "http://one/news", "News", "news.uk"),
+            channel("Movies", "http://one/movies", "Movies", ""),
+        ];
+        let second = vec![
+            channel("News HD", "http://two/news", "News", "news.uk"),
+            channel("Movies Backup", "http://one/movies", "Movies", ""),
+            channel("Sport", "http://two/sport", "Sport", ""),
+        ];
+
+        let merged = merge_member_channels(
+            vec![(1, first), (2, second)],
+            &VirtualListFilters::default(),
+        )
+        .unwrap();
+
+        let names: Vec<&str> = merged.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["News", "Movies", "Sport"]);
+        let sources: Vec<Option<i32>> = merged.iter().map(|c| c.source_list_id).collect();
+        assert_eq!(sources, vec![Some(1), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_merge_applies_filters() {
+        let channels = vec![
+            channel("BBC News", "http://one/bbc", "News", ""),
+            channel("CNN", "http://one/cnn", "News", ""),
+            channel("BBC Sport", "http://one/sport", "Sport", ""),
+            channel("BBC Adult", "http://one/adult", "Adult", ""),
+        ];
+        let filters = VirtualListFilters {
+            include_groups: vec![],
+            exclude_groups: vec!["Adult".to_string()],
+            name_pattern: Some("^BBC".to_string()),
+        };
+
+        let merged = merge_member_channels(vec![(1, channels)], &filters).unwrap();
+        let names: Vec<&str> = merged.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["BBC News", "BBC Sport"]);
+
+        let invalid = VirtualListFilters {
+            name_pattern: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(merge_member_channels(vec![], &invalid).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load_virtual_list() {
+        let mut conn = create_test_db();
+        let filters = VirtualListFilters {
+            include_groups: vec!["News".to_string()],
+            ..Default::default()
+        };
+
+        let id = save_virtual_list(&mut conn, None, "Combined", &[2, 1], &filters).unwrap();
+        assert!(is_virtual_list(&conn, id));
+        assert!(!is_virtual_list(&conn, 1));
+
+        let list = load_virtual_list(&conn, id).unwrap().unwrap();
+        assert_eq!(list.name, "Combined");
+        assert_eq!(list.member_ids, vec![2, 1]);
+        assert_eq!(list.filters, filters);
+
+        save_virtual_list(
+            &mut conn,
+            Some(id),
+            "Renamed",
+            &[1],
+            &VirtualListFilters::default(),
+        )
+        .unwrap();
+        let list = load_virtual_list(&conn, id).unwrap().unwrap();
+        assert_eq!(list.name, "Renamed");
+        assert_eq!(list.member_ids, vec![1]);
+
+        forget_virtual_list(&conn, 1).unwrap();
+        let list = load_virtual_list(&conn, id).unwrap().unwrap();
+        assert!(list.member_ids.is_empty());
+    }
+
+    #[test]
+    fn test_save_virtual_list_rejects_invalid_members() {
+        let mut conn = create_test_db();
+        let filters = VirtualListFilters::default();
+
+        assert!(save_virtual_list(&mut conn, None, "Empty", &[], &filters).is_err());
+        assert!(save_virtual_list(&mut conn, None, "Missing", &[1, 9], &filters).is_err());
+        assert!(save_virtual_list(&mut conn, None, "Twice", &[1, 1], &filters).is_err());
+
+        let id = save_virtual_list(&mut conn, None, "Combined", &[1, 2], &filters).unwrap();
+        assert!(save_virtual_list(&mut conn, None, "Nested", &[id], &filters).is_err());
+        assert!(save_virtual_list(&mut conn, Some(1), "Normal", &[2], &filters).is_err());
+    }
+}
@@ -0,0 +1,757 @@
+use crate::http_client::{proxied_builder, ProxiedClient};
+use crate::m3u_parser::{Channel, StreamHttpOptions};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use regex::Regex;
+use ring::hmac;
+use ring::rand::SystemRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tauri::State;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex as AsyncMutex;
+use uuid::Uuid;
+
+const RELAY_PATH_PREFIX: &str = "/stream/";
+const MAX_REQUEST_HEAD_BYTES: usize = 16 * 1024;
+// Older routes are dropped once this many streams have been relayed
+const MAX_RELAY_ROUTES: usize = 32;
+
+static RE_URI_ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"URI="([^"]*)""#).unwrap());
+
+#[derive(Default)]
+struct RouteCounters {
+    bytes: AtomicU64,
+    requests: AtomicU64,
+    errors: AtomicU64,
+}
+
+#[derive(Clone)]
+struct RelayRoute {
+    channel_name: String,
+    url: String,
+    options: StreamHttpOptions,
+    /// Signs the resource URLs the manifest rewrite hands out for this route.
+    key: hmac::Key,
+    counters: Arc<RouteCounters>,
+    started_at: i64,
+}
+
+impl RelayRoute {
+    /// The upstream URL a request on this route asks for. Resource URLs are
+    /// only followed when this route signed them, so the relay can't be
+    /// pointed at arbitrary hosts.
+    fn target_url(&self, target: Option<RelayTarget>) -> Option<String> {
+        let Some(target) = target else {
+            return Some(self.url.clone());
+        };
+        let signature = URL_SAFE_NO_PAD.decode(&target.signature).ok()?;
+        hmac::verify(&self.key, target.url.as_bytes(), &signature).ok()?;
+        Some(target.url)
+    }
+
+    /// The request options for `url`. Custom headers and cookies are meant for
+    /// the stream's own origin and are not sent anywhere else.
+    fn options_for(&self, url: &str) -> StreamHttpOptions {
+        if is_same_origin(&self.url, url) {
+            return self.options.clone();
+        }
+        StreamHttpOptions {
+            headers: Vec::new(),
+            ..self.options.clone()
+        }
+    }
+}
+
+/// A resource URL of a relayed stream and the route's signature over it.
+#[derive(Debug, PartialEq)]
+struct RelayTarget {
+    url: String,
+    signature: String,
+}
+
+struct RelayInner {
+    client: ProxiedClient<reqwest::Client>,
+    routes: Mutex<HashMap<String, RelayRoute>>,
+    active_connections: AtomicUsize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RelayStreamStats {
+    pub channel_name: String,
+    pub upstream_url: String,
+    pub bytes_relayed: u64,
+    pub requests: u64,
+    pub errors: u64,
+    pub average_bytes_per_second: f64,
+    pub started_at: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RelayStats {
+    pub running: bool,
+    pub port: Option<u16>,
+    pub active_connections: usize,
+    pub total_bytes_relayed: u64,
+    pub streams: Vec<RelayStreamStats>,
+}
+
+pub struct RelayState {
+    inner: Arc<RelayInner>,
+    port: AsyncMutex<Option<u16>>,
+}
+
+impl RelayState {
+    pub fn new() -> Self {
+        let client = ProxiedClient::new(|settings| {
+            proxied_builder(settings)?
+                .connect_timeout(Duration::from_secs(10))
+                .build()
+                .map_err(|e| format!("Failed to create HTTP client: {}", e))
+        });
+        Self {
+            inner: Arc::new(RelayInner {
+                client,
+                routes: Mutex::new(HashMap::new()),
+                active_connections: AtomicUsize::new(0),
+            }),
+            port: AsyncMutex::new(None),
+        }
+    }
+
+    /// Starts the relay on an ephemeral loopback port the first time it is needed.
+    pub async fn ensure_started(&self) -> Result<u16, String> {
+        let mut port = self.port.lock().await;
+        if let Some(port) = *port {
+            return Ok(port);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| format!("Failed to start stream relay: {}", e))?;
+        let local_port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to start stream relay: {}", e))?
+            .port();
+
+        let inner = self.inner.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        eprintln!("Stream relay failed to accept connection: {}", e);
+                        continue;
+                    }
+                };
+                let inner = inner.clone();
+                tokio::spawn(async move {
+                    inner.active_connections.fetch_add(1, Ordering::Relaxed);
+                    if let Err(e) = handle_connection(socket, &inner).await {
+                        eprintln!("Stream relay error: {}", e);
+                    }
+                    inner.active_connections.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+
+        println!("Stream relay listening on 127.0.0.1:{}", local_port);
+        *port = Some(local_port);
+        Ok(local_port)
+    }
+
+    /// Registers `url` for relaying with the channel's request options and
+    /// returns the loopback URL a player should open instead.
+    pub async fn register(&self, channel: &Channel, url: &str) -> Result<String, String> {
+        // A relay that can't reach the stream through the proxy is not handed out
+        self.inner.client.current()?;
+        let port = self.ensure_started().await?;
+        let token = Uuid::new_v4().simple().to_string();
+        let key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .map_err(|_| "Failed to create relay key".to_string())?;
+
+        let mut routes = self.inner.routes.lock().unwrap();
+        if routes.len() >= MAX_RELAY_ROUTES {
+            if let Some(oldest) = routes
+                .iter()
+                .min_by_key(|(_, route)| route.started_at)
+                .map(|(token, _)| token.clone())
+            {
+                routes.remove(&oldest);
+            }
+        }
+        routes.insert(
+            token.clone(),
+            RelayRoute {
+                channel_name: channel.name.clone(),
+                url: url.to_string(),
+                options: channel.http_options.clone().unwrap_or_default(),
+                key,
+                counters: Arc::new(RouteCounters::default()),
+                started_at: Utc::now().timestamp(),
+            },
+        );
+
+        Ok(format!(
+            "http://127.0.0.1:{}{}{}",
+            port, RELAY_PATH_PREFIX, token
+        ))
+    }
+
+    /// Maps a relay URL reported by a player back to the upstream stream URL.
+    pub fn upstream_url(&self, relay_url: &str) -> Option<String> {
+        let path = reqwest::Url::parse(relay_url).ok()?;
+        let (token, target) = parse_relay_target(path.path(), path.query())?;
+        let routes = self.inner.routes.lock().unwrap();
+        routes.get(&token)?.target_url(target)
+    }
+
+    pub async fn stats(&self) -> RelayStats {
+        let port = *self.port.lock().await;
+        let now = Utc::now().timestamp();
+        let routes = self.inner.routes.lock().unwrap();
+
+        let mut streams: Vec<RelayStreamStats> = routes
+            .values()
+            .map(|route| {
+                let bytes = route.counters.bytes.load(Ordering::Relaxed);
+                let elapsed = (now - route.started_at).max(1) as f64;
+                RelayStreamStats {
+                    channel_name: route.channel_name.clone(),
+                    upstream_url: route.url.clone(),
+                    bytes_relayed: bytes,
+                    requests: route.counters.requests.load(Ordering::Relaxed),
+                    errors: route.counters.errors.load(Ordering::Relaxed),
+                    average_bytes_per_second: bytes as f64 / elapsed,
+                    started_at: route.started_at,
+                }
+            })
+            .collect();
+        streams.sort_by_key(|stream| std::cmp::Reverse(stream.started_at));
+
+        RelayStats {
+            running: port.is_some(),
+            port,
+            active_connections: self.inner.active_connections.load(Ordering::Relaxed),
+            total_bytes_relayed: streams.iter().map(|s| s.bytes_relayed).sum(),
+            streams,
+        }
+    }
+}
+
+/// Relay path for a resource of a relayed stream, relative to the relay root,
+/// signed with the route's `key`.
+fn relay_path(token: &str, key: &hmac::Key, target: &str) -> String {
+    let signature = URL_SAFE_NO_PAD.encode(hmac::sign(key, target.as_bytes()));
+    let mut url = reqwest::Url::parse("http://127.0.0.1/").unwrap();
+    url.set_path(&format!("{}{}", RELAY_PATH_PREFIX, token));
+    url.query_pairs_mut()
+        .append_pair("url", target)
+        .append_pair("sig", &signature);
+    format!("{}?{}", url.path(), url.query().unwrap_or_default())
+}
+
+/// Splits a relay request path into the route token and an optional target.
+fn parse_relay_target(path: &str, query: Option<&str>) -> Option<(String, Option<RelayTarget>)> {
+    let token = path.strip_prefix(RELAY_PATH_PREFIX)?;
+    if token.is_empty() || token.contains('/') {
+        return None;
+    }
+    let target = query.and_then(|query| {
+        let parsed = reqwest::Url::parse(&format!("http://127.0.0.1/?{}", query)).ok()?;
+        let param = |name: &str| {
+            parsed
+                .query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        Some(RelayTarget {
+            url: param("url")?,
+            signature: param("sig").unwrap_or_default(),
+        })
+    });
+    Some((token.to_string(), target))
+}
+
+fn is_same_origin(a: &str, b: &str) -> bool {
+    match (reqwest::Url::parse(a), reqwest::Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.origin() == b.origin(),
+        _ => false,
+    }
+}
+
+fn resolve_url(base_url: &str, uri: &str) -> String {
+    match reqwest::Url::parse(base_url).and_then(|base| base.join(uri)) {
+        Ok(resolved) => resolved.to_string(),
+        Err(_) => uri.to_string(),
+    }
+}
+
+/// Rewrites every URI in an HLS playlist (segments, variants, keys, maps and
+/// alternate renditions) to go through the relay route `token`, signed with
+/// its `key`.
+pub fn rewrite_manifest(content: &str, base_url: &str, token: &str, key: &hmac::Key) -> String {
+    let mut rewritten = String::with_capacity(content.len() * 2);
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            rewritten.push_str(line);
+        } else if trimmed.starts_with('#') {
+            let line = RE_URI_ATTRIBUTE.replace_all(line, |caps: &regex::Captures| {
+                format!(
+                    "URI=\"{}\"",
+                    relay_path(token, key, &resolve_url(base_url, &caps[1]))
+                )
+            });
+            rewritten.push_str(&line);
+        } else {
+            rewritten.push_str(&relay_path(token, key, &resolve_url(base_url, trimmed)));
+        }
+        rewritten.push('\n');
+    }
+    rewritten
+}
+
+fn is_hls_response(content_type: &str, url: &str) -> bool {
+    let content_type = content_type.to_lowercase();
+    content_type.contains("mpegurl")
+        || reqwest::Url::parse(url)
+            .map(|url| url.path().to_lowercase().ends_with(".m3u8"))
+            .unwrap_or(false)
+}
+
+struct RelayRequest {
+    method: String,
+    path: String,
+    query: Option<String>,
+    range: Option<String>,
+}
+
+async fn read_request(socket: &mut TcpStream) -> Result<RelayRequest, String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 2048];
+    loop {
+        let read = socket
+            .read(&mut chunk)
+            .await
+            .map_err(|e| format!("Failed to read request: {}", e))?;
+        if read == 0 {
+            return Err("Connection closed before request was complete".to_string());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+            break;
+        }
+        if buffer.len() > MAX_REQUEST_HEAD_BYTES {
+            return Err("Request head too large".to_string());
+        }
+    }
+
+    let head = String::from_utf8_lossy(&buffer);
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    };
+
+    let range = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("range")
+            .then(|| value.trim().to_string())
+    });
+
+    Ok(RelayRequest {
+        method,
+        path,
+        query,
+        range,
+    })
+}
+
+async fn write_status(socket: &mut TcpStream, status: u16, reason: &str) -> Result<(), String> {
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status, reason
+    );
+    socket
+        .write_all(response.as_bytes())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn handle_connection(mut socket: TcpStream, inner: &RelayInner) -> Result<(), String> {
+    let request = read_request(&mut socket).await?;
+    if request.method != "GET" && request.method != "HEAD" {
+        return write_status(&mut socket, 405, "Method Not Allowed").await;
+    }
+
+    let Some((token, target)) = parse_relay_target(&request.path, request.query.as_deref()) else {
+        return write_status(&mut socket, 404, "Not Found").await;
+    };
+    let route = inner.routes.lock().unwrap().get(&token).cloned();
+    let Some(route) = route else {
+        return write_status(&mut socket, 404, "Not Found").await;
+    };
+    let Some(upstream_url) = route.target_url(target) else {
+        return write_status(&mut socket, 403, "Forbidden").await;
+    };
+    route.counters.requests.fetch_add(1, Ordering::Relaxed);
+
+    let client = match inner.client.current() {
+        Ok(client) => client,
+        Err(e) => {
+            route.counters.errors.fetch_add(1, Ordering::Relaxed);
+            eprintln!("Stream relay has no HTTP client: {}", e);
+            return write_status(&mut socket, 502, "Bad Gateway").await;
+        }
+    };
+    let mut upstream = route
+        .options_for(&upstream_url)
+        .apply_to(client.get(&upstream_url));
+    if let Some(ref range) = request.range {
+        upstream = upstream.header(reqwest::header::RANGE, range);
+    }
+
+    let mut response = match upstream.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            route.counters.errors.fetch_add(1, Ordering::Relaxed);
+            eprintln!("Stream relay upstream request failed: {}", e);
+            return write_status(&mut socket, 502, "Bad Gateway").await;
+        }
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        route.counters.errors.fetch_add(1, Ordering::Relaxed);
+    }
+    let final_url = response.url().to_string();
+    let header_value = |name: reqwest::header::HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let content_type = header_value(reqwest::header::CONTENT_TYPE);
+    let content_length = header_value(reqwest::header::CONTENT_LENGTH);
+    let content_range = header_value(reqwest::header::CONTENT_RANGE);
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    );
+
+    if status.is_success() && is_hls_response(content_type.as_deref().unwrap_or(""), &final_url) {
+        let content = response.text().await.map_err(|e| {
+            route.counters.errors.fetch_add(1, Ordering::Relaxed);
+            format!("Failed to read upstream playlist: {}", e)
+        })?;
+        let body = rewrite_manifest(&content, &final_url, &token, &route.key);
+        head.push_str(&format!(
+            "Content-Type: application/vnd.apple.mpegurl\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        ));
+        socket
+            .write_all(head.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        if request.method == "GET" {
+            socket
+                .write_all(body.as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+            route
+                .counters
+                .bytes
+                .fetch_add(body.len() as u64, Ordering::Relaxed);
+        }
+        return Ok(());
+    }
+
+    if let Some(content_type) = content_type {
+        head.push_str(&format!("Content-Type: {}\r\n", content_type));
+    }
+    if let Some(content_length) = content_length {
+        head.push_str(&format!("Content-Length: {}\r\n", content_length));
+    }
+    if let Some(content_range) = content_range {
+        head.push_str(&format!("Content-Range: {}\r\n", content_range));
+    }
+    head.push_str("Accept-Ranges: bytes\r\nConnection: close\r\n\r\n");
+    socket
+        .write_all(head.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    if request.method == "HEAD" {
+        return Ok(());
+    }
+
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                // A write error means the player went away, which ends the stream normally
+                if socket.write_all(&chunk).await.is_err() {
+                    break;
+                }
+                route
+                    .counters
+                    .bytes
+                    .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }
+            Ok(None) => break,
+            Err(e) => {
+                route.counters.errors.fetch_add(1, Ordering::Relaxed);
+                return Err(format!("Upstream stream interrupted: {}", e));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_relay_stats(relay_state: State<'_, RelayState>) -> Result<RelayStats, String> {
+    Ok(relay_state.stats().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const TOKEN: &str = "abc123";
+
+    fn test_key() -> hmac::Key {
+        hmac::Key::new(hmac::HMAC_SHA256, b"relay test key")
+    }
+
+    fn test_route(url: &str) -> RelayRoute {
+        RelayRoute {
+            channel_name: "Test".to_string(),
+            url: url.to_string(),
+            options: StreamHttpOptions {
+                user_agent: Some("RelayTest".to_string()),
+                referrer: None,
+                headers: vec![("X-Token".to_string(), "secret".to_string())],
+            },
+            key: test_key(),
+            counters: Arc::new(RouteCounters::default()),
+            started_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_relay_path_round_trip() {
+        let target = "http://example.com/live/seg 1.ts?token=a&b=c";
+        let path = relay_path(TOKEN, &test_key(), target);
+        let (path, query) = path.split_once('?').unwrap();
+
+        let (token, parsed) = parse_relay_target(path, Some(query)).unwrap();
+        assert_eq!(token, TOKEN);
+        let route = test_route("http://example.com/live/index.m3u8");
+        assert_eq!(route.target_url(parsed).as_deref(), Some(target));
+
+        assert_eq!(
+            parse_relay_target("/stream/abc123", None),
+            Some((TOKEN.to_string(), None))
+        );
+        assert_eq!(parse_relay_target("/other/abc123", None), None);
+        assert_eq!(parse_relay_target("/stream/", None), None);
+    }
+
+    #[test]
+    fn test_unsigned_targets_are_refused() {
+        let route = test_route("http://example.com/live/index.m3u8");
+        let forged = |signature: &str| RelayTarget {
+            url: "http://attacker.example/".to_string(),
+            signature: signature.to_string(),
+        };
+        assert_eq!(route.target_url(Some(forged(""))), None);
+
+        // A signature from another route's key doesn't carry over
+        let other_key = hmac::Key::new(hmac::HMAC_SHA256, b"another route");
+        let signature = URL_SAFE_NO_PAD.encode(hmac::sign(&other_key, b"http://attacker.example/"));
+        assert_eq!(route.target_url(Some(forged(&signature))), None);
+    }
+
+    #[test]
+    fn test_custom_headers_stay_on_the_stream_origin() {
+        let route = test_route("http://example.com/live/index.m3u8");
+        let same = route.options_for("http://example.com/live/seg1.ts");
+        assert_eq!(same.headers.len(), 1);
+
+        let other = route.options_for("http://cdn.example.com/seg1.ts");
+        assert!(other.headers.is_empty());
+        assert_eq!(other.user_agent.as_deref(), Some("RelayTest"));
+    }
+
+    #[test]
+    fn test_rewrite_media_playlist() {
+        let playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n#EXTINF:6.0,\nseg1.ts\n#EXTINF:6.0,\nhttp://cdn.example.com/seg2.ts\n";
+        let rewritten = rewrite_manifest(
+            playlist,
+            "http://example.com/live/index.m3u8",
+            TOKEN,
+            &test_key(),
+        );
+        let lines: Vec<&str> = rewritten.lines().collect();
+
+        assert_eq!(lines[0], "#EXTM3U");
+        assert_eq!(
+            lines[2],
+            format!(
+                "#EXT-X-KEY:METHOD=AES-128,URI=\"{}\"",
+                relay_path(TOKEN, &test_key(), "http://example.com/live/key.bin")
+            )
+        );
+        assert_eq!(
+            lines[4],
+            relay_path(TOKEN, &test_key(), "http://example.com/live/seg1.ts")
+        );
+        assert_eq!(
+            lines[6],
+            relay_path(TOKEN, &test_key(), "http://cdn.example.com/seg2.ts")
+        );
+    }
+
+    #[test]
+    fn test_rewrite_master_playlist() {
+        let playlist = "#EXTM3U\n#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",URI=\"audio/en.m3u8\"\n#EXT-X-STREAM-INF:BANDWIDTH=800000,AUDIO=\"aac\"\nlow/index.m3u8\n";
+        let rewritten = rewrite_manifest(
+            playlist,
+            "http://example.com/master.m3u8",
+            TOKEN,
+            &test_key(),
+        );
+
+        assert!(rewritten.contains(&relay_path(
+            TOKEN,
+            &test_key(),
+            "http://example.com/audio/en.m3u8"
+        )));
+        assert!(rewritten.contains(&relay_path(
+            TOKEN,
+            &test_key(),
+            "http://example.com/low/index.m3u8"
+        )));
+        assert!(rewritten.contains("#EXT-X-STREAM-INF:BANDWIDTH=800000,AUDIO=\"aac\""));
+    }
+
+    #[test]
+    fn test_is_hls_response() {
+        assert!(is_hls_response(
+            "application/vnd.apple.mpegurl",
+            "http://a/b"
+        ));
+        assert!(is_hls_response("audio/x-mpegurl", "http://a/b"));
+        assert!(is_hls_response("", "http://a/live.M3U8?token=1"));
+        assert!(!is_hls_response("video/mp2t", "http://a/seg.ts"));
+    }
+
+    // Minimal upstream that only serves requests carrying the expected user agent
+    async fn spawn_upstream() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    break;
+                };
+                tokio::spawn(async move {
+                    let mut buffer = vec![0u8; 4096];
+                    let read = socket.read(&mut buffer).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buffer[..read]).to_lowercase();
+
+                    let (status, content_type, body) = if !request.contains("user-agent: relaytest")
+                        || !request.contains("x-token: secret")
+                    {
+                        ("403 Forbidden", "text/plain", "forbidden".to_string())
+                    } else if request.starts_with("get /live.m3u8") {
+                        (
+                            "200 OK",
+                            "application/vnd.apple.mpegurl",
+                            "#EXTM3U\n#EXTINF:6.0,\nseg1.ts\n".to_string(),
+                        )
+                    } else {
+                        ("200 OK", "video/mp2t", "x".repeat(1000))
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        content_type,
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_relay_forwards_headers_and_rewrites_playlist() {
+        let upstream_port = spawn_upstream().await;
+        let relay = RelayState::new();
+        let channel = Channel {
+            http_options: Some(StreamHttpOptions {
+                user_agent: Some("RelayTest".to_string()),
+                referrer: None,
+                headers: vec![("X-Token".to_string(), "secret".to_string())],
+            }),
+            ..test_support::channel(
+                "Relay Test",
+                &format!("http://127.0.0.1:{}/live.m3u8", upstream_port),
+                "",
+            )
+        };
+
+        let relay_url = relay.register(&channel, &channel.url).await.unwrap();
+        assert_eq!(
+            relay.upstream_url(&relay_url).as_deref(),
+            Some(channel.url.as_str())
+        );
+
+        let client = reqwest::Client::new();
+        let playlist = client.get(&relay_url).send().await.unwrap();
+        assert!(playlist.status().is_success());
+        let playlist = playlist.text().await.unwrap();
+        let segment_path = playlist.lines().find(|l| !l.starts_with('#')).unwrap();
+        assert!(segment_path.starts_with(RELAY_PATH_PREFIX));
+
+        let port = relay.ensure_started().await.unwrap();
+        let segment = client
+            .get(format!("http://127.0.0.1:{}{}", port, segment_path))
+            .send()
+            .await
+            .unwrap();
+        assert!(segment.status().is_success());
+        assert_eq!(segment.bytes().await.unwrap().len(), 1000);
+
+        // Targets the manifest rewrite didn't sign are refused
+        let forged = client
+            .get(format!("{}?url=http%3A%2F%2Fexample.com%2F", relay_url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(forged.status(), reqwest::StatusCode::FORBIDDEN);
+
+        let stats = relay.stats().await;
+        assert!(stats.running);
+        assert_eq!(stats.streams.len(), 1);
+        assert_eq!(stats.streams[0].requests, 2);
+        assert_eq!(stats.streams[0].errors, 0);
+        assert!(stats.total_bytes_relayed >= 1000);
+    }
+}
@@ -2,14 +2,18 @@ use crate::m3u_parser::Channel;
 use crate::state::{ChannelCacheState, DbState};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{LazyLock, Mutex};
 use std::time::{Duration, SystemTime};
 use tauri::{AppHandle, Emitter, State};
 
+use crate::channel_overrides::has_channel_overrides;
 use crate::channels::{get_cached_channels, ChannelLoadingStatus};
+use crate::database::resolve_channel_list_id;
+use crate::fts_search::{narrow_by_fts, use_fts_backend};
 use crate::fuzzy_search::FuzzyMatcher;
+use crate::groups::{has_group_mapping, order_groups};
+use crate::stream_health::filter_dead_channels;
 
 #[cfg(test)]
 mod tests {
@@ -26,6 +30,8 @@ mod tests {
                 tvg_id: "bbc1".to_string(),
                 resolution: "1080p".to_string(),
                 extra_info: "HD".to_string(),
+                http_options: None,
+                source_list_id: None,
             },
             Channel {
                 name: "CNN International".to_string(),
@@ -35,6 +41,8 @@ mod tests {
                 tvg_id: "cnn1".to_string(),
                 resolution: "720p".to_string(),
                 extra_info: "".to_string(),
+                http_options: None,
+                source_list_id: None,
             },
             Channel {
                 name: "ESPN Sports".to_string(),
@@ -44,6 +52,8 @@ mod tests {
                 tvg_id: "espn1".to_string(),
                 resolution: "1080p".to_string(),
                 extra_info: "HD".to_string(),
+                http_options: None,
+                source_list_id: None,
             },
         ]
     }
@@ -450,6 +460,7 @@ fn find_best_cached_prefix(
 fn get_search_space(
     query: &str,
     channel_list_id: Option<i32>,
+    app_handle: &AppHandle,
     db_state: &State<DbState>,
     cache_state: &State<ChannelCacheState>,
 ) -> Result<Vec<Channel>, String> {
@@ -472,13 +483,74 @@ fn get_search_space(
         }
     }
 
-    // 3. Cache miss - use full dataset
+    // 3. Cache miss - narrow large lists through the FTS indexes
     CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
-    get_cached_channels(db_state.clone(), cache_state.clone(), channel_list_id)
+    if let Some(candidates) =
+        get_fts_search_space(query, channel_list_id, app_handle, db_state, cache_state)?
+    {
+        return Ok(candidates);
+    }
+
+    // 4. Use full dataset
+    get_cached_channels(
+        app_handle.clone(),
+        db_state.clone(),
+        cache_state.clone(),
+        channel_list_id,
+    )
+}
+
+fn get_fts_search_space(
+    query: &str,
+    channel_list_id: Option<i32>,
+    app_handle: &AppHandle,
+    db_state: &State<DbState>,
+    cache_state: &State<ChannelCacheState>,
+) -> Result<Option<Vec<Channel>>, String> {
+    let cache_loaded = {
+        let cache = cache_state.cache.lock().unwrap();
+        matches!(*cache, Some(ref cached) if cached.channel_list_id == channel_list_id)
+    };
+    if !cache_loaded {
+        get_cached_channels(
+            app_handle.clone(),
+            db_state.clone(),
+            cache_state.clone(),
+            channel_list_id,
+        )?;
+    }
+
+    // Same lock order as get_cached_channels: channel cache, then database
+    let cache = cache_state.cache.lock().unwrap();
+    let Some(ref cached) = *cache else {
+        return Ok(None);
+    };
+    if cached.channel_list_id != channel_list_id || !use_fts_backend(cached.channels.len()) {
+        return Ok(None);
+    }
+
+    let db = db_state.reader();
+    let Some(list_id) = resolve_channel_list_id(&db, channel_list_id) else {
+        return Ok(None);
+    };
+    // The indexes hold channels as parsed, so edited lists are searched in memory
+    if has_channel_overrides(&db, list_id) || has_group_mapping(&db, list_id) {
+        return Ok(None);
+    }
+    match narrow_by_fts(&db, list_id, &cached.channels, query) {
+        // No candidates may still mean a typo; let the fuzzy matcher see the whole list
+        Ok(Some(candidates)) if !candidates.is_empty() => Ok(Some(candidates)),
+        Ok(_) => Ok(None),
+        Err(e) => {
+            eprintln!("FTS search failed, searching in memory: {}", e);
+            Ok(None)
+        }
+    }
 }
 
 #[tauri::command]
 pub fn search_channels(
+    app_handle: AppHandle,
     db_state: State<DbState>,
     cache_state: State<ChannelCacheState>,
     query: String,
@@ -495,8 +567,9 @@ pub fn search_channels(
 
     // If query is empty, clear cache and return all channels
     if query.is_empty() {
-        let original_channels = get_cached_channels(db_state, cache_state, id)?;
-        return Ok(original_channels);
+        let original_channels = get_cached_channels(app_handle, db_state.clone(), cache_state, id)?;
+        let db = db_state.reader();
+        return Ok(filter_dead_channels(&db, id, original_channels));
     }
 
     // Periodic cache maintenance
@@ -505,7 +578,7 @@ pub fn search_channels(
     }
 
     // Get search space using advanced cache strategy
-    let channels_to_search = get_search_space(&query, id, &db_state, &cache_state)?;
+    let channels_to_search = get_search_space(&query, id, &app_handle, &db_state, &cache_state)?;
 
     // Check if we're still the active search
     {
@@ -532,7 +605,9 @@ pub fn search_channels(
     let cache_entry = AdvancedSearchCacheEntry::new(query.clone(), filtered_channels.clone(), id);
     ADVANCED_CACHE.insert(cache_key, cache_entry);
 
-    Ok(filtered_channels)
+    // Dead channels are hidden on the way out so the cache stays independent of health data
+    let db = db_state.reader();
+    Ok(filter_dead_channels(&db, id, filtered_channels))
 }
 
 // Helper function to clear advanced cache when channel data changes
@@ -564,6 +639,7 @@ pub fn get_cache_stats() -> Result<CacheStats, String> {
 
 #[tauri::command]
 pub fn warm_cache_with_common_searches(
+    app_handle: AppHandle,
     db_state: State<DbState>,
     cache_state: State<ChannelCacheState>,
     id: Option<i32>,
@@ -572,6 +648,7 @@ pub fn warm_cache_with_common_searches(
 
     for search_term in common_searches {
         let _ = search_channels(
+            app_handle.clone(),
             db_state.clone(),
             cache_state.clone(),
             search_term.to_string(),
@@ -584,20 +661,19 @@ pub fn warm_cache_with_common_searches(
 
 #[tauri::command]
 pub fn get_groups(
+    app_handle: AppHandle,
     db_state: State<DbState>,
     cache_state: State<ChannelCacheState>,
     id: Option<i32>,
 ) -> Result<Vec<String>, String> {
     // Get original channels from cache (this already returns a clone)
-    let original_channels = get_cached_channels(db_state, cache_state, id)?;
+    let original_channels = get_cached_channels(app_handle, db_state.clone(), cache_state, id)?;
+    let db = db_state.reader();
+    let original_channels = filter_dead_channels(&db, id, original_channels);
 
-    // Extract unique groups from cached channels without consuming the original
-    let mut groups = HashSet::new();
-    for channel in &original_channels {
-        // Use reference to avoid consuming
-        groups.insert(channel.group_title.clone()); // Clone the group title
-    }
-    Ok(groups.into_iter().collect())
+    // Unique groups, in the list's saved group order
+    let groups = original_channels.into_iter().map(|channel| channel.group_title);
+    Ok(order_groups(&db, resolve_channel_list_id(&db, id), groups))
 }
 
 #[tauri::command]
@@ -622,7 +698,7 @@ pub async fn search_channels_async(
     );
 
     // Use the main search function (now with advanced caching and cancellation)
-    let channels = search_channels(db_state, cache_state, query_clone, id)?;
+    let channels = search_channels(app_handle.clone(), db_state, cache_state, query_clone, id)?;
 
     // Emit completion
     let _ = app_handle.emit(
@@ -657,7 +733,7 @@ pub async fn get_groups_async(
     );
 
     // For now, use the blocking version directly to avoid lifetime issues
-    let groups = get_groups(db_state, cache_state, id)?;
+    let groups = get_groups(app_handle.clone(), db_state, cache_state, id)?;
 
     // Emit completion
     let _ = app_handle.emit(
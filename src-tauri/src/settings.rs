@@ -28,7 +28,7 @@ pub fn detect_default_player() -> String {
 
 #[tauri::command]
 pub fn get_player_command(state: State<DbState>) -> Result<String, String> {
-    let db = state.db.lock().unwrap();
+    let db = state.reader();
     match db.query_row(
         "SELECT player_command FROM settings WHERE id = 1",
         [],
@@ -54,7 +54,7 @@ pub fn set_player_command(state: State<DbState>, command: String) -> Result<(),
 
 #[tauri::command]
 pub fn get_cache_duration(state: State<DbState>) -> Result<i64, String> {
-    let db = state.db.lock().unwrap();
+    let db = state.reader();
     db.query_row(
         "SELECT cache_duration_hours FROM settings WHERE id = 1",
         [],
@@ -74,7 +74,7 @@ pub fn set_cache_duration(state: State<DbState>, hours: i64) -> Result<(), Strin
 
 #[tauri::command]
 pub fn get_enable_preview(state: State<DbState>) -> Result<bool, String> {
-    let db = state.db.lock().unwrap();
+    let db = state.reader();
     let enable_preview: bool = db.query_row(
         "SELECT enable_preview FROM settings WHERE id = 1",
         [],
@@ -108,7 +108,7 @@ pub fn set_enable_preview(state: State<DbState>, enabled: bool) -> Result<(), St
 // --- Video Player Settings: Mute on Start ---
 #[tauri::command]
 pub fn get_mute_on_start(state: State<DbState>) -> Result<bool, String> {
-    let db = state.db.lock().unwrap();
+    let db = state.reader();
     let mute_on_start: bool = db.query_row(
         "SELECT mute_on_start FROM settings WHERE id = 1",
         [],
@@ -137,7 +137,7 @@ pub fn set_mute_on_start(state: State<DbState>, enabled: bool) -> Result<(), Str
 // --- Video Player Settings: Show Controls ---
 #[tauri::command]
 pub fn get_show_controls(state: State<DbState>) -> Result<bool, String> {
-    let db = state.db.lock().unwrap();
+    let db = state.reader();
     let show_controls: bool = db.query_row(
         "SELECT show_controls FROM settings WHERE id = 1",
         [],
@@ -166,7 +166,7 @@ pub fn set_show_controls(state: State<DbState>, enabled: bool) -> Result<(), Str
 // --- Video Player Settings: Autoplay ---
 #[tauri::command]
 pub fn get_autoplay(state: State<DbState>) -> Result<bool, String> {
-    let db = state.db.lock().unwrap();
+    let db = state.reader();
     let autoplay: bool = db.query_row(
         "SELECT autoplay FROM settings WHERE id = 1",
         [],
@@ -190,4 +190,61 @@ pub fn set_autoplay(state: State<DbState>, enabled: bool) -> Result<(), String>
         ).map_err(|e| e.to_string())?;
     }
     Ok(())
-} 
\ No newline at end of file
+} 
+// --- Channel Health: Hide Dead Channels ---
+#[tauri::command]
+pub fn get_hide_dead_channels(state: State<DbState>) -> Result<bool, String> {
+    let db = state.reader();
+    let hide_dead_channels: bool = db.query_row(
+        "SELECT hide_dead_channels FROM settings WHERE id = 1",
+        [],
+        |row| row.get(0),
+    ).unwrap_or(false); // Default to false if not found
+    Ok(hide_dead_channels)
+}
+
+#[tauri::command]
+pub fn set_hide_dead_channels(state: State<DbState>, enabled: bool) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    let rows_affected = db.execute(
+        "UPDATE settings SET hide_dead_channels = ?1 WHERE id = 1",
+        [&enabled],
+    ).map_err(|e| e.to_string())?;
+    if rows_affected == 0 {
+        let default_player = detect_default_player();
+        db.execute(
+            "INSERT INTO settings (id, player_command, cache_duration_hours, enable_preview, mute_on_start, show_controls, autoplay, hide_dead_channels) VALUES (1, ?1, 24, 1, 0, 1, 0, ?2)",
+            rusqlite::params![default_player, enabled],
+        ).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// --- Stream Relay ---
+#[tauri::command]
+pub fn get_use_stream_relay(state: State<DbState>) -> Result<bool, String> {
+    let db = state.reader();
+    let use_stream_relay: bool = db.query_row(
+        "SELECT use_stream_relay FROM settings WHERE id = 1",
+        [],
+        |row| row.get(0),
+    ).unwrap_or(false); // Default to false if not found
+    Ok(use_stream_relay)
+}
+
+#[tauri::command]
+pub fn set_use_stream_relay(state: State<DbState>, enabled: bool) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    let rows_affected = db.execute(
+        "UPDATE settings SET use_stream_relay = ?1 WHERE id = 1",
+        [&enabled],
+    ).map_err(|e| e.to_string())?;
+    if rows_affected == 0 {
+        let default_player = detect_default_player();
+        db.execute(
+            "INSERT INTO settings (id, player_command, cache_duration_hours, enable_preview, mute_on_start, show_controls, autoplay, use_stream_relay) VALUES (1, ?1, 24, 1, 0, 1, 0, ?2)",
+            rusqlite::params![default_player, enabled],
+        ).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
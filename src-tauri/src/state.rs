@@ -1,12 +1,96 @@
+use crate::db_pool::{PooledConnection, ReaderPool};
 use crate::image_cache::ImageCache;
 use crate::m3u_parser::Channel;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::SystemTime;
 
+/// The writer connection, plus read-only connections to the same file so reads
+/// are not queued behind a long write such as a playlist import.
+#[derive(Clone)]
 pub struct DbState {
-    pub db: Mutex<Connection>,
+    pub db: Arc<Mutex<Connection>>,
+    readers: Arc<ReaderPool>,
+}
+
+impl DbState {
+    /// State without reader connections, e.g. for an in-memory database.
+    /// Reads then share the writer.
+    pub fn new(conn: Connection) -> Self {
+        Self {
+            db: Arc::new(Mutex::new(conn)),
+            readers: Arc::new(ReaderPool::empty()),
+        }
+    }
+
+    /// State for the database file at `path`, which should be in WAL mode.
+    pub fn with_readers(
+        conn: Connection,
+        path: &Path,
+        reader_count: usize,
+    ) -> rusqlite::Result<Self> {
+        Ok(Self {
+            db: Arc::new(Mutex::new(conn)),
+            readers: Arc::new(ReaderPool::open(path, reader_count)?),
+        })
+    }
+
+    /// A connection for reading on the current thread.
+    pub fn reader(&self) -> DbReader<'_> {
+        match self.readers.checkout() {
+            Some(conn) => DbReader::Pooled(conn),
+            None => DbReader::Writer(self.db.lock().unwrap()),
+        }
+    }
+
+    /// Runs `query` on a reader connection on the blocking thread pool.
+    pub async fn read<T, F>(&self, query: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let readers = Arc::clone(&self.readers);
+        let writer = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || match readers.checkout() {
+            Some(conn) => query(&conn),
+            None => query(&writer.lock().unwrap()),
+        })
+        .await
+        .map_err(|e| format!("Database task failed: {}", e))?
+        .map_err(|e| e.to_string())
+    }
+
+    /// Runs `update` on the writer connection on the blocking thread pool.
+    pub async fn write<T, F>(&self, update: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let writer = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || update(&mut writer.lock().unwrap()))
+            .await
+            .map_err(|e| format!("Database task failed: {}", e))?
+            .map_err(|e| e.to_string())
+    }
+}
+
+pub enum DbReader<'a> {
+    Pooled(PooledConnection),
+    Writer(MutexGuard<'a, Connection>),
+}
+
+impl Deref for DbReader<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            DbReader::Pooled(conn) => conn,
+            DbReader::Writer(conn) => conn,
+        }
+    }
 }
 
 pub struct ImageCacheState {
@@ -32,4 +116,9 @@ pub struct ChannelList {
     pub is_default: bool,
     pub filepath: Option<String>,
     pub last_fetched: Option<i64>,
+    /// Hours between automatic refreshes, `None` when the list is refreshed by hand.
+    pub refresh_interval_hours: Option<i64>,
+    /// Whether the list combines other lists rather than fetching a playlist
+    #[serde(default)]
+    pub is_virtual: bool,
 }
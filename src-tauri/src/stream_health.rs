@@ -0,0 +1,814 @@
+use crate::channels::get_cached_channels;
+use crate::database::resolve_channel_list_id;
+use crate::http_client::client_builder;
+use crate::m3u_parser::Channel;
+use crate::state::{ChannelCacheState, DbState};
+use chrono::Utc;
+use reqwest::header::{CONTENT_TYPE, RANGE};
+use reqwest::StatusCode;
+use rusqlite::{Connection, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::task::JoinSet;
+
+const DEFAULT_SCAN_CONCURRENCY: usize = 16;
+const MAX_SCAN_CONCURRENCY: usize = 64;
+const PROBE_TIMEOUT_SECONDS: u64 = 10;
+const MANIFEST_SNIFF_BYTES: usize = 64 * 1024;
+const SAVE_BATCH_SIZE: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamHealthStatus {
+    Alive,
+    Dead,
+    Timeout,
+}
+
+impl StreamHealthStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StreamHealthStatus::Alive => "alive",
+            StreamHealthStatus::Dead => "dead",
+            StreamHealthStatus::Timeout => "timeout",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "alive" => Some(StreamHealthStatus::Alive),
+            "dead" => Some(StreamHealthStatus::Dead),
+            "timeout" => Some(StreamHealthStatus::Timeout),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelHealth {
+    pub url: String,
+    pub status: StreamHealthStatus,
+    pub http_status: Option<u16>,
+    pub latency_ms: Option<u64>,
+    pub last_checked: i64,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HealthScanStatus {
+    pub channel_list_id: i64,
+    pub status: String, // "running", "completed", "error"
+    pub checked: usize,
+    pub total: usize,
+    pub alive: usize,
+    pub dead: usize,
+    pub timeout: usize,
+    /// Channels whose probe task failed, so their state is unknown.
+    pub failed: usize,
+    pub error: Option<String>,
+}
+
+pub struct HealthScanState {
+    pub scans: Arc<AsyncMutex<HashMap<i64, HealthScanStatus>>>,
+}
+
+impl HealthScanState {
+    pub fn new() -> Self {
+        Self {
+            scans: Arc::new(AsyncMutex::new(HashMap::new())),
+        }
+    }
+}
+
+pub fn build_probe_client(timeout: Duration) -> reqwest::Result<reqwest::Client> {
+    client_builder()
+        .timeout(timeout)
+        .connect_timeout(timeout)
+        .user_agent("Mozilla/5.0")
+        .build()
+}
+
+fn is_probeable(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+fn looks_like_hls(url: &str, content_type: Option<&str>) -> bool {
+    let path = url.split('?').next().unwrap_or(url).to_lowercase();
+    path.ends_with(".m3u8")
+        || content_type
+            .map(|ct| ct.to_lowercase().contains("mpegurl"))
+            .unwrap_or(false)
+}
+
+// A usable HLS manifest is either a master playlist or a media playlist
+fn is_valid_hls_manifest(body: &str) -> bool {
+    let trimmed = body.trim_start_matches('\u{feff}').trim_start();
+    trimmed.starts_with("#EXTM3U")
+        && (trimmed.contains("#EXT-X-STREAM-INF")
+            || trimmed.contains("#EXTINF")
+            || trimmed.contains("#EXT-X-TARGETDURATION"))
+}
+
+fn content_type(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+/// Reads at most `limit` bytes of the body, so live streams can't run on.
+pub async fn read_prefix(mut response: reqwest::Response, limit: usize) -> reqwest::Result<String> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        if buffer.len() >= limit {
+            buffer.truncate(limit);
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&buffer).to_string())
+}
+
+struct ProbeOutcome {
+    status: StreamHealthStatus,
+    http_status: Option<u16>,
+    error: Option<String>,
+}
+
+impl ProbeOutcome {
+    fn alive(http_status: StatusCode) -> Self {
+        Self {
+            status: StreamHealthStatus::Alive,
+            http_status: Some(http_status.as_u16()),
+            error: None,
+        }
+    }
+
+    fn dead(http_status: Option<StatusCode>, error: impl Into<String>) -> Self {
+        Self {
+            status: StreamHealthStatus::Dead,
+            http_status: http_status.map(|s| s.as_u16()),
+            error: Some(error.into()),
+        }
+    }
+
+    fn from_error(error: reqwest::Error) -> Self {
+        let status = if error.is_timeout() {
+            StreamHealthStatus::Timeout
+        } else {
+            StreamHealthStatus::Dead
+        };
+        Self {
+            status,
+            http_status: error.status().map(|s| s.as_u16()),
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+async fn check_manifest(response: reqwest::Response) -> ProbeOutcome {
+    let http_status = response.status();
+    match read_prefix(response, MANIFEST_SNIFF_BYTES).await {
+        Ok(body) if is_valid_hls_manifest(&body) => ProbeOutcome::alive(http_status),
+        Ok(_) => ProbeOutcome::dead(Some(http_status), "Invalid HLS manifest"),
+        Err(e) => ProbeOutcome::from_error(e),
+    }
+}
+
+async fn ranged_get(client: &reqwest::Client, url: &str) -> ProbeOutcome {
+    let response = match client.get(url).header(RANGE, "bytes=0-1023").send().await {
+        Ok(response) => response,
+        Err(e) => return ProbeOutcome::from_error(e),
+    };
+
+    let http_status = response.status();
+    if !http_status.is_success() {
+        return ProbeOutcome::dead(Some(http_status), format!("HTTP {}", http_status));
+    }
+
+    if looks_like_hls(url, content_type(&response).as_deref()) {
+        return check_manifest(response).await;
+    }
+
+    ProbeOutcome::alive(http_status)
+}
+
+async fn probe_outcome(client: &reqwest::Client, url: &str) -> ProbeOutcome {
+    // HLS streams are only alive if the manifest itself is sane, so skip HEAD
+    if looks_like_hls(url, None) {
+        return match client.get(url).send().await {
+            Ok(response) if response.status().is_success() => check_manifest(response).await,
+            Ok(response) => {
+                let http_status = response.status();
+                ProbeOutcome::dead(Some(http_status), format!("HTTP {}", http_status))
+            }
+            Err(e) => ProbeOutcome::from_error(e),
+        };
+    }
+
+    match client.head(url).send().await {
+        Ok(response) if response.status().is_success() => {
+            if looks_like_hls(url, content_type(&response).as_deref()) {
+                // HEAD has no body, fetch the manifest to validate it
+                ranged_get(client, url).await
+            } else {
+                ProbeOutcome::alive(response.status())
+            }
+        }
+        Err(e) if e.is_timeout() => ProbeOutcome::from_error(e),
+        // Many stream servers reject HEAD, so fall back to a small ranged GET
+        _ => ranged_get(client, url).await,
+    }
+}
+
+pub async fn probe_stream(client: &reqwest::Client, url: &str) -> ChannelHealth {
+    let started = Instant::now();
+    let outcome = probe_outcome(client, url).await;
+    let latency_ms = match outcome.status {
+        StreamHealthStatus::Timeout => None,
+        _ => Some(started.elapsed().as_millis() as u64),
+    };
+
+    ChannelHealth {
+        url: url.to_string(),
+        status: outcome.status,
+        http_status: outcome.http_status,
+        latency_ms,
+        last_checked: Utc::now().timestamp(),
+        error: outcome.error,
+    }
+}
+
+// Probes every URL, keeping at most `concurrency` requests in flight. Each
+// URL gets exactly one result; a probe task that fails yields its URL.
+pub async fn probe_streams<F>(
+    client: reqwest::Client,
+    urls: Vec<String>,
+    concurrency: usize,
+    mut on_result: F,
+) where
+    F: FnMut(Result<ChannelHealth, String>),
+{
+    let mut pending = urls.into_iter();
+    let mut in_flight = JoinSet::new();
+    let mut probing = HashMap::new();
+
+    for url in pending.by_ref().take(concurrency.max(1)) {
+        spawn_probe(&mut in_flight, &mut probing, &client, url);
+    }
+
+    while let Some(joined) = in_flight.join_next_with_id().await {
+        match joined {
+            Ok((id, health)) => {
+                probing.remove(&id);
+                on_result(Ok(health));
+            }
+            Err(e) => {
+                if let Some(url) = probing.remove(&e.id()) {
+                    eprintln!("Health probe for {} failed: {}", url, e);
+                    on_result(Err(url));
+                }
+            }
+        }
+        if let Some(url) = pending.next() {
+            spawn_probe(&mut in_flight, &mut probing, &client, url);
+        }
+    }
+}
+
+fn spawn_probe(
+    in_flight: &mut JoinSet<ChannelHealth>,
+    probing: &mut HashMap<tokio::task::Id, String>,
+    client: &reqwest::Client,
+    url: String,
+) {
+    let client = client.clone();
+    let task_url = url.clone();
+    let task = in_flight.spawn(async move { probe_stream(&client, &task_url).await });
+    probing.insert(task.id(), url);
+}
+
+pub fn save_channel_health(
+    conn: &mut Connection,
+    channel_list_id: i64,
+    results: &[ChannelHealth],
+) -> RusqliteResult<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO channel_health (channel_list_id, url, status, http_status, latency_ms, last_checked, error) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for health in results {
+            stmt.execute(rusqlite::params![
+                channel_list_id,
+                health.url,
+                health.status.as_str(),
+                health.http_status,
+                health.latency_ms.map(|ms| ms as i64),
+                health.last_checked,
+                health.error,
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn load_channel_health(
+    conn: &Connection,
+    channel_list_id: i64,
+) -> RusqliteResult<Vec<ChannelHealth>> {
+    let mut stmt = conn.prepare(
+        "SELECT url, status, http_status, latency_ms, last_checked, error FROM channel_health WHERE channel_list_id = ?1",
+    )?;
+    let health_iter = stmt.query_map([channel_list_id], |row| {
+        let status: String = row.get(1)?;
+        let latency_ms: Option<i64> = row.get(3)?;
+        Ok(ChannelHealth {
+            url: row.get(0)?,
+            status: StreamHealthStatus::parse(&status).unwrap_or(StreamHealthStatus::Dead),
+            http_status: row.get(2)?,
+            latency_ms: latency_ms.map(|ms| ms as u64),
+            last_checked: row.get(4)?,
+            error: row.get(5)?,
+        })
+    })?;
+
+    let mut results = Vec::new();
+    for health in health_iter {
+        results.push(health?);
+    }
+    Ok(results)
+}
+
+// Dead and timed out streams are both treated as unreachable
+pub fn get_unreachable_urls(
+    conn: &Connection,
+    channel_list_id: i64,
+) -> RusqliteResult<HashSet<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT url FROM channel_health WHERE channel_list_id = ?1 AND status IN ('dead', 'timeout')",
+    )?;
+    let url_iter = stmt.query_map([channel_list_id], |row| row.get::<_, String>(0))?;
+
+    let mut urls = HashSet::new();
+    for url in url_iter {
+        urls.insert(url?);
+    }
+    Ok(urls)
+}
+
+/// Removes unreachable channels when the `hide_dead_channels` setting is on.
+pub fn filter_dead_channels(
+    conn: &Connection,
+    id: Option<i32>,
+    channels: Vec<Channel>,
+) -> Vec<Channel> {
+    let hide_dead: bool = conn
+        .query_row(
+            "SELECT hide_dead_channels FROM settings WHERE id = 1",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap_or(false);
+    if !hide_dead {
+        return channels;
+    }
+
+    let unreachable = match resolve_channel_list_id(conn, id)
+        .and_then(|list_id| get_unreachable_urls(conn, list_id).ok())
+    {
+        Some(urls) if !urls.is_empty() => urls,
+        _ => return channels,
+    };
+
+    channels
+        .into_iter()
+        .filter(|channel| !unreachable.contains(&channel.url))
+        .collect()
+}
+
+async fn update_scan_status(app_handle: &AppHandle, status: HealthScanStatus) {
+    let scan_state = app_handle.state::<HealthScanState>();
+    scan_state
+        .scans
+        .lock()
+        .await
+        .insert(status.channel_list_id, status.clone());
+
+    if let Err(e) = app_handle.emit("channel_health_scan", &status) {
+        eprintln!("Failed to emit channel_health_scan event: {}", e);
+    }
+}
+
+async fn persist_batch(app_handle: &AppHandle, channel_list_id: i64, batch: Vec<ChannelHealth>) {
+    let db_state = app_handle.state::<DbState>();
+    let saved = db_state
+        .write(move |conn| save_channel_health(conn, channel_list_id, &batch))
+        .await;
+    if let Err(e) = saved {
+        eprintln!("Failed to save channel health results: {}", e);
+    }
+}
+
+async fn run_health_scan(
+    app_handle: AppHandle,
+    channel_list_id: i64,
+    urls: Vec<String>,
+    concurrency: usize,
+) {
+    let mut status = HealthScanStatus {
+        channel_list_id,
+        status: "running".to_string(),
+        checked: 0,
+        total: urls.len(),
+        alive: 0,
+        dead: 0,
+        timeout: 0,
+        failed: 0,
+        error: None,
+    };
+
+    let client = match build_probe_client(Duration::from_secs(PROBE_TIMEOUT_SECONDS)) {
+        Ok(client) => client,
+        Err(e) => {
+            status.status = "error".to_string();
+            status.error = Some(format!("Failed to create HTTP client: {}", e));
+            update_scan_status(&app_handle, status).await;
+            return;
+        }
+    };
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let prober = tokio::spawn(probe_streams(client, urls, concurrency, move |result| {
+        let _ = tx.send(result);
+    }));
+
+    let mut batch = Vec::with_capacity(SAVE_BATCH_SIZE);
+    while let Some(result) = rx.recv().await {
+        status.checked += 1;
+        let health = match result {
+            Ok(health) => health,
+            Err(_) => {
+                status.failed += 1;
+                continue;
+            }
+        };
+        match health.status {
+            StreamHealthStatus::Alive => status.alive += 1,
+            StreamHealthStatus::Dead => status.dead += 1,
+            StreamHealthStatus::Timeout => status.timeout += 1,
+        }
+        batch.push(health);
+
+        if batch.len() >= SAVE_BATCH_SIZE {
+            let full = std::mem::replace(&mut batch, Vec::with_capacity(SAVE_BATCH_SIZE));
+            persist_batch(&app_handle, channel_list_id, full).await;
+            update_scan_status(&app_handle, status.clone()).await;
+        }
+    }
+    let _ = prober.await;
+
+    if !batch.is_empty() {
+        persist_batch(&app_handle, channel_list_id, batch).await;
+    }
+
+    status.status = "completed".to_string();
+    update_scan_status(&app_handle, status).await;
+}
+
+#[tauri::command]
+pub async fn start_channel_health_scan(
+    app_handle: AppHandle,
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    scan_state: State<'_, HealthScanState>,
+    id: Option<i32>,
+    concurrency: Option<usize>,
+) -> Result<(), String> {
+    let channel_list_id = {
+        let db = db_state.reader();
+        resolve_channel_list_id(&db, id).ok_or_else(|| "Channel list not found".to_string())?
+    };
+
+    let mut status = HealthScanStatus {
+        channel_list_id,
+        status: "running".to_string(),
+        checked: 0,
+        total: 0,
+        alive: 0,
+        dead: 0,
+        timeout: 0,
+        failed: 0,
+        error: None,
+    };
+
+    // Claim the list in the same critical section as the check, so two
+    // concurrent calls can't both start a scan
+    {
+        let mut scans = scan_state.scans.lock().await;
+        if let Some(existing) = scans.get(&channel_list_id) {
+            if existing.status == "running" {
+                return Err("A health scan is already running for this list".to_string());
+            }
+        }
+        scans.insert(channel_list_id, status.clone());
+    }
+
+    let channels = match get_cached_channels(app_handle.clone(), db_state, cache_state, id) {
+        Ok(channels) => channels,
+        Err(e) => {
+            status.status = "error".to_string();
+            status.error = Some(e.clone());
+            update_scan_status(&app_handle, status).await;
+            return Err(e);
+        }
+    };
+    let mut seen = HashSet::new();
+    let urls: Vec<String> = channels
+        .into_iter()
+        .map(|channel| channel.url)
+        .filter(|url| is_probeable(url) && seen.insert(url.clone()))
+        .collect();
+
+    let concurrency = concurrency
+        .unwrap_or(DEFAULT_SCAN_CONCURRENCY)
+        .clamp(1, MAX_SCAN_CONCURRENCY);
+
+    status.total = urls.len();
+    update_scan_status(&app_handle, status).await;
+
+    tauri::async_runtime::spawn(run_health_scan(
+        app_handle,
+        channel_list_id,
+        urls,
+        concurrency,
+    ));
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_channel_health(
+    db_state: State<DbState>,
+    id: Option<i32>,
+) -> Result<Vec<ChannelHealth>, String> {
+    let db = db_state.reader();
+    let channel_list_id =
+        resolve_channel_list_id(&db, id).ok_or_else(|| "Channel list not found".to_string())?;
+    load_channel_health(&db, channel_list_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_channel_health_scan_status(
+    db_state: State<'_, DbState>,
+    scan_state: State<'_, HealthScanState>,
+    id: Option<i32>,
+) -> Result<Option<HealthScanStatus>, String> {
+    let channel_list_id = {
+        let db = db_state.reader();
+        resolve_channel_list_id(&db, id).ok_or_else(|| "Channel list not found".to_string())?
+    };
+    let scans = scan_state.scans.lock().await;
+    Ok(scans.get(&channel_list_id).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{channel, create_test_db};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Minimal HTTP server: the handler maps "METHOD /path" to a raw response,
+    // or None to hold the connection open and force a client timeout.
+    fn spawn_test_server(handler: fn(&str) -> Option<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                std::thread::spawn(move || {
+                    let mut buffer = [0u8; 4096];
+                    let read = stream.read(&mut buffer).unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+                    let request_line = request.lines().next().unwrap_or_default();
+                    let mut parts = request_line.split_whitespace();
+                    let key = format!(
+                        "{} {}",
+                        parts.next().unwrap_or_default(),
+                        parts.next().unwrap_or_default()
+                    );
+
+                    match handler(&key) {
+                        Some(response) => {
+                            let _ = stream.write_all(response.as_bytes());
+                        }
+                        None => std::thread::sleep(Duration::from_secs(3)),
+                    }
+                });
+            }
+        });
+
+        format!("http://{}", address)
+    }
+
+    fn response(status: &str, content_type: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        )
+    }
+
+    fn test_handler(request: &str) -> Option<String> {
+        match request {
+            "HEAD /stream.ts" => Some(response("200 OK", "video/mp2t", "")),
+            "HEAD /no-head.ts" => Some(response("405 Method Not Allowed", "text/plain", "")),
+            "GET /no-head.ts" => Some(response("206 Partial Content", "video/mp2t", "data")),
+            "GET /live.m3u8" => Some(response(
+                "200 OK",
+                "application/vnd.apple.mpegurl",
+                "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nsegment1.ts\n",
+            )),
+            "GET /broken.m3u8" => Some(response(
+                "200 OK",
+                "application/vnd.apple.mpegurl",
+                "<html>Not a playlist</html>",
+            )),
+            "GET /slow.m3u8" => None,
+            _ => Some(response("404 Not Found", "text/plain", "")),
+        }
+    }
+
+    fn test_client() -> reqwest::Client {
+        build_probe_client(Duration::from_millis(500)).unwrap()
+    }
+
+    fn create_health_db() -> Connection {
+        let conn = create_test_db();
+        conn.execute(
+            "INSERT INTO settings (id, player_command, hide_dead_channels) VALUES (1, 'mpv', 1)",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn health(url: &str, status: StreamHealthStatus) -> ChannelHealth {
+        ChannelHealth {
+            url: url.to_string(),
+            status,
+            http_status: None,
+            latency_ms: None,
+            last_checked: 0,
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_alive_with_head() {
+        let base = spawn_test_server(test_handler);
+        let result = probe_stream(&test_client(), &format!("{}/stream.ts", base)).await;
+        assert_eq!(result.status, StreamHealthStatus::Alive);
+        assert_eq!(result.http_status, Some(200));
+        assert!(result.latency_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_probe_falls_back_to_range_get() {
+        let base = spawn_test_server(test_handler);
+        let result = probe_stream(&test_client(), &format!("{}/no-head.ts", base)).await;
+        assert_eq!(result.status, StreamHealthStatus::Alive);
+        assert_eq!(result.http_status, Some(206));
+    }
+
+    #[tokio::test]
+    async fn test_probe_dead_on_404() {
+        let base = spawn_test_server(test_handler);
+        let result = probe_stream(&test_client(), &format!("{}/missing.ts", base)).await;
+        assert_eq!(result.status, StreamHealthStatus::Dead);
+        assert_eq!(result.http_status, Some(404));
+    }
+
+    #[tokio::test]
+    async fn test_probe_hls_manifest() {
+        let base = spawn_test_server(test_handler);
+        let alive = probe_stream(&test_client(), &format!("{}/live.m3u8", base)).await;
+        assert_eq!(alive.status, StreamHealthStatus::Alive);
+
+        let broken = probe_stream(&test_client(), &format!("{}/broken.m3u8", base)).await;
+        assert_eq!(broken.status, StreamHealthStatus::Dead);
+        assert_eq!(broken.error.as_deref(), Some("Invalid HLS manifest"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_timeout() {
+        let base = spawn_test_server(test_handler);
+        let result = probe_stream(&test_client(), &format!("{}/slow.m3u8", base)).await;
+        assert_eq!(result.status, StreamHealthStatus::Timeout);
+        assert!(result.latency_ms.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_probe_streams_reports_every_url() {
+        let base = spawn_test_server(test_handler);
+        let urls: Vec<String> = ["/stream.ts", "/missing.ts", "/live.m3u8", "/no-head.ts"]
+            .iter()
+            .map(|path| format!("{}{}", base, path))
+            .collect();
+
+        let mut results = Vec::new();
+        probe_streams(test_client(), urls.clone(), 2, |result| {
+            results.push(result.unwrap())
+        })
+        .await;
+
+        assert_eq!(results.len(), urls.len());
+        let alive = results
+            .iter()
+            .filter(|h| h.status == StreamHealthStatus::Alive)
+            .count();
+        assert_eq!(alive, 3);
+    }
+
+    #[test]
+    fn test_is_valid_hls_manifest() {
+        assert!(is_valid_hls_manifest(
+            "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1280000\nlow.m3u8"
+        ));
+        assert!(is_valid_hls_manifest(
+            "\u{feff}#EXTM3U\n#EXTINF:10,\nseg.ts"
+        ));
+        assert!(!is_valid_hls_manifest("#EXTM3U\n"));
+        assert!(!is_valid_hls_manifest("<html></html>"));
+    }
+
+    #[test]
+    fn test_save_and_load_channel_health() {
+        let mut conn = create_health_db();
+        let results = vec![
+            health("http://a", StreamHealthStatus::Alive),
+            health("http://b", StreamHealthStatus::Dead),
+            health("http://c", StreamHealthStatus::Timeout),
+        ];
+        save_channel_health(&mut conn, 1, &results).unwrap();
+
+        let loaded = load_channel_health(&conn, 1).unwrap();
+        assert_eq!(loaded.len(), 3);
+
+        // Re-saving the same URL replaces the previous result
+        save_channel_health(
+            &mut conn,
+            1,
+            &[health("http://b", StreamHealthStatus::Alive)],
+        )
+        .unwrap();
+        let unreachable = get_unreachable_urls(&conn, 1).unwrap();
+        assert_eq!(unreachable.len(), 1);
+        assert!(unreachable.contains("http://c"));
+    }
+
+    #[test]
+    fn test_filter_dead_channels() {
+        let mut conn = create_health_db();
+        save_channel_health(
+            &mut conn,
+            1,
+            &[
+                health("http://alive", StreamHealthStatus::Alive),
+                health("http://dead", StreamHealthStatus::Dead),
+            ],
+        )
+        .unwrap();
+
+        let channels = vec![
+            channel("Alive", "http://alive", "Test"),
+            channel("Dead", "http://dead", "Test"),
+            channel("Unchecked", "http://unchecked", "Test"),
+        ];
+
+        let filtered = filter_dead_channels(&conn, None, channels.clone());
+        assert_eq!(filtered.len(), 2);
+        assert!(filtered.iter().all(|c| c.name != "Dead"));
+
+        conn.execute(
+            "UPDATE settings SET hide_dead_channels = 0 WHERE id = 1",
+            [],
+        )
+        .unwrap();
+        let unfiltered = filter_dead_channels(&conn, Some(1), channels);
+        assert_eq!(unfiltered.len(), 3);
+    }
+}
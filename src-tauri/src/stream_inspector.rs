@@ -0,0 +1,503 @@
+use crate::database::resolve_channel_list_id;
+use crate::http_client::client_builder;
+use crate::m3u_parser::{Channel, StreamHttpOptions};
+use crate::search::clear_advanced_cache;
+use crate::state::{ChannelCacheState, DbState};
+use crate::stream_health::read_prefix;
+use chrono::Utc;
+use reqwest::header::CONTENT_TYPE;
+use rusqlite::{Connection, Result as RusqliteResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::State;
+
+const INSPECT_TIMEOUT_SECONDS: u64 = 15;
+/// Master playlists are small; anything longer is not worth parsing.
+const MAX_MANIFEST_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamVariant {
+    pub url: String,
+    pub bandwidth: Option<u64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codecs: Option<String>,
+    pub frame_rate: Option<f64>,
+}
+
+impl StreamVariant {
+    /// Resolution label in the same style the M3U parser extracts from names, e.g. "1080p".
+    pub fn resolution_label(&self) -> Option<String> {
+        self.height.map(|height| format!("{}p", height))
+    }
+}
+
+// Splits an HLS attribute list on commas that are not inside quoted strings
+fn parse_attribute_list(input: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut parts = Vec::new();
+
+    for ch in input.chars() {
+        match ch {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(ch);
+            }
+            ',' if !in_quotes => parts.push(std::mem::take(&mut current)),
+            _ => current.push(ch),
+        }
+    }
+    parts.push(current);
+
+    for part in parts {
+        if let Some((key, value)) = part.split_once('=') {
+            attributes.insert(
+                key.trim().to_uppercase(),
+                value.trim().trim_matches('"').to_string(),
+            );
+        }
+    }
+    attributes
+}
+
+fn resolve_uri(base_url: &str, uri: &str) -> String {
+    match reqwest::Url::parse(base_url).and_then(|base| base.join(uri)) {
+        Ok(resolved) => resolved.to_string(),
+        Err(_) => uri.to_string(),
+    }
+}
+
+/// Parses the `#EXT-X-STREAM-INF` variants of an HLS master playlist.
+/// Media playlists have no variants and yield an empty list.
+pub fn parse_master_playlist(content: &str, base_url: &str) -> Vec<StreamVariant> {
+    let mut variants = Vec::new();
+    let mut lines = content.lines().map(str::trim);
+
+    while let Some(line) = lines.next() {
+        let Some(attribute_list) = line.strip_prefix("#EXT-X-STREAM-INF:") else {
+            continue;
+        };
+        let attributes = parse_attribute_list(attribute_list);
+
+        // The variant URI is the next line that is neither blank nor a tag
+        let Some(uri) = lines
+            .by_ref()
+            .find(|l| !l.is_empty() && !l.starts_with('#'))
+        else {
+            break;
+        };
+
+        let (width, height) = attributes
+            .get("RESOLUTION")
+            .and_then(|resolution| resolution.split_once(['x', 'X']))
+            .map(|(w, h)| (w.parse().ok(), h.parse().ok()))
+            .unwrap_or((None, None));
+
+        variants.push(StreamVariant {
+            url: resolve_uri(base_url, uri),
+            bandwidth: attributes.get("BANDWIDTH").and_then(|b| b.parse().ok()),
+            width,
+            height,
+            codecs: attributes.get("CODECS").cloned(),
+            frame_rate: attributes.get("FRAME-RATE").and_then(|f| f.parse().ok()),
+        });
+    }
+
+    variants
+}
+
+// Media served as audio or video is the stream itself, not a playlist
+fn is_media_type(content_type: &str) -> bool {
+    let content_type = content_type.to_lowercase();
+    (content_type.starts_with("video/") || content_type.starts_with("audio/"))
+        && !content_type.contains("mpegurl")
+}
+
+/// Fetches the manifest at `url` with the channel's request `options` and
+/// returns its variants, best first.
+pub async fn inspect_stream(
+    client: &reqwest::Client,
+    url: &str,
+    options: Option<&StreamHttpOptions>,
+) -> Result<Vec<StreamVariant>, String> {
+    let mut request = client.get(url);
+    if let Some(options) = options {
+        request = options.apply_to(request);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch stream: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Failed to fetch stream: HTTP {}",
+            response.status()
+        ));
+    }
+
+    let is_media = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(is_media_type);
+    if is_media {
+        return Err("Stream is not an HLS playlist".to_string());
+    }
+
+    // Redirects change the base that relative variant URIs resolve against
+    let final_url = response.url().to_string();
+    let content = read_prefix(response, MAX_MANIFEST_BYTES)
+        .await
+        .map_err(|e| format!("Failed to read stream manifest: {}", e))?;
+    if !content
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with("#EXTM3U")
+    {
+        return Err("Stream is not an HLS playlist".to_string());
+    }
+
+    let mut variants = parse_master_playlist(&content, &final_url);
+    variants.sort_by_key(|v| std::cmp::Reverse(v.bandwidth.unwrap_or(0)));
+    Ok(variants)
+}
+
+pub fn save_stream_variants(
+    conn: &mut Connection,
+    channel_list_id: i64,
+    channel_url: &str,
+    variants: &[StreamVariant],
+) -> RusqliteResult<()> {
+    let inspected_at = Utc::now().timestamp();
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM stream_variants WHERE channel_list_id = ?1 AND channel_url = ?2",
+        rusqlite::params![channel_list_id, channel_url],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO stream_variants (channel_list_id, channel_url, position, url, bandwidth, width, height, codecs, frame_rate, inspected_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+        for (position, variant) in variants.iter().enumerate() {
+            stmt.execute(rusqlite::params![
+                channel_list_id,
+                channel_url,
+                position as i64,
+                variant.url,
+                variant.bandwidth.map(|b| b as i64),
+                variant.width,
+                variant.height,
+                variant.codecs,
+                variant.frame_rate,
+                inspected_at,
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn load_stream_variants(
+    conn: &Connection,
+    channel_list_id: i64,
+    channel_url: &str,
+) -> RusqliteResult<Vec<StreamVariant>> {
+    let mut stmt = conn.prepare(
+        "SELECT url, bandwidth, width, height, codecs, frame_rate FROM stream_variants WHERE channel_list_id = ?1 AND channel_url = ?2 ORDER BY position",
+    )?;
+    let variant_iter = stmt.query_map(rusqlite::params![channel_list_id, channel_url], |row| {
+        let bandwidth: Option<i64> = row.get(1)?;
+        Ok(StreamVariant {
+            url: row.get(0)?,
+            bandwidth: bandwidth.map(|b| b as u64),
+            width: row.get(2)?,
+            height: row.get(3)?,
+            codecs: row.get(4)?,
+            frame_rate: row.get(5)?,
+        })
+    })?;
+
+    let mut variants = Vec::new();
+    for variant in variant_iter {
+        variants.push(variant?);
+    }
+    Ok(variants)
+}
+
+/// Replaces name-guessed resolutions with the best inspected variant of each stream.
+pub fn apply_inspected_resolutions(conn: &Connection, id: Option<i32>, channels: &mut [Channel]) {
+    let Some(channel_list_id) = resolve_channel_list_id(conn, id) else {
+        return;
+    };
+
+    let heights: HashMap<String, u32> = match conn
+        .prepare(
+            "SELECT channel_url, MAX(height) FROM stream_variants WHERE channel_list_id = ?1 AND height IS NOT NULL GROUP BY channel_url",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([channel_list_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        }) {
+        Ok(heights) => heights,
+        Err(_) => return,
+    };
+    if heights.is_empty() {
+        return;
+    }
+
+    for channel in channels.iter_mut() {
+        if let Some(height) = heights.get(&channel.url) {
+            channel.resolution = format!("{}p", height);
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn inspect_channel_stream(
+    db_state: State<'_, DbState>,
+    cache_state: State<'_, ChannelCacheState>,
+    channel: Channel,
+    id: Option<i32>,
+) -> Result<Vec<StreamVariant>, String> {
+    let channel_list_id = {
+        let db = db_state.reader();
+        resolve_channel_list_id(&db, id).ok_or_else(|| "Channel list not found".to_string())?
+    };
+
+    let client = client_builder()
+        .timeout(Duration::from_secs(INSPECT_TIMEOUT_SECONDS))
+        .user_agent("Mozilla/5.0")
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let variants = inspect_stream(&client, &channel.url, channel.http_options.as_ref()).await?;
+
+    {
+        let (url, saved) = (channel.url.clone(), variants.clone());
+        db_state
+            .write(move |db| save_stream_variants(db, channel_list_id, &url, &saved))
+            .await?;
+    }
+
+    // Patch the cached channels in place rather than reparsing the whole list
+    if let Some(label) = variants
+        .iter()
+        .max_by_key(|v| v.height)
+        .and_then(StreamVariant::resolution_label)
+    {
+        let mut cache = cache_state.cache.lock().unwrap();
+        if let Some(ref mut cached) = *cache {
+            if cached.channel_list_id == id {
+                for cached_channel in cached.channels.iter_mut() {
+                    if cached_channel.url == channel.url {
+                        cached_channel.resolution = label.clone();
+                    }
+                }
+                clear_advanced_cache();
+            }
+        }
+    }
+
+    Ok(variants)
+}
+
+#[tauri::command]
+pub fn get_channel_variants(
+    db_state: State<DbState>,
+    channel_url: String,
+    id: Option<i32>,
+) -> Result<Vec<StreamVariant>, String> {
+    let db = db_state.reader();
+    let channel_list_id =
+        resolve_channel_list_id(&db, id).ok_or_else(|| "Channel list not found".to_string())?;
+    load_stream_variants(&db, channel_list_id, &channel_url).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{channel, create_test_db};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    const MASTER_PLAYLIST: &str = r#"#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,CODECS="avc1.640028,mp4a.40.2",FRAME-RATE=50.000
+hd/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1500000,RESOLUTION=854x480,CODECS="avc1.4d401e,mp4a.40.2"
+
+http://cdn.example.com/sd/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS="mp4a.40.2"
+audio.m3u8
+"#;
+
+    // Serves MASTER_PLAYLIST at /master.m3u8 to requests carrying the channel's
+    // token, and an endless MPEG-TS body at /live.ts and, untyped, at /live.bin
+    fn spawn_stream_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                std::thread::spawn(move || {
+                    let mut buffer = [0u8; 4096];
+                    let read = stream.read(&mut buffer).unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buffer[..read]).to_lowercase();
+                    let live_type = if request.starts_with("get /live.ts") {
+                        Some("video/mp2t")
+                    } else if request.starts_with("get /live.bin") {
+                        Some("application/octet-stream")
+                    } else {
+                        None
+                    };
+                    if let Some(content_type) = live_type {
+                        let head = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nConnection: close\r\n\r\n",
+                            content_type
+                        );
+                        let _ = stream.write_all(head.as_bytes());
+                        let packet = [0x47u8; 188];
+                        while stream.write_all(&packet).is_ok() {}
+                        return;
+                    }
+                    let response = if request.contains("x-token: secret") {
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/vnd.apple.mpegurl\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            MASTER_PLAYLIST.len(),
+                            MASTER_PLAYLIST
+                        )
+                    } else {
+                        "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    };
+                    let _ = stream.write_all(response.as_bytes());
+                });
+            }
+        });
+
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn test_inspect_stream_sends_channel_options() {
+        let base = spawn_stream_server();
+        let url = format!("{}/master.m3u8", base);
+        let client = reqwest::Client::new();
+        let options = StreamHttpOptions {
+            headers: vec![("X-Token".to_string(), "secret".to_string())],
+            ..Default::default()
+        };
+
+        assert!(inspect_stream(&client, &url, None).await.is_err());
+        let variants = inspect_stream(&client, &url, Some(&options)).await.unwrap();
+        assert_eq!(variants.len(), 3);
+        assert_eq!(variants[0].height, Some(1080));
+    }
+
+    #[tokio::test]
+    async fn test_inspect_stream_rejects_live_media() {
+        let base = spawn_stream_server();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        for path in ["/live.ts", "/live.bin"] {
+            let result = inspect_stream(&client, &format!("{}{}", base, path), None).await;
+            assert_eq!(result, Err("Stream is not an HLS playlist".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_parse_master_playlist() {
+        let variants =
+            parse_master_playlist(MASTER_PLAYLIST, "http://example.com/live/master.m3u8");
+        assert_eq!(variants.len(), 3);
+
+        let hd = &variants[0];
+        assert_eq!(hd.url, "http://example.com/live/hd/index.m3u8");
+        assert_eq!(hd.bandwidth, Some(5_000_000));
+        assert_eq!((hd.width, hd.height), (Some(1920), Some(1080)));
+        assert_eq!(hd.codecs.as_deref(), Some("avc1.640028,mp4a.40.2"));
+        assert_eq!(hd.frame_rate, Some(50.0));
+        assert_eq!(hd.resolution_label().as_deref(), Some("1080p"));
+
+        // Absolute URIs are kept, blank lines before the URI are skipped
+        assert_eq!(variants[1].url, "http://cdn.example.com/sd/index.m3u8");
+        assert_eq!(variants[1].frame_rate, None);
+
+        // Audio-only variants have no resolution
+        assert_eq!(variants[2].height, None);
+        assert_eq!(variants[2].resolution_label(), None);
+    }
+
+    #[test]
+    fn test_parse_media_playlist_has_no_variants() {
+        let media = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nsegment0.ts\n";
+        assert!(parse_master_playlist(media, "http://example.com/live.m3u8").is_empty());
+    }
+
+    #[test]
+    fn test_parse_attribute_list_with_quoted_commas() {
+        let attributes = parse_attribute_list(
+            r#"BANDWIDTH=800000,CODECS="avc1.4d401e,mp4a.40.2",resolution=640x360"#,
+        );
+        assert_eq!(
+            attributes.get("BANDWIDTH").map(String::as_str),
+            Some("800000")
+        );
+        assert_eq!(
+            attributes.get("CODECS").map(String::as_str),
+            Some("avc1.4d401e,mp4a.40.2")
+        );
+        assert_eq!(
+            attributes.get("RESOLUTION").map(String::as_str),
+            Some("640x360")
+        );
+    }
+
+    #[test]
+    fn test_save_and_load_stream_variants() {
+        let mut conn = create_test_db();
+        let variants = parse_master_playlist(MASTER_PLAYLIST, "http://example.com/master.m3u8");
+
+        save_stream_variants(&mut conn, 1, "http://example.com/master.m3u8", &variants).unwrap();
+        let loaded = load_stream_variants(&conn, 1, "http://example.com/master.m3u8").unwrap();
+        assert_eq!(loaded, variants);
+
+        // Re-inspecting replaces the previous variants
+        save_stream_variants(
+            &mut conn,
+            1,
+            "http://example.com/master.m3u8",
+            &variants[..1],
+        )
+        .unwrap();
+        let loaded = load_stream_variants(&conn, 1, "http://example.com/master.m3u8").unwrap();
+        assert_eq!(loaded.len(), 1);
+    }
+
+    #[test]
+    fn test_apply_inspected_resolutions() {
+        let mut conn = create_test_db();
+        let variants = parse_master_playlist(MASTER_PLAYLIST, "http://example.com/master.m3u8");
+        save_stream_variants(&mut conn, 1, "http://example.com/master.m3u8", &variants).unwrap();
+
+        let mut channels = vec![
+            Channel {
+                resolution: "720p".to_string(),
+                ..channel("Inspected 720p", "http://example.com/master.m3u8", "")
+            },
+            Channel {
+                resolution: "720p".to_string(),
+                ..channel("Not inspected 720p", "http://example.com/other.m3u8", "")
+            },
+        ];
+
+        apply_inspected_resolutions(&conn, None, &mut channels);
+        assert_eq!(channels[0].resolution, "1080p");
+        assert_eq!(channels[1].resolution, "720p");
+    }
+}
@@ -0,0 +1,38 @@
+//! Fixtures shared by the unit tests.
+
+use crate::m3u_parser::Channel;
+use crate::migrations::run_migrations;
+use rusqlite::Connection;
+
+/// A channel with the given name, URL and group, every other field empty.
+pub fn channel(name: &str, url: &str, group: &str) -> Channel {
+    Channel {
+        name: name.to_string(),
+        logo: "".to_string(),
+        url: url.to_string(),
+        group_title: group.to_string(),
+        tvg_id: "".to_string(),
+        resolution: "".to_string(),
+        extra_info: "".to_string(),
+        http_options: None,
+        source_list_id: None,
+    }
+}
+
+/// An in-memory database with every migration applied and no channel lists.
+pub fn migrated_db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    run_migrations(&mut conn, None).unwrap();
+    conn
+}
+
+/// A migrated database whose only list, id 1, is the default one.
+pub fn create_test_db() -> Connection {
+    let conn = migrated_db();
+    conn.execute(
+        "INSERT INTO channel_lists (id, name, source, is_default) VALUES (1, 'Test', 'http://example.com', 1)",
+        [],
+    )
+    .unwrap();
+    conn
+}
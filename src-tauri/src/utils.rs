@@ -1,14 +1,11 @@
-use crate::error::{Result as TolloResult, TolloError};
+use crate::error::Result as TolloResult;
+use crate::paths::data_paths;
 use std::fs;
 use rusqlite::Connection;
-use dirs;
 
 // Add cleanup function near the top with other utility functions
 pub fn cleanup_orphaned_channel_files(db_connection: &Connection) -> TolloResult<()> {
-    let data_dir = dirs::data_dir()
-        .ok_or_else(|| TolloError::DataDirectoryAccess)?
-        .join("tollo");
-    let channel_lists_dir = data_dir.join("channel_lists");
+    let channel_lists_dir = data_paths().channel_lists_dir();
     
     // Create channel_lists directory if it doesn't exist
     if let Err(e) = fs::create_dir_all(&channel_lists_dir) {
@@ -23,7 +20,8 @@ pub fn cleanup_orphaned_channel_files(db_connection: &Connection) -> TolloResult
                 .filter_map(|entry| entry.ok())
                 .filter(|entry| {
                     entry.path().is_file() && 
-                    entry.path().extension().map_or(false, |ext| ext == "m3u")
+                    // .part files are playlist writes cut short by a crash
+                    entry.path().extension().is_some_and(|ext| ext == "m3u" || ext == "part")
                 })
                 .filter_map(|entry| {
                     entry.file_name().to_str().map(|s| s.to_string())
@@ -237,15 +237,17 @@ export function ChannelListsSettings({
                         <span className="async-progress-message">
                           {asyncStatus.message}
                         </span>
-                        <span className="async-progress-percentage">
-                          {Math.round(asyncStatus.progress * 100)}%
-                        </span>
+                        {asyncStatus.progress !== null && (
+                          <span className="async-progress-percentage">
+                            {Math.round(asyncStatus.progress * 100)}%
+                          </span>
+                        )}
                       </div>
                       <div className="async-progress-bar">
                         <div
-                          className="async-progress-fill"
+                          className={`async-progress-fill ${asyncStatus.progress === null ? "indeterminate" : ""}`}
                           style={{
-                            width: `${asyncStatus.progress * 100}%`,
+                            width: `${(asyncStatus.progress ?? 1) * 100}%`,
                             backgroundColor: getStatusColor(asyncStatus.status),
                           }}
                         />
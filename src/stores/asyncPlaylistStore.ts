@@ -4,7 +4,7 @@ import { listen } from "@tauri-apps/api/event";
 export interface PlaylistFetchStatus {
   id: number;
   status: string; // "starting", "fetching", "processing", "saving", "completed", "error"
-  progress: number; // 0.0 to 1.0
+  progress: number | null; // 0.0 to 1.0, null while it can't be measured
   message: string;
   channel_count?: number;
   error?: string;
@@ -102,9 +102,10 @@ export class AsyncPlaylistStore {
   }
 
   // Get progress percentage for display
-  getProgressPercentage(id: number): number {
+  getProgressPercentage(id: number): number | null {
     const status = this.fetchStatuses.get(id);
-    return status ? Math.round(status.progress * 100) : 0;
+    if (!status) return 0;
+    return status.progress === null ? null : Math.round(status.progress * 100);
   }
 
   // Usage example methods: